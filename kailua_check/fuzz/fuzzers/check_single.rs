@@ -28,6 +28,7 @@ pub extern fn go(data: &[u8]) {
     impl Report for DummyReport {
         fn message_locale(&self) -> Locale { Locale::dummy() }
         fn add_span(&self, _: Kind, _: Span, _: &Localize) -> kailua_diag::Result<()> { Ok(()) }
+        fn max_kind(&self) -> Option<Kind> { None }
     }
 
     struct DummyOptions;
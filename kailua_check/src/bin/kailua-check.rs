@@ -6,18 +6,25 @@ extern crate kailua_check;
 extern crate env_logger;
 
 use std::str;
-use std::io;
+use std::cmp;
+use std::io::{self, Write};
 use std::env;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::collections::HashSet;
 use std::path::Path;
+use std::process;
+use std::fs::File;
 
-use kailua_env::{Spanned, Source, SourceFile, WithLoc};
-use kailua_diag::{Stop, Report, ConsoleReport, TrackMaxKind};
+use kailua_env::{Pos, Span, Spanned, Source, SourceFile, SourceSlice, WithLoc};
+use kailua_diag::{Stop, Kind, Locale, Localize, Localized, Report, ConsoleReport, ColorMode};
+use kailua_diag::TrackMaxKind;
+use kailua_diag::message::get_message_locale;
 use kailua_syntax::{parse_chunk, Chunk};
-use kailua_check::check_from_chunk;
+use kailua_check::check_from_chunk_with_options;
 use kailua_check::env::Context;
-use kailua_check::options::{FsSource, FsOptions};
+use kailua_check::signature::write_signature;
+use kailua_check::options::{FsSource, FsOptions, CheckOptions, DEFAULT_MAX_TYPE_COMPLEXITY};
 
 struct LocalFsSource {
     source: Rc<RefCell<Source>>,
@@ -46,10 +53,139 @@ impl FsSource for LocalFsSource {
     }
 }
 
-fn parse_and_check(mainpath: &Path) -> Result<(), String> {
+// collects reports as the Language Server Protocol `Diagnostic`s in JSON.
+// every report is a separate diagnostic; unspanned reports are put to the beginning of file.
+struct JsonDiagReport {
+    source: Rc<RefCell<Source>>,
+    locale: Locale,
+    diags: RefCell<Vec<String>>,
+    seen: RefCell<HashSet<String>>,
+    last_error: Cell<Option<Span>>,
+    max_kind: Cell<Option<Kind>>,
+}
+
+impl JsonDiagReport {
+    fn new(source: Rc<RefCell<Source>>) -> JsonDiagReport {
+        JsonDiagReport {
+            source: source,
+            locale: get_message_locale().unwrap_or_else(|| Locale::dummy()),
+            diags: RefCell::new(Vec::new()),
+            seen: RefCell::new(HashSet::new()),
+            last_error: Cell::new(None),
+            max_kind: Cell::new(None),
+        }
+    }
+
+    // returns 0-based lines and u16 offsets for the beginning and end (exclusive) of the span
+    fn translate_span(&self, span: Span) -> Option<((usize, usize), (usize, usize))> {
+        let source = self.source.borrow();
+        let file = source.get_file(span.unit())?;
+        let (beginline, mut spans, endline) = file.lines_from_span(span)?;
+
+        // LSP expects the u16 offset, as JavaScript `.charAt` does
+        let u16_offset = |linebegin: Pos, pos: Pos| {
+            let begin = linebegin.to_usize();
+            let off = pos.to_usize();
+            match file.data() {
+                SourceSlice::U8(s) => {
+                    let s = &s[begin..off];
+                    // non-BMP codepoints (starting with 0b11110xxx) result in two u16s
+                    let ncps = s.iter().filter(|&&b| b & 0b1100_0000 != 0b1000_0000).count();
+                    let nnonbmps = s.iter().filter(|&&b| b >= 0b1111_0000).count();
+                    ncps + nnonbmps
+                }
+                SourceSlice::U16(_) => off - begin,
+            }
+        };
+
+        let beginspan = spans.next()?;
+        let endspan = spans.next_back().unwrap_or(beginspan);
+        let beginch = u16_offset(beginspan.begin(), span.begin());
+        let endch = u16_offset(endspan.begin(), span.end());
+        Some(((beginline, beginch), (endline, endch)))
+    }
+
+    fn to_json(&self) -> String {
+        format!("[{}]", self.diags.borrow().join(","))
+    }
+}
+
+impl Report for JsonDiagReport {
+    fn message_locale(&self) -> Locale {
+        self.locale
+    }
+
+    fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> kailua_diag::Result<()> {
+        self.max_kind.set(cmp::max(self.max_kind.get(), Some(kind)));
+        if kind >= Kind::Error {
+            self.last_error.set(Some(span));
+        }
+        let severity = match kind {
+            Kind::Fatal | Kind::Error => 1,
+            Kind::Warning => 2,
+            Kind::Note | Kind::Info | Kind::Cause => 3,
+        };
+        let ((beginline, beginch), (endline, endch)) =
+            self.translate_span(span).unwrap_or(((0, 0), (0, 0)));
+        let msg = Localized::new(msg, self.message_locale()).to_string();
+        self.diags.borrow_mut().push(format!(
+            "{{\"range\":{{\"start\":{{\"line\":{},\"character\":{}}},\
+                          \"end\":{{\"line\":{},\"character\":{}}}}},\
+               \"severity\":{},\"message\":{},\"source\":\"kailua\"}}",
+            beginline, beginch, endline, endch, severity, json_string(&msg)));
+
+        if kind == Kind::Fatal { Err(Stop) } else { Ok(()) }
+    }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.max_kind.get()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.seen.borrow_mut().insert(key.to_owned())
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.last_error.get()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.max_kind.get().is_none()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse_and_check(mainpath: &Path, strict_return_types: bool,
+                   max_type_complexity: Option<usize>, verify_annotations: bool,
+                   dump_signature: bool, json_diag: bool,
+                   color: ColorMode) -> Result<(), String> {
     let source = Rc::new(RefCell::new(Source::new()));
-    let report = Rc::new(TrackMaxKind::new(ConsoleReport::new(source.clone())));
+    let json = Rc::new(JsonDiagReport::new(source.clone()));
+    let inner: Box<Report> = if json_diag {
+        Box::new(json.clone())
+    } else {
+        Box::new(ConsoleReport::new_with_color(source.clone(), color))
+    };
+    let report = Rc::new(TrackMaxKind::new(inner));
     let mut context = Context::new(report.clone());
+    context.set_verify_annotations(verify_annotations);
 
     let fssource = LocalFsSource { source: source };
     let filechunk = fssource.chunk_from_path(mainpath.without_loc(), &report).map_err(|_| {
@@ -60,25 +196,118 @@ fn parse_and_check(mainpath: &Path) -> Result<(), String> {
     let root = mainpath.parent().unwrap_or(&Path::new(".."));
     let opts = Rc::new(RefCell::new(FsOptions::new(fssource, root.to_owned())));
 
-    if check_from_chunk(&mut context, filechunk, opts).is_ok() && report.can_continue() {
-        Ok(())
-    } else {
-        Err("stopped due to prior errors".into())
+    let checkopts = CheckOptions { strict_return_types: strict_return_types,
+                                   max_type_complexity: max_type_complexity };
+    let ret = check_from_chunk_with_options(&mut context, filechunk, opts, checkopts);
+    if json_diag {
+        let _ = writeln!(io::stderr(), "{}", json.to_json());
     }
+
+    if !(ret.is_ok() && report.can_continue()) {
+        return Err("stopped due to prior errors".into());
+    }
+
+    if dump_signature {
+        let stub_path = mainpath.with_extension("d.lua");
+        File::create(&stub_path).and_then(|f| write_signature(&context, f)).map_err(|e| {
+            format!("couldn't write a signature file `{}`: {}", stub_path.display(), e)
+        })?;
+    }
+    Ok(())
 }
 
 pub fn main() {
     env_logger::init().unwrap();
-    for path in env::args().skip(1) {
-        println!("--== {} ==--", path);
-        if let Err(e) = parse_and_check(&Path::new(&path)) {
-            // hide the internal error message, which will be eventually removed
-            println!("stopped due to prior errors.");
-            info!("error while checking {}: {}", path, e);
+
+    let mut strict_return_types = false;
+    let mut max_type_complexity = Some(DEFAULT_MAX_TYPE_COMPLEXITY);
+    let mut verify_annotations = false;
+    let mut dump_signature = false;
+    let mut json_diag = false;
+    let mut color = ColorMode::Auto;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--check-return-types" {
+            strict_return_types = true;
+        } else if arg == "--max-type-complexity" {
+            // checking stops when any expression type is nested deeper than this
+            match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => max_type_complexity = Some(n),
+                None => {
+                    let _ = writeln!(io::stderr(), "--max-type-complexity requires a number");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--verify-annotations" {
+            // explicit annotations should be as general as the inferred types
+            verify_annotations = true;
+        } else if arg == "--dump-signature" {
+            // a signature stub is written to `<path without extension>.d.lua` after checking
+            dump_signature = true;
+        } else if arg == "--json-diag" {
+            // diagnostics are printed to stderr in the LSP `Diagnostic` format,
+            // and nothing else is printed
+            json_diag = true;
+        } else if arg == "--color=always" {
+            color = ColorMode::Always;
+        } else if arg == "--color=never" {
+            color = ColorMode::Never;
+        } else if arg == "--color=auto" {
+            color = ColorMode::Auto;
         } else {
-            println!("done.");
+            paths.push(arg);
+        }
+    }
+
+    for path in paths {
+        if !json_diag {
+            println!("--== {} ==--", path);
+        }
+        let ret = parse_and_check(&Path::new(&path), strict_return_types, max_type_complexity,
+                                  verify_annotations, dump_signature, json_diag, color);
+        if let Err(ref e) = ret {
+            info!("error while checking {}: {}", path, e);
+        }
+        if !json_diag {
+            if ret.is_err() {
+                // hide the internal error message, which will be eventually removed
+                println!("stopped due to prior errors.");
+            } else {
+                println!("done.");
+            }
+            println!("");
         }
-        println!("");
     }
 }
 
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string(""), r#""""#);
+    assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    assert_eq!(json_string("\n\r\t\u{1}"), r#""\n\r\t\u0001""#);
+    assert_eq!(json_string("\u{e9}\u{1f600}"), "\"\u{e9}\u{1f600}\"");
+}
+
+#[test]
+fn test_translate_span() {
+    let source = Rc::new(RefCell::new(Source::new()));
+    let report = JsonDiagReport::new(source.clone());
+
+    // `é` is a single u16 and `😀` is two u16s, while they are two and four bytes in UTF-8
+    let code = "a = '\u{e9}\u{1f600}' .. b\nc = 'd'";
+    let span = source.borrow_mut().add(SourceFile::from_u8("a.lua".to_string(),
+                                                           code.as_bytes().to_owned()));
+    let b = code.find(" b").unwrap() + 1;
+    let bspan = Span::new(span.begin() + b, span.begin() + b + 1);
+    assert_eq!(report.translate_span(bspan), Some(((0, 13), (0, 14))));
+    let c = code.find("c").unwrap();
+    let multiline = Span::new(span.begin() + b, span.begin() + c + 1);
+    assert_eq!(report.translate_span(multiline), Some(((0, 13), (1, 1))));
+
+    // u16 sources are already in u16 offsets
+    let code16: Vec<u16> = code.encode_utf16().collect();
+    let span16 = source.borrow_mut().add(SourceFile::from_u16("b.lua".to_string(), code16));
+    let bspan16 = Span::new(span16.begin() + 13, span16.begin() + 14);
+    assert_eq!(report.translate_span(bspan16), Some(((0, 13), (0, 14))));
+}
@@ -3,14 +3,16 @@ use std::cmp;
 use std::ops;
 use std::str;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use take_mut::take;
 
-use kailua_env::{Span, Spanned, WithLoc};
+use kailua_env::{Span, Spanned, WithLoc, ScopedId};
 use kailua_diag::{self, Result, Report, Reporter};
 use kailua_syntax::{Str, Name};
 use kailua_syntax::ast::{self, NameRef, Var, TypeSpec, Kind, Sig, Ex, Exp, UnOp, BinOp, Table};
 use kailua_syntax::ast::{SelfParam, TypeScope, Args, St, Stmt, Block, K, Attr, M, MM, Varargs};
+use kailua_syntax::ast::LocalAttr;
+use kailua_syntax::ast::AstNode;
 use kailua_types::diag::{TypeReport, TypeReportHint, TypeReportMore};
 use kailua_types::ty::{Displayed, Display, TypeContext, TypeResolver};
 use kailua_types::ty::{Dyn, Nil, T, Ty, TySeq, SpannedTySeq, Lattice, Union, Dummy};
@@ -19,7 +21,11 @@ use kailua_types::ty::{F, Slot, SlotSeq, SpannedSlotSeq, Tag, Class, ClassId};
 use kailua_types::ty::flags::*;
 use kailua_types::env::Types;
 use env::{Env, Returns, Frame, Scope, Module, Context, SlotSpec};
+use options::CheckOptions;
 use class_system::make_predefined_class_system;
+use defs::get_defs;
+use pattern::{self, Capture};
+use pack::{self, PackValue};
 use message as m;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -144,7 +150,7 @@ impl<'chk, 'inp, 'envr, 'env, R: Report>
 }
 
 impl<'chk, 'inp, 'envr, 'env, R: Report> Drop for ScopedChecker<'chk, 'inp, 'envr, 'env, R> {
-    fn drop(&mut self) { self.checker.env.leave(); }
+    fn drop(&mut self) { self.checker.leave(); }
 }
 
 // conditions out of boolean expression, used for assertion and branch typing
@@ -224,12 +230,55 @@ impl<'inp> PendingModules<'inp> {
 pub struct Checker<'inp, 'envr, 'env: 'envr, R: 'env> {
     env: &'envr mut Env<'env, R>,
     pending_modules: Vec<PendingModules<'inp>>,
+
+    // numeric `for` loop variables, assignments to which do not affect the loop.
+    // local names are never reused in a single chunk, so it is never cleared.
+    for_vars: HashSet<ScopedId>,
+
+    // `WHATEVER` variables currently read as records inferred from table literals,
+    // with the inferred slots and the scope depths where they were assigned.
+    // they are only visible from the same function and expire at the end of that scope.
+    dynamic_records: HashMap<NameRef, (Slot, usize)>,
+
+    // whether each scope entered by the checker is a function scope.
+    scopes: Vec<bool>,
+
+    // types yielded from function literals via `coroutine.yield`, keyed by their spans.
+    // used to refine the return types of `coroutine.wrap`.
+    // function literals calling other functions with unknown yields are not recorded.
+    yields: HashMap<Span, TySeq>,
+
+    // local names bound to function literals, with the number of enclosing function scopes.
+    // calls to them from the same function do not make yields unknown,
+    // as their yields are already collected to the enclosing function.
+    // the name is removed when assigned again.
+    yielding_locals: HashMap<ScopedId, usize>,
+
+    // the span and tag of the last function call expression, used for `for ... in` iterators.
+    last_call_tag: Option<(Span, Tag)>,
+
+    // local names being initialized with function literals (`local f = function() ... end`),
+    // with outer bindings of the same name (None if global), spans of those names and literals
+    // and whether it has been warned.
+    // such names are not yet in scope inside the literals, unlike `local function f()`.
+    local_funcs: Vec<(Name, Option<ScopedId>, Span, Span, bool)>,
+
+    // options for the checking behavior, inherited to checkers for `require`d chunks.
+    options: CheckOptions,
 }
 
 impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     /// Creates a new checker from the per-file context.
     pub fn new(env: &'envr mut Env<'env, R>) -> Checker<'inp, 'envr, 'env, R> {
-        Checker { env: env, pending_modules: Vec::new() }
+        Checker::with_options(env, CheckOptions::default())
+    }
+
+    pub fn with_options(env: &'envr mut Env<'env, R>,
+                        options: CheckOptions) -> Checker<'inp, 'envr, 'env, R> {
+        Checker { env: env, pending_modules: Vec::new(), for_vars: HashSet::new(),
+                  dynamic_records: HashMap::new(), scopes: Vec::new(),
+                  yields: HashMap::new(), yielding_locals: HashMap::new(), last_call_tag: None,
+                  local_funcs: Vec::new(), options: options }
     }
 
     fn types(&mut self) -> &mut Types {
@@ -245,10 +294,20 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     }
 
     fn scoped<'chk>(&'chk mut self, scope: Scope) -> ScopedChecker<'chk, 'inp, 'envr, 'env, R> {
+        self.scopes.push(scope.get_frame().is_some());
         self.env.enter(scope);
         ScopedChecker { checker: self }
     }
 
+    fn leave(&mut self) {
+        self.env.leave();
+        self.scopes.pop();
+
+        // records inferred in the scope being left are no longer valid
+        let depth = self.scopes.len();
+        self.dynamic_records.retain(|_, &mut (_, vardepth)| vardepth <= depth);
+    }
+
     // XXX in general reachability checking should continue when the type variable get resolved
     fn check_bool(&self, seq: TySeq) -> Bool {
         let empty_seq = seq.head.is_empty();
@@ -315,6 +374,42 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         }
     }
 
+    // warns if `lhs == rhs` (or `lhs ~= rhs`) is a constant, since they have no common values.
+    fn check_comparison(&mut self, lhs: &Ty, op: BinOp, rhs: &Ty, expspan: Span) -> Result<()> {
+        let flags_of = |ty: &Ty, checker: &Checker<R>| {
+            let mut flags = checker.env.get_type_bounds(ty).1;
+            if let T::None = **ty {
+                if ty.nil() != Nil::Absent { flags |= T_NOISY_NIL; } // `nil`
+            }
+            if flags == T_NONE {
+                None // no known values (e.g. type variables), do not try to warn
+            } else {
+                Some(flags)
+            }
+        };
+
+        let (mut lflags, mut rflags) = match (flags_of(lhs, self), flags_of(rhs, self)) {
+            (Some(lflags), Some(rflags)) => (lflags, rflags),
+            (_, _) => return Ok(()),
+        };
+
+        // a silent nil is not in the flags but can be nil in runtime, so it can equal to `nil`
+        if rflags == T_NOISY_NIL && lhs.nil() != Nil::Absent { lflags |= T_NOISY_NIL; }
+        if lflags == T_NOISY_NIL && rhs.nil() != Nil::Absent { rflags |= T_NOISY_NIL; }
+
+        if !lflags.can_equal(rflags) {
+            let lhs = self.display(lhs);
+            let rhs = self.display(rhs);
+            if op == BinOp::Eq {
+                self.env.warn(expspan, m::AlwaysFalseComparison { lhs: lhs, rhs: rhs }).done()?;
+            } else {
+                self.env.warn(expspan, m::AlwaysTrueComparison { lhs: lhs, rhs: rhs }).done()?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn check_bin_op(&mut self, lhs: &Spanned<Slot>, op: BinOp, rhs: &Spanned<Slot>,
                     expspan: Span) -> Result<Slot> {
         let finalize = |r: TypeReport, checker: &mut Checker<R>| {
@@ -466,6 +561,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             }
 
             BinOp::Eq | BinOp::Ne => { // works for any types
+                self.check_comparison(&lhs.unlift(), op, &rhs.unlift(), expspan)?;
                 Ok(Slot::just(Ty::new(T::Boolean)))
             }
 
@@ -476,9 +572,9 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
                 match self.check_bool(TySeq::from(lhs.unlift().clone())) {
                     // True and T => T
-                    Bool::Truthy => Ok(Slot::just(rhs.unlift().clone())),
+                    Bool::Truthy => Ok(rhs.to_rvalue()),
                     // False and T => False
-                    Bool::Falsy => Ok(Slot::just(lhs.unlift().clone())),
+                    Bool::Falsy => Ok(lhs.to_rvalue()),
                     // unsure, both can be possible (but truthy types in lhs are not kept)
                     Bool::Unknown => {
                         let falsy_lhs = lhs.as_ref().map(|t| t.unlift().clone().falsy());
@@ -495,9 +591,9 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
                 match self.check_bool(TySeq::from(lhs.unlift().clone())) {
                     // True or T => True
-                    Bool::Truthy => Ok(Slot::just(lhs.unlift().clone())),
+                    Bool::Truthy => Ok(lhs.to_rvalue()),
                     // False or T => T
-                    Bool::Falsy => Ok(Slot::just(rhs.unlift().clone())),
+                    Bool::Falsy => Ok(rhs.to_rvalue()),
                     // unsure, both can be possible (but falsy types in lhs are not kept)
                     Bool::Unknown => {
                         let truthy_lhs = lhs.as_ref().map(|t| t.unlift().clone().truthy());
@@ -525,27 +621,21 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         // check if generalize(func.args) :> args and gather generalize(func.returns)
         let mut returns = match *functy.get_functions().unwrap() {
             Functions::Simple(ref f) => {
-                let generalize_tyseq = |seq: &TySeq, ctx: &mut TypeContext| {
-                    let head = seq.head.iter().map(|t| t.clone().generalize(ctx)).collect();
-                    let tail = seq.tail.as_ref().map(|t| t.clone().generalize(ctx));
-                    TySeq { head: head, tail: tail }
+                let (funcargs, returns) = f.generalize(self.types());
+                let funcargs = funcargs.all_with_loc(func);
+                let hint = if methodcall {
+                    TypeReportHint::MethodArgs
+                } else {
+                    TypeReportHint::FuncArgs
                 };
-
-                let funcargs = generalize_tyseq(&f.args, self.types()).all_with_loc(func);
-                if let Err(r) = args.assert_sub(&funcargs, self.types()) {
-                    let hint = if methodcall {
-                        TypeReportHint::MethodArgs
-                    } else {
-                        TypeReportHint::FuncArgs
-                    };
-                    self.env.error(func, m::CallToWrongType { func: self.display(func) })
-                            .report_types(r, hint)
-                            .done()?;
+                if !self.env.assert_sub_reporting(args, &funcargs, func.span, hint, |types| {
+                    Box::new(m::CallToWrongType { func: func.base.display(types) })
+                })? {
                     return Ok(Exitable::dummy());
                 }
 
-                if let Some(ref returns) = f.returns {
-                    generalize_tyseq(returns, self.types())
+                if let Some(returns) = returns {
+                    returns
                 } else {
                     return Ok(Exitable::diverging());
                 }
@@ -614,22 +704,349 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let knil = k.clone().with_nil();
                 let v = v.unlift().clone().without_nil();
                 *returns.ensure_at_mut(0) = Ty::new(T::func(Function {
+                    typarams: Vec::new(),
                     args: TySeq { head: vec![tab.clone(), k.clone()], tail: None },
                     argnames: Vec::new(),
                     returns: Some(TySeq { head: vec![knil, v], tail: None }),
+                    returnnames: Vec::new(),
                 }));
                 *returns.ensure_at_mut(1) = tab;
                 *returns.ensure_at_mut(2) = k;
             })();
         }
 
+        // `math.max` and similar: integer arguments result in an integer
+        if functy.tag() == Some(Tag::PreserveInteger) {
+            let mut all_integral = true;
+            let mut dynamic = None;
+            for arg in args.head.iter().chain(args.tail.iter()) {
+                let flags = self.env.get_type_bounds(&arg.base).1;
+                if let Some(dyn) = flags.get_dynamic() {
+                    dynamic = Some(dyn);
+                    break;
+                }
+                if !flags.is_integral() {
+                    all_integral = false;
+                }
+            }
+
+            if let Some(dyn) = dynamic {
+                *returns.ensure_at_mut(0) = Ty::new(T::Dynamic(dyn));
+            } else if all_integral && !args.head.is_empty() {
+                *returns.ensure_at_mut(0) = Ty::new(T::Integer);
+            }
+        }
+
+        // `math.type`: the result depends on which kind of numbers the argument can be
+        if functy.tag() == Some(Tag::MathType) {
+            if let Some(arg) = args.head.first() {
+                let flags = self.env.get_type_bounds(&arg.base).1;
+                if flags.get_dynamic().is_none() && !flags.is_empty() {
+                    let mut names = Vec::new();
+                    if flags.intersects(T_INTEGER) {
+                        names.push(Str::from(b"integer"[..].to_owned()));
+                    }
+                    if flags.intersects(T_NONINTEGER) {
+                        names.push(Str::from(b"float"[..].to_owned()));
+                    }
+                    let mut ty = Ty::new(if names.is_empty() { T::None } else { T::strs(names) });
+                    if flags.intersects(!T_NUMBER) {
+                        // non-numbers result in `nil`
+                        ty = ty.with_nil();
+                    }
+                    *returns.ensure_at_mut(0) = ty;
+                }
+            }
+        }
+
+        // `pcall`: check the call to the first argument and wrap its return types
+        if functy.tag() == Some(Tag::Pcall) {
+            let callee = args.head.first().and_then(|f| {
+                self.env.resolve_exact_type(f).map(|fty| fty.with_loc(f))
+            });
+
+            if let Some(dyn) = callee.as_ref().and_then(|f| f.get_dynamic()) {
+                returns = TySeq { head: vec![Ty::new(T::Boolean)],
+                                  tail: Some(Ty::new(T::Dynamic(dyn))) };
+            } else if let Some(callee) = callee.and_then(|f| {
+                if f.get_functions().is_some() { Some(f) } else { None }
+            }) {
+                let calleeargs = SpannedTySeq { head: args.head[1..].to_owned(),
+                                                tail: args.tail.clone(),
+                                                span: args.span };
+                let Exitable(exit, calleereturns) =
+                    self.check_callable(&callee, &calleeargs, false)?;
+
+                let errty = Ty::new(T::String);
+                let mut head = vec![Ty::new(T::Boolean)];
+                let mut tail = None;
+                if exit == ExprExit::Stop {
+                    // the callee never returns, so the call always fails
+                    head.push(errty);
+                } else {
+                    let TySeq { head: mut calleehead, tail: calleetail } = calleereturns;
+                    let rest = if calleehead.is_empty() {
+                        Vec::new()
+                    } else {
+                        calleehead.split_off(1)
+                    };
+                    let first = calleehead.pop().unwrap_or_else(|| {
+                        calleetail.clone().map_or_else(Ty::noisy_nil, |t| t.with_nil())
+                    });
+                    head.push(match first.union(&errty, false, self.types()) {
+                        Ok(t) => t,
+                        Err(_) => Ty::dummy(),
+                    });
+                    // the remaining values are missing when the call fails
+                    head.extend(rest.into_iter().map(|t| t.with_nil()));
+                    tail = calleetail.map(|t| t.with_nil());
+                }
+                returns = TySeq { head: head, tail: tail };
+            }
+        }
+
+        // `string.find` and similar: refine the return types from the pattern literal
+        match functy.tag() {
+            Some(tag @ Tag::StringFind) |
+            Some(tag @ Tag::StringMatch) |
+            Some(tag @ Tag::StringGmatch) => {
+                // `string.find` with `plain` returns no captures
+                let plain = tag == Tag::StringFind && args.head.len() > 3;
+                let captures = args.head.get(1).and_then(|pat| {
+                    self.env.resolve_exact_type(pat).and_then(|pat| {
+                        pat.as_string().and_then(|pat| pattern::parse_captures(pat))
+                    })
+                });
+
+                if let (false, Some(captures)) = (plain, captures) {
+                    let mut captys: Vec<Ty> = captures.into_iter().map(|cap| match cap {
+                        Capture::String => Ty::new(T::String),
+                        Capture::Position => Ty::new(T::Integer),
+                    }).collect();
+
+                    match tag {
+                        Tag::StringFind => {
+                            returns.head.truncate(2);
+                            returns.head.extend(captys);
+                            returns.tail = None;
+                        }
+                        Tag::StringMatch => {
+                            // without captures the entire match is returned
+                            if captys.is_empty() { captys.push(Ty::new(T::String)); }
+                            returns = TySeq { head: captys, tail: None };
+                        }
+                        Tag::StringGmatch => {
+                            if captys.is_empty() { captys.push(Ty::new(T::String)); }
+                            // the iterator returns nil at the end
+                            take(&mut captys[0], |t| t.union_nil(Nil::Noisy));
+                            // keep the arguments to the iterator (called by `for`-`in`)
+                            let iterargs = match returns.ensure_at(0).get_functions() {
+                                Some(&Functions::Simple(ref f)) => f.args.clone(),
+                                _ => TySeq::new(),
+                            };
+                            *returns.ensure_at_mut(0) = Ty::new(T::func(Function {
+                                typarams: Vec::new(),
+                                args: iterargs,
+                                argnames: Vec::new(),
+                                returns: Some(TySeq { head: captys, tail: None }),
+                                returnnames: Vec::new(),
+                            }));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // `string.pack` and `string.unpack`: check or infer the values from the format literal
+        match functy.tag() {
+            Some(tag @ Tag::StringPack) |
+            Some(tag @ Tag::StringUnpack) => {
+                let values = args.head.get(0).and_then(|fmt| {
+                    self.env.resolve_exact_type(fmt).and_then(|fmt| {
+                        fmt.as_string().and_then(|fmt| pack::parse_format(fmt))
+                    })
+                });
+
+                if let Some(values) = values {
+                    let mut valtys: Vec<Ty> = values.into_iter().map(|value| match value {
+                        PackValue::Integer => Ty::new(T::Integer),
+                        PackValue::Number => Ty::new(T::Number),
+                        PackValue::String => Ty::new(T::String),
+                    }).collect();
+
+                    if tag == Tag::StringPack {
+                        // the format should be followed by exactly those values
+                        let mut head = vec![Ty::new(T::String)];
+                        head.extend(valtys);
+                        let expected = TySeq { head: head, tail: None }.all_with_loc(func);
+                        let hint = if methodcall {
+                            TypeReportHint::MethodArgs
+                        } else {
+                            TypeReportHint::FuncArgs
+                        };
+                        if !self.env.assert_sub_reporting(args, &expected, func.span, hint,
+                                                          |types| {
+                            Box::new(m::CallToWrongType { func: func.base.display(types) })
+                        })? {
+                            return Ok(Exitable::dummy());
+                        }
+                    } else {
+                        // the unpacked values are followed by the next position
+                        valtys.push(Ty::new(T::Integer));
+                        returns = TySeq { head: valtys, tail: None };
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // `table.sort`: the comparator should accept two elements of the vector
+        if let Some(Tag::TableSort) = functy.tag() {
+            let elem = args.head.get(0).and_then(|tab| {
+                self.env.resolve_exact_type(tab).and_then(|tab| match tab.get_tables() {
+                    Some(&Tables::Array(ref value)) => Some(value.unlift().clone()),
+                    _ => None,
+                })
+            });
+
+            if let (Some(elem), Some(cmp)) = (elem, args.head.get(1)) {
+                let expected = Ty::new(T::func(Function {
+                    typarams: Vec::new(),
+                    args: TySeq { head: vec![elem.clone(), elem.clone()], tail: None },
+                    argnames: Vec::new(),
+                    returns: Some(TySeq { head: vec![Ty::new(T::Boolean)], tail: None }),
+                    returnnames: Vec::new(),
+                })).with_nil().with_loc(func); // the comparator is optional
+                if !self.env.assert_sub_reporting(cmp, &expected, cmp.span, TypeReportHint::None,
+                                                  |types| {
+                    Box::new(m::TableSortWithInvalidComparator { cmp: cmp.base.display(types),
+                                                                 elem: elem.display(types) })
+                })? {
+                    return Ok(Exitable::dummy());
+                }
+            }
+        }
+
+        // `table.move`: elements are moved from the source to the destination
+        if let (Some(Tag::TableMove), Some(src)) = (functy.tag(), args.head.get(0)) {
+            let srcty = self.env.resolve_exact_type(src);
+            if let Some(&Tables::Map(ref key, _)) = srcty.as_ref().and_then(|t| t.get_tables()) {
+                if !self.types().subtype_of(key, &Ty::new(T::Integer)) {
+                    self.env.error(src, m::TableMoveWithNonIntegerKeys { tab: self.display(src) })
+                            .done()?;
+                    return Ok(Exitable::dummy());
+                }
+            }
+
+            // the destination defaults to the source when missing or nil
+            let dest = match args.head.get(4) {
+                Some(dest) if dest.flags() != T_NONE => {
+                    let dest = dest.clone().map(|t| t.without_nil());
+                    let destty = self.env.resolve_exact_type(&dest);
+                    let elems = match (srcty.as_ref().and_then(|t| t.get_tables()),
+                                       destty.as_ref().and_then(|t| t.get_tables())) {
+                        (Some(&Tables::Array(ref src)), Some(&Tables::Array(ref dest))) |
+                        (Some(&Tables::Map(_, ref src)), Some(&Tables::Array(ref dest))) => {
+                            Some((src.unlift().clone(), dest.unlift().clone()))
+                        }
+                        _ => None,
+                    };
+
+                    let compatible = if let Some((srcelem, destelem)) = elems {
+                        // elements are copied, so they only have to fit in the destination
+                        self.env.assert_sub_reporting(&srcelem, &destelem, dest.span,
+                                                      TypeReportHint::None, |types| {
+                            Box::new(m::TableMoveWithIncompatibleElements {
+                                src: srcelem.display(types), dest: destelem.display(types),
+                            })
+                        })?
+                    } else {
+                        self.env.assert_sub_reporting(src, &dest, dest.span,
+                                                      TypeReportHint::None, |types| {
+                            Box::new(m::TableMoveWithIncompatibleTables {
+                                src: src.base.display(types), dest: dest.base.display(types),
+                            })
+                        })?
+                    };
+                    if !compatible {
+                        return Ok(Exitable::dummy());
+                    }
+                    dest.base
+                }
+                _ => src.base.clone(),
+            };
+            *returns.ensure_at_mut(0) = dest;
+        }
+
+        // `io.read` and `io.lines`: refine the values read from the format literals
+        match functy.tag() {
+            Some(tag @ Tag::IoRead) |
+            Some(tag @ Tag::IoLines) => {
+                // formats are variadic arguments following fixed ones (`self`, file name etc.)
+                let nfixed = match functy.get_functions() {
+                    Some(&Functions::Simple(ref f)) => f.args.head.len(),
+                    _ => 0,
+                };
+                let mut values: Vec<Ty> =
+                    args.head.iter().skip(nfixed).map(|fmt| self.read_format_type(fmt)).collect();
+                if values.is_empty() {
+                    // same to `*l`
+                    values.push(Ty::new(T::String).or_nil(Nil::Noisy));
+                }
+
+                if tag == Tag::IoRead {
+                    returns = TySeq { head: values, tail: None };
+                } else {
+                    // keep the arguments to the iterator (called by `for`-`in`)
+                    let iterargs = match returns.ensure_at(0).get_functions() {
+                        Some(&Functions::Simple(ref f)) => f.args.clone(),
+                        _ => TySeq::new(),
+                    };
+                    *returns.ensure_at_mut(0) = Ty::new(T::func(Function {
+                        typarams: Vec::new(),
+                        args: iterargs,
+                        argnames: Vec::new(),
+                        returns: Some(TySeq { head: values, tail: None }),
+                        returnnames: Vec::new(),
+                    }));
+                }
+            }
+            _ => {}
+        }
+
         Ok(Exitable::new(returns))
     }
 
+    // returns a type of the value read from the `io.read` format, or `WHATEVER` if unknown.
+    // like Lua 5.1, only the first letter after the optional `*` is significant.
+    fn read_format_type(&self, fmt: &Ty) -> Ty {
+        let fmt = match self.env.resolve_exact_type(fmt) {
+            Some(fmt) => fmt,
+            None => return Ty::new(T::Dynamic(Dyn::User)),
+        };
+        if let Some(fmt) = fmt.as_string() {
+            let fmt: &[u8] = fmt;
+            let fmt = if fmt.starts_with(b"*") { &fmt[1..] } else { fmt };
+            match fmt.first() {
+                Some(&b'n') => Ty::new(T::Number).or_nil(Nil::Noisy),
+                Some(&b'a') => Ty::new(T::String),
+                Some(&b'l') | Some(&b'L') => Ty::new(T::String).or_nil(Nil::Noisy),
+                _ => Ty::new(T::Dynamic(Dyn::User)),
+            }
+        } else if fmt.flags() == T_INTEGER {
+            // returns `nil` at the end of file
+            Ty::new(T::String).or_nil(Nil::Noisy)
+        } else {
+            Ty::new(T::Dynamic(Dyn::User))
+        }
+    }
+
     fn cannot_index(&self, span: Span, tab: &Slot, key: &Slot) -> Result<()> {
         // use a special message when the table is a record and key is a string literal
         match (tab.unlift().get_tables(), key.unlift().as_string()) {
-            (Some(&Tables::Fields(_)), Some(s)) => {
+            (Some(&Tables::Fields(..)), Some(s)) => {
                 self.env.error(span,
                                m::CannotIndexWithStr { tab: self.display(tab), key: s })
                         .done()
@@ -642,11 +1059,164 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         }
     }
 
+    // returns a field with given name in the metatable of given type, if any.
+    // only records with known record metatables (from `setmetatable`) are considered.
+    fn get_metamethod(&mut self, ty: &Ty, name: &[u8]) -> Option<Slot> {
+        let meta = match ty.get_tables() {
+            Some(&Tables::Fields(_, Some(ref meta))) => meta.clone(),
+            _ => return None,
+        };
+        let meta = match self.env.resolve_exact_type(&meta) {
+            Some(meta) => meta,
+            None => return None,
+        };
+        match meta.get_tables() {
+            Some(&Tables::Fields(ref rvar, _)) => {
+                let fields = self.types().get_rvar_fields(rvar.clone());
+                fields.into_iter().find(|&(ref k, _)| *k == name).map(|(_, v)| v)
+            },
+            _ => None,
+        }
+    }
+
+    // adds types given to `coroutine.yield` to the current function frame.
+    // the union failure is ignored, as the yields only refine the type of `coroutine.wrap`.
+    fn add_yielded(&mut self, seq: TySeq, span: Span) {
+        let yielded = match self.env.get_frame_mut().yielded.take() {
+            Some(yielded) => {
+                let yielded = yielded.all_with_loc(span);
+                match seq.all_with_loc(span).union(&yielded, false, self.types()) {
+                    Ok(yielded) => yielded.unspan(),
+                    Err(_) => yielded.unspan(),
+                }
+            }
+            None => seq,
+        };
+        self.env.get_frame_mut().yielded = Some(yielded);
+    }
+
+    // the number of function scopes entered by the checker.
+    fn func_depth(&self) -> usize {
+        self.scopes.iter().filter(|&&func| func).count()
+    }
+
+    // marks the current function as calling a function whose yields are not known,
+    // unless the called function (`func` of the type `functy`) is known not to yield
+    // other than via `add_yielded`. this makes `coroutine.wrap` fall back to its declared type.
+    fn check_call_yields(&mut self, func: Option<&Ex>, functy: &Ty) {
+        let tag = self.env.resolve_exact_type(functy).and_then(|ty| ty.tag());
+        let known = match tag {
+            // these run given functions or chunks, which may yield
+            Some(Tag::Pcall) | Some(Tag::TableSort) |
+            Some(Tag::Require) | Some(Tag::GlobalEval) => false,
+            Some(_) => true,
+            None => match func {
+                Some(&Ex::Var(Spanned { base: NameRef::Local(ref id), .. })) =>
+                    self.yielding_locals.get(id) == Some(&self.func_depth()),
+                _ => false,
+            },
+        };
+        if !known {
+            self.env.get_frame_mut().yields_unknown = true;
+        }
+    }
+
+    fn set_last_call_tag(&mut self, functy: &Ty, span: Span) {
+        let tag = self.env.resolve_exact_type(functy).and_then(|ty| ty.tag());
+        self.last_call_tag = tag.map(|tag| (span, tag));
+    }
+
+    // returns true if the type is resolved to a table (and nothing else).
+    // used to avoid duplicate errors from the indexing when the argument is not a table.
+    fn is_exact_table(&self, ty: &Ty) -> bool {
+        match self.env.resolve_exact_type(ty) {
+            Some(ty) => ty.get_tables().is_some() && T_TABLE.contains(ty.flags()),
+            None => false,
+        }
+    }
+
+    // returns true if the key is a string literal naming a field in the string metatable.
+    fn is_string_method(&mut self, key: &Ty) -> bool {
+        let name = match key.as_string() {
+            Some(name) => name.to_owned(),
+            None => return false,
+        };
+        let meta = match self.env.get_string_meta() {
+            Some(meta) => meta,
+            None => return false,
+        };
+        let meta = match self.env.resolve_exact_type(&meta.unlift()) {
+            Some(meta) => meta,
+            None => return false,
+        };
+        match meta.get_tables() {
+            Some(&Tables::Fields(ref rvar, _)) => {
+                let fields = self.types().get_rvar_fields(rvar.clone());
+                fields.into_iter().any(|(k, _)| k == &name[..])
+            },
+            _ => false,
+        }
+    }
+
+    // looks up a missing field of the record via the `__index` metamethod.
+    // the table `__index` is followed until the field is found (or no more metatables are left),
+    // and the function `__index` results in its first return type.
+    fn check_index_via_metatable(&mut self, ety: &Ty, kty0: &Spanned<Slot>, litkey: &Key,
+                                 expspan: Span) -> Result<Index> {
+        let mut ety = ety.clone();
+        let mut seen = HashSet::new();
+        loop {
+            let index = match self.get_metamethod(&ety, b"__index") {
+                Some(index) => index,
+                None => return Ok(Index::Missing),
+            };
+            let index = match self.env.resolve_exact_type(&index.unlift()) {
+                Some(index) => index,
+                None => return Ok(Index::Missing),
+            };
+
+            if let Some(dyn) = index.get_dynamic() {
+                return Ok(Index::Found(Slot::just(Ty::new(T::Dynamic(dyn)))));
+            }
+
+            if let Some(&Functions::Simple(ref f)) = index.get_functions() {
+                // the arguments (the table and the key) are not checked
+                let value = f.returns.clone().map_or_else(Ty::dummy, |seq| seq.into_first());
+                return Ok(Index::Found(Slot::just(value)));
+            }
+
+            match index.get_tables() {
+                Some(&Tables::Fields(ref rvar, _)) => {
+                    let fields = self.types().get_rvar_fields(rvar.clone());
+                    if let Some((_, vslot)) = fields.into_iter().find(|&(ref k, _)| *k == *litkey) {
+                        return Ok(Index::Found(vslot));
+                    }
+
+                    // continue to the metatable of `__index` (avoiding cycles)
+                    if !seen.insert(rvar.clone()) {
+                        return Ok(Index::Missing);
+                    }
+                }
+
+                // other tables cannot have metatables, so they are indexed as usual
+                Some(_) => {
+                    let index = Slot::just(index.clone()).with_loc(expspan);
+                    return self.check_index_common(&index, kty0, expspan, false, false);
+                }
+
+                None => return Ok(Index::Missing),
+            }
+
+            ety = index;
+        }
+    }
+
     // common routine for check_{l,r}val_index
     // when lval is true, the field is created as needed (otherwise it's an error)
     // when lval is false, the missing field is returned as Index::Missing
+    // when raw is true, the metatable is not consulted for the missing field (as in `rawget`)
     fn check_index_common(&mut self, ety0: &Spanned<Slot>, kty0: &Spanned<Slot>, expspan: Span,
-                          lval: bool) -> Result<Index> {
+                          lval: bool, raw: bool) -> Result<Index> {
         debug!("indexing {:?} with {:?} as an {}-value", ety0, kty0, if lval { "l" } else { "r" });
 
         let mut ety0: Cow<Spanned<Slot>> = Cow::Borrowed(ety0);
@@ -656,7 +1226,28 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
         let (_, flags) = self.env.get_type_bounds(&ety);
         if !flags.is_tabular() {
-            self.env.error(&*ety0, m::IndexToNonTable { tab: self.display(&*ety0) }).done()?;
+            // suggest the most likely fix when a string method is used with non-strings
+            let string_method = self.is_string_method(&kty);
+            let report = self.env.error(&*ety0, m::IndexToNonTable { tab: self.display(&*ety0) });
+            let report = if !string_method {
+                report
+            } else if !flags.is_empty() && !flags.intersects(!T_NUMBER) {
+                report.note(&*ety0, m::IndexToNumberHint {})
+            } else if flags.intersects(T_STRING) {
+                report.note(&*ety0, m::IndexToMaybeStringHint {})
+            } else {
+                report
+            };
+            report.done()?;
+            return Ok(Index::dummy());
+        }
+
+        // `--# pragma strict-nil` requires the implicitly nilable table to be checked first
+        // (string literals are exempted as they are never `nil`)
+        if self.env.strict_nil() && !flags.is_dynamic() && ety.nil() == Nil::Silent &&
+           ety.as_string().is_none() {
+            self.env.error(&*ety0, m::IndexToNilInStrictNil { tab: self.display(&*ety0) })
+                    .done()?;
             return Ok(Index::dummy());
         }
 
@@ -676,8 +1267,9 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         let mut ety = if let Some(ety) = self.env.resolve_exact_type(&ety) {
             ety
         } else {
-            self.env.error(&*ety0,
-                           m::IndexToInexactType { tab: self.display(&*ety0) }).done()?;
+            self.env.error(&*ety0, m::IndexToInexactType { tab: self.display(&*ety0) })
+                    .note(&*ety0, m::IndexToInexactTypeHint {})
+                    .done()?;
             return Ok(Index::dummy());
         };
 
@@ -801,7 +1393,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         let had_litkey = litkey.is_some();
         if let Some(litkey) = litkey {
             match ety.get_tables() {
-                Some(&Tables::Fields(ref rvar)) => {
+                Some(&Tables::Fields(ref rvar, _)) => {
                     // find a field in the rvar
                     let mut vslot = None;
                     let _ = self.env.context().list_rvar_fields(rvar.clone(), &mut |k, v| {
@@ -827,8 +1419,14 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                             (vslot, true)
                         },
 
-                        // the field does not exist and is used as an r-value, return nothing
-                        (None, false) => return Ok(Index::Missing),
+                        // the field does not exist and is used as an r-value,
+                        // try the metatable if any (otherwise return nothing)
+                        (None, false) => {
+                            if raw {
+                                return Ok(Index::Missing);
+                            }
+                            return self.check_index_via_metatable(&ety, kty0, &litkey, expspan);
+                        },
                     };
 
                     vslot.adapt(ety0.flex(), self.types());
@@ -902,7 +1500,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
     fn check_rval_index(&mut self, ety: &Spanned<Slot>, kty: &Spanned<Slot>,
                         expspan: Span) -> Result<Slot> {
-        match self.check_index_common(ety, kty, expspan, false)? {
+        match self.check_index_common(ety, kty, expspan, false, false)? {
             Index::Missing => {
                 self.cannot_index(expspan, ety, kty)?;
                 Ok(Slot::dummy())
@@ -915,7 +1513,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     // this should be followed by assign_to_lval_index
     fn check_lval_index(&mut self, ety: &Spanned<Slot>, kty: &Spanned<Slot>,
                         expspan: Span) -> Result<Lvalue> {
-        let (found, slot) = match self.check_index_common(ety, kty, expspan, true)? {
+        let (found, slot) = match self.check_index_common(ety, kty, expspan, true, false)? {
             Index::Missing => unreachable!(),
             Index::Created(slot) => (false, slot),
             Index::Found(slot) => (true, slot),
@@ -955,9 +1553,9 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
         #[derive(Clone, Debug)]
         enum TableExtract {
-            // a record with flexibility & nilability (both for reconstruction),
+            // a record with flexibility, nilability & metatable (all for reconstruction),
             // all fields except `next_key` and the slot for `next_key` if any
-            Rec(F, Nil, Vec<(Key, Slot)>, Option<Slot>),
+            Rec(F, Nil, Option<Ty>, Vec<(Key, Slot)>, Option<Slot>),
 
             // a class prototype, only possible at the root
             Proto(ClassId),
@@ -1001,7 +1599,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 return Ok(None);
             }
 
-            if let Some(&Tables::Fields(ref rvar)) = ty.get_tables() {
+            if let Some(&Tables::Fields(ref rvar, ref meta)) = ty.get_tables() {
                 if static_ {
                     env.error(span, m::AssumeFieldStaticToNonClass { slot: env.display(prev) })
                        .done()?;
@@ -1016,7 +1614,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     }
                 });
                 let popped = pos.map(|i| fields.remove(i).1);
-                Ok(Some(TableExtract::Rec(prev.flex(), ty.nil(), fields, popped)))
+                Ok(Some(TableExtract::Rec(prev.flex(), ty.nil(), meta.clone(), fields, popped)))
             } else {
                 env.error(span, m::AssumeFieldToNonRecord { slot: env.display(prev) }).done()?;
                 Ok(None)
@@ -1067,8 +1665,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
                 return Ok(root);
             }
-            Some(TableExtract::Rec(flex, nil, fields, next)) => {
-                tables.push((flex, nil, fields));
+            Some(TableExtract::Rec(flex, nil, meta, fields, next)) => {
+                tables.push((flex, nil, meta, fields));
                 table = next;
             }
             None => { // the error occurred and already reported
@@ -1082,8 +1680,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 span |= name.span;
                 match extract_table(&prevtable, name, span, self.env, false)? {
                     Some(TableExtract::Proto(_)) => unreachable!(),
-                    Some(TableExtract::Rec(flex, nil, fields, next)) => {
-                        tables.push((flex, nil, fields));
+                    Some(TableExtract::Rec(flex, nil, meta, fields, next)) => {
+                        tables.push((flex, nil, meta, fields));
                         table = next;
                     }
                     None => { // the error occurred and already reported
@@ -1102,14 +1700,14 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
         // put the last table to the second-to-last table and so on
         let mut slot = slot;
-        for (name, (flex, nil, mut fields)) in names.iter().rev().zip(tables.into_iter()) {
+        for (name, (flex, nil, meta, mut fields)) in names.iter().rev().zip(tables.into_iter()) {
             fields.push((Key::Str(name.base.clone().into()), slot));
             let rvar = self.types().gen_rvar();
             self.types().assert_rvar_includes(rvar.clone(), &fields).expect(
                 "cannot insert updated disjoint fields into a fresh row variable"
             );
-            slot = Slot::new(flex,
-                             Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar)))).or_nil(nil));
+            let tab = Tables::Fields(rvar, meta);
+            slot = Slot::new(flex, Ty::new(T::Tables(Cow::Owned(tab))).or_nil(nil));
         }
 
         // the final table should be assumed back to the current scope
@@ -1129,7 +1727,14 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         let mut exprexit = ExprExit::None;
         let varrefspecs = vars.iter().map(|varspec| {
             let varref = match varspec.base.base {
-                Var::Name(ref nameref) => VarRef::Name(nameref),
+                Var::Name(ref nameref) => {
+                    if let NameRef::Local(ref id) = nameref.base {
+                        if self.for_vars.contains(id) {
+                            self.env.warn(nameref, m::AssignToForVar {}).done()?;
+                        }
+                    }
+                    VarRef::Name(nameref)
+                }
 
                 Var::Index(ref e, ref key) => {
                     let Exitable(exit1, ty) = self.visit_exp(e, None)?;
@@ -1188,7 +1793,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         };
 
         // unlike St::Local, do not tolerate the uninitialized variables
-        for (var, (varref, specinfo)) in vars.iter().zip(varrefspecs.into_iter()) {
+        for (i, (var, (varref, specinfo))) in vars.iter().zip(varrefspecs.into_iter())
+                                                   .enumerate() {
             // ideally should be done via zip, but then concrete types will collide
             // just ignore the assignment when info is None instead
             let info = infos.as_mut().and_then(|it| it.next());
@@ -1203,7 +1809,10 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     } else {
                         // variable assignment
                         if let Some(info) = info {
-                            Some(self.env.assign_to_var(nameref, info)?)
+                            let tablelit = exps.and_then(|exps| exps.get(i)).map_or(false, |exp| {
+                                if let Ex::Table(..) = *exp.base { true } else { false }
+                            });
+                            Some(self.assign_to_var(nameref, info, tablelit)?)
                         } else {
                             None
                         }
@@ -1237,6 +1846,67 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         exprexit.to_stmt(stmtspan, self.env)
     }
 
+    // same to `Env::assign_to_var`, but a table literal (`tablelit`) assigned to
+    // a `WHATEVER` variable is inferred as a record, so that subsequent field accesses
+    // in the same scope are checked until the variable is assigned again.
+    // the declared type of the variable itself is kept intact.
+    fn assign_to_var(&mut self, nameref: &Spanned<NameRef>, info: Spanned<Slot>,
+                     tablelit: bool) -> Result<Slot> {
+        self.dynamic_records.remove(&nameref.base);
+        if let NameRef::Local(ref id) = nameref.base {
+            self.yielding_locals.remove(id);
+        }
+
+        let record = self.widen_record(&info.base);
+        let slot = self.env.assign_to_var(nameref, info)?;
+
+        let varslot = self.env.get_var(nameref).and_then(|def| def.slot.slot().cloned());
+        if let (true, Some(varslot)) = (tablelit, varslot) {
+            let dynamic = varslot.unlift().get_dynamic() == Some(Dyn::User);
+            if dynamic && varslot.flex() != F::Dynamic(Dyn::Oops) {
+                self.dynamic_records.insert(nameref.base.clone(), (record, self.scopes.len()));
+            }
+        }
+        Ok(slot)
+    }
+
+    // coerces the table literal and its fields, so that the inferred record can be updated
+    // as if the variable were a `WHATEVER` (e.g. `a = {x = 1}; a.x = 2`).
+    fn widen_record(&mut self, slot: &Slot) -> Slot {
+        let slot = slot.coerce();
+        let fields = match slot.unlift().get_tables() {
+            Some(&Tables::Fields(ref rvar, ref meta)) => Some((rvar.clone(), meta.clone())),
+            _ => None,
+        };
+        let (rvar, meta) = match fields {
+            Some(fields) => fields,
+            None => return slot,
+        };
+
+        let fields: Vec<_> = self.types().get_rvar_fields(rvar).into_iter().map(|(k, v)| {
+            (k, self.widen_record(&v))
+        }).collect();
+        let rvar = self.types().gen_rvar();
+        if !fields.is_empty() {
+            self.types().assert_rvar_includes(rvar.clone(), &fields).expect(
+                "cannot insert coerced fields into a fresh row variable"
+            );
+        }
+        let tab = Tables::Fields(rvar, meta);
+        let ty = Ty::new(T::Tables(Cow::Owned(tab))).or_nil(slot.unlift().nil());
+        Slot::new(slot.flex(), ty)
+    }
+
+    // returns a record inferred by `assign_to_var` if it is visible from the current scope
+    fn dynamic_record(&self, nameref: &NameRef) -> Option<Slot> {
+        let &(ref record, depth) = self.dynamic_records.get(nameref)?;
+        if self.scopes[depth..].iter().any(|&func| func) {
+            None // the function can be called after the variable has been reassigned
+        } else {
+            Some(record.clone())
+        }
+    }
+
     #[cfg(feature = "no_implicit_func_sig")]
     fn error_on_implicit_sig(&mut self, sig: &Sig) -> Result<()> {
         if sig.args.head.iter().any(|spec| spec.kind.is_none()) {
@@ -1307,6 +1977,22 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         Ok(())
     }
 
+    // visits a block which is one of the alternatives (e.g. `if` cases).
+    // `--: once` slots assigned in the block are reverted and collected to `once_assigned`,
+    // so that other alternatives can also assign to them for the first time.
+    fn visit_branch(&mut self, block: &'inp Spanned<Block>,
+                    once_assigned: &mut Vec<Slot>) -> Result<Exit> {
+        let outer = self.env.take_once_assigned();
+        let exit = self.visit_block(block);
+        let inner = self.env.take_once_assigned();
+        self.env.add_once_assigned(outer);
+        for slot in &inner {
+            slot.set_once_assigned(false);
+        }
+        once_assigned.extend(inner);
+        exit
+    }
+
     fn visit_block_(&mut self, block: &'inp Spanned<Block>) -> Result<Exit> {
         let mut exit = Exit::None;
         let mut ignored_stmts: Option<Span> = None;
@@ -1394,6 +2080,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let mut condexit = Exit::None; // C1 & ... & Ck
 
                 let mut ignored_blocks = None; // or Some((first truthy cond span, blocks span))
+                let mut once_assigned = Vec::new(); // `--: once` slots assigned in any block
                 for &Spanned { base: (ref cond, ref block), span } in conds {
                     // do not update the exit, as blocks after a truthy condition will be ignored
                     if let Some((_, ref mut blocks_span)) = ignored_blocks {
@@ -1407,7 +2094,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     match boolean {
                         Bool::Truthy => {
                             ignored_blocks = Some((cond.span, Span::dummy()));
-                            exit |= condexit & self.visit_block(block)?;
+                            exit |= condexit & self.visit_branch(block, &mut once_assigned)?;
                         }
                         Bool::Falsy => {
                             #[cfg(feature = "warn_on_useless_conds")] {
@@ -1418,7 +2105,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                             exit |= condexit;
                         }
                         Bool::Unknown => {
-                            exit |= condexit & self.visit_block(block)?;
+                            exit |= condexit & self.visit_branch(block, &mut once_assigned)?;
                         }
                     }
                 }
@@ -1427,7 +2114,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     if let Some((_, ref mut blocks_span)) = ignored_blocks {
                         *blocks_span |= block.span;
                     } else {
-                        exit |= condexit & self.visit_block(block)?;
+                        exit |= condexit & self.visit_branch(block, &mut once_assigned)?;
                     }
                 } else {
                     if ignored_blocks.is_none() {
@@ -1435,6 +2122,12 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     }
                 }
 
+                // `--: once` slots assigned in any block cannot be assigned after the statement
+                for slot in &once_assigned {
+                    slot.set_once_assigned(true);
+                }
+                self.env.add_once_assigned(once_assigned);
+
                 #[cfg(feature = "warn_on_useless_conds")] {
                     if let Some((truthy_span, blocks_span)) = ignored_blocks {
                         if blocks_span.is_dummy() {
@@ -1506,6 +2199,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     }
                 }
 
+                self.for_vars.insert(localname.base.clone());
+
                 let mut scope = self.scoped(Scope::new());
                 let indty = Slot::var(Ty::new(indty));
                 let nameref = NameRef::Local(localname.base.clone()).with_loc(localname);
@@ -1519,7 +2214,22 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             }
 
             St::ForIn(ref names, ref exps, _blockscope, ref block) => {
+                self.last_call_tag = None;
                 let (mut exit, infos) = self.visit_explist_from_stmt(exps, None)?;
+
+                // the iterator is called from the loop, so it may yield unless it comes from
+                // a library function known to return an iterator without yields
+                let builtin_iter = match (&exps[..], self.last_call_tag) {
+                    (&[ref exp], Some((span, tag))) if exp.span == span => match tag {
+                        Tag::GenericPairs | Tag::StringGmatch | Tag::IoLines => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if !builtin_iter {
+                    self.env.get_frame_mut().yields_unknown = true;
+                }
+
                 let expspan = infos.all_span();
                 let mut infos = infos.into_iter_with_nil();
                 let func = infos.next().unwrap(); // iterator function
@@ -1559,14 +2269,12 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                         self.check_callable(&func.clone().with_loc(expspan), &args, false)?;
                     exit &= exit_.to_stmt(expspan, self.env)?;
 
-                    if let Err(r) = last.assert_sub(&indvar, self.types()) {
-                        // it is very hard to describe, but it is conceptually
-                        // an extension of check_callable
-                        self.env.error(expspan,
-                                       m::BadFuncIterator { iter: self.display(&func) })
-                                .report_types(r, TypeReportHint::None)
-                                .done()?;
-                    }
+                    // it is very hard to describe, but it is conceptually
+                    // an extension of check_callable
+                    self.env.assert_sub_reporting(&last, &indvar, expspan, TypeReportHint::None,
+                                                  |types| {
+                        Box::new(m::BadFuncIterator { iter: func.base.display(types) })
+                    })?;
 
                     // note that we ignore indvar here. it is only kept internally and
                     // not visible outside; returns is what we should assign to variables!
@@ -1607,7 +2315,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     // this is very rare but valid case where the local variable is
                     // overwritten by a local function decl (so the NameRef is local
                     // but there is no new sibling scope). it's equivalent to assignment.
-                    Some(self.env.assign_to_var(name, info)?)
+                    Some(self.assign_to_var(name, info, false)?)
                 } else {
                     // otherwise it is a new variable.
                     self.env.add_var(name, None, Some(info))?
@@ -1616,6 +2324,11 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     self.register_module_if_needed(&varslot);
                 }
 
+                if let NameRef::Local(ref id) = name.base {
+                    let depth = self.func_depth();
+                    self.yielding_locals.insert(id.clone(), depth);
+                }
+
                 let (tag, no_check) = self.visit_sig_attrs(&sig.attrs)?;
                 let functy = self.visit_func_body(tag, no_check, None, sig, block,
                                                   stmt.span, None)?;
@@ -1696,7 +2409,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 Ok(Exit::None)
             }
 
-            St::Local(ref names, ref exps, _nextscope) => {
+            St::Local(ref names, ref attrs, ref exps, _nextscope) => {
                 // collect specified types first (required for hints)
                 let nameinfos = names.iter().map(|namespec| {
                     let info = self.visit_type_spec(namespec)?;
@@ -1714,12 +2427,41 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     tail: None,
                     span: names.span,
                 };
+
+                let nlocalfuncs = self.local_funcs.len();
+                for (&(localname, _), exp) in nameinfos.iter().zip(exps.iter()) {
+                    if let Ex::Func(..) = *exp.base {
+                        // the name as seen right before this statement
+                        let map = self.env.scope_map();
+                        let name = localname.base.name(map).clone();
+                        let outer = map.parent_scope(localname.base.scope(map)).and_then(|scope| {
+                            map.find_name_in_scope(scope, &name).map(|(_, id)| id)
+                        });
+                        self.local_funcs.push((name, outer, localname.span, exp.span, false));
+                        let depth = self.func_depth();
+                        self.yielding_locals.insert(localname.base.clone(), depth);
+                    }
+                }
                 let (exit, infos) = self.visit_explist_from_stmt(exps, Some(hint))?;
+                self.local_funcs.truncate(nlocalfuncs);
 
-                for ((localname, specinfo), info) in nameinfos.into_iter()
-                                                              .zip(infos.into_iter_with_none()) {
+                let namesinfos = nameinfos.into_iter().zip(infos.into_iter_with_none());
+                for (((localname, specinfo), info), attr) in namesinfos.zip(attrs) {
                     let nameref = NameRef::Local(localname.base.clone()).with_loc(localname);
                     if let Some(varslot) = self.env.add_var(&nameref, specinfo, info)? {
+                        if let Some(Spanned { base: LocalAttr::Close, span }) = *attr {
+                            if !self.types().has_close_metamethod(&varslot.unlift()) {
+                                self.env.error(span, m::CloseWithoutMetamethod {
+                                                         ty: self.display(&varslot),
+                                                     })
+                                        .done()?;
+                            }
+                        }
+
+                        // only the top-level module declarations can be returned from the chunk
+                        if varslot.flex() == F::Module && self.pending_modules.len() == 1 {
+                            self.env.add_module_decl(varslot.clone().with_loc(localname));
+                        }
                         self.register_module_if_needed(&varslot);
                     }
                 }
@@ -1739,9 +2481,27 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
             St::Break => Ok(Exit::Break),
 
+            // pragmas are processed by lint passes after the type checking
+            St::KailuaPragma(..) => Ok(Exit::None),
+
+            St::KailuaPragmaOption(ref name) => {
+                match &name.base[..] {
+                    b"strict-nil" => self.env.set_strict_nil(true),
+                    _ => {
+                        self.env.warn(name, m::UnknownPragmaOption { name: &name.base }).done()?;
+                    }
+                }
+                Ok(Exit::None)
+            }
+
             St::KailuaOpen(ref name) => {
-                let opts = self.env.opts().clone();
-                self.env.context().open_library(name.as_ref().map(|n| &n[..]), opts)?;
+                let is_library = str::from_utf8(&name.base).ok().and_then(get_defs).is_some();
+                if is_library {
+                    let opts = self.env.opts().clone();
+                    self.env.context().open_library(name.as_ref().map(|n| &n[..]), opts)?;
+                } else {
+                    self.open_module(name.as_ref().map(|n| &n[..]), stmt.span)?;
+                }
                 Ok(Exit::None)
             }
 
@@ -1926,11 +2686,35 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
             Returns::Explicit(returns) => {
                 let returns = returns.all_with_loc(stmtspan);
-                if let Err(r) = seq.assert_sub(&returns, self.types()) {
-                    self.env.error(stmtspan, m::CannotReturn { returns: self.display(&returns),
-                                                               ty: self.display(&seq) })
-                            .report_types(r, TypeReportHint::Returns)
-                            .done()?;
+                let ok = self.env.assert_sub_reporting(&seq, &returns, stmtspan,
+                                                       TypeReportHint::Returns, |types| {
+                    Box::new(m::CannotReturn { returns: returns.display(types),
+                                               ty: seq.display(types) })
+                })?;
+                if ok && self.options.strict_return_types {
+                    // collect the actual return types, checked at the end of the function.
+                    // literal types are widened, otherwise `return 42` would never be equal.
+                    let seq = seq.unspan();
+                    let seq = TySeq { head: seq.head.into_iter().map(|t| t.coerce()).collect(),
+                                      tail: seq.tail.map(|t| t.coerce()) };
+                    let returned = match self.env.get_frame_mut().returned.take() {
+                        Some(returned) => {
+                            let returned = returned.all_with_loc(stmtspan);
+                            match seq.all_with_loc(stmtspan).union(&returned, false,
+                                                                   self.types()) {
+                                Ok(returned) => returned.unspan(),
+                                Err(r) => {
+                                    self.env.error(stmtspan, m::CannotCollectReturnTypes {})
+                                            .report_types(r, TypeReportHint::Returns)
+                                            .done()?;
+                                    // the final check is meaningless now, make it always pass
+                                    returns.unspan()
+                                }
+                            }
+                        }
+                        None => seq,
+                    };
+                    self.env.get_frame_mut().returned = Some(returned);
                 }
             }
         }
@@ -2084,11 +2868,16 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         //
         // TODO the exception should be made to the recursive usage;
         // we probably need to put a type variable that is later equated to the actual returns
+        let mut returnnames = Vec::new();
         let returns = if let Some(ref returns) = sig.returns {
             match *returns {
                 ast::Returns::Never(_) => Returns::Never,
-                ast::Returns::Seq(ref seq) =>
-                    Returns::Explicit(TySeq::from_kind_seq(seq, |kind| kind, &mut self.env)?),
+                ast::Returns::Seq(ref seq) => {
+                    returnnames = Function::names_from_kind_seq(&seq.head);
+                    let returns = TySeq::from_kind_seq(seq, |namekind| &namekind.1,
+                                                       &mut self.env)?;
+                    Returns::Explicit(returns)
+                },
             }
         } else if let Some(hint) = hintreturns {
             // use a hint if possible ([NO_CHECK] can rely on this hint as well)
@@ -2106,7 +2895,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         } else {
             Returns::None
         };
-        let frame = Frame { vararg: vainfo, returns: returns };
+        let frame = Frame { vararg: vainfo, returns: returns, returned: None, yielded: None,
+                            yields_unknown: false };
 
         let mut argshead = Vec::new();
         let mut argnames = Vec::new();
@@ -2194,14 +2984,133 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             }
         }
 
+        // explicit return types should not be more general than actual ones if requested
+        let declared = sig.returns.as_ref().map(|returns| returns.span());
+        if let (Some(declspan), true) = (declared, scope.options.strict_return_types) {
+            let frame = scope.env.get_frame().clone();
+            if let (Returns::Explicit(mut returns), Some(mut returned)) =
+                    (frame.returns, frame.returned) {
+                // each actual return type is already a subtype of the declared one,
+                // so the converse is checked without affecting any type variable
+                let len = cmp::max(returns.head.len(), returned.head.len()) + 1;
+                let mut equal = true;
+                for i in 0..len {
+                    let (lhs, rhs) = (returns.ensure_at(i).clone(), returned.ensure_at(i).clone());
+                    if !scope.env.types().subtype_of(&lhs, &rhs) {
+                        equal = false;
+                        break;
+                    }
+                }
+                if !equal {
+                    let returns = returns.all_with_loc(declspan);
+                    let returned = returned.all_with_loc(declspan);
+                    scope.env.error(declspan,
+                                    m::ReturnTypeTooGeneral { returns: scope.display(&returns),
+                                                              ty: scope.display(&returned) })
+                             .done()?;
+                }
+            }
+        }
+
+        let yielded = scope.env.get_frame_mut().yielded.take();
+        let yields_unknown = scope.env.get_frame().yields_unknown;
         let returns = match scope.env.get_frame().returns {
             Returns::Implicit(ref ret) | Returns::Explicit(ref ret) => Some(ret.clone()),
             Returns::Never | Returns::None => None,
         };
-        let func = Function { args: args, argnames: argnames, returns: returns };
+        drop(scope);
+
+        // a nested function yields from the coroutine running the enclosing function
+        // when called, so its yields also belong to the enclosing function body
+        if yields_unknown {
+            self.env.get_frame_mut().yields_unknown = true;
+        }
+        if let Some(yielded) = yielded {
+            self.add_yielded(yielded.clone(), declspan);
+            if !yields_unknown {
+                self.yields.insert(declspan, yielded);
+            }
+        }
+
+        let func = Function { typarams: Vec::new(), args: args, argnames: argnames,
+                              returns: returns, returnnames: returnnames };
         Ok(Slot::just(Ty::new(T::func(func)).with_tag(tag)))
     }
 
+    // warns when a method (whose first argument is `self`) is called with `a.b(...)`.
+    // this is allowed when `self` is explicitly passed, as in `Parent.method(self, ...)`.
+    fn check_method_call_syntax(&mut self, func: &Spanned<Exp>, functy: &Spanned<Ty>,
+                                args: &Spanned<Args>) -> Result<()> {
+        let (recv, method) = if let Ex::IndexName(ref recv, ref method) = *func.base {
+            (recv, method)
+        } else {
+            return Ok(());
+        };
+
+        let is_method = self.env.resolve_exact_type(functy).map_or(false, |ty| {
+            match ty.get_functions() {
+                Some(&Functions::Simple(ref f)) => f.is_method(),
+                _ => false,
+            }
+        });
+        if !is_method {
+            return Ok(());
+        }
+
+        if let Args::List(ref args) = args.base {
+            if let Some(arg) = args.first() {
+                if let Ex::Var(Spanned { base: NameRef::Local(ref id), .. }) = *arg.base {
+                    if &id.name(self.env.scope_map())[..] == b"self" {
+                        return Ok(());
+                    }
+                }
+
+                // `p.a(p)` explicitly passes the receiver, which is same to `p:a()`
+                if let (&Ex::Var(ref recvname), &Ex::Var(ref argname)) = (&*recv.base, &*arg.base) {
+                    if recvname.base == argname.base {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        // methods called through the class prototype (e.g. `Parent.init(self)`)
+        // should receive an instance explicitly
+        if let Ex::Var(ref recvname) = *recv.base {
+            let recvslot = self.env.get_var(recvname).and_then(|def| def.slot.slot().cloned());
+            if let Some(recvslot) = recvslot {
+                let recvty = self.env.resolve_exact_type(&recvslot.unlift());
+                if let Some(&T::Class(Class::Prototype(_))) = recvty.as_ref().map(|ty| &**ty) {
+                    return Ok(());
+                }
+            }
+        }
+
+        self.env.warn(func, m::MethodCalledWithDot { name: &method.base }).done()
+    }
+
+    // warns when `f` is used inside `local f = function() ... end`,
+    // which refers to the outer (or global) `f` instead of the local function being defined.
+    // other references with the same name (e.g. shadowing parameters) are left alone.
+    fn check_local_func_ref(&mut self, name: &Spanned<NameRef>) -> Result<()> {
+        let env = &*self.env;
+        let refname = env.get_name(name);
+        for &mut (ref localname, ref outer, namespan, funcspan, ref mut warned) in
+                &mut self.local_funcs {
+            let same = match name.base {
+                NameRef::Local(ref id) => outer.as_ref() == Some(id),
+                NameRef::Global(ref name) => outer.is_none() && localname == name,
+            };
+            if !*warned && same && funcspan.contains(name.span.begin()) {
+                *warned = true;
+                env.warn(name, m::LocalFuncRefNotInScope { name: refname })
+                   .note(namespan, m::LocalFuncDefinedHere {})
+                   .done()?;
+            }
+        }
+        Ok(())
+    }
+
     fn visit_func_call(&mut self, functy: &Spanned<Ty>, selfinfo: Option<Spanned<Slot>>,
                        args: &'inp Spanned<Args>, expspan: Span) -> Result<Exitable<SlotSeq>> {
         let functy = if let Some(func) = self.env.resolve_exact_type(functy) {
@@ -2211,8 +3120,11 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             return Ok(Exitable::dummy());
         };
 
-        // construct hints; they are given at the best effort basis
-        let hint = if let Some(&Functions::Simple(ref f)) = functy.get_functions() {
+        // construct hints; they are given at the best effort basis.
+        // setmetatable should not get hints, as they will erase the record type in arguments.
+        let hint = if functy.tag() == Some(Tag::SetMetatable) {
+            None
+        } else if let Some(&Functions::Simple(ref f)) = functy.get_functions() {
             let mut args = f.args.clone();
             if selfinfo.is_some() && !args.head.is_empty() {
                 args.head.remove(0); // args do not contain self, so do hints
@@ -2241,6 +3153,21 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             },
         };
 
+        // a table with the `__call` metamethod is called via that metamethod,
+        // with the table itself prepended to the arguments
+        let mut calledtab = None;
+        let functy = match self.get_metamethod(&functy, b"__call") {
+            Some(call) => {
+                if let Some(call) = self.env.resolve_exact_type(&call.unlift()) {
+                    calledtab = Some(functy.clone());
+                    call.with_loc(&functy)
+                } else {
+                    functy
+                }
+            },
+            None => functy,
+        };
+
         if !self.env.get_type_bounds(&functy).1.is_callable() {
             self.env.error(&functy, m::CallToNonFunc { func: self.display(&functy) }).done()?;
             return Ok(exit.with_dummy());
@@ -2289,7 +3216,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
                 // non-list arguments have no usable conditions (always evaluate to true)
                 if let Args::List(ref args) = args.base {
-                    if let (Some(cond), _seq) = self.collect_conds_from_exp(&args[0])? {
+                    if let Exitable(_, (Some(cond), _)) = self.collect_conds_from_exp(&args[0])? {
                         self.assert_cond(cond, false)?;
                     }
                 }
@@ -2306,7 +3233,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 }
 
                 if let Args::List(ref args) = args.base {
-                    if let (Some(cond), _seq) = self.collect_conds_from_exp(&args[0])? {
+                    if let Exitable(_, (Some(cond), _)) = self.collect_conds_from_exp(&args[0])? {
                         self.assert_cond(cond, true)?;
                     }
                 }
@@ -2373,9 +3300,128 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         } else {
             false
         };
+        if let Some(tab) = calledtab {
+            argtys.head.insert(0, Slot::just(tab.base).with_loc(tab.span));
+        }
+
+        let mut argslots = argtys.clone();
+        let mut argtys = argtys.unlift();
+        let Exitable(retexit, mut returns) = self.check_callable(&functy, &argtys, methodcall)?;
+
+        // setmetatable and getmetatable refine the return type from the arguments
+        match functy.tag() {
+            // setmetatable(table, metatable)
+            Some(Tag::SetMetatable) if !methodcall && nargs >= 2 => {
+                let tab = self.env.resolve_exact_type(argtys.ensure_at(0));
+                let meta = self.env.resolve_exact_type(argtys.ensure_at(1));
+                if let (Some(tab), Some(meta)) = (tab, meta) {
+                    let rvar = match tab.get_tables() {
+                        Some(&Tables::Fields(ref rvar, _)) => Some(rvar.clone()),
+                        _ => None,
+                    };
+                    let meta = if meta.get_dynamic().is_some() {
+                        None
+                    } else if meta.flags() == T_NONE {
+                        Some(None)
+                    } else if meta.get_tables().is_some() && T_TABLE.contains(meta.flags()) {
+                        Some(Some(meta))
+                    } else {
+                        None
+                    };
+                    if let (Some(rvar), Some(meta)) = (rvar, meta) {
+                        let tables = Tables::Fields(rvar, meta);
+                        *returns.ensure_at_mut(0) = Ty::new(T::Tables(Cow::Owned(tables)));
+                    }
+                }
+            }
+
+            // getmetatable(object)
+            Some(Tag::GetMetatable) if !methodcall && nargs >= 1 => {
+                let obj = self.env.resolve_exact_type(argtys.ensure_at(0));
+                if let Some(obj) = obj {
+                    if let Some(&Tables::Fields(_, Some(ref meta))) = obj.get_tables() {
+                        *returns.ensure_at_mut(0) = meta.clone();
+                    }
+                }
+            }
+
+            // rawget(table, index)
+            Some(Tag::RawGet) if !methodcall && nargs >= 2 => {
+                let dyn = self.env.resolve_exact_type(argtys.ensure_at(0))
+                                  .and_then(|tab| tab.get_dynamic());
+                if let Some(dyn) = dyn {
+                    *returns.ensure_at_mut(0) = Ty::new(T::Dynamic(dyn));
+                } else if self.is_exact_table(argtys.ensure_at(0)) {
+                    let tab = argslots.ensure_at(0).clone();
+                    let key = argslots.ensure_at(1).clone();
+                    // the table may be missing the field at the runtime (or have a nil value),
+                    // and unlike the ordinary indexing a missing field is not an error
+                    let value = match self.check_index_common(&tab, &key, expspan, false, true)? {
+                        Index::Missing => Ty::silent_nil(),
+                        Index::Created(..) => unreachable!(),
+                        Index::Found(slot) => slot.unlift().clone().with_nil(),
+                    };
+                    *returns.ensure_at_mut(0) = value;
+                }
+            }
+
+            // coroutine.yield(...)
+            Some(Tag::CoroutineYield) if !methodcall => {
+                // literal types are widened, as in the collection of actual return types
+                let seq = argtys.clone().unspan();
+                let seq = TySeq { head: seq.head.into_iter().map(|t| t.coerce()).collect(),
+                                  tail: seq.tail.map(|t| t.coerce()) };
+                self.add_yielded(seq, expspan);
+            }
+
+            // coroutine.wrap(function literal)
+            Some(Tag::CoroutineWrap) if !methodcall && nargs >= 1 => {
+                let body = argslots.ensure_at(0).clone();
+                if let Some(yielded) = self.yields.get(&body.span).cloned() {
+                    // the wrapper returns yielded values, and finally what the body returns
+                    let bodyreturns = self.env.resolve_exact_type(&body.unlift()).and_then(|ty| {
+                        match ty.get_functions() {
+                            Some(&Functions::Simple(ref f)) => f.returns.clone(),
+                            _ => None,
+                        }
+                    });
+                    let wrapped = match bodyreturns {
+                        Some(bodyreturns) => {
+                            let yielded = yielded.all_with_loc(expspan);
+                            match bodyreturns.all_with_loc(body.span).union(&yielded, false,
+                                                                            self.types()) {
+                                Ok(wrapped) => wrapped.unspan(),
+                                Err(_) => yielded.unspan(),
+                            }
+                        }
+                        // the body never returns
+                        None => yielded,
+                    };
+                    let wrapper = Function {
+                        typarams: Vec::new(),
+                        args: TySeq { head: Vec::new(), tail: Some(Ty::new(T::All)) },
+                        argnames: Vec::new(),
+                        returns: Some(wrapped),
+                        returnnames: Vec::new(),
+                    };
+                    *returns.ensure_at_mut(0) = Ty::new(T::func(wrapper));
+                }
+            }
+
+            // rawset(table, index, value)
+            Some(Tag::RawSet) if !methodcall && nargs >= 3 => {
+                if self.is_exact_table(argtys.ensure_at(0)) {
+                    let tab = argslots.ensure_at(0).clone();
+                    let key = argslots.ensure_at(1).clone();
+                    let value = argslots.ensure_at(2).clone();
+                    let lvalue = self.check_lval_index(&tab, &key, expspan)?;
+                    self.assign_to_lval_index(&tab, &key, &lvalue, &value, None)?;
+                    *returns.ensure_at_mut(0) = tab.unlift().clone();
+                }
+            }
 
-        let Exitable(retexit, returns) =
-            self.check_callable(&functy, &argtys.unlift(), methodcall)?;
+            _ => {}
+        }
 
         // merge exits; do not use `ExprExit::then` as this is the only way to generate Stop.
         // TODO this should be Var instead of Just!!!!!
@@ -2409,7 +3455,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                         let v = v.clone().with_nil().with_loc(&hint);
                         Some(Target::Map(k, v))
                     },
-                    Some(&Tables::Fields(_)) => Some(Target::Fields(true, Vec::new())),
+                    Some(&Tables::Fields(..)) => Some(Target::Fields(true, Vec::new())),
                     None => None,
                 }
             })
@@ -2460,7 +3506,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 Target::Fields(explicit, ref mut fields) => {
                     if let Some(key) = litkey {
                         if !dup {
-                            fields.push((key, Slot::just(v.unlift().clone())));
+                            fields.push((key, v.to_rvalue()));
                         }
                     } else {
                         let mut more =
@@ -2491,38 +3537,29 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
 
                     let v_ = v.as_ref().map(|s| s.unlift());
                     let vty_ = vty.as_ref().map(|s| s.unlift());
-                    if let Err(r) = v_.assert_sub(&vty_, env.types()) {
-                        env.error(&v,
-                                  m::TableLitWithInvalidArrayValue {
-                                      given: env.display(&v), value: env.display(vty),
-                                  })
-                           .report_types(r, TypeReportHint::None)
-                           .done()?;
-                    }
+                    env.assert_sub_reporting(&v_, &vty_, v.span, TypeReportHint::None, |types| {
+                        Box::new(m::TableLitWithInvalidArrayValue {
+                            given: v.base.display(types), value: vty.base.display(types),
+                        })
+                    })?;
                 }
 
                 Target::Map(ref mut kty, ref mut vty) => {
-                    if let Err(r) = k.assert_sub(kty, env.types()) {
-                        env.error(&k,
-                                  m::TableLitWithInvalidMapKey {
-                                      given: env.display(&k),
-                                      key: env.display(kty), value: env.display(vty),
-                                  })
-                           .report_types(r, TypeReportHint::None)
-                           .done()?;
-                    }
+                    env.assert_sub_reporting(&k, kty, k.span, TypeReportHint::None, |types| {
+                        Box::new(m::TableLitWithInvalidMapKey {
+                            given: k.base.display(types),
+                            key: kty.base.display(types), value: vty.base.display(types),
+                        })
+                    })?;
 
                     let v_ = v.as_ref().map(|s| s.unlift());
                     let vty_ = vty.as_ref().map(|s| s.unlift());
-                    if let Err(r) = v_.assert_sub(&vty_, env.types()) {
-                        env.error(&v,
-                                  m::TableLitWithInvalidMapValue {
-                                      given: env.display(&v),
-                                      key: env.display(kty), value: env.display(vty),
-                                  })
-                           .report_types(r, TypeReportHint::None)
-                           .done()?;
-                    }
+                    env.assert_sub_reporting(&v_, &vty_, v.span, TypeReportHint::None, |types| {
+                        Box::new(m::TableLitWithInvalidMapValue {
+                            given: v.base.display(types),
+                            key: kty.base.display(types), value: vty.base.display(types),
+                        })
+                    })?;
                 }
             }
 
@@ -2573,7 +3610,7 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                         "cannot insert disjoint fields into a fresh row variable"
                     );
                 }
-                Tables::Fields(rvar)
+                Tables::Fields(rvar, None)
             },
 
             Target::Array(vty, min, max, count) => {
@@ -2608,6 +3645,16 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     {
         let Exitable(exit, slotseq) = self.visit_exp_(exp, hint)?;
 
+        // pathological (e.g. recursive) types can make later displays and comparisons
+        // run for exponential time, so the checking stops when the type is too complex
+        if let Some(limit) = self.options.max_type_complexity {
+            for slot in slotseq.head.iter().chain(slotseq.tail.iter()) {
+                if slot.unlift().depth(limit, self.types()) > limit {
+                    return self.env.fatal(exp, m::TypeTooComplex { limit: limit }).done();
+                }
+            }
+        }
+
         // mark the resulting slot (sequence) to the span
         let slot = if let Some(slot) = slotseq.head.first() {
             slot.clone()
@@ -2655,7 +3702,10 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 }
             },
             Ex::Var(ref name) => {
-                if self.env.get_var(name).is_some() {
+                self.check_local_func_ref(name)?;
+                if let Some(record) = self.dynamic_record(name) {
+                    Exitable::new(SlotSeq::from(record))
+                } else if self.env.get_var(name).is_some() {
                     Exitable::new(SlotSeq::from(self.env.ensure_var(name)?))
                 } else {
                     self.env.error(exp, m::NoVar { name: self.env.get_name(name) }).done()?;
@@ -2683,7 +3733,11 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
             Ex::FuncCall(ref func, ref args) => {
                 let Exitable(exit, funcinfo) = self.visit_exp(func, None)?;
                 let funcinfo = funcinfo.into_first().map(|t| t.unlift().clone());
-                exit.then(self.visit_func_call(&funcinfo, None, args, exp.span)?)
+                self.check_method_call_syntax(func, &funcinfo, args)?;
+                let returns = self.visit_func_call(&funcinfo, None, args, exp.span)?;
+                self.check_call_yields(Some(&*func.base), &funcinfo);
+                self.set_last_call_tag(&funcinfo, exp.span);
+                exit.then(returns)
             },
 
             Ex::MethodCall(Spanned { base: (ref e, ref method), span }, ref args) => {
@@ -2694,7 +3748,10 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let methinfo = self.check_rval_index(&ty, &kty, exp.span)?;
                 self.context().spanned_slots_mut().insert(methinfo.clone().with_loc(span));
                 let methinfo = methinfo.unlift().clone().with_loc(span);
-                exit.then(self.visit_func_call(&methinfo, Some(ty), args, exp.span)?)
+                let returns = self.visit_func_call(&methinfo, Some(ty), args, exp.span)?;
+                self.check_call_yields(None, &methinfo);
+                self.set_last_call_tag(&methinfo, exp.span);
+                exit.then(returns)
             },
 
             Ex::Index(ref e, ref key) => {
@@ -2729,6 +3786,13 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 exit.with(SlotSeq::from(info))
             },
 
+            // the right hand side of `and` and `or` is narrowed by the left hand side
+            Ex::Bin(_, Spanned { base: BinOp::And, .. }, _) |
+            Ex::Bin(_, Spanned { base: BinOp::Or, .. }, _) => {
+                let Exitable(exit, (_, seq)) = self.collect_conds_from_exp(exp)?;
+                exit.with(seq.unspan())
+            },
+
             Ex::Bin(ref l, op, ref r) => {
                 let Exitable(exit1, lhs) = self.visit_exp(l, None)?;
                 let Exitable(exit2, rhs) = self.visit_exp(r, None)?;
@@ -2811,7 +3875,8 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     fn visit_type_spec<X>(&mut self,
                           spec: &TypeSpec<Spanned<X>>) -> Result<Option<SlotSpec>> {
         // no modifier and kind requested, this should be considered a missing type spec
-        if spec.modf == MM::None && spec.kind.is_none() {
+        // (`var` alone is same to no modifier)
+        if (spec.modf == MM::None || spec.modf == MM::Var) && spec.kind.is_none() {
             return Ok(None);
         }
 
@@ -2843,36 +3908,74 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     /// Returns the resulting module (that may have diverged, in which case `returns` is `None`)
     /// or `None` when the error occurred and has been recovered.
     pub fn require(&mut self, modname: Spanned<&[u8]>, expspan: Span) -> Result<Option<Module>> {
+        if let Some(module) = self.try_require(modname, expspan)? {
+            Ok(module)
+        } else {
+            self.env.warn(modname, m::CannotResolveModName {}).done()?;
+
+            // since the failure to resolve the module name is a mere warning,
+            // we don't want to return the dummy type here
+            Ok(Some(Module {
+                returns: Some(Slot::just(Ty::new(T::All))),
+                exported_types: HashMap::new(),
+            }))
+        }
+    }
+
+    // same to `require` but returns `None` (without any report) if the module cannot be resolved
+    fn try_require(&mut self, modname: Spanned<&[u8]>,
+                   expspan: Span) -> Result<Option<Option<Module>>> {
         let mut module = self.context().get_loaded_module(&modname, expspan)?;
 
         if module.is_none() {
-            self.context().mark_module_as_loading(&modname, expspan);
-
             info!("requiring {:?}", modname);
             let opts = self.env.opts().clone();
             let chunk = match opts.borrow_mut().require_chunk(modname, self.env) {
                 Ok(chunk) => chunk,
-                Err(_) => {
-                    self.env.warn(modname, m::CannotResolveModName {}).done()?;
-
-                    // since the failure to resolve the module name is a mere warning,
-                    // we don't want to return the dummy type here
-                    return Ok(Some(Module {
-                        returns: Some(Slot::just(Ty::new(T::All))),
-                        exported_types: HashMap::new(),
-                    }));
-                }
+                Err(_) => return Ok(None),
             };
 
+            self.context().mark_module_as_loading(&modname, expspan);
+            self.context().ignore_spans(&chunk.ignored_lines);
+
             let mut env = Env::new(self.env.context(), opts, chunk.map);
             let exit = {
-                let mut sub = Checker::new(&mut env);
+                let mut sub = Checker::with_options(&mut env, self.options.clone());
                 sub.visit_block(&chunk.block)?
             };
             module = env.return_from_module(&modname, exit >= Exit::Stop, expspan)?;
+            self.context().run_lints(&chunk.block)?;
         }
 
-        Ok(module)
+        Ok(Some(module))
+    }
+
+    // `--# open` with a non-library name: requires the module and
+    // brings its exported types and the fields of the returned table into the global scope
+    fn open_module(&mut self, modname: Spanned<&[u8]>, expspan: Span) -> Result<()> {
+        let module = match self.try_require(modname, expspan)? {
+            Some(Some(module)) => module,
+            Some(None) => return Ok(()), // the error has been already reported
+            None => {
+                self.env.error(modname, m::CannotOpenLibrary {}).done()?;
+                return Ok(());
+            }
+        };
+
+        self.env.import_types(module.exported_types.with_loc(expspan))?;
+
+        if let Some(returns) = module.returns {
+            if let Some(fields) = self.context().get_available_fields(&returns.unlift()) {
+                for (key, slot) in fields {
+                    if let Key::Str(ref name) = key {
+                        let name = Name::from(&name[..]).with_loc(expspan);
+                        self.env.open_var(name, expspan, slot.with_loc(expspan))?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
     }
 
     fn register_module_if_needed(&mut self, slot: &Slot) {
@@ -2885,53 +3988,56 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
     }
 
     fn collect_type_from_exp(&mut self, exp: &'inp Spanned<Exp>)
-            -> Result<(Option<Spanned<Slot>>, SpannedSlotSeq)> {
+            -> Result<Exitable<(Option<(Tag, Spanned<Slot>)>, SpannedSlotSeq)>> {
         if let Ex::FuncCall(ref func, ref args) = *exp.base {
-            let Exitable(_, funcseq) = self.visit_exp(func, None)?;
+            let Exitable(funcexit, funcseq) = self.visit_exp(func, None)?;
             let funcspan = funcseq.all_span();
             let funcinfo = funcseq.into_first();
             let funcinfo = funcinfo.unlift();
-            let typeofexp = if funcinfo.tag() == Some(Tag::Type) {
-                // there should be a single argument there
-                match args.base {
-                    Args::List(ref args) if args.len() >= 1 => {
-                        let Exitable(_, info) = self.visit_exp(&args[0], None)?;
-                        Some(info.into_first())
-                    },
-                    Args::List(_) => {
-                        self.env.error(exp, m::BuiltinGivenLessArgs { name: "type", nargs: 1 })
-                                .done()?;
-                        None
-                    },
-                    Args::Str(ref s) => {
-                        let argstr = Str::from(s[..].to_owned());
-                        Some(Slot::just(Ty::new(T::Str(Cow::Owned(argstr)))).with_loc(args))
-                    },
-                    Args::Table(ref tab) => {
-                        let Exitable(_, table) = self.visit_table(tab, args.span, None)?;
-                        Some(Slot::just(Ty::new(table)).with_loc(args))
-                    },
-                }
-            } else {
-                None
+            let typeofexp = match funcinfo.tag() {
+                Some(tag @ Tag::Type) | Some(tag @ Tag::MathType) => {
+                    // there should be a single argument there
+                    let info = match args.base {
+                        Args::List(ref args) if args.len() >= 1 => {
+                            let Exitable(_, info) = self.visit_exp(&args[0], None)?;
+                            Some(info.into_first())
+                        },
+                        Args::List(_) => {
+                            let name = if tag == Tag::Type { "type" } else { "math.type" };
+                            self.env.error(exp, m::BuiltinGivenLessArgs { name: name, nargs: 1 })
+                                    .done()?;
+                            None
+                        },
+                        Args::Str(ref s) => {
+                            let argstr = Str::from(s[..].to_owned());
+                            Some(Slot::just(Ty::new(T::Str(Cow::Owned(argstr)))).with_loc(args))
+                        },
+                        Args::Table(ref tab) => {
+                            let Exitable(_, table) = self.visit_table(tab, args.span, None)?;
+                            Some(Slot::just(Ty::new(table)).with_loc(args))
+                        },
+                    };
+                    info.map(|info| (tag, info))
+                },
+                _ => None,
             };
-            let Exitable(_, seq) = self.visit_func_call(&funcinfo.clone().with_loc(funcspan),
-                                                        None, args, exp.span)?;
-            Ok((typeofexp, seq.all_with_loc(exp)))
+            let Exitable(exit, seq) = self.visit_func_call(&funcinfo.clone().with_loc(funcspan),
+                                                           None, args, exp.span)?;
+            Ok(funcexit.then(exit.with((typeofexp, seq.all_with_loc(exp)))))
         } else {
-            let Exitable(_, seq) = self.visit_exp(exp, None)?;
-            Ok((None, seq))
+            let Exitable(exit, seq) = self.visit_exp(exp, None)?;
+            Ok(exit.with((None, seq)))
         }
     }
 
     // similar to visit_exp but also tries to collect Cond
     fn collect_conds_from_exp(&mut self, exp: &'inp Spanned<Exp>)
-            -> Result<(Option<Cond>, SpannedSlotSeq)> {
+            -> Result<Exitable<(Option<Cond>, SpannedSlotSeq)>> {
         debug!("collecting conditions from exp {:?}", *exp);
 
         match *exp.base {
             Ex::Un(Spanned { base: UnOp::Not, .. }, ref e) => {
-                let (cond, seq) = self.collect_conds_from_exp(e)?;
+                let Exitable(exit, (cond, seq)) = self.collect_conds_from_exp(e)?;
                 let cond = match cond {
                     Some(Cond::Not(cond)) => match *cond {
                         Cond::Not(cond) => Some(*cond),
@@ -2943,12 +4049,12 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 };
                 let info = seq.into_first();
                 let info = self.check_un_op(UnOp::Not, &info, exp.span)?;
-                Ok((cond, SpannedSlotSeq::from(info.with_loc(exp))))
+                Ok(exit.with((cond, SpannedSlotSeq::from(info.with_loc(exp)))))
             }
 
             Ex::Bin(ref l, Spanned { base: BinOp::Eq, .. }, ref r) => {
-                let (lty, linfo) = self.collect_type_from_exp(l)?;
-                let (rty, rinfo) = self.collect_type_from_exp(r)?;
+                let Exitable(lexit, (lty, linfo)) = self.collect_type_from_exp(l)?;
+                let Exitable(rexit, (rty, rinfo)) = self.collect_type_from_exp(r)?;
 
                 let linfo = linfo.into_first();
                 let rinfo = rinfo.into_first();
@@ -2958,15 +4064,15 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 // but it is not common and results in a very subtle semi-equivalence condition
                 // that we cannot readily handle.
                 let cond = match (lty, rty) {
-                    (Some(ty), None) => {
-                        if let Some(flags) = self.literal_ty_to_flags(&rinfo)? {
+                    (Some((tag, ty)), None) => {
+                        if let Some(flags) = self.typeof_literal_to_flags(tag, &rinfo)? {
                             Some(Cond::Flags(ty, flags))
                         } else {
                             None // the rhs is not a literal, so we don't what it is
                         }
                     },
-                    (None, Some(ty)) => {
-                        if let Some(flags) = self.literal_ty_to_flags(&linfo)? {
+                    (None, Some((tag, ty))) => {
+                        if let Some(flags) = self.typeof_literal_to_flags(tag, &linfo)? {
                             Some(Cond::Flags(ty, flags))
                         } else {
                             None
@@ -2975,14 +4081,19 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                     (_, _) => None,
                 };
 
+                self.check_comparison(&linfo.unlift(), BinOp::Eq, &rinfo.unlift(), exp.span)?;
+
                 // TODO when cond is None try to assert the type equivalence;
                 // it is currently not implemented due to bad interaction with sub-literal types
-                Ok((cond, SpannedSlotSeq::from(T::Boolean.with_loc(exp))))
+                let info = SpannedSlotSeq::from(T::Boolean.with_loc(exp));
+                Ok(lexit.collide(rexit).with((cond, info)))
             }
 
             Ex::Bin(ref l, Spanned { base: BinOp::And, .. }, ref r) => {
-                let (lcond, lseq) = self.collect_conds_from_exp(l)?;
-                let (rcond, rseq) = self.collect_conds_from_exp(r)?;
+                let Exitable(lexit, (lcond, lseq)) = self.collect_conds_from_exp(l)?;
+                // `r` is evaluated only when `l` is truthy
+                let Exitable(rexit, (rcond, rseq)) =
+                    self.collect_conds_under_cond(lcond.as_ref(), false, r)?;
 
                 let cond = match (lcond, rcond) {
                     (None, cond) | (cond, None) => cond,
@@ -3003,12 +4114,14 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let linfo = lseq.into_first();
                 let rinfo = rseq.into_first();
                 let info = self.check_bin_op(&linfo, BinOp::And, &rinfo, exp.span)?;
-                Ok((cond, SpannedSlotSeq::from(info.with_loc(exp))))
+                Ok(lexit.collide(rexit).with((cond, SpannedSlotSeq::from(info.with_loc(exp)))))
             }
 
             Ex::Bin(ref l, Spanned { base: BinOp::Or, .. }, ref r) => {
-                let (lcond, lseq) = self.collect_conds_from_exp(l)?;
-                let (rcond, rseq) = self.collect_conds_from_exp(r)?;
+                let Exitable(lexit, (lcond, lseq)) = self.collect_conds_from_exp(l)?;
+                // `r` is evaluated only when `l` is falsy
+                let Exitable(rexit, (rcond, rseq)) =
+                    self.collect_conds_under_cond(lcond.as_ref(), true, r)?;
 
                 let cond = match (lcond, rcond) {
                     (None, cond) | (cond, None) => cond,
@@ -3029,17 +4142,62 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let linfo = lseq.into_first();
                 let rinfo = rseq.into_first();
                 let info = self.check_bin_op(&linfo, BinOp::Or, &rinfo, exp.span)?;
-                Ok((cond, SpannedSlotSeq::from(info.with_loc(exp))))
+                Ok(lexit.collide(rexit).with((cond, SpannedSlotSeq::from(info.with_loc(exp)))))
             }
 
-            _ => {
-                let Exitable(_, seq) = self.visit_exp(exp, None)?;
+            // only variables can be narrowed; other slots (e.g. fields) are shared
+            // with their containers, and filtering them in place would alter the containers.
+            Ex::Var(_) => {
+                let Exitable(exit, seq) = self.visit_exp(exp, None)?;
                 let info = seq.into_first();
-                // XXX should detect non-local slots and reject them!
-                // probably we can do that via proper weakening, but who knows.
-                Ok((Some(Cond::Flags(info.clone(), T_TRUTHY)), SpannedSlotSeq::from(info)))
+                Ok(exit.with((Some(Cond::Flags(info.clone(), T_TRUTHY)),
+                              SpannedSlotSeq::from(info))))
+            }
+
+            _ => {
+                let Exitable(exit, seq) = self.visit_exp(exp, None)?;
+                Ok(exit.with((None, seq)))
+            }
+        }
+    }
+
+    // same to collect_conds_from_exp but `exp` is visited while `cond` (or its negation)
+    // is temporarily asserted, so that e.g. `x and x.y` can index `x` even when `x` is nilable.
+    fn collect_conds_under_cond(&mut self, cond: Option<&Cond>, negated: bool,
+                                exp: &'inp Spanned<Exp>)
+            -> Result<Exitable<(Option<Cond>, SpannedSlotSeq)>> {
+        let cond = match cond {
+            Some(cond) => cond,
+            None => return self.collect_conds_from_exp(exp),
+        };
+
+        // remember the original types to be restored later
+        fn collect_slots(cond: &Cond, slots: &mut Vec<(Slot, Ty)>) {
+            match *cond {
+                Cond::Flags(ref info, _) => {
+                    slots.push((info.base.clone(), info.unlift().clone()));
+                }
+                Cond::And(ref l, ref r) | Cond::Or(ref l, ref r) => {
+                    collect_slots(l, slots);
+                    collect_slots(r, slots);
+                }
+                Cond::Not(ref c) => collect_slots(c, slots),
             }
         }
+        let mut slots = Vec::new();
+        collect_slots(cond, &mut slots);
+
+        let ret = match self.assert_cond(cond.clone(), negated) {
+            Ok(()) => self.collect_conds_from_exp(exp),
+            Err(e) => Err(e),
+        };
+
+        // restore the slots even on errors
+        for (slot, ty) in slots.into_iter().rev() {
+            slot.replace_ty(ty);
+        }
+
+        ret
     }
 
     fn assert_cond(&mut self, cond: Cond, negated: bool) -> Result<()> {
@@ -3050,6 +4208,10 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
                 let flags = if negated { !flags } else { flags };
                 // XXX this is temporary, the entire condition assertion should be changed!
                 info.filter_by_flags(flags, self.types()).map_err(|_| kailua_diag::Stop)?;
+                // `--# pragma strict-nil` should know that the type has been checked for `nil`
+                if self.env.strict_nil() && !flags.intersects(T_NOISY_NIL) {
+                    info.remove_implicit_nil();
+                }
                 debug!("resulted in {:?}", info);
             }
 
@@ -3098,6 +4260,27 @@ impl<'inp, 'envr, 'env, R: Report> Checker<'inp, 'envr, 'env, R> {
         }
     }
 
+    // MathType tag accepts different strings from Type
+    fn typeof_literal_to_flags(&self, tag: Tag, info: &Spanned<Slot>) -> Result<Option<Flags>> {
+        if tag != Tag::MathType {
+            return self.literal_ty_to_flags(info);
+        }
+
+        if let Some(s) = info.unlift().as_string() {
+            let flags = match &s[..] {
+                b"integer" => T_INTEGER,
+                b"float" => T_NONINTEGER,
+                _ => {
+                    self.env.error(info, m::UnknownLiteralMathTypeName {}).done()?;
+                    return Ok(None);
+                }
+            };
+            Ok(Some(flags))
+        } else {
+            Ok(None)
+        }
+    }
+
     // AssertType tag accepts more strings than Type
     fn ext_literal_ty_to_flags(&self, info: &Spanned<Slot>) -> Result<Option<Flags>> {
         if let Some(s) = info.unlift().as_string() {
@@ -197,8 +197,8 @@ impl GiderosClassSystem {
         // now `init` is: function(/* removed self */, ...) -> any
         // fix the return type to make a signature for the `new` method
         let returns = T::Class(Class::Instance(cid));
-        let ctor = Function { args: func.args, argnames: func.argnames,
-                              returns: Some(TySeq::from(returns)) };
+        let ctor = Function { typarams: func.typarams, args: func.args, argnames: func.argnames,
+                              returns: Some(TySeq::from(returns)), returnnames: Vec::new() };
         let ctor = Slot::new(F::Const, Ty::new(T::func(ctor)));
 
         Ok(ctor)
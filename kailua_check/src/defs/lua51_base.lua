@@ -2,7 +2,7 @@
 
 --# -- TODO return generics
 --# assume global `assert`:
---#     --[[ [assert] ]] function(v: any, message: string?)
+--#     [assert] function(v: any, message: string?)
 --#
 --# assume global `collectgarbage`:
 --#     function(opt: string?, arg: any?) --> any
@@ -20,7 +20,7 @@
 --#     function(f: function|integer?) --> table
 --#
 --# assume global `getmetatable`:
---#     function(object: any) --> table
+--#     [getmetatable] function(object: any) --> table
 --#
 --# assume global `ipairs`:
 --#     [generic_pairs]
@@ -48,9 +48,8 @@
 --#     [generic_pairs] function(t: table) --> (function(table, any) --> (any?, any), table, any)
 --#
 --# -- TODO `f` should be once function
---# -- TODO genericity
 --# assume global `pcall`:
---#     function(f: function, any...) --> (boolean, any...)
+--#     [pcall] function(f: function, any...) --> (boolean, any...)
 --#
 --# assume global `print`:
 --#     function(any...)
@@ -59,10 +58,10 @@
 --#     function(v1: any, v2: any) --> boolean
 --#
 --# assume global `rawget`:
---#     function(table: table, index: any) --> any
+--#     [rawget] function(table: table, index: any) --> any
 --#
 --# assume global `rawset`:
---#     function(table: table, index: any, value: any) --> table
+--#     [rawset] function(table: table, index: any, value: any) --> table
 --#
 --# -- TODO genericity
 --# assume global `select`:
@@ -72,7 +71,7 @@
 --#     function(f: function|integer?, table: table) --> function
 --#
 --# assume global `setmetatable`:
---#     function(table: table, metatable: any?) --> table
+--#     [setmetatable] function(table: table, metatable: any?) --> table
 --#
 --# assume global `tonumber`:
 --#     function(e: any, base: integer?) --> number
@@ -103,9 +102,8 @@
 --#         `resume`: function(co: thread, any...) --> (boolean, any...);
 --#         `running`: function() --> thread;
 --#         `status`: function(co: thread) --> string;
---#         -- TODO genericity
---#         `wrap`: function(f: function) --> thread;
---#         `yield`: function(any...) --> (any...);
+--#         `wrap`: [coroutine_wrap] function(f: function) --> function(any...) --> (any...);
+--#         `yield`: [coroutine_yield] function(any...) --> (any...);
 --#         ...
 --#     }
 
@@ -4,12 +4,10 @@
 --#     -- TODO method receivers and recursive types are generally not implemented yet
 --#     `close`: function(self: WHATEVER);
 --#     `flush`: function(self: WHATEVER);
---#     `lines`: function(self: WHATEVER) --> function() --> string?;
---#     -- TODO should be: function(self, '*n') --> number &
---#     --                 function(self, '*a') --> string &
---#     --                 function(self, '*l'|integer?) --> string
---#     -- for now, let's ignore *n (which is most useless)
---#     `read`: function(self: WHATEVER, format: '*a'|'*l'|integer?) --> string;
+--#     -- formats to `lines` are only accepted for Lua 5.2 and later
+--#     `lines`: function(self: WHATEVER) --> function(nil, nil) --> string?;
+--#     -- return types are refined from the format literals
+--#     `read`: [io_read] function(self: WHATEVER, '*n'|'*a'|'*l'|integer...) --> (WHATEVER...);
 --#     `seek`: function(self: WHATEVER, whence: 'set'|'cur'|'end'?, offset: integer?);
 --#     -- TODO again, 'no' does not accept an integer
 --#     `setvbuf`: function(self: WHATEVER, mode: 'no'|'full'|'line', size: integer?);
@@ -22,12 +20,12 @@
 --#         `flush`: function();
 --#         -- TODO should be separated
 --#         `input`: function(file: string|file?) --> file;
---#         `lines`: function(filename: string?) --> function() --> string?;
+--#         `lines`: function(filename: string?) --> function(nil, nil) --> string?;
 --#         -- TODO sequence conditional union: (file) | (nil, string)
 --#         `open`: function(filename: string, mode: string?) --> (file, string);
 --#         `output`: function(file: string|file?) --> file;
 --#         `popen`: function(prog: string, mode: string?) --> file;
---#         `read`: function(format: '*a'|'*l'|integer?) --> string;
+--#         `read`: [io_read] function('*n'|'*a'|'*l'|integer...) --> (WHATEVER...);
 --#         `tmpfile`: function() --> file;
 --#         `type`: function(obj: any) --> 'file'|'closed file';
 --#         `write`: function(string|number...);
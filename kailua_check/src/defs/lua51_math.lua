@@ -2,7 +2,7 @@
 
 --# assume global `math`:
 --#     {
---#         `abs`: function(x: number) --> number;
+--#         `abs`: [preserve_integer] function(x: number) --> number;
 --#         `acos`: function(x: number) --> number;
 --#         `asin`: function(x: number) --> number;
 --#         `atan`: function(x: number) --> number;
@@ -22,11 +22,11 @@
 --#         -- TODO should really be
 --#         --      `function(x: integer, integer...) --> integer &
 --#         --       function(x: number, number...) --> number`
---#         `max`: function(x: number, number...) --> number;
+--#         `max`: [preserve_integer] function(x: number, number...) --> number;
 --#         -- TODO should really be
 --#         --      `function(x: integer, integer...) --> integer &
 --#         --       function(x: number, number...) --> number`
---#         `min`: function(x: number, number...) --> number;
+--#         `min`: [preserve_integer] function(x: number, number...) --> number;
 --#         `modf`: function(x: number) --> (integer, number);
 --#         `pi`: number;
 --#         `pow`: function(x: number, y: number) --> number;
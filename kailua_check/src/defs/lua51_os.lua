@@ -16,15 +16,14 @@
 --#         };
 --#         `difftime`: function(t2: number, t1: number) --> number;
 --#         `execute`: function(command: string?) --> integer;
---#         -- TODO diverging function signature
---#         `exit`: function(code: integer?);
---#         `getenv`: function(varname: string) --> string;
+--#         `exit`: function(code: integer?) --> !;
+--#         `getenv`: function(varname: string) --> string?;
 --#         -- TODO sequence conditional union: (true) | (nil, string)
 --#         -- there is also an undocumented 3rd return type (integer) for errno, omitted here
 --#         `remove`: function(filename: string) --> (boolean, string);
 --#         -- TODO sequence conditional union: (true) | (nil, string)
 --#         `rename`: function(oldname: string, newname: string) --> (boolean, string);
---#         `setlocale`: function(locale: string?, category: string?) --> string;
+--#         `setlocale`: function(locale: string?, category: string?) --> string?;
 --#         `time`: function(table: tm?) --> integer;
 --#         `tmpname`: function() --> string;
 --#         ...
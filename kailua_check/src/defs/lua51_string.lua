@@ -5,11 +5,13 @@
 --#         `byte`: function(s: string, i: integer?, j: integer?) --> (integer...);
 --#         `char`: function(integer...) --> string;
 --#         `dump`: function(`function`: function) --> string;
---#         -- TODO repeating return types can contain integer when the pattern contains `()`
---#         `find`: function(s: string, pattern: string, init: integer?, plain: boolean?) -->
+--#         -- the trailing return types are refined from the pattern literal if possible
+--#         `find`: [string_find]
+--#                 function(s: string, pattern: string, init: integer?, plain: boolean?) -->
 --#                          (integer, integer, string...);
 --#         `format`: function(formatstring: string, any...) --> string;
---#         `gmatch`: function(s: string, pattern: string) --> function() --> string?;
+--#         `gmatch`: [string_gmatch]
+--#                   function(s: string, pattern: string) --> function(nil, nil) --> string?;
 --#         -- TODO have to constrain the function argument, but not easy
 --#         `gsub`: function(s: string, pattern: string,
 --#                          repl: string | map<string, string> |
@@ -17,8 +19,9 @@
 --#                          n: integer?) --> string;
 --#         `len`: function(s: string) --> integer;
 --#         `lower`: function(s: string) --> string;
---#         -- TODO repeating return types can contain integer when the pattern contains `()`
---#         `match`: function(s: string, pattern: string, init: integer?) --> (string...);
+--#         -- the return types are refined from the pattern literal if possible
+--#         `match`: [string_match] function(s: string, pattern: string, init: integer?) -->
+--#                                  (string...);
 --#         `rep`: function(s: string, n: integer) --> string;
 --#         `reverse`: function(s: string) --> string;
 --#         `sub`: function(s: string, i: integer, j: integer?) --> string;
@@ -10,8 +10,8 @@
 --#         `insert`: function(table: vector<WHATEVER>, value: any);
 --#         `maxn`: function(table: vector<const any>) --> integer;
 --#         `remove`: function(table: vector<WHATEVER>, pos: integer?) --> any;
---#         `sort`: function(table: vector<WHATEVER>,
---#                          comp: (function(WHATEVER, WHATEVER) --> boolean)?);
+--#         `sort`: [table_sort] function(table: vector<WHATEVER>,
+--#                                       comp: (function(WHATEVER, WHATEVER) --> boolean)?);
 --#         ...
 --#     }
 
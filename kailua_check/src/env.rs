@@ -2,6 +2,7 @@
 
 use std::ops;
 use std::str;
+use std::mem;
 use std::fmt;
 use std::result;
 use std::cell::RefCell;
@@ -11,9 +12,9 @@ use std::sync::Arc;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use kailua_env::{self, Span, Spanned, WithLoc, ScopedId, ScopeMap, SpanMap};
-use kailua_diag::{Result, Kind, Report, Reporter, Locale, Localize};
+use kailua_diag::{Result, Kind, Report, Reporter, Locale, Localize, IgnoreSpans};
 use kailua_syntax::{Str, Name};
-use kailua_syntax::ast::NameRef;
+use kailua_syntax::ast::{NameRef, Block};
 use kailua_types::diag::{TypeReportHint, TypeReportMore};
 use kailua_types::ty::{Displayed, Display, DisplayState, DisplayName};
 use kailua_types::ty::{Ty, TySeq, Nil, T, Slot, SpannedSlotSeq, F, TVar, Lattice, Union, Tag};
@@ -25,6 +26,7 @@ use defs::get_defs;
 use class_system::ClassSystem;
 use class_system::dumb::DumbClassSystem;
 use options::Options;
+use lint::{LintRegistry, SelfOutsideMethod};
 use check::Checker;
 use message as m;
 
@@ -38,6 +40,12 @@ pub enum Id {
 
     /// A global name, simply identified with its name.
     Global(Name),
+
+    /// A name brought into the scope by `--# open`, identified with an index to a per-file
+    /// scope map, a span to the directive and its name.
+    ///
+    /// It is referred as a global name but only visible from the scope with the directive.
+    Opened(usize, Span, Name),
 }
 
 impl Id {
@@ -51,7 +59,7 @@ impl Id {
     pub fn name<'a, R: Report>(&'a self, ctx: &'a Context<R>) -> &'a Name {
         match *self {
             Id::Local(map_index, ref scoped_id) => scoped_id.name(&ctx.scope_maps[map_index]),
-            Id::Global(ref name) => name,
+            Id::Global(ref name) | Id::Opened(_, _, ref name) => name,
         }
     }
 
@@ -59,13 +67,13 @@ impl Id {
         match *self {
             Id::Local(map_index, ref scoped_id) =>
                 Some(scoped_id.scope(&ctx.scope_maps[map_index])),
-            Id::Global(_) => None,
+            Id::Global(_) | Id::Opened(..) => None,
         }
     }
 
     pub fn is_global(&self) -> bool {
         match *self {
-            Id::Local(..) => false,
+            Id::Local(..) | Id::Opened(..) => false,
             Id::Global(..) => true,
         }
     }
@@ -83,7 +91,8 @@ pub struct IdDisplay<'a, R: 'a> {
 }
 
 /// In the debugging output a globally unique name reference is denoted
-/// <code>`<i>Name</i>`$<i>MAP</i><i>id</i></code> or <code>`<i>Name</i>`_</code>,
+/// <code>`<i>Name</i>`$<i>MAP</i><i>id</i></code>, <code>`<i>Name</i>`_</code>
+/// or <code>`<i>Name</i>`@<i>span</i></code> (for names from `--# open`),
 /// where <code><i>MAP</i></code> is a unique alphabetic code for the scope map index.
 impl<'a, R: Report> fmt::Display for IdDisplay<'a, R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -111,6 +120,9 @@ impl<'a, R: Report> fmt::Display for IdDisplay<'a, R> {
             Id::Global(ref name) => {
                 write!(f, "{:?}_", name)
             }
+            Id::Opened(_, span, ref name) => {
+                write!(f, "{:?}@{:?}", name, span)
+            }
         }
     }
 }
@@ -161,6 +173,17 @@ pub struct Frame {
 
     /// Return types.
     pub returns: Returns<TySeq>,
+
+    /// The union of actually returned types so far, only collected for
+    /// explicit return types when `CheckOptions::strict_return_types` is enabled.
+    pub returned: Option<TySeq>,
+
+    /// The union of types given to `coroutine.yield` so far, if any.
+    pub yielded: Option<TySeq>,
+
+    /// True if the function calls other functions which may yield values
+    /// not collected to `yielded`.
+    pub yields_unknown: bool,
 }
 
 /// A name definition.
@@ -222,15 +245,16 @@ pub struct TypeDef {
 pub struct Scope {
     frame: Option<Frame>,
     types: HashMap<Name, TypeDef>,
+    opened: HashMap<Name, Id>,
 }
 
 impl Scope {
     pub fn new() -> Scope {
-        Scope { frame: None, types: HashMap::new() }
+        Scope { frame: None, types: HashMap::new(), opened: HashMap::new() }
     }
 
     pub fn new_function(frame: Frame) -> Scope {
-        Scope { frame: Some(frame), types: HashMap::new() }
+        Scope { frame: Some(frame), types: HashMap::new(), opened: HashMap::new() }
     }
 
     pub fn get_frame<'a>(&'a self) -> Option<&'a Frame> {
@@ -393,8 +417,13 @@ impl ClassProvider for ClassContext {
 /// Due to the presence of a report receiver this is not easily shared or sent across threads;
 /// `Context::into_output` will give a report-free type that is suitable for analysis.
 pub struct Context<R> {
-    report: R,
+    report: IgnoreSpans<R>,
+    lints: LintRegistry,
     output: Output,
+
+    // explicit annotations and their initializers, collected for `verify_annotations`
+    verify_annotations: bool,
+    annotations: Vec<(Spanned<Slot>, Spanned<Slot>)>,
 }
 
 /// A report-free version of `Context`. Suitable for analysis.
@@ -417,6 +446,12 @@ pub struct Output {
     // runtime information
     string_meta: Option<Spanned<Slot>>,
 
+    // the first return type of the main chunk, if any
+    exports: Option<Ty>,
+
+    // types exported from the main chunk
+    exported_types: HashMap<Name, TypeDef>,
+
     // class and class system (shared with Types)
     classes: ClassContext,
 }
@@ -425,8 +460,11 @@ impl<R: Report> Context<R> {
     pub fn new(report: R) -> Context<R> {
         let locale = report.message_locale();
         let classes = ClassContext::new();
+        let mut lints = LintRegistry::new();
+        lints.register(Box::new(SelfOutsideMethod));
         let mut ctx = Context {
-            report: report,
+            report: IgnoreSpans::new(report),
+            lints: lints,
             output: Output {
                 ids: HashMap::new(),
                 scope_maps: Vec::new(),
@@ -436,18 +474,74 @@ impl<R: Report> Context<R> {
                 opened: HashSet::new(),
                 loaded: HashMap::new(),
                 string_meta: None,
+                exports: None,
+                exported_types: HashMap::new(),
                 classes: classes,
-            }
+            },
+            verify_annotations: false,
+            annotations: Vec::new(),
         };
 
         // it is fine to return from the top-level, so we treat it as like a function frame
-        let global_frame = Frame { vararg: None, returns: Returns::None };
+        let global_frame = Frame { vararg: None, returns: Returns::None, returned: None,
+                                  yielded: None, yields_unknown: false };
         ctx.global_scope.frame = Some(global_frame);
         ctx
     }
 
     pub fn report(&self) -> &R {
-        &self.report
+        self.report.inner()
+    }
+
+    /// Ignores any subsequent report starting within given spans.
+    ///
+    /// Used for `--# ignore-next-line` directives (see `kailua_syntax::Chunk::ignored_lines`).
+    pub fn ignore_spans(&mut self, spans: &[Span]) {
+        for &span in spans {
+            self.report.ignore(span);
+        }
+    }
+
+    pub fn lints(&self) -> &LintRegistry {
+        &self.lints
+    }
+
+    pub fn lints_mut(&mut self) -> &mut LintRegistry {
+        &mut self.lints
+    }
+
+    pub fn verify_annotations(&self) -> bool {
+        self.verify_annotations
+    }
+
+    /// When enabled, every explicit type annotation with an initializer is verified
+    /// to be at least as general as the inferred type after the checking.
+    pub fn set_verify_annotations(&mut self, verify: bool) {
+        self.verify_annotations = verify;
+    }
+
+    /// Verifies all annotations collected so far, reporting inconsistent ones.
+    ///
+    /// Does nothing unless `set_verify_annotations(true)` has been called.
+    pub fn check_annotations(&mut self) -> Result<()> {
+        for (spec, init) in mem::replace(&mut self.annotations, Vec::new()) {
+            let specty = (*spec.unlift()).clone();
+            let initty = (*init.unlift()).clone();
+            // only verifying, so the subtyping should not bind type variables
+            let types = &self.output.types as &TypeContext;
+            if !types.subtype_of(&initty, &specty) {
+                self.report.error(&spec, m::InconsistentAnnotation { spec: specty.display(types),
+                                                                    init: initty.display(types) })
+                    .note_if(&init, m::OtherTypeOrigin {})
+                    .done()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs all registered lint passes over the already checked block.
+    pub fn run_lints(&self, block: &Spanned<Block>) -> Result<()> {
+        self.lints.check_block(block, &self.output.types, self)
     }
 
     pub fn open_library(&mut self, name: Spanned<&[u8]>, opts: Rc<RefCell<Options>>) -> Result<()> {
@@ -573,6 +667,16 @@ impl Output {
         self.string_meta.clone()
     }
 
+    /// Returns the type of the value returned from the main chunk, if any.
+    pub fn exports(&self) -> Option<&Ty> {
+        self.exports.as_ref()
+    }
+
+    /// Returns the types exported from the main chunk (`--# type NAME = ...`).
+    pub fn exported_types(&self) -> &HashMap<Name, TypeDef> {
+        &self.exported_types
+    }
+
     // TODO if we've got a common crate for IDE support, this will be there
     pub fn get_available_fields<'a>(&'a self, ty: &Ty) -> Option<HashMap<Key, Slot>> {
         if let Some(mut ty) = self.resolve_exact_type(ty) {
@@ -607,7 +711,7 @@ impl Output {
 
             // otherwise it should be a record or similar
             match ty.get_tables() {
-                Some(&Tables::Fields(ref rvar)) => {
+                Some(&Tables::Fields(ref rvar, _)) => {
                     let mut fields = HashMap::new();
                     self.list_rvar_fields(rvar.clone(), &mut |k, v| -> result::Result<(), ()> {
                         fields.insert(k.clone(), v.clone());
@@ -658,6 +762,22 @@ impl<R: Report> Report for Context<R> {
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> {
         self.report.add_span(k, s, m)
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.report.max_kind()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.report.mark_once(key)
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.report.last_error_span()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.report.is_empty()
+    }
 }
 
 /// A per-file environment which depends to `Context`.
@@ -668,6 +788,12 @@ pub struct Env<'ctx, R: 'ctx> {
     scopes: Vec<Scope>,
     // separate from scoped types, `--# type` will set both
     exported_types: HashMap<Name, TypeDef>,
+    // top-level local variables declared with `--: module`, checked against the return type
+    module_decls: Vec<Spanned<Slot>>,
+    // set by `--# pragma strict-nil` for the remainder of the current file
+    strict_nil: bool,
+    // `--: once` slots that have been assigned (and turned into `const`) in the current branch
+    once_assigned: Vec<Slot>,
 }
 
 impl<'ctx, R: Report> Env<'ctx, R> {
@@ -675,7 +801,8 @@ impl<'ctx, R: Report> Env<'ctx, R> {
                map: ScopeMap<Name>) -> Env<'ctx, R> {
         let map_index = context.scope_maps.len();
         context.scope_maps.push(map);
-        let global_frame = Frame { vararg: None, returns: Returns::None };
+        let global_frame = Frame { vararg: None, returns: Returns::None, returned: None,
+                                  yielded: None, yields_unknown: false };
         Env {
             context: context,
             opts: opts,
@@ -683,6 +810,9 @@ impl<'ctx, R: Report> Env<'ctx, R> {
             // we have local variables even at the global position, so we need at least one Scope
             scopes: vec![Scope::new_function(global_frame)],
             exported_types: HashMap::new(),
+            module_decls: Vec::new(),
+            strict_nil: false,
+            once_assigned: Vec::new(),
         }
     }
 
@@ -708,8 +838,31 @@ impl<'ctx, R: Report> Env<'ctx, R> {
         x.display(&self.context.types)
     }
 
+    /// Asserts that `lhs` is a subtype of `rhs`, and reports an error at `span` otherwise.
+    ///
+    /// This is `Lattice::assert_sub_reporting` with the current type context and report.
+    pub fn assert_sub_reporting<'a, A, B, Msg>(&'a mut self, lhs: &A, rhs: &B, span: Span,
+                                               hint: TypeReportHint, msg: Msg) -> Result<bool>
+        where A: Lattice<B>, Msg: FnOnce(&'a TypeContext) -> Box<Localize + 'a>
+    {
+        let context = &mut *self.context;
+        lhs.assert_sub_reporting(rhs, span, hint, &mut context.output.types, &context.report, msg)
+    }
+
     pub fn id_from_nameref(&self, nameref: &Spanned<NameRef>) -> Spanned<Id> {
-        Id::from(self.map_index, nameref.base.clone()).with_loc(nameref)
+        self.resolve_id(&nameref.base).with_loc(nameref)
+    }
+
+    // same to `Id::from` but global names can be shadowed by names from `--# open`
+    fn resolve_id(&self, nameref: &NameRef) -> Id {
+        if let NameRef::Global(ref name) = *nameref {
+            for scope in self.scopes.iter().rev() {
+                if let Some(id) = scope.opened.get(name) {
+                    return id.clone();
+                }
+            }
+        }
+        Id::from(self.map_index, nameref.clone())
     }
 
     pub fn enter(&mut self, scope: Scope) {
@@ -738,6 +891,38 @@ impl<'ctx, R: Report> Env<'ctx, R> {
         self.context.resolve_exact_type(ty)
     }
 
+    /// Records a top-level local variable declared with `--: module`.
+    ///
+    /// The type returned from the chunk should be consistent to one of recorded types.
+    pub fn add_module_decl(&mut self, slot: Spanned<Slot>) {
+        self.module_decls.push(slot);
+    }
+
+    /// Returns true if `--# pragma strict-nil` is in effect,
+    /// in which case the implicitly nilable types cannot be indexed without `nil` checks.
+    pub fn strict_nil(&self) -> bool {
+        self.strict_nil
+    }
+
+    pub fn set_strict_nil(&mut self, strict_nil: bool) {
+        self.strict_nil = strict_nil;
+    }
+
+    /// Records the value returned from and the types exported from the chunk
+    /// as the exports of the output.
+    ///
+    /// Unlike `return_from_module`, this does not validate the returned type,
+    /// as it is for the main chunk which is not loaded by `require`.
+    pub fn record_exports(&mut self) {
+        let exports = match self.scopes[0].frame.as_ref().map(|frame| &frame.returns) {
+            Some(&Returns::Implicit(ref returns)) |
+            Some(&Returns::Explicit(ref returns)) => Some(returns.clone().into_first()),
+            _ => None,
+        };
+        self.context.exports = exports;
+        self.context.exported_types = self.exported_types.clone();
+    }
+
     pub fn return_from_module(mut self, modname: &[u8], diverging: bool,
                               span: Span) -> Result<Option<Module>> {
         // note that this scope is distinct from the global scope
@@ -759,6 +944,27 @@ impl<'ctx, R: Report> Env<'ctx, R> {
                     return Ok(None);
                 }
 
+                // the module should be consistent to at least one declared module type, if any
+                let module_decls = mem::replace(&mut self.module_decls, Vec::new());
+                if let Some(decl) = module_decls.first() {
+                    // probe without binding type variables, then commit to the chosen one
+                    let chosen = module_decls.iter().find(|decl| {
+                        self.types().subtype_of(&ty, &*decl.unlift())
+                    });
+                    let consistent = match chosen {
+                        Some(chosen) => ty.assert_sub(&*chosen.unlift(), self.types()).is_ok(),
+                        None => false,
+                    };
+                    if !consistent {
+                        self.error(decl, m::ModReturnsInconsistentType {
+                                       returns: self.display(&ty),
+                                       declared: self.display(&decl.base),
+                                   })
+                            .done()?;
+                        return Ok(None);
+                    }
+                }
+
                 // simulate `require` behavior, i.e. nil translates to true
                 let ty = if ty.nil() == Nil::Noisy {
                     let tywithoutnil = ty.without_nil().with_loc(span);
@@ -817,11 +1023,12 @@ impl<'ctx, R: Report> Env<'ctx, R> {
     }
 
     pub fn get_var<'a>(&'a self, nameref: &NameRef) -> Option<&'a NameDef> {
-        self.context.ids.get(&Id::from(self.map_index, nameref.clone()))
+        self.context.ids.get(&self.resolve_id(nameref))
     }
 
     pub fn get_var_mut<'a>(&'a mut self, nameref: &NameRef) -> Option<&'a mut NameDef> {
-        self.context.ids.get_mut(&Id::from(self.map_index, nameref.clone()))
+        let id = self.resolve_id(nameref);
+        self.context.ids.get_mut(&id)
     }
 
     pub fn get_frame<'a>(&'a self) -> &'a Frame {
@@ -883,15 +1090,30 @@ impl<'ctx, R: Report> Env<'ctx, R> {
 
     fn assign_(&mut self, lhs: &Spanned<Slot>, rhs: &Spanned<Slot>, init: bool) -> Result<()> {
         if self.assign_special(lhs, rhs)? {
+            let once = lhs.flex() == F::Once;
             if lhs.accept(rhs, self.types(), init).is_err() {
                 self.error(lhs, m::CannotAssign { lhs: self.display(lhs), rhs: self.display(rhs) })
                     .note_if(rhs, m::OtherTypeOrigin {})
                     .done()?;
+            } else if once {
+                self.once_assigned.push(lhs.base.clone());
             }
         }
         Ok(())
     }
 
+    /// Returns and clears the list of `--: once` slots assigned so far.
+    ///
+    /// The checker uses this to track the first assignments per each branch.
+    pub fn take_once_assigned(&mut self) -> Vec<Slot> {
+        mem::replace(&mut self.once_assigned, Vec::new())
+    }
+
+    /// Adds `--: once` slots to the list of slots assigned so far.
+    pub fn add_once_assigned(&mut self, slots: Vec<Slot>) {
+        self.once_assigned.extend(slots);
+    }
+
     /// Same to `Slot::accept` but also able to handle the built-in semantics;
     /// should be used for any kind of non-internal assignments.
     pub fn assign(&mut self, lhs: &Spanned<Slot>, rhs: &Spanned<Slot>) -> Result<()> {
@@ -1043,7 +1265,7 @@ impl<'ctx, R: Report> Env<'ctx, R> {
         self.context.name_class(cid, name.clone())?;
 
         let scope = match id.base {
-            Id::Local(..) => self.current_scope_mut(),
+            Id::Local(..) | Id::Opened(..) => self.current_scope_mut(),
             Id::Global(..) => self.global_scope_mut(),
         };
         let ret = scope.put_type(name, Ty::new(T::Class(Class::Instance(cid))));
@@ -1073,6 +1295,12 @@ impl<'ctx, R: Report> Env<'ctx, R> {
         }
 
         let slot = if let Some(initinfo) = initinfo {
+            if self.context.verify_annotations {
+                if let Some(SlotSpec::Explicit(ref spec)) = specinfo {
+                    self.context.annotations.push((spec.clone(), initinfo.clone()));
+                }
+            }
+
             let specinfo = self.assign_from_spec(&initinfo, specinfo.as_ref())?;
 
             // name the class if it is currently unnamed
@@ -1183,16 +1411,30 @@ impl<'ctx, R: Report> Env<'ctx, R> {
     /// Returns the resulting slot of that variable.
     /// The slot is referentially identical to what one will get from using it as an r-value.
     pub fn assume_var(&mut self, name: &Spanned<NameRef>, info: Spanned<Slot>) -> Result<Slot> {
-        let id = Id::from(self.map_index, name.base.clone());
+        let id = self.id_from_nameref(name);
+        self.assume_id(id, info)
+    }
+
+    /// Adds a name brought by `--# open` at `openspan` to the current scope.
+    /// Global names with the same name are shadowed until the end of the scope.
+    pub fn open_var(&mut self, name: Spanned<Name>, openspan: Span,
+                    info: Spanned<Slot>) -> Result<Slot> {
+        let id = Id::Opened(self.map_index, openspan, name.base.clone());
+        self.current_scope_mut().opened.insert(name.base.clone(), id.clone());
+        self.assume_id(id.with_loc(name), info)
+    }
+
+    fn assume_id(&mut self, id: Spanned<Id>, info: Spanned<Slot>) -> Result<Slot> {
         debug!("(force) adding a variable {} as {:?}", id.display(&self.context), info);
 
         self.assume_special(&info)?;
 
-        let varname = id.name(self.context).clone().with_loc(name);
+        let varname = id.name(self.context).clone().with_loc(&id);
         let info = info.base.set_display(DisplayName::Var(varname));
 
-        let mut def = self.context.ids.entry(id).or_insert_with(|| {
-            NameDef { span: name.span, slot: NameSlot::None }
+        let span = id.span;
+        let mut def = self.context.ids.entry(id.base).or_insert_with(|| {
+            NameDef { span: span, slot: NameSlot::None }
         });
         def.slot = NameSlot::Set(info.clone());
 
@@ -1396,6 +1638,22 @@ impl<'ctx, R: Report> Report for Env<'ctx, R> {
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> {
         self.context.report.add_span(k, s, m)
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.context.report.max_kind()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.context.report.mark_once(key)
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.context.report.last_error_span()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.context.report.is_empty()
+    }
 }
 
 impl<'ctx, R: Report> TypeResolver for Env<'ctx, R> {
@@ -1437,3 +1695,98 @@ fn test_context_is_send_and_sync() {
     _assert_sync(Context::new(NoReport));
 }
 
+#[test]
+fn test_verify_annotations() {
+    use options::CheckOptions;
+    use check_for_test;
+
+    let check = |code: &[u8], verify: bool| -> Vec<String> {
+        let (_, _, reports) = check_for_test(code, CheckOptions::default(), |context| {
+            context.set_verify_annotations(verify);
+        });
+        reports.into_iter().filter(|r| r.0 == Kind::Error).map(|r| r.2).collect()
+    };
+
+    assert_eq!(check(b"local a = 5 --: integer", true), Vec::<String>::new());
+
+    let errors = check(b"local a = 'x' --: integer", true);
+    assert_eq!(errors.len(), 2);
+    assert!(errors[1].starts_with("Inconsistent annotation"));
+
+    let errors = check(b"local a = 'x' --: integer", false);
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_strict_return_types() {
+    use options::CheckOptions;
+    use check_for_test;
+
+    let check = |code: &[u8], strict: bool| -> Vec<String> {
+        let checkopts = CheckOptions { strict_return_types: strict, ..CheckOptions::default() };
+        let (_, _, reports) = check_for_test(code, checkopts, |_| {});
+        reports.into_iter().filter(|r| r.0 == Kind::Error).map(|r| r.2).collect()
+    };
+
+    let widened = b"--v function(x: integer) --> number\n\
+                    local function f(x) return x * 2 end";
+    assert_eq!(check(widened, false), Vec::<String>::new());
+    let errors = check(widened, true);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("Given return type `(number)` is more general"));
+
+    // literals are widened, and every return is taken into account
+    let exact = b"--v function(x: boolean) --> integer?\n\
+                  local function f(x) if x then return 42 else return nil end end";
+    assert_eq!(check(exact, true), Vec::<String>::new());
+
+    // implicit return types are not affected
+    assert_eq!(check(b"local function f() return 42 end", true), Vec::<String>::new());
+
+    // the failure to collect actual return types is reported once, without further errors
+    let ununionable = b"--v function(x: boolean) --> any\n\
+                        local function f(x) if x then return {a = 1} else return {a = 'x'} end end";
+    assert_eq!(check(ununionable, false), Vec::<String>::new());
+    let errors = check(ununionable, true);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("Cannot collect the actual return types"));
+}
+
+#[test]
+fn test_max_type_complexity() {
+    use options::{CheckOptions, DEFAULT_MAX_TYPE_COMPLEXITY};
+    use check_for_test;
+
+    let check = |code: &[u8], limit: Option<usize>| -> Vec<(Kind, String)> {
+        let checkopts = CheckOptions { max_type_complexity: limit, ..CheckOptions::default() };
+        let (_, _, reports) = check_for_test(code, checkopts, |_| {});
+        reports.into_iter().map(|r| (r.0, r.2)).collect()
+    };
+
+    let nested = |depth: usize| -> Vec<u8> {
+        format!("local t = {}{}", "{".repeat(depth), "}".repeat(depth)).into_bytes()
+    };
+
+    assert_eq!(check(&nested(DEFAULT_MAX_TYPE_COMPLEXITY),
+                     Some(DEFAULT_MAX_TYPE_COMPLEXITY)), vec![]);
+    let reports = check(&nested(DEFAULT_MAX_TYPE_COMPLEXITY + 1),
+                        Some(DEFAULT_MAX_TYPE_COMPLEXITY));
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].0, Kind::Fatal);
+    assert!(reports[0].1.starts_with("The type of this expression is nested too deeply"));
+
+    // a smaller limit, or no limit at all
+    assert_eq!(check(b"local t = {{{}}}", Some(2)).len(), 1);
+    assert_eq!(check(&nested(DEFAULT_MAX_TYPE_COMPLEXITY + 1), None), vec![]);
+
+    // recursive references are not followed again
+    assert_eq!(check(b"local t = {}\nt.x = t\nt.x.x.y = 1", Some(3)), vec![]);
+
+    // shared records are visited once, otherwise this would take 2^64 steps
+    let mut shared = b"local t0 = {}".to_vec();
+    for i in 1..65 {
+        shared.extend(format!("\nlocal t{} = {{a = t{}, b = t{}}}", i, i - 1, i - 1).bytes());
+    }
+    assert_eq!(check(&shared, Some(DEFAULT_MAX_TYPE_COMPLEXITY)), vec![]);
+    assert_eq!(check(&shared, Some(64)).len(), 1);
+}
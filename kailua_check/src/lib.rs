@@ -1,6 +1,6 @@
 //! Type checker for Kailua.
 //!
-//! Type checking involves four types:
+//! Type checking involves five types:
 //!
 //! * `kailua_check::env::Context` is a global context for all checked files.
 //!   It also exposes an interface to the type environment, `kailua_types::env::Types`.
@@ -12,6 +12,9 @@
 //! * `kailua_check::options::Options` is a configurable portion of the type checker.
 //!   Currently it allows you to configure the `require` path and the actual loading process.
 //!
+//! * `kailua_check::lint::LintRegistry` in `Context` holds additional lint passes,
+//!   which are run over each chunk after the type checking.
+//!
 //! * `kailua_check::Checker` is the actual checker.
 //!   Due to the internal architecture, it also holds some side information
 //!   depending on the input chunk (and cannot be put to `Env` due to the lifetime mismatch).
@@ -38,8 +41,12 @@ pub use check::Checker;
 mod message;
 pub mod options;
 pub mod env;
+pub mod lint;
 mod defs;
 mod class_system;
+mod pattern;
+mod pack;
+pub mod signature;
 mod check;
 
 /// Options to populate the execution environment before checking.
@@ -67,9 +74,24 @@ pub fn check_from_chunk<R: Report>(
     chunk: kailua_syntax::Chunk,
     opts: Rc<RefCell<options::Options>>
 ) -> kailua_diag::Result<()> {
-    let mut env = env::Env::new(context, opts, chunk.map);
-    let mut checker = Checker::new(&mut env);
-    checker.visit(&chunk.block)
+    check_from_chunk_with_options(context, chunk, opts, options::CheckOptions::default())
+}
+
+/// Same to `check_from_chunk` but with options for the checking behavior.
+pub fn check_from_chunk_with_options<R: Report>(
+    context: &mut env::Context<R>,
+    chunk: kailua_syntax::Chunk,
+    opts: Rc<RefCell<options::Options>>,
+    checkopts: options::CheckOptions
+) -> kailua_diag::Result<()> {
+    context.ignore_spans(&chunk.ignored_lines);
+    {
+        let mut env = env::Env::new(context, opts, chunk.map);
+        Checker::with_options(&mut env, checkopts).visit(&chunk.block)?;
+        env.record_exports();
+    }
+    context.check_annotations()?;
+    context.run_lints(&chunk.block)
 }
 
 /// Same to `check_from_chunk` but with preloading.
@@ -79,19 +101,58 @@ pub fn check_from_chunk_with_preloading<R: Report>(
     opts: Rc<RefCell<options::Options>>,
     preload: &Preload
 ) -> kailua_diag::Result<()> {
+    context.ignore_spans(&chunk.ignored_lines);
+
     // preload `--# open`s into the context
     for name in &preload.open {
         context.open_library(name.as_ref().map(|n| &n[..]), opts.clone())?;
     }
 
-    let mut env = env::Env::new(context, opts, chunk.map);
-    let mut checker = Checker::new(&mut env);
+    {
+        let mut env = env::Env::new(context, opts, chunk.map);
+        {
+            let mut checker = Checker::new(&mut env);
 
-    // preload `require`s into the checker
-    for name in &preload.require {
-        checker.require(name.as_ref().map(|n| &n[..]), name.span)?;
-    }
+            // preload `require`s into the checker
+            for name in &preload.require {
+                checker.require(name.as_ref().map(|n| &n[..]), name.span)?;
+            }
 
-    checker.visit(&chunk.block)
+            checker.visit(&chunk.block)?;
+        }
+        env.record_exports();
+    }
+    context.check_annotations()?;
+    context.run_lints(&chunk.block)
 }
 
+/// Parses and checks given code for unit tests.
+///
+/// `setup` can configure the context (e.g. registering lints) before the checking.
+/// Returns the checking result, the output from the context and all collected reports.
+#[cfg(test)]
+fn check_for_test<F>(code: &[u8], checkopts: options::CheckOptions, setup: F)
+    -> (kailua_diag::Result<()>, env::Output, Vec<(kailua_diag::Kind, kailua_env::Span, String)>)
+    where F: FnOnce(&mut env::Context<Rc<kailua_diag::CollectedReport>>)
+{
+    use kailua_env::{Source, SourceFile};
+    use kailua_diag::{CollectedReport, Locale};
+    use kailua_syntax::parse_chunk;
+
+    struct NoOptions;
+    impl options::Options for NoOptions {}
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("<test>".to_string(), code.to_owned()));
+    let report = Rc::new(CollectedReport::new(Locale::dummy()));
+    let chunk = parse_chunk(&source, span, &*report).expect("parse error");
+    let (result, output) = {
+        let mut context = env::Context::new(report.clone());
+        setup(&mut context);
+        let opts = Rc::new(RefCell::new(NoOptions));
+        let result = check_from_chunk_with_options(&mut context, chunk, opts, checkopts);
+        (result, context.into_output())
+    };
+    let reports = Rc::try_unwrap(report).ok().unwrap().into_reports();
+    (result, output, reports)
+}
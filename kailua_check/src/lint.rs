@@ -0,0 +1,385 @@
+//! Extensible lint passes.
+//!
+//! A lint pass is an additional check run over the already type-checked chunk.
+//! Lints are registered to `LintRegistry` in the `Context`,
+//! so they apply to every chunk checked with that context (including `require`d ones).
+//!
+//! Each lint can be disabled or re-enabled for the remainder of the current file
+//! with `--# pragma disable lint-name` and `--# pragma enable lint-name`.
+
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use kailua_env::Spanned;
+use kailua_diag::{Result, Report, Reporter};
+use kailua_syntax::Name;
+use kailua_syntax::ast::{Ex, Exp, St, Stmt, Block, Var, Args, Table, NameRef};
+use kailua_types::env::Types;
+use message as m;
+
+/// An identifier for the lint pass, used by `--# pragma`.
+///
+/// By convention it is a list of lowercased words delimited by `-`, e.g. `unused-locals`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct LintId(Name);
+
+impl LintId {
+    pub fn name(&self) -> &Name {
+        &self.0
+    }
+}
+
+impl From<Name> for LintId {
+    fn from(name: Name) -> LintId { LintId(name) }
+}
+
+impl<'a> From<&'a str> for LintId {
+    fn from(name: &'a str) -> LintId { LintId(name.as_bytes().into()) }
+}
+
+impl fmt::Debug for LintId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+/// A lint pass.
+///
+/// Each hook is called for every expression or statement in the chunk, in the source order
+/// (a node is visited before its children). The type environment is fully populated by then.
+/// Both hooks do nothing by default.
+///
+/// Lints are kept in the `Context`, which should be `Send` and `Sync`.
+pub trait Lint: Send + Sync {
+    /// Returns the identifier for this lint, which should be unique in the registry.
+    fn id(&self) -> LintId;
+
+    /// Called for each expression.
+    fn check_exp(&self, _exp: &Spanned<Exp>, _types: &Types, _report: &Report) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for each statement.
+    fn check_stmt(&self, _stmt: &Spanned<Stmt>, _types: &Types, _report: &Report) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A list of registered lint passes.
+pub struct LintRegistry {
+    lints: Vec<Box<Lint>>,
+}
+
+impl LintRegistry {
+    pub fn new() -> LintRegistry {
+        LintRegistry { lints: Vec::new() }
+    }
+
+    /// Registers a new lint pass. Lints are run in the order of registration.
+    pub fn register(&mut self, lint: Box<Lint>) {
+        self.lints.push(lint);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lints.is_empty()
+    }
+
+    /// Runs all registered lint passes over given block.
+    ///
+    /// The block is assumed to be an entire chunk, so that all lints are enabled initially.
+    pub fn check_block(&self, block: &Spanned<Block>, types: &Types, report: &Report) -> Result<()> {
+        if self.lints.is_empty() {
+            return Ok(());
+        }
+
+        let walker = Walker {
+            lints: &self.lints,
+            ids: self.lints.iter().map(|lint| lint.id()).collect(),
+            pragmas: RefCell::new(HashMap::new()),
+            types: types,
+            report: report,
+        };
+        walker.walk_block(block)
+    }
+}
+
+impl Default for LintRegistry {
+    fn default() -> LintRegistry {
+        LintRegistry::new()
+    }
+}
+
+struct Walker<'a> {
+    lints: &'a [Box<Lint>],
+    ids: Vec<LintId>, // same order as `lints`
+    pragmas: RefCell<HashMap<LintId, bool>>, // true if enabled, missing ids are enabled
+    types: &'a Types,
+    report: &'a Report,
+}
+
+impl<'a> Walker<'a> {
+    fn is_enabled(&self, id: &LintId) -> bool {
+        self.pragmas.borrow().get(id).cloned().unwrap_or(true)
+    }
+
+    fn set_pragma(&self, enable: bool, name: &Spanned<Name>) -> Result<()> {
+        let id = LintId::from(name.base.clone());
+        if !self.ids.contains(&id) {
+            self.report.warn(name, m::UnknownLintInPragma { name: &name.base }).done()?;
+        }
+        self.pragmas.borrow_mut().insert(id, enable);
+        Ok(())
+    }
+
+    fn walk_block(&self, block: &Spanned<Block>) -> Result<()> {
+        for stmt in &block.base {
+            self.walk_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn walk_exps(&self, exps: &[Spanned<Exp>]) -> Result<()> {
+        for exp in exps {
+            self.walk_exp(exp)?;
+        }
+        Ok(())
+    }
+
+    fn walk_var(&self, var: &Spanned<Var>) -> Result<()> {
+        match var.base {
+            Var::Name(_) => Ok(()),
+            Var::Index(ref e, ref key) => { self.walk_exp(e)?; self.walk_exp(key) }
+            Var::IndexName(ref e, _) => self.walk_exp(e),
+        }
+    }
+
+    fn walk_table(&self, tab: &Table) -> Result<()> {
+        for &(ref key, ref value) in &tab.items {
+            if let Some(ref key) = *key {
+                self.walk_exp(key)?;
+            }
+            self.walk_exp(value)?;
+        }
+        Ok(())
+    }
+
+    fn walk_args(&self, args: &Spanned<Args>) -> Result<()> {
+        match args.base {
+            Args::List(ref args) => self.walk_exps(args),
+            Args::Str(_) => Ok(()),
+            Args::Table(ref tab) => self.walk_table(tab),
+        }
+    }
+
+    fn walk_stmt(&self, stmt: &Spanned<Stmt>) -> Result<()> {
+        for (lint, id) in self.lints.iter().zip(self.ids.iter()) {
+            if self.is_enabled(id) {
+                lint.check_stmt(stmt, self.types, self.report)?;
+            }
+        }
+
+        match *stmt.base {
+            St::Void(ref exp) => self.walk_exp(exp),
+
+            St::Assign(ref vars, ref exps) => {
+                for var in &vars.base {
+                    self.walk_var(&var.base)?;
+                }
+                if let Some(ref exps) = *exps {
+                    self.walk_exps(&exps.base)?;
+                }
+                Ok(())
+            }
+
+            St::Do(ref block) => self.walk_block(block),
+            St::While(ref cond, ref block) => { self.walk_exp(cond)?; self.walk_block(block) }
+            St::Repeat(ref block, ref cond) => { self.walk_block(block)?; self.walk_exp(cond) }
+
+            St::If(ref conds, ref lastblock) => {
+                for cond in conds {
+                    let (ref cond, ref block) = cond.base;
+                    self.walk_exp(cond)?;
+                    self.walk_block(block)?;
+                }
+                if let Some(ref block) = *lastblock {
+                    self.walk_block(block)?;
+                }
+                Ok(())
+            }
+
+            St::For(_, ref start, ref end, ref step, _, ref block) => {
+                self.walk_exp(start)?;
+                self.walk_exp(end)?;
+                if let Some(ref step) = *step {
+                    self.walk_exp(step)?;
+                }
+                self.walk_block(block)
+            }
+
+            St::ForIn(_, ref exps, _, ref block) => {
+                self.walk_exps(&exps.base)?;
+                self.walk_block(block)
+            }
+
+            St::FuncDecl(_, _, _, ref block, _) => self.walk_block(block),
+            St::MethodDecl(_, _, _, _, ref block) => self.walk_block(block),
+            St::Local(_, _, ref exps, _) => self.walk_exps(&exps.base),
+            St::Return(ref exps) => self.walk_exps(&exps.base),
+            St::KailuaPragma(enable, ref name) => self.set_pragma(enable, name),
+
+            St::Oops | St::Break | St::KailuaOpen(..) | St::KailuaType(..) |
+            St::KailuaAssume(..) | St::KailuaAssumeField(..) | St::KailuaAssumeMethod(..) |
+            St::KailuaClassSystem(..) | St::KailuaAssumeClass(..) |
+            St::KailuaPragmaOption(..) => Ok(()),
+        }
+    }
+
+    fn walk_exp(&self, exp: &Spanned<Exp>) -> Result<()> {
+        for (lint, id) in self.lints.iter().zip(self.ids.iter()) {
+            if self.is_enabled(id) {
+                lint.check_exp(exp, self.types, self.report)?;
+            }
+        }
+
+        match *exp.base {
+            Ex::Func(_, _, ref block) => self.walk_block(block),
+            Ex::Table(ref tab) => self.walk_table(tab),
+            Ex::Exp(ref e) => self.walk_exp(e),
+            Ex::FuncCall(ref func, ref args) => { self.walk_exp(func)?; self.walk_args(args) }
+            Ex::MethodCall(ref meth, ref args) => { self.walk_exp(&meth.0)?; self.walk_args(args) }
+            Ex::Index(ref e, ref key) => { self.walk_exp(e)?; self.walk_exp(key) }
+            Ex::IndexName(ref e, _) => self.walk_exp(e),
+            Ex::Un(_, ref e) => self.walk_exp(e),
+            Ex::Bin(ref lhs, _, ref rhs) => { self.walk_exp(lhs)?; self.walk_exp(rhs) }
+
+            Ex::Oops | Ex::Nil | Ex::False | Ex::True | Ex::Num(_) | Ex::Str(_) |
+            Ex::Varargs | Ex::Var(_) => Ok(()),
+        }
+    }
+}
+
+/// A lint pass that warns on `self` used outside of a method context.
+///
+/// `self` is implicitly declared only in methods (`function a:b() ... end`),
+/// so it resolves to a global variable in other functions unless an argument is named `self`.
+/// This is legal but usually a mistake. Registered to every `Context` by default.
+pub struct SelfOutsideMethod;
+
+impl SelfOutsideMethod {
+    fn check_name(&self, nameref: &Spanned<NameRef>, report: &Report) -> Result<()> {
+        if let NameRef::Global(ref name) = nameref.base {
+            if &name[..] == b"self" {
+                report.warn(nameref, m::SelfOutsideMethod {}).done()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Lint for SelfOutsideMethod {
+    fn id(&self) -> LintId {
+        LintId::from("self-outside-method")
+    }
+
+    fn check_exp(&self, exp: &Spanned<Exp>, _types: &Types, report: &Report) -> Result<()> {
+        if let Ex::Var(ref nameref) = *exp.base {
+            self.check_name(nameref, report)?;
+        }
+        Ok(())
+    }
+
+    fn check_stmt(&self, stmt: &Spanned<Stmt>, _types: &Types, report: &Report) -> Result<()> {
+        if let St::Assign(ref vars, _) = *stmt.base {
+            for var in &vars.base {
+                if let Var::Name(ref nameref) = var.base.base {
+                    self.check_name(nameref, report)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_lint_registry() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use kailua_diag::Reporter;
+    use options::CheckOptions;
+    use check_for_test;
+
+    struct CountCalls {
+        exps: Arc<AtomicUsize>,
+        stmts: Arc<AtomicUsize>,
+    }
+
+    impl Lint for CountCalls {
+        fn id(&self) -> LintId {
+            LintId::from("count-calls")
+        }
+
+        fn check_exp(&self, exp: &Spanned<Exp>, _types: &Types, report: &Report) -> Result<()> {
+            self.exps.fetch_add(1, Ordering::SeqCst);
+            if let Ex::Nil = *exp.base {
+                report.warn(exp.span, "nil found").done()?;
+            }
+            Ok(())
+        }
+
+        fn check_stmt(&self, _stmt: &Spanned<Stmt>, _types: &Types, _report: &Report) -> Result<()> {
+            self.stmts.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    let exps = Arc::new(AtomicUsize::new(0));
+    let stmts = Arc::new(AtomicUsize::new(0));
+    let lint = CountCalls { exps: exps.clone(), stmts: stmts.clone() };
+    let (result, _, reports) = check_for_test(b"local a = {nil}\nif a then a = 1 + 2 end",
+                                              CheckOptions::default(), |context| {
+        context.lints_mut().register(Box::new(lint));
+    });
+    result.unwrap();
+
+    assert_eq!(exps.load(Ordering::SeqCst), 6); // {nil}, nil, a, 1 + 2, 1, 2
+    assert_eq!(stmts.load(Ordering::SeqCst), 3); // local, if, assignment
+    assert_eq!(reports.iter().filter(|r| r.2 == "nil found").count(), 1);
+}
+
+#[test]
+fn test_lint_pragma() {
+    use options::CheckOptions;
+    use check_for_test;
+
+    struct NoNil;
+
+    impl Lint for NoNil {
+        fn id(&self) -> LintId {
+            LintId::from("no-nil")
+        }
+
+        fn check_exp(&self, exp: &Spanned<Exp>, _types: &Types, report: &Report) -> Result<()> {
+            if let Ex::Nil = *exp.base {
+                report.warn(exp.span, "nil found").done()?;
+            }
+            Ok(())
+        }
+    }
+
+    let code = b"\
+        local a = nil\n\
+        --# pragma disable no-nil\n\
+        local b = nil\n\
+        do\n\
+        --# pragma enable no-nil\n\
+        end\n\
+        local c = nil\n\
+        --# pragma disable no-such-lint\n";
+    let (result, _, reports) = check_for_test(code, CheckOptions::default(), |context| {
+        context.lints_mut().register(Box::new(NoNil));
+    });
+    result.unwrap();
+
+    assert_eq!(reports.iter().filter(|r| r.2 == "nil found").count(), 2); // a and c
+    assert_eq!(reports.iter().filter(|r| r.2.contains("no-such-lint")).count(), 1);
+}
@@ -208,11 +208,28 @@ define_msg! { pub IndexToNonTable<'a> { tab: Slot<'a> }:
     _    => "Tried to index a non-table type `{tab}`",
 }
 
+define_msg! { pub IndexToNumberHint:
+    "ko" => "숫자는 인덱싱할 수 없습니다. 문자열 메소드를 쓰려면 먼저 `tostring`으로 \
+             변환해야 합니다",
+    _    => "Numbers cannot be indexed; convert it with `tostring` first \
+             in order to use string methods",
+}
+
+define_msg! { pub IndexToMaybeStringHint:
+    "ko" => "문자열 메소드는 값이 확실히 문자열일 때만 쓸 수 있습니다",
+    _    => "String methods can be used only when the value is definitely a string",
+}
+
 define_msg! { pub IndexToInexactType<'a> { tab: Slot<'a> }:
     "ko" => "`{tab}` 타입은 테이블이긴 하지만 아직 덜 추론되었습니다",
     _    => "The type `{tab}` is tabular but not known enough to index",
 }
 
+define_msg! { pub IndexToInexactTypeHint:
+    "ko" => "이 값의 타입을 테이블 타입으로 명시해 주십시오",
+    _    => "Consider annotating this value with a table type",
+}
+
 define_msg! { pub IndexToUnknownClass<'a> { cls: Slot<'a> }:
     "ko" => "`{cls}` 타입이 정확히 하나의 클래스로 추론되지 않아 인덱싱할 수 없습니다",
     _    => "Cannot index `{cls}` that cannot be inferred to a single class",
@@ -271,11 +288,38 @@ define_msg! { pub NonNumericFor:
     _    => "`for` statement was given non-numeric type(s)",
 }
 
+define_msg! { pub AssignToForVar:
+    "ko" => "숫자 `for` 문의 반복 변수에 대입해도 반복 횟수에는 영향을 주지 않습니다",
+    _    => "Assigning to the numeric `for` loop variable has no effect on the loop counter",
+}
+
 define_msg! { pub NonFuncIterator<'a> { iter: Ty<'a> }:
     "ko" => "`for`-`in` 문에 주어진 반복자가 함수가 아닌 `{iter}` 타입을 반환했습니다",
     _    => "The iterator given to `for`-`in` statement returned a non-function type `{iter}`",
 }
 
+define_msg! { pub TableSortWithInvalidComparator<'a> { cmp: Ty<'a>, elem: Ty<'a> }:
+    "ko" => "`{cmp}` 타입의 비교 함수로는 `{elem}` 타입의 원소를 정렬할 수 없습니다",
+    _    => "The comparator of the type `{cmp}` cannot sort elements of the type `{elem}`",
+}
+
+define_msg! { pub TableMoveWithNonIntegerKeys<'a> { tab: Ty<'a> }:
+    "ko" => "`table.move`는 정수가 아닌 키를 가지는 `{tab}` 타입에서 원소를 옮길 수 없습니다",
+    _    => "`table.move` cannot move elements from the type `{tab}` with non-integer keys",
+}
+
+define_msg! { pub TableMoveWithIncompatibleTables<'a> { src: Ty<'a>, dest: Ty<'a> }:
+    "ko" => "`table.move`는 `{src}` 타입에서 `{dest}` 타입으로 원소를 옮길 수 없습니다",
+    _    => "`table.move` cannot move elements from the type `{src}` to the type `{dest}`",
+}
+
+define_msg! { pub TableMoveWithIncompatibleElements<'a> { src: Ty<'a>, dest: Ty<'a> }:
+    "ko" => "`table.move`는 `{src}` 타입의 원소를 `{dest}` 타입의 원소를 가지는 테이블로 \
+             옮길 수 없습니다",
+    _    => "`table.move` cannot move elements of the type `{src}` \
+             to a table with elements of the type `{dest}`",
+}
+
 define_msg! { pub BadFuncIterator<'a> { iter: Ty<'a> }:
     "ko" => "`for`-`in` 문에 주어진 반복자가 예상치 못한 `{iter}` 타입을 반환했습니다",
     _    => "The iterator given to `for`-`in` statement returned an unexpected type `{iter}`",
@@ -292,6 +336,19 @@ define_msg! { pub CannotReturn<'a> { returns: SpannedTySeq<'a>, ty: SpannedTySeq
              given return type `{returns}`",
 }
 
+define_msg! { pub CannotCollectReturnTypes:
+    "ko" => "반환 타입이 실제로 반환되는 타입과 같은지 확인하기 위해 \
+             반환되는 타입들을 합칠 수 없습니다",
+    _    => "Cannot collect the actual return types \
+             for checking if they are equal to the return type",
+}
+
+define_msg! { pub ReturnTypeTooGeneral<'a> { returns: SpannedTySeq<'a>, ty: SpannedTySeq<'a> }:
+    "ko" => "지정된 반환 타입 `{returns}`이(가) 실제로 반환되는 `{ty}`보다 더 일반적입니다",
+    _    => "Given return type `{returns}` is more general than \
+             the actual return type `{ty}`",
+}
+
 define_msg! { pub BadRecursiveCall:
     "ko" => "재귀호출되는 함수가 필요로 하는 타입과 실제 타입이 호환되지 않습니다",
     _    => "A required type and the actual type of the recursive function is not compatible",
@@ -303,8 +360,8 @@ define_msg! { pub BuiltinGivenLessArgs<'a> { name: &'a str, nargs: usize }:
 }
 
 define_msg! { pub CannotOpenLibrary:
-    "ko" => "`--# open` 명령에 주어진 내장 라이브러리 이름을 찾을 수 없습니다",
-    _    => "Cannot find the built-in library name given to `--# open` directive",
+    "ko" => "`--# open` 명령에 주어진 내장 라이브러리나 모듈 이름을 찾을 수 없습니다",
+    _    => "Cannot find the built-in library or module name given to `--# open` directive",
 }
 
 define_msg! { pub CannotResolveModName:
@@ -334,11 +391,23 @@ define_msg! { pub ModCannotReturnInexactType<'a> { returns: Ty<'a> }:
     _    => "The module has returned a type `{returns}` that is not yet fully resolved",
 }
 
+define_msg! { pub ModReturnsInconsistentType<'a> { returns: Ty<'a>, declared: Slot<'a> }:
+    "ko" => "모듈이 반환하는 타입 `{returns}`이(가) 여기에서 선언된 모듈 타입 \
+             `{declared}`의 서브타입이 아닙니다",
+    _    => "The module returns a type `{returns}` that is not a subtype of \
+             the module type `{declared}` declared here",
+}
+
 define_msg! { pub UnknownLiteralTypeName:
     "ko" => "리터럴이 `type`의 반환값으로 나올 수 있는 타입이 아닙니다",
     _    => "The literal cannot appear as a return type name for `type`",
 }
 
+define_msg! { pub UnknownLiteralMathTypeName:
+    "ko" => "리터럴이 `math.type`의 반환값으로 나올 수 없습니다",
+    _    => "The literal cannot appear as a return value of `math.type`",
+}
+
 define_msg! { pub DuplicateAttrInSig:
     "ko" => "이미 속성이 붙어 있는 함수 명세에 속성을 더 붙일 수 없습니다",
     _    => "Cannot add an attribute to a function specification with an existing attribute",
@@ -404,6 +473,16 @@ define_msg! { pub IfCaseWithFalsyCond:
     _    => "This condition always evaluates to a falsy value",
 }
 
+define_msg! { pub AlwaysFalseComparison<'a> { lhs: Ty<'a>, rhs: Ty<'a> }:
+    "ko" => "`{lhs}`와(과) `{rhs}`에 공통된 값이 없으므로 이 비교는 항상 거짓입니다",
+    _    => "This comparison is always false, because `{lhs}` and `{rhs}` have no common values",
+}
+
+define_msg! { pub AlwaysTrueComparison<'a> { lhs: Ty<'a>, rhs: Ty<'a> }:
+    "ko" => "`{lhs}`와(과) `{rhs}`에 공통된 값이 없으므로 이 비교는 항상 참입니다",
+    _    => "This comparison is always true, because `{lhs}` and `{rhs}` have no common values",
+}
+
 #[cfg(feature = "warn_on_dead_code")]
 define_msg! { pub DeadCode:
     "ko" => "이 코드는 실행되지 않을 것입니다",
@@ -662,3 +741,60 @@ define_msg! { pub ClassInheritFromDifferentClassSystem:
     _    => "The class cannot inherit from a class using a different class system",
 }
 
+define_msg! { pub MethodCalledWithDot<'a> { name: &'a Name }:
+    "ko" => "메소드 {name}가(이) `.`으로 호출되었습니다; `:`을 사용하려고 했습니까?",
+    _    => "Method {name} is called with `.`; did you mean to use `:`?",
+}
+
+define_msg! { pub UnknownLintInPragma<'a> { name: &'a Name }:
+    "ko" => "pragma에 지정된 {name} 린트가 존재하지 않습니다",
+    _    => "The lint {name} in the pragma does not exist",
+}
+
+define_msg! { pub UnknownPragmaOption<'a> { name: &'a Name }:
+    "ko" => "pragma에 지정된 {name} 옵션이 존재하지 않습니다",
+    _    => "The option {name} in the pragma does not exist",
+}
+
+define_msg! { pub IndexToNilInStrictNil<'a> { tab: Slot<'a> }:
+    "ko" => "`{tab}` 타입은 `nil`일 수 있으므로 `strict-nil` pragma 하에서는 \
+             `nil`인지 먼저 확인해야 인덱싱할 수 있습니다",
+    _    => "The type `{tab}` can be `nil` and should be checked for `nil` \
+             before indexing under the `strict-nil` pragma",
+}
+
+define_msg! { pub InconsistentAnnotation<'a> { spec: Ty<'a>, init: Ty<'a> }:
+    "ko" => "`{spec}` 타입 지정이 추론된 `{init}` 타입과 일치하지 않습니다",
+    _    => "Inconsistent annotation: the annotated type `{spec}` \
+             is not as general as the inferred type `{init}`",
+}
+
+define_msg! { pub SelfOutsideMethod:
+    "ko" => "메소드가 아닌 곳에서 `self`가 쓰였습니다; 함수를 `:`으로 선언하려고 했습니까?",
+    _    => "`self` is used outside of a method; did you mean to declare the function with `:`?",
+}
+
+define_msg! { pub CloseWithoutMetamethod<'a> { ty: Slot<'a> }:
+    "ko" => "`<close>` 속성이 붙은 변수는 `__close` 메타메소드가 있어야 하는데 \
+             `{ty}` 타입에는 없습니다",
+    _    => "A variable with the `<close>` attribute should have a `__close` metamethod, \
+             but the type `{ty}` does not have it",
+}
+
+define_msg! { pub LocalFuncRefNotInScope<'a> { name: &'a Name }:
+    "ko" => "{name}은(는) 아직 정의되지 않은 지역 함수를 가리키지 않습니다; \
+             재귀적으로 호출하려면 `local function {name:-}(...)`을 사용하십시오",
+    _    => "{name} does not refer to the local function being defined here; \
+             use `local function {name:-}(...)` for recursive references",
+}
+
+define_msg! { pub LocalFuncDefinedHere:
+    "ko" => "지역 함수는 여기서 정의되었습니다",
+    _    => "The local function is defined here",
+}
+
+define_msg! { pub TypeTooComplex { limit: usize }:
+    "ko" => "표현식의 타입이 너무 깊게 중첩되어 있어 ({limit}단계 초과) 검사를 중단합니다",
+    _    => "The type of this expression is nested too deeply (more than {limit} levels), \
+             stopping the checking",
+}
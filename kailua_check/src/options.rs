@@ -40,6 +40,34 @@ pub trait Options {
     }
 }
 
+/// Options for the type checking itself, as opposed to `Options` which connects
+/// the checker to the outside world.
+#[derive(Clone, Debug)]
+pub struct CheckOptions {
+    /// When enabled, explicit return types of every function should be equal to
+    /// the actual return types, so that unintentionally general return types are caught.
+    pub strict_return_types: bool,
+
+    /// The maximal structural depth of any expression type, or `None` for no limit.
+    ///
+    /// Recursive table types can make the display and comparison of types exponentially slow;
+    /// the checking is aborted with a fatal error when the limit is exceeded.
+    /// Defaults to `DEFAULT_MAX_TYPE_COMPLEXITY`.
+    pub max_type_complexity: Option<usize>,
+}
+
+/// The default value for `CheckOptions::max_type_complexity`.
+pub const DEFAULT_MAX_TYPE_COMPLEXITY: usize = 100;
+
+impl Default for CheckOptions {
+    fn default() -> CheckOptions {
+        CheckOptions {
+            strict_return_types: false,
+            max_type_complexity: Some(DEFAULT_MAX_TYPE_COMPLEXITY),
+        }
+    }
+}
+
 /// Checker options that are tailored to loading from the file system.
 ///
 /// Follows the same error conventions as `Options`.
@@ -0,0 +1,115 @@
+//! A minimal analyzer for Lua 5.3 pack formats, used to check `string.pack` and `string.unpack`.
+
+/// A value packed or unpacked by the format option.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PackValue {
+    /// `b`, `B`, `h`, `H`, `l`, `L`, `j`, `J`, `T`, `i[n]` and `I[n]`.
+    Integer,
+    /// `f`, `d` and `n`.
+    Number,
+    /// `s[n]`, `z` and `cn`.
+    String,
+}
+
+// the maximal size of integers in the format
+const MAX_INT_SIZE: u32 = 16;
+
+/// Returns a list of values packed or unpacked by the format, in order.
+///
+/// Returns `None` if the format is malformed, in which case Lua will raise an error at runtime.
+pub fn parse_format(format: &[u8]) -> Option<Vec<PackValue>> {
+    let mut values = Vec::new();
+
+    let mut i = 0;
+    while i < format.len() {
+        let opt = format[i];
+        i += 1;
+
+        match opt {
+            // sizes with a default and an upper limit
+            b'i' | b'I' | b's' | b'!' => {
+                let (size, next) = read_size(format, i);
+                i = next;
+                if let Some(size) = size {
+                    if size < 1 || size > MAX_INT_SIZE { return None; }
+                }
+            }
+            // a mandatory size
+            b'c' => {
+                let (size, next) = read_size(format, i);
+                i = next;
+                if size.is_none() { return None; }
+            }
+            // aligns to the next option, which should have a size
+            b'X' => {
+                match format.get(i) {
+                    Some(&b'b') | Some(&b'B') | Some(&b'h') | Some(&b'H') |
+                    Some(&b'l') | Some(&b'L') | Some(&b'j') | Some(&b'J') | Some(&b'T') |
+                    Some(&b'f') | Some(&b'd') | Some(&b'n') => { i += 1; }
+                    Some(&b'i') | Some(&b'I') | Some(&b'!') => {
+                        let (size, next) = read_size(format, i + 1);
+                        i = next;
+                        if let Some(size) = size {
+                            if size < 1 || size > MAX_INT_SIZE { return None; }
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            _ => {}
+        }
+
+        match opt {
+            b'b' | b'B' | b'h' | b'H' | b'l' | b'L' | b'j' | b'J' | b'T' | b'i' | b'I' => {
+                values.push(PackValue::Integer);
+            }
+            b'f' | b'd' | b'n' => {
+                values.push(PackValue::Number);
+            }
+            b's' | b'z' | b'c' => {
+                values.push(PackValue::String);
+            }
+            // no values
+            b'x' | b'X' | b' ' | b'<' | b'>' | b'=' | b'!' => {}
+            _ => return None,
+        }
+    }
+
+    Some(values)
+}
+
+// reads an optional size at `format[start..]`, returning the size and the index after it
+fn read_size(format: &[u8], start: usize) -> (Option<u32>, usize) {
+    let mut i = start;
+    let mut size: Option<u32> = None;
+    while let Some(&c) = format.get(i) {
+        if c < b'0' || c > b'9' { break; }
+        let digit = (c - b'0') as u32;
+        // saturates, so that an absurdly large size is rejected by the caller
+        size = Some(size.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        i += 1;
+    }
+    (size, i)
+}
+
+#[test]
+fn test_parse_format() {
+    use self::PackValue::{Integer as I, Number as N, String as S};
+
+    assert_eq!(parse_format(b""), Some(vec![]));
+    assert_eq!(parse_format(b">I4B"), Some(vec![I, I]));
+    assert_eq!(parse_format(b"<i8 d n f"), Some(vec![I, N, N, N]));
+    assert_eq!(parse_format(b"=!4hHlLjJT"), Some(vec![I, I, I, I, I, I, I]));
+    assert_eq!(parse_format(b"s4zc10"), Some(vec![S, S, S]));
+    assert_eq!(parse_format(b"bxXi4i"), Some(vec![I, I]));
+    assert_eq!(parse_format(b"i16s1"), Some(vec![I, S]));
+
+    assert_eq!(parse_format(b"i0"), None);
+    assert_eq!(parse_format(b"i17"), None);
+    assert_eq!(parse_format(b"s99999999999999"), None);
+    assert_eq!(parse_format(b"c"), None);
+    assert_eq!(parse_format(b"X"), None);
+    assert_eq!(parse_format(b"Xc1"), None);
+    assert_eq!(parse_format(b"Xz"), None);
+    assert_eq!(parse_format(b"q"), None);
+}
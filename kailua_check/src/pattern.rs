@@ -0,0 +1,107 @@
+//! A minimal analyzer for Lua patterns, used to infer the results of `string.find` and similar.
+
+/// A capture in the pattern.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Capture {
+    /// `(...)`, captures a substring.
+    String,
+    /// `()`, captures the current position (an integer).
+    Position,
+}
+
+/// Returns a list of captures in the pattern, in the order of their opening parentheses.
+///
+/// Returns `None` if the pattern is malformed, in which case Lua will raise an error at runtime.
+pub fn parse_captures(pattern: &[u8]) -> Option<Vec<Capture>> {
+    let mut captures = Vec::new();
+    let mut depth = 0;
+
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'%' => {
+                match pattern.get(i + 1) {
+                    // `%bxy` matches a balanced string delimited by `x` and `y`
+                    Some(&b'b') => {
+                        if i + 3 >= pattern.len() { return None; }
+                        i += 4;
+                    }
+                    // `%f[set]` is a frontier pattern
+                    Some(&b'f') => {
+                        if pattern.get(i + 2) != Some(&b'[') { return None; }
+                        i = skip_set(pattern, i + 2)?;
+                    }
+                    Some(_) => { i += 2; }
+                    None => return None,
+                }
+            }
+
+            b'[' => { i = skip_set(pattern, i)?; }
+
+            b'(' => {
+                if pattern.get(i + 1) == Some(&b')') {
+                    captures.push(Capture::Position);
+                    i += 2;
+                } else {
+                    captures.push(Capture::String);
+                    depth += 1;
+                    i += 1;
+                }
+            }
+
+            b')' => {
+                if depth == 0 { return None; }
+                depth -= 1;
+                i += 1;
+            }
+
+            _ => { i += 1; }
+        }
+    }
+
+    if depth > 0 { None } else { Some(captures) }
+}
+
+// skips a set `[...]` starting at `pattern[start]`, returning the index after the closing `]`
+fn skip_set(pattern: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if pattern.get(i) == Some(&b'^') { i += 1; }
+    // the first `]` in the set is a literal
+    if pattern.get(i) == Some(&b']') { i += 1; }
+
+    loop {
+        match pattern.get(i) {
+            Some(&b']') => return Some(i + 1),
+            Some(&b'%') => {
+                if i + 1 >= pattern.len() { return None; }
+                i += 2;
+            }
+            Some(_) => { i += 1; }
+            None => return None,
+        }
+    }
+}
+
+#[test]
+fn test_parse_captures() {
+    use self::Capture::{String as S, Position as P};
+
+    assert_eq!(parse_captures(b""), Some(vec![]));
+    assert_eq!(parse_captures(b"^%a+$"), Some(vec![]));
+    assert_eq!(parse_captures(b"(%a+)=(%d+)"), Some(vec![S, S]));
+    assert_eq!(parse_captures(b"()ll()"), Some(vec![P, P]));
+    assert_eq!(parse_captures(b"((a)(b))"), Some(vec![S, S, S]));
+    assert_eq!(parse_captures(b"%(%)"), Some(vec![]));
+    assert_eq!(parse_captures(b"[()]"), Some(vec![]));
+    assert_eq!(parse_captures(b"[]()]"), Some(vec![]));
+    assert_eq!(parse_captures(b"[^%]]+(.)"), Some(vec![S]));
+    assert_eq!(parse_captures(b"%b()(x)"), Some(vec![S]));
+    assert_eq!(parse_captures(b"%f[%w](%w+)"), Some(vec![S]));
+
+    assert_eq!(parse_captures(b"(a"), None);
+    assert_eq!(parse_captures(b"a)"), None);
+    assert_eq!(parse_captures(b"[a"), None);
+    assert_eq!(parse_captures(b"a%"), None);
+    assert_eq!(parse_captures(b"%b("), None);
+    assert_eq!(parse_captures(b"%fa"), None);
+}
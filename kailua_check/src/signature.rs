@@ -0,0 +1,276 @@
+//! Signature stubs (`.d.lua`) generated from the checked output.
+//!
+//! A stub consists of `--# type` statements for types exported from the main chunk,
+//! `--# assume global` statements for every global name defined by the checked sources,
+//! followed by the exported value of the main chunk if any,
+//! so that it can be checked (or `--# open`-ed) in place of the original sources.
+
+use std::io;
+use std::collections::HashSet;
+use kailua_diag::Locale;
+use kailua_types::ty::{Display, TypeContext, Dyn, T, Ty, TySeq, Slot, F, TVar, RVar};
+use kailua_types::ty::{Key, Tables, Functions, Unioned};
+use env::{Id, Output};
+
+/// Writes a signature stub for the types exported from the main chunk,
+/// the global names defined in the checked sources and the value returned from the main chunk.
+///
+/// Exported types are written in the order of definition, so that they can refer to each other.
+/// Global names are sorted for the stable output.
+/// Names that cannot be written without quotes are skipped,
+/// and types that cannot be written in the Kailua syntax are assumed to be `WHATEVER`.
+pub fn write_signature<W: io::Write>(output: &Output, mut w: W) -> io::Result<()> {
+    let ctx = output.types() as &TypeContext;
+
+    let mut types: Vec<_> = output.exported_types().iter().collect();
+    types.sort_by_key(|&(_, def)| def.span.begin());
+    for (name, def) in types {
+        if name.quote_required() {
+            continue;
+        }
+        if is_representable(&def.ty, ctx, &mut Seen::default()) {
+            let ty = def.ty.display(ctx).to_string_with_locale(Locale::dummy());
+            writeln!(w, "--# type {:-} = {}", name, ty)?;
+        } else {
+            writeln!(w, "--# type {:-} = WHATEVER", name)?;
+        }
+    }
+
+    let mut globals = Vec::new();
+    for (id, def) in output.all() {
+        let name = match *id {
+            Id::Global(ref name) => name,
+            Id::Local(..) | Id::Opened(..) => continue,
+        };
+
+        // built-in names (including those from `--# open`) have no source-dependent span
+        if !def.span.is_source_dependent() || name.quote_required() {
+            continue;
+        }
+
+        if let Some(slot) = def.slot.slot() {
+            // implicit literal types should be widened unless the name cannot be updated
+            let slot = match slot.flex() {
+                F::Unknown => continue,
+                F::Just | F::Var => slot.coerce(),
+                _ => slot.clone(),
+            };
+            globals.push((name, slot));
+        }
+    }
+    globals.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (name, slot) in globals {
+        if is_slot_representable(&slot, ctx, &mut Seen::default()) {
+            let ty = slot.display(ctx).to_string_with_locale(Locale::dummy());
+            writeln!(w, "--# assume global {:-}: {}", name, ty)?;
+        } else {
+            writeln!(w, "--# assume global {:-}: WHATEVER", name)?;
+        }
+    }
+
+    if let Some(exports) = output.exports() {
+        let exports = exports.clone().coerce();
+        if is_representable(&exports, ctx, &mut Seen::default()) {
+            let ty = exports.display(ctx).to_string_with_locale(Locale::dummy());
+            writeln!(w, "--# assume exports: {}", ty)?;
+        } else {
+            writeln!(w, "--# assume exports: WHATEVER")?;
+        }
+        writeln!(w, "return exports")?;
+    }
+    Ok(())
+}
+
+// type and row variables being visited, which are displayed as `<...>` when recursive
+#[derive(Default)]
+struct Seen {
+    tvars: HashSet<TVar>,
+    rvars: HashSet<RVar>,
+}
+
+// the display form mostly follows the Kailua syntax, but some types are displayed as
+// placeholders like `<error>` or `<unknown type>`, or as names not defined in the stub
+// (e.g. classes, which may also be disambiguated like `X#1`).
+fn is_representable(ty: &Ty, ctx: &TypeContext, seen: &mut Seen) -> bool {
+    match **ty {
+        T::Dynamic(Dyn::User) | T::All | T::None | T::Boolean | T::True | T::False |
+        T::Integer | T::Number | T::String | T::Thread | T::UserData |
+        T::Int(_) | T::Str(_) => true,
+        T::Dynamic(Dyn::Oops) | T::Class(_) => false,
+        T::TVar(tv) => {
+            if !seen.tvars.insert(tv) {
+                return false;
+            }
+            let ret = ctx.get_tvar_exact_type(tv).map_or(false, |ty| {
+                is_representable(&ty, ctx, seen)
+            });
+            seen.tvars.remove(&tv);
+            ret
+        }
+        T::Tables(ref tab) => is_tables_representable(tab, ctx, seen),
+        T::Functions(ref func) => is_functions_representable(func, ctx, seen),
+        T::Union(ref u) => is_unioned_representable(u, ctx, seen),
+    }
+}
+
+fn is_slot_representable(slot: &Slot, ctx: &TypeContext, seen: &mut Seen) -> bool {
+    match slot.flex() {
+        F::Unknown | F::Dynamic(Dyn::Oops) | F::Module => false,
+        F::Dynamic(Dyn::User) => true,
+        F::Just | F::Const | F::Var | F::Once => is_representable(&slot.unlift(), ctx, seen),
+    }
+}
+
+fn is_tables_representable(tab: &Tables, ctx: &TypeContext, seen: &mut Seen) -> bool {
+    match *tab {
+        Tables::All => true,
+        Tables::Array(ref v) => is_slot_representable(v, ctx, seen),
+        Tables::Map(ref k, ref v) => {
+            is_representable(k, ctx, seen) && is_slot_representable(v, ctx, seen)
+        }
+
+        // `vector<T> & {n: integer}` has no corresponding syntax
+        Tables::ArrayN(_) => false,
+
+        // the metatable is not displayed and would be lost
+        Tables::Fields(_, Some(_)) => false,
+
+        Tables::Fields(ref rvar, None) => {
+            if !seen.rvars.insert(rvar.clone()) {
+                return false;
+            }
+
+            // the fields should form either a tuple (consecutive integer keys from 1)
+            // or a record (names only); only the record can be extensible
+            let mut nints = 0;
+            let mut nstrs = 0;
+            let mut maxint = 0;
+            let mut ok = true;
+            let last = ctx.list_rvar_fields(rvar.clone(), &mut |k, v| {
+                match *k {
+                    Key::Int(i) => { nints += 1; maxint = maxint.max(i); }
+                    Key::Str(_) => { nstrs += 1; }
+                }
+                ok = ok && is_slot_representable(v, ctx, seen);
+                Ok(())
+            });
+            seen.rvars.remove(rvar);
+
+            let last = match last {
+                Ok(last) => last,
+                Err(()) => return false,
+            };
+            ok && match (nints, nstrs) {
+                (0, _) => true,
+                (_, 0) => maxint == nints && last == RVar::empty(),
+                (_, _) => false,
+            }
+        }
+    }
+}
+
+fn is_functions_representable(func: &Functions, ctx: &TypeContext,
+                              seen: &mut Seen) -> bool {
+    let is_seq_representable = |seq: &TySeq, seen: &mut Seen| {
+        seq.head.iter().all(|ty| is_representable(ty, ctx, seen)) &&
+            seq.tail.as_ref().map_or(true, |ty| is_representable(ty, ctx, seen))
+    };
+
+    match *func {
+        Functions::All => true,
+        Functions::Simple(ref f) => {
+            is_seq_representable(&f.args, seen) &&
+                f.returns.as_ref().map_or(true, |returns| is_seq_representable(returns, seen))
+        }
+    }
+}
+
+fn is_unioned_representable(u: &Unioned, ctx: &TypeContext, seen: &mut Seen) -> bool {
+    u.classes.is_empty() &&
+        u.tables.as_ref().map_or(true, |tab| is_tables_representable(tab, ctx, seen)) &&
+        u.functions.as_ref().map_or(true, |func| is_functions_representable(func, ctx, seen))
+}
+
+#[test]
+fn test_write_signature() {
+    use std::str;
+    use options::CheckOptions;
+    use check_for_test;
+
+    fn check(code: &str) -> Output {
+        let (result, output, _) = check_for_test(code.as_bytes(), CheckOptions::default(), |_| {});
+        result.expect("check error");
+        output
+    }
+
+    let output = check("--# open lua51\n\
+                        --v function(x: integer) --> string\n\
+                        function stringify(x) return tostring(x) end\n\
+                        --# assume global VERSION: const string\n\
+                        counter = 42\n\
+                        local hidden = true\n");
+    let mut stub = Vec::new();
+    write_signature(&output, &mut stub).unwrap();
+    let stub = str::from_utf8(&stub).unwrap();
+    assert_eq!(stub, "--# assume global VERSION: const string\n\
+                      --# assume global counter: integer\n\
+                      --# assume global stringify: function(x: integer) --> string\n");
+
+    // the stub itself should be a valid input reproducing the same signature
+    let output = check(stub);
+    let mut restub = Vec::new();
+    write_signature(&output, &mut restub).unwrap();
+    assert_eq!(str::from_utf8(&restub).unwrap(), stub);
+
+    // the exported value is written after globals
+    let output = check("--v function(x: integer) --> integer\n\
+                        local function inc(x) return x + 1 end\n\
+                        VERSION = 'a'\n\
+                        return { inc = inc, version = 1 }\n");
+    let mut stub = Vec::new();
+    write_signature(&output, &mut stub).unwrap();
+    let stub = str::from_utf8(&stub).unwrap();
+    assert_eq!(stub, "--# assume global VERSION: string\n\
+                      --# assume exports: {inc: function(x: integer) --> integer, \
+                                           version: 1, ...}\n\
+                      return exports\n");
+
+    let output = check(stub);
+    let mut restub = Vec::new();
+    write_signature(&output, &mut restub).unwrap();
+    assert_eq!(str::from_utf8(&restub).unwrap(), stub);
+
+    // exported types are written first in the order of definition
+    let output = check("--# type Vec = {x: number, y: number}\n\
+                        --# type Line = vector<Vec>\n\
+                        --# assume global origin: Vec\n\
+                        --v function(l: Line) --> number\n\
+                        function length(l) return #l end\n");
+    let mut stub = Vec::new();
+    write_signature(&output, &mut stub).unwrap();
+    let stub = str::from_utf8(&stub).unwrap();
+    assert_eq!(stub, "--# type Vec = {x: number, y: number}\n\
+                      --# type Line = vector<Vec>\n\
+                      --# assume global length: function(l: Line) --> number\n\
+                      --# assume global origin: Vec\n");
+
+    let output = check(stub);
+    let mut restub = Vec::new();
+    write_signature(&output, &mut restub).unwrap();
+    assert_eq!(str::from_utf8(&restub).unwrap(), stub);
+
+    // types that cannot be parsed back are written as `WHATEVER`
+    let output = check("--# open lua51\n\
+                        --# assume global x: any\n\
+                        --# assume global class Hello\n\
+                        --# assume global h: Hello\n\
+                        return setmetatable({}, {})\n");
+    let mut stub = Vec::new();
+    write_signature(&output, &mut stub).unwrap();
+    assert_eq!(str::from_utf8(&stub).unwrap(), "--# assume global Hello: WHATEVER\n\
+                                               --# assume global h: WHATEVER\n\
+                                               --# assume global x: any\n\
+                                               --# assume exports: WHATEVER\n\
+                                               return exports\n");
+}
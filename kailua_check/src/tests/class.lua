@@ -131,7 +131,7 @@ local xx = Hello.x --: integer --@< Error: Cannot index `<initializing> <prototy
 local yy = Hello.y --: string
 --! error
 
---8<-- assume-field-class-method
+--8<-- assume-field-class-method -- exact
 --# assume global class Hello
 --# assume Hello.f: method() --> string
 --# assume Hello.g: method(s: string, base: integer?) --> number
@@ -333,7 +333,7 @@ h:foo(3, 4, 5) --@< Error: The type `function(self: Hello, x: integer, y: intege
                --@^^ Note: The other type originates here
 --! error
 
---8<-- gideros-class-method-bad-arity-3
+--8<-- gideros-class-method-bad-arity-3 -- exact
 --# class system gideros
 --# assume `class`: [make_class(gideros)] function() --> table
 Hello = class()
@@ -348,6 +348,7 @@ local h = Hello.new()
 h.foo() --@< Error: The type `function(self: Hello, x: integer, y: integer) --> ()` cannot be called
         --@^ Cause: First function argument cannot be omitted because its type is `Hello`
         --@^^ Note: The other type originates here
+        --@^^^ Warning: Method `foo` is called with `.`; did you mean to use `:`?
 --! error
 
 --8<-- gideros-class-fields-after-ctor
@@ -759,7 +760,7 @@ local h = Hello.new() --: Hello
 
 --! error
 
---8<-- gideros-class-call-ctor
+--8<-- gideros-class-call-ctor -- exact
 --# class system gideros
 --# assume `class`: [make_class(gideros)] function() --> table
 Hello = class()
@@ -774,6 +775,28 @@ Hello.init(h) -- fine!
 
 --! error
 
+--8<-- gideros-class-call-parent-method -- exact
+--# class system gideros
+--# assume `class`: [make_class(gideros)] function() --> table
+A = class()
+B = class(A)
+
+--v method()
+function A:init() end
+
+--v method()
+function A:foo() end
+
+--v method()
+function B:init() A.init(self) end
+
+--v method()
+function B:bar() A.foo(self) end
+
+local b = B.new()
+b:bar()
+--! ok
+
 --8<-- gideros-class-overriding-ctor
 --# class system gideros
 --# assume `class`: [make_class(gideros)] function() --> table
@@ -182,3 +182,21 @@ end
 local y = x.x --@< Error: Missing key "x" in `{a: X, b: X#1, c: Y, d: X#2, e: Y#1, ...}`
 --! error
 
+--8<-- display-func-named-returns
+local f --: function() --> (x: integer, y: string)
+local g --: function() --> (x: integer)
+local a = f --: nil
+--@^ Error: Cannot assign `function() --> (x: integer, y: string)` into `nil`
+--@^^ Note: The other type originates here
+local b = g --: nil
+--@^ Error: Cannot assign `function() --> (x: integer)` into `nil`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- display-func-named-returns-sig
+--v function() --> (x: integer, y: string)
+local function f() return 1, 'a' end
+local a = f --: nil
+--@^ Error: Cannot assign `function() --> (x: integer, y: string)` into `nil`
+--@^^ Note: The other type originates here
+--! error
@@ -15,14 +15,21 @@ print('hello')
 print('hello') --@< Error: Global or local variable `print` is not defined
 --! error
 
--->8-- lua51-assert-truthy
+--8<-- lua51-assert-truthy
 --# open lua51
 --# assume p: integer?
 assert(p)
 print(p + 5)
 --! ok
 
--->8-- lua51-assert-disjunctive
+--8<-- lua51-assert-truthy-local
+--# open lua51
+local p = nil --: integer?
+assert(p)
+print(p + 5)
+--! ok
+
+--8<-- lua51-assert-disjunctive
 --# open lua51
 --# assume p: integer?
 --# assume q: integer?
@@ -31,7 +38,7 @@ print(p + 5) --@< Error: Cannot apply + operator to `integer?` and `5`
              --@^ Cause: `integer?` is not a subtype of `number`
 --! error
 
--->8-- lua51-assert-conjunctive
+--8<-- lua51-assert-conjunctive
 --# open lua51
 --# assume p: integer?
 --# assume q: integer?
@@ -39,7 +46,7 @@ assert(p and q)
 print(p + q)
 --! ok
 
--->8-- lua51-assert-conjunctive-partial-1
+--8<-- lua51-assert-conjunctive-partial-1
 --# open lua51
 --# assume p: integer?
 --# assume q: integer?
@@ -47,7 +54,7 @@ assert(p and not q)
 print(p + 5)
 --! ok
 
--->8-- lua51-assert-conjunctive-partial-2
+--8<-- lua51-assert-conjunctive-partial-2
 --# open lua51
 --# assume p: integer?
 --# assume q: integer?
@@ -56,7 +63,7 @@ print(q + 5) --@< Error: Cannot apply + operator to `nil` and `5`
              --@^ Cause: `nil` is not a subtype of `number`
 --! error
 
--->8-- lua51-assert-conjunctive-partial-dynamic
+--8<-- lua51-assert-conjunctive-partial-dynamic
 --# open lua51
 --# assume p: WHATEVER
 --# assume q: WHATEVER
@@ -65,33 +72,33 @@ print(p + 5)
 print(q + 5) -- should not alter dynamic types
 --! ok
 
--->8-- lua51-assert-number-type-1
+--8<-- lua51-assert-number-type-1
 --# open lua51
 --# assume p: integer|string
 assert(type(p) == 'number')
 print(p + 5)
 --! ok
 
--->8-- lua51-assert-number-type-2
+--8<-- lua51-assert-number-type-2
 --# open lua51
 --# assume p: integer|string
 assert('number' == type(p))
 print(p + 5)
 --! ok
 
--->8-- lua51-assert-integer-type
+--8<-- lua51-assert-integer-type
 --# open lua51
 --# assume p: integer|string
 assert(type(p) == 'integer') -- no such type in Lua 5.1
 --@^ Error: The literal cannot appear as a return type name for `type`
 --! error
 
--->8-- lua51-assert-same-type
+--8<-- lua51-assert-same-type
 --# open lua51
 assert(type(13) == type('string')) -- no-op
 --! ok
 
--->8-- lua51-assert-not-1
+--8<-- lua51-assert-not-1
 --# open lua51
 --# assume assert_not: const [assert_not] function(any)
 --# assume p: integer?
@@ -100,7 +107,7 @@ assert_not(p or not q) -- i.e. assert(not p and q)
 print(q + 5)
 --! ok
 
--->8-- lua51-assert-not-2
+--8<-- lua51-assert-not-2
 --# open lua51
 --# assume assert_not: const [assert_not] function(any)
 --# assume p: integer?
@@ -110,7 +117,7 @@ print(p + 5) --@< Error: Cannot apply + operator to `nil` and `5`
              --@^ Cause: `nil` is not a subtype of `number`
 --! error
 
--->8-- lua51-assert-type
+--8<-- lua51-assert-type
 --# open lua51
 --# assume assert_type: const [assert_type] function(any, string)
 --# assume p: integer|string
@@ -145,6 +152,26 @@ for x, y in ipairs(p) do
 end
 --! error
 
+--8<-- lua51-ipairs-string-array-3
+--# open lua51
+--# assume p: vector<string>
+for x, y in ipairs(p) do
+    local a = #x --@< Error: Cannot apply # operator to `integer`
+                 --@^ Cause: `integer` is not a subtype of `(string|table)`
+    local b = #y
+end
+--! error
+
+--8<-- lua51-ipairs-record-field
+--# open lua51
+--# assume p: {names: vector<string>}
+for x, y in ipairs(p.names) do
+    local a = x * 3
+    local b = y * 4 --@< Error: Cannot apply * operator to `string` and `4`
+                    --@^ Cause: `string` is not a subtype of `number`
+end
+--! error
+
 --8<-- lua51-ipairs-no-map
 --# open lua51
 --# assume p: map<integer, string>
@@ -347,6 +374,28 @@ local x = ('notice'):sub(1, 3) --: string
 local x = ('notice'):sub(10, 10) --: string
 --! ok
 
+--8<-- lua51-string-byte
+--# open lua51
+local a = string.byte('abc') --: integer
+local a, b, c = string.byte('abc', 1, -1) --: integer, integer, integer
+local t = {string.byte('abc', 1, -1)} --: vector<integer>
+--! ok
+
+--8<-- lua51-string-char
+--# open lua51
+local a, b = string.byte('ab', 1, 2)
+local x = string.char() --: string
+local y = string.char(a, b, 65) --: string
+--! ok
+
+--8<-- lua51-string-char-not-integer
+--# open lua51
+local x = string.char('x')
+--@^ Error: The type `function(integer...) --> string` cannot be called
+--@^^ Cause: First function argument `"x"` is not a subtype of `integer`
+--@^^^ Note: The other type originates here
+--! error
+
 --8<-- lua51-string-meta-extension
 --# open lua51
 
@@ -412,6 +461,47 @@ local n = table.maxn(x) --: integer
 local m = table.maxn(y) --: integer
 --! ok
 
+--8<-- lua51-table-sort
+--# open lua51
+local x = {3, 1, 2} --: vector<integer>
+table.sort(x)
+table.sort(x, nil)
+table.sort(x, function(a, b) return a > b end)
+--v function(a: number, b: number) --> boolean
+local function lt(a, b) return a < b end
+table.sort(x, lt)
+--! ok
+
+--8<-- lua51-table-sort-wrong-comparator
+--# open lua51
+local x = {3, 1, 2} --: vector<integer>
+--v function(a: string, b: string) --> boolean
+local function lt(a, b) return a < b end
+table.sort(x, lt)
+--@^ Error: The comparator of the type `function(a: string, b: string) --> boolean` cannot sort elements of the type `integer`
+--@^^ Cause: `function(a: string, b: string) --> boolean` is not a subtype of `function(integer, integer) --> boolean`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-table-sort-wrong-return
+--# open lua51
+local x = {'foo', 'bar'} --: vector<string>
+--v function(a: string, b: string) --> string
+local function cmp(a, b) return a end
+table.sort(x, cmp)
+--@^ Error: The type `[table_sort] function(table: vector<WHATEVER>, comp: function(WHATEVER, WHATEVER) --> boolean?) --> ()` cannot be called
+--@^^ Cause: Second function argument `function(a: string, b: string) --> string` is not a subtype of `function(WHATEVER, WHATEVER) --> boolean?`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-table-move
+--# open lua51
+-- `table.move` is only available in Lua 5.3 and later
+local a = {1, 2, 3} --: vector<integer>
+local x = table.move(a, 1, 3, 2)
+--@^ Error: Missing key "move" in `{concat: function(table: vector<const (number|string)>, sep: string?, i: integer?, j: integer?) --> string, insert: function(table: vector<WHATEVER>, value: any) --> (), maxn: function(table: vector<const any>) --> integer, remove: function(table: vector<WHATEVER>, pos: integer?) --> any, sort: [table_sort] function(table: vector<WHATEVER>, comp: function(WHATEVER, WHATEVER) --> boolean?) --> (), ...}`
+--! error
+
 --8<-- lua51-index-genv
 --# open lua51
 local x = _G.x --@< Error: Cannot index `[genv] table` without further type information; specify more detailed type, or use `--# assume` as a last resort
@@ -444,3 +534,466 @@ print(42) --@< Warning: This code will never execute
 math.randomseed(os.time())
 --! ok
 
+--8<-- lua51-math-max-integer
+--# open lua51
+local a = math.max(1, 2, 3) --: integer
+local b = math.min(4, 5) --: integer
+local c = math.abs(-6) --: integer
+--! ok
+
+--8<-- lua51-math-max-number
+--# open lua51
+local a = math.max(1, 2.5, 3) --: integer --@< Error: Cannot assign `number` into `integer`
+                                         --@^ Note: The other type originates here
+--! error
+
+--8<-- lua51-math-abs-number
+--# open lua51
+local a = math.abs(-6.5) --: integer --@< Error: Cannot assign `number` into `integer`
+                                     --@^ Note: The other type originates here
+--! error
+
+--8<-- lua51-math-max-dynamic
+--# open lua51
+--# assume p: WHATEVER
+local a = math.max(1, p) --: string
+--! ok
+
+--8<-- lua51-string-match-captures
+--# open lua51
+local s = 'key=42'
+local k, v = s:match('(%a+)=(%d+)') --: string, string
+local p, q = string.match(s, '()=()') --: integer, integer
+local all = s:match('%a+') --: string
+--! ok
+
+--8<-- lua51-string-match-position-not-string
+--# open lua51
+local p = ('key=42'):match('()=') --: string --@< Error: Cannot assign `integer` into `string`
+                                              --@^ Note: The other type originates here
+--! error
+
+--8<-- lua51-string-find-captures
+--# open lua51
+local s = 'key=42'
+local i, j, k = s:find('(%a+)=') --: integer, integer, string
+local x, y, z = s:find('()=') --: integer, integer, integer
+local a, b = s:find('=', 1, true) --: integer, integer
+--! ok
+
+--8<-- lua51-string-gmatch
+--# open lua51
+--# assume pat: string
+for k in ('a=1, b=2'):gmatch(pat) do
+    local kk = k --: string
+end
+--! ok
+
+--8<-- lua51-string-gmatch-captures
+--# open lua51
+for k, v in ('a=1, b=2'):gmatch('(%w+)=(%w+)') do
+    local kk = k --: string
+    local vv = v --: string
+end
+for p in ('a=1, b=2'):gmatch('()=') do
+    local pp = p --: integer
+end
+--! ok
+
+--8<-- lua51-string-match-dynamic-pattern
+--# open lua51
+--# assume pat: string
+local a, b, c = ('key=42'):match(pat) --: string, string, string
+--! ok
+
+--8<-- lua51-setmetatable-index-table
+--# open lua51
+local proto = { x = 42 }
+local t = setmetatable({ y = 'foo' }, { __index = proto })
+local a = t.x --: integer
+local b = t.y --: string
+--! ok
+
+--8<-- lua51-setmetatable-index-table-missing
+--# open lua51
+local proto = { x = 42 }
+local t = setmetatable({}, { __index = proto })
+local a = t.z --@< Error: Missing key "z" in `{...}`
+--! error
+
+--8<-- lua51-setmetatable-index-chain
+--# open lua51
+local base = { x = 42 }
+local derived = setmetatable({ y = 54 }, { __index = base })
+local t = setmetatable({}, { __index = derived })
+local a = t.x + t.y --: integer
+--! ok
+
+--8<-- lua51-setmetatable-index-func
+--# open lua51
+--v function(t: table, k: string) --> string
+local function index(t, k)
+    return k
+end
+local t = setmetatable({}, { __index = index })
+local a = t.whatever --: string
+--! ok
+
+--8<-- lua51-setmetatable-index-func-first-return
+--# open lua51
+--v function(t: table, k: string) --> (integer, string)
+local function index(t, k)
+    return 42, k
+end
+local t = setmetatable({ y = 'foo' }, { __index = index })
+local a = t.y --: string
+local b = t.z --: integer
+local c = rawget(t, 'z') --: nil
+--! ok
+
+--8<-- lua51-setmetatable-index-func-chain
+--# open lua51
+--v function(t: table, k: string) --> integer
+local function index(t, k)
+    return 42
+end
+local base = setmetatable({ y = 'foo' }, { __index = index })
+local t = setmetatable({}, { __index = base })
+local a = t.y --: string
+local b = t.z --: integer
+--! ok
+
+--8<-- lua51-setmetatable-call
+--# open lua51
+--v function(self: table, x: integer) --> integer
+local function call(self, x)
+    return x + 1
+end
+local t = setmetatable({}, { __call = call })
+local a = t(42) --: integer
+--! ok
+
+--8<-- lua51-setmetatable-call-bad-arg
+--# open lua51
+--v function(self: table, x: integer) --> integer
+local function call(self, x)
+    return x + 1
+end
+local t = setmetatable({}, { __call = call })
+local a = t('foo')
+--@^ Error: The type `function(self: table, x: integer) --> integer` cannot be called
+--@^^ Cause: Second function argument `"foo"` is not a subtype of `integer`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-getmetatable
+--# open lua51
+local mt = { __index = { x = 42 } }
+local t = setmetatable({}, mt)
+local a = getmetatable(t).__index.x --: integer
+--! ok
+
+--8<-- lua51-table-concat
+--# open lua51
+local a = {'a', 'b'} --: vector<string>
+local b = {1, 2} --: vector<integer>
+local x = table.concat(a, ',') --: string
+local y = table.concat(b) --: string
+local z = table.concat({'a', 3}, ',', 1, 2) --: string
+--! ok
+
+--8<-- lua51-table-concat-not-stringy
+--# open lua51
+local a = {true, false} --: vector<boolean>
+local x = table.concat(a)
+--@^ Error: The type `function(table: vector<const (number|string)>, sep: string?, i: integer?, j: integer?) --> string` cannot be called
+--@^^ Cause: First function argument `vector<boolean>` is not a subtype of `vector<const (number|string)>`
+--@^^^ Note: The other type originates here
+--@ Cause: `boolean` is not a subtype of `const (number|string)`
+--! error
+
+--8<-- lua51-table-concat-map
+--# open lua51
+--# assume m: map<string, string>
+local x = table.concat(m)
+--@^ Error: The type `function(table: vector<const (number|string)>, sep: string?, i: integer?, j: integer?) --> string` cannot be called
+--@^^ Cause: First function argument `map<string, string>` is not a subtype of `vector<const (number|string)>`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-os-time
+--# open lua51
+local a = os.clock() --: number
+local b = os.time() --: integer
+local c = os.time({year = 2017, month = 1, day = 1}) --: integer
+local d = os.difftime(b, c) --: number
+--! ok
+
+--8<-- lua51-os-time-not-integer
+--# open lua51
+local a = os.clock() --: integer
+--@^ Error: Cannot assign `number` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-os-getenv-nilable
+--# open lua51
+local a = os.getenv('HOME') --: string!
+--@^ Error: Cannot assign `string?` into `string!`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-os-exit-diverges -- feature:warn_on_dead_code
+--# open lua51
+os.exit(1)
+print(42) --@< Warning: This code will never execute
+--! ok
+
+--8<-- lua51-rawget
+--# open lua51
+local t = {x = 1, y = 'a'} --: {x: integer, y: string}
+local a = rawget(t, 'x') --: integer
+local b = rawget(t, 'y') --: string
+local v = {} --: vector<string>
+local c = rawget(v, 1) --: string
+--! ok
+
+--8<-- lua51-rawget-missing
+--# open lua51
+local t = {x = 1}
+local a = rawget(t, 'y') --: nil
+local b = rawget(t, 'x') --: integer?
+--! ok
+
+--8<-- lua51-rawget-ignores-metatable
+--# open lua51
+local t = setmetatable({}, {__index = {foo = 1}})
+local a = t.foo --: integer
+local b = rawget(t, 'foo') --: nil
+--! ok
+
+--8<-- lua51-rawget-dynamic
+--# open lua51
+local t --: WHATEVER
+local a = rawget(t, 1) --: integer
+--! ok
+
+--8<-- lua51-rawset
+--# open lua51
+local t = {x = 1} --: {x: integer}
+local u = rawset(t, 'x', 2) --: {x: integer}
+local v = {} --: vector<string>
+rawset(v, 1, 'foo')
+--! ok
+
+--8<-- lua51-rawset-wrong-type
+--# open lua51
+local v = {} --: vector<string>
+rawset(v, 1, 42) --@< Error: Cannot assign `42` into `string`
+                 --@^ Note: The other type originates here
+--! error
+
+--8<-- lua51-rawset-non-table
+--# open lua51
+rawset(42, 1, 2)
+--@^ Error: The type `[rawset] function(table: table, index: any, value: any) --> table` cannot be called
+--@^^ Cause: First function argument `42` is not a subtype of `table`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-rawequal
+--# open lua51
+local a = rawequal({}, 42) --: boolean
+--! ok
+
+--8<-- lua51-coroutine-wrap
+--# open lua51
+local gen = coroutine.wrap(function()
+    coroutine.yield('foo')
+    coroutine.yield('bar')
+end)
+local s = gen() --: string
+--! ok
+
+--8<-- lua51-coroutine-wrap-union
+--# open lua51
+local gen = coroutine.wrap(function()
+    coroutine.yield(1)
+    coroutine.yield('bar')
+end)
+local s = gen() --: integer|string
+--! ok
+
+--8<-- lua51-coroutine-wrap-wrong-type
+--# open lua51
+local gen = coroutine.wrap(function()
+    coroutine.yield(1)
+end)
+local s = gen() --: string
+--@^ Error: Cannot assign `integer?` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-coroutine-wrap-return
+--# open lua51
+local gen = coroutine.wrap(function()
+    coroutine.yield('foo')
+    return 42
+end)
+local s = gen() --: integer|string
+--! ok
+
+--8<-- lua51-coroutine-wrap-nested
+--# open lua51
+local gen = coroutine.wrap(function()
+    local function helper()
+        coroutine.yield('foo')
+    end
+    helper()
+    coroutine.yield(1)
+end)
+local s = gen() --: integer|string
+--! ok
+
+--8<-- lua51-coroutine-wrap-nested-wrong-type
+--# open lua51
+local gen = coroutine.wrap(function()
+    local function helper()
+        coroutine.yield('foo')
+    end
+    helper()
+end)
+local s = gen() --: integer
+--@^ Error: Cannot assign `string?` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-coroutine-wrap-non-literal
+--# open lua51
+local function f()
+    coroutine.yield(1)
+end
+local gen = coroutine.wrap(f)
+local s = gen() --: any
+--! ok
+
+--8<-- lua51-coroutine-wrap-outer-helper
+-- the helper defined outside of the body may yield anything
+--# open lua51
+local function helper()
+    coroutine.yield('foo')
+end
+local gen = coroutine.wrap(function()
+    helper()
+    coroutine.yield(1)
+end)
+local s = gen() --: integer
+--@^ Error: Cannot assign `any` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-coroutine-wrap-reassigned-helper
+--# open lua51
+--# assume other: function()
+local gen = coroutine.wrap(function()
+    local function helper()
+        coroutine.yield('foo')
+    end
+    helper = other
+    helper()
+end)
+local s = gen() --: string
+--@^ Error: Cannot assign `any` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-coroutine-wrap-iterator
+--# open lua51
+--# assume iter: function(any, any) --> integer?
+local gen = coroutine.wrap(function()
+    for i in iter do
+        coroutine.yield(i)
+    end
+end)
+local s = gen() --: integer
+--@^ Error: Cannot assign `any` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-coroutine-wrap-pairs
+--# open lua51
+local gen = coroutine.wrap(function()
+    for _, v in ipairs({'foo', 'bar'}) do
+        coroutine.yield(v)
+    end
+end)
+local s = gen() --: string?
+--! ok
+
+--8<-- lua51-io-read-formats
+--# open lua51
+local n, a, l, s = io.read('*n', '*a', '*l', 10)
+local n2 = n --: number?
+local a2 = a --: string
+local l2 = l --: string?
+local s2 = s --: string?
+local d = io.read() --: string?
+--! ok
+
+--8<-- lua51-io-read-wrong-type
+--# open lua51
+local a = io.read('*a') --: number
+--@^ Error: Cannot assign `string` into `number`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- lua51-io-read-non-literal
+--# open lua51
+local fmt = '*a' --: '*a'|'*l'
+local v = io.read(fmt)
+v = 42 -- WHATEVER
+--! ok
+
+--8<-- lua51-file-read
+--# open lua51
+local f = io.open('foo')
+local n, l = f:read('*n', '*l')
+local n2 = n --: number?
+local l2 = l --: string?
+--! ok
+
+--8<-- lua51-io-lines
+--# open lua51
+for l in io.lines('foo') do
+    local l2 = l --: string
+end
+local f = io.open('foo')
+for l in f:lines() do
+    local l2 = l --: string
+end
+--! ok
+
+--8<-- lua51-io-lines-no-formats
+--# open lua51
+-- Lua 5.1 does not accept formats to `lines`
+for n in io.lines('foo', '*n') do
+    --@^ Error: The type `function(filename: string?) --> function(nil, nil) --> string?` cannot be called
+    --@^^ Cause: Cannot give more than 1 argument(s) to the function
+    --@^^^ Note: The other type originates here
+end
+--! error
+
+--8<-- io-lines-formats
+--# assume lines: [io_lines] function(filename: string?, '*n'|'*a'|'*l'|integer...)
+--#                             --> function(nil, nil) --> string?
+for l in lines('foo') do
+    local l2 = l --: string
+end
+for n in lines('foo', '*n') do
+    local n2 = n --: number
+end
+for a, l in lines('foo', '*a', '*l') do
+    local a2 = a --: string
+    local l2 = l --: string?
+end
+--! ok
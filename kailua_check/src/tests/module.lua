@@ -635,3 +635,64 @@ function b.four() t.d = t.c end
 
 --! ok
 
+--8<-- module-return-consistent
+--# assume global `require`: [require] function(string) --> any
+local a = require 'a'
+a.foo(42)
+
+--& a
+local M = {} --: module
+
+--v function(n: integer)
+function M.foo(n) end
+
+return M
+
+--! ok
+
+--8<-- module-return-consistent-explicit
+--# assume global `require`: [require] function(string) --> any
+local a = require 'a'
+a.foo(42)
+
+--& a
+local M = {} --: module {...}
+
+--v function(n: integer)
+function M.foo(n) end
+
+local N = { foo = M.foo }
+return N
+
+--! ok
+
+--8<-- module-return-inconsistent
+--# assume global `require`: [require] function(string) --> any
+local a = require 'a'
+
+--& a
+local M = {} --: module
+--@^ Error: The module returns a type `42` that is not a subtype of the module type `{foo: function(n: integer) --> (), ...}` declared here
+
+--v function(n: integer)
+function M.foo(n) end
+
+return 42
+
+--! error
+
+--8<-- module-return-nested-module
+--# assume global `require`: [require] function(string) --> any
+local a = require 'a'
+
+--& a
+local function f()
+    local M = {} --: module
+    --v function(n: integer)
+    function M.foo(n) end
+    return M
+end
+
+return f()
+
+--! ok
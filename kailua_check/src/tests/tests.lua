@@ -54,6 +54,54 @@ p() --@< Error: The type `function(x: integer!) --> ()` cannot be called
     --@^^ Note: The other type originates here
 --! error
 
+--8<-- funccall-typarams-bound
+--# assume f: function<T where T: Stringy>(x: T) --> string
+local a = f(42) --: string
+local b = f('foo') --: string
+local c = f(true) --@< Error: The type `function<T: Stringy>(x: T) --> string` cannot be called
+                  --@^ Cause: First function argument `true` is not a subtype of `<unknown type>`
+                  --@^^ Note: The other type originates here
+--! error
+
+--8<-- funccall-typarams-per-call
+--# assume f: function<T>(x: T) --> T
+local a = f(42) --: integer
+local b = f('foo') --: string
+--! ok
+
+--8<-- funccall-typarams-per-call-mismatch
+--# assume f: function<T>(x: T) --> T
+local a = f(42) --: integer
+local b = f(true) --: string
+--@^ Error: Cannot assign `<unknown type>` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- funccall-typarams-bound-per-call
+--# assume f: function<T where T: Stringy>(x: T) --> T
+local a = f(42) --: integer
+local b = f('x') --: string
+--! ok
+
+--8<-- funccall-typarams-bound-per-call-mismatch
+--# assume f: function<T where T: Stringy>(x: T) --> T
+local a = f('x') --: string
+local b = f(42) --: string
+--@^ Error: Cannot assign `<unknown type>` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- funccall-typarams-display
+--# assume f: function<T where T: Stringy>(x: T) --> string
+local x = f + 1 --@< Error: Cannot apply + operator to `function<T: Stringy>(x: T) --> string` and `1`
+                --@^ Cause: `function<T: Stringy>(x: T) --> string` is not a subtype of `number`
+--! error
+
+--8<-- funccall-typarams-unknown-bound
+--# assume f: function<T: Whatever>(x: T)
+--@^ Error: Unknown bound `Whatever` for the type parameter
+--! error
+
 --8<-- funccall-var-outside-of-scope-1
 local c
 if c then
@@ -212,6 +260,38 @@ local x = f() --@< Error: Global or local variable `f` is not defined
 local y = 3 < x
 --! error
 
+--8<-- eq-integer-nil
+local a = 42 --: integer
+local b = a == nil
+local c = a ~= nil
+--! ok
+
+--8<-- eq-integer-no-nil
+local a = 42 --: integer!
+local b = a == nil --@< Warning: This comparison is always false, because `integer!` and `nil` have no common values
+local c = a ~= nil --@< Warning: This comparison is always true, because `integer!` and `nil` have no common values
+--! ok
+
+--8<-- eq-integer-string
+local a = 42 --: integer
+local b = 'foo' --: string
+if a == b then --@< Warning: This comparison is always false, because `integer` and `string` have no common values
+end
+--! ok
+
+--8<-- eq-integer-number
+local a = 42 --: integer
+local b = 42 --: number
+local c = a == b
+local d = b ~= 42
+--! ok
+
+--8<-- eq-dynamic
+--# assume a: WHATEVER
+local b = a == 'foo'
+local c = a ~= nil
+--! ok
+
 --8<-- unknown-type
 --# assume p: unknown_type --@< Error: Type `unknown_type` is not defined
 --! error
@@ -302,6 +382,38 @@ local x = {a = 42} --: {a: integer}?
 local p = x.a --@< Error: Tried to index a non-table type `{a: integer}?`
 --! error
 
+--8<-- index-rec-or-nil-and
+local x = {a = 42} --: {a: integer}?
+local p = x and x.a --: integer?
+local q = (x and x.a) or 0 --: integer
+--! ok
+
+--8<-- index-rec-or-nil-or
+local x = {a = 42} --: {a: integer}?
+local p = not x or x.a
+--! ok
+
+--8<-- index-rec-or-nil-and-restored
+local x = {a = 42} --: {a: integer}?
+local p = x and x.a
+local q = x.a --@< Error: Tried to index a non-table type `{a: integer}?`
+--! error
+
+--8<-- index-rec-or-nil-or-not-narrowed
+local x = {a = 42} --: {a: integer}?
+local p = x or x.a --@< Error: Tried to index a non-table type `nil`
+--! error
+
+--8<-- and-type-narrowing
+--# assume global `type`: [type] function(v: any) --> string
+--# assume x: integer | string
+local p = type(x) == 'number' and x + 1
+local q = type(x) == 'string' or x + 1
+local r = x + 1 --@< Error: Cannot apply + operator to `(integer|string)` and `1`
+                --@^ Cause: `(integer|string)` is not a subtype of `number`
+                --@ Cause: `string` is not a subtype of `number`
+--! error
+
 --8<-- index-table
 local x = {a = 'foo'} --: table
 local p = x.a --@< Error: Cannot index `table` without further type information; specify more detailed type, or use `--# assume` as a last resort
@@ -311,6 +423,7 @@ local p = x.a --@< Error: Cannot index `table` without further type information;
 --# open `internal kailua_test`
 local x = kailua_test.gen_tvar()
 local p = x.a --@< Error: The type `<unknown type>` is tabular but not known enough to index
+              --@^ Note: Consider annotating this value with a table type
 --! error
 
 --8<-- methodcall-empty
@@ -371,6 +484,24 @@ local p = x:hello() --@< Error: The type `function() --> ()` cannot be called
 local s = (42):char() --@< Error: Tried to index a non-table type `42`
 --! error
 
+--8<-- methodcall-integer-string-meta
+--# assume global string: [string_meta] { char: function(integer...) --> string }
+local s = (42):char() --@< Error: Tried to index a non-table type `42`
+                      --@^ Note: Numbers cannot be indexed; convert it with `tostring` first in order to use string methods
+--! error
+
+--8<-- methodcall-maybe-string
+--# assume global string: [string_meta] { upper: function(string) --> string }
+--# assume x: string | integer
+local s = x:upper() --@< Error: Tried to index a non-table type `(integer|string)`
+                    --@^ Note: String methods can be used only when the value is definitely a string
+--! error
+
+--8<-- methodcall-integer-non-string-method
+--# assume global string: [string_meta] { char: function(integer...) --> string }
+local s = (42):foo() --@< Error: Tried to index a non-table type `42`
+--! error
+
 --8<-- index-func
 local p = (function() end)[3] --@< Error: Tried to index a non-table type `function() --> ()`
 --! error
@@ -770,6 +901,27 @@ end
 local a = 3 + #'heck'
 --! ok
 
+--8<-- len-string-integer
+local n = #"hello" --: integer
+--! ok
+
+--8<-- len-table-integer
+local m = #{} --: integer
+--! ok
+
+--8<-- len-vector-integer
+local t = {} --: vector<string>
+local n = #t --: integer
+--! ok
+
+--8<-- len-dynamic-integer
+local t --: WHATEVER
+local n = #t --: integer
+local s = #t --: string
+--@^ Error: Cannot assign `integer` into `string`
+--@^^ Note: The other type originates here
+--! error
+
 --8<-- len-integer
 local a = 3 + #4 --@< Error: Cannot apply # operator to `4`
                  --@^ Cause: `4` is not a subtype of `(string|table)`
@@ -801,6 +953,40 @@ for i = 1, 9, 2.1 do
 end
 --! error
 
+--8<-- for-assign-to-var -- exact
+for i = 1, 9 do
+    i = i + 1 --@< Warning: Assigning to the numeric `for` loop variable has no effect on the loop counter
+end
+--! ok
+
+--8<-- for-assign-to-var-in-closure -- exact
+for i = 1, 9 do
+    local function f()
+        i = 42 --@< Warning: Assigning to the numeric `for` loop variable has no effect on the loop counter
+    end
+end
+--! ok
+
+--8<-- for-assign-to-shadowed-var -- exact
+for i = 1, 9 do
+    local i = i
+    i = i + 1
+end
+for j = 1, 9 do
+    for i = 1, 9 do end
+    local i = 42
+    i = 54
+end
+--! ok
+
+--8<-- for-in-assign-to-var -- exact
+--# open lua51
+--# assume t: {integer}
+for i, v in ipairs(t) do
+    v = 42
+end
+--! ok
+
 --8<-- for-diverges-1
 --# assume f: function() --> !
 for i = 1, f(), 2 do --@< Warning: A portion of this expression won't be evaluated because it contains a call to a function that never returns
@@ -832,6 +1018,30 @@ function f() --> !
 end --@< Error: Tried to return from a function that is marked that it never returns
 --! error
 
+--8<-- func-diverges-type-never-1
+function f() --> never
+    while true do end
+end
+--! ok
+
+--8<-- func-diverges-type-never-2
+--v function() --> never
+function f()
+end --@< Error: Tried to return from a function that is marked that it never returns
+--! error
+
+--8<-- func-diverges-type-never-call
+--v function(msg: string) --> never
+function fail(msg)
+    while true do end
+end
+--v function(x: integer) --> string
+function g(x)
+    if x > 0 then return 'positive' end
+    fail('not positive')
+end
+--! ok
+
 --8<-- func-diverges-chain-1
 --# assume f: function() --> !
 function g() --> !
@@ -1110,7 +1320,7 @@ p(function(x, y)
 end)
 --! ok
 
---8<-- methodcall-func-hint
+--8<-- methodcall-func-hint -- exact
 local tab = {}
 
 --v method(a: function(int, int) --> int)
@@ -1125,7 +1335,7 @@ tab:p(function(x, y)
 end)
 --! ok
 
---8<-- methodcall-func-hint-not-func -- feature:no_implicit_func_sig
+--8<-- methodcall-func-hint-not-func -- feature:no_implicit_func_sig exact
 local tab = {}
 
 --v method(a: string)
@@ -1165,7 +1375,7 @@ tab:p(function(x)
 end)
 --! error
 
---8<-- methodcall-func-hint-more-arity -- feature:no_implicit_func_sig
+--8<-- methodcall-func-hint-more-arity -- feature:no_implicit_func_sig exact
 local tab = {}
 
 --v method(a: function(integer, integer) --> integer)
@@ -1190,7 +1400,7 @@ tab:p(function(x, y, z)
 end)
 --! error
 
---8<-- methodcall-func-hint-varargs
+--8<-- methodcall-func-hint-varargs -- exact
 local tab = {}
 
 --v method(a: function(integer, integer, integer...) --> integer)
@@ -1205,7 +1415,7 @@ tab:p(function(x, y, ...)
 end)
 --! ok
 
---8<-- methodcall-func-hint-varargs-less-arity -- feature:no_implicit_func_sig
+--8<-- methodcall-func-hint-varargs-less-arity -- feature:no_implicit_func_sig exact
 local tab = {}
 
 --v method(a: function(integer, integer, integer...) --> integer)
@@ -1222,7 +1432,7 @@ tab:p(function(x, ...)
 end)
 --! error
 
---8<-- methodcall-func-hint-varargs-more-arity
+--8<-- methodcall-func-hint-varargs-more-arity -- exact
 local tab = {}
 
 --v method(a: function(integer, integer, integer...) --> integer)
@@ -1417,6 +1627,121 @@ local a = {} --: const map<number, number>
 a[1] = 42 --@< Error: Cannot update the immutable type `const map<number, number>` by indexing
 --! error
 
+--8<-- const-vector-covariant
+local a = {1, 2, 3} --: const vector<integer>
+local b = a --: const vector<number>
+--! ok
+
+--8<-- const-vector-covariant-not-contravariant
+local a = {1, 2, 3} --: const vector<number>
+local b = a --: const vector<integer>
+--@^ Error: Cannot assign `const vector<number>` into `const vector<integer>`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- const-vector-covariant-var
+local a = {1, 2, 3} --: vector<integer>
+local b = a --: vector<number>
+--@^ Error: Cannot assign `vector<integer>` into `vector<number>`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- const-vector-covariant-from-var
+local a = {1, 2, 3} --: vector<integer>
+local b = a --: const vector<number>
+--! ok
+
+--8<-- const-vector-covariant-nested
+local a = {{1}} --: vector<vector<integer>>
+local b = a --: const vector<vector<number>>
+--@^ Error: Cannot assign `vector<vector<integer>>` into `const vector<vector<number>>`
+--@^^ Note: The other type originates here
+local c = b[1]
+c[1] = 1.5
+local d = a[1][1] --: integer
+--! error
+
+--8<-- const-vector-covariant-nested-const
+local a = {{1}} --: vector<const vector<integer>>
+local b = a --: const vector<const vector<number>>
+--! ok
+
+--8<-- const-vector-covariant-map
+local a = {} --: const map<integer, integer>
+local b = a --: const map<integer, number>
+--@^ Error: Cannot assign `const map<integer, integer>` into `const map<integer, number>`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- var-explicit
+local a = 3 --: var integer
+a = 4
+a = 'x' --@< Error: Cannot assign `"x"` into `integer`
+        --@^ Note: The other type originates here
+--! error
+
+--8<-- var-explicit-no-kind
+local a = 3 --: var
+a = 4
+a = 'x' --@< Error: Cannot assign `"x"` into `integer`
+        --@^ Note: The other type originates here
+--! error
+
+--8<-- once-init
+local a = 3 --: once integer
+local b = a + 1 --: integer
+a = 4 --@< Error: Cannot assign `4` into `const integer`
+      --@^ Note: The other type originates here
+--! error
+
+--8<-- once-deferred
+--# assume f: boolean
+local a --: once integer
+if f then a = 1 end
+a = 2 --@< Error: Cannot assign `2` into `const integer`
+      --@^ Note: The other type originates here
+--! error
+
+--8<-- once-if-else -- exact
+--# assume f: boolean
+local a --: once integer
+if f then a = 1 else a = 2 end
+local b = a + 1 --: integer
+--! ok
+
+--8<-- once-if-elseif-after
+--# assume f: boolean
+--# assume g: boolean
+local a --: once integer
+if f then a = 1 elseif g then a = 2 else a = 3 end
+a = 4 --@< Error: Cannot assign `4` into `const integer`
+      --@^ Note: The other type originates here
+--! error
+
+--8<-- once-if-twice-in-branch
+--# assume f: boolean
+local a --: once integer
+if f then
+    a = 1
+    a = 2 --@< Error: Cannot assign `2` into `const integer`
+          --@^ Note: The other type originates here
+else
+    a = 3
+end
+--! error
+
+--8<-- once-deferred-ok
+local a --: once string
+a = 'foo'
+local b = a .. 'bar' --: string
+--! ok
+
+--8<-- once-type-mismatch
+local a --: once integer
+a = 'foo' --@< Error: Cannot assign `"foo"` into `once integer`
+          --@^ Note: The other type originates here
+--! error
+
 --8<-- var-any-update
 local a --: any
 a = {}
@@ -1667,6 +1992,22 @@ local a, b = p() --: integer, integer
 --@^^ Note: The other type originates here
 --! error
 
+--8<-- assign-named-types
+--v a, b: integer, string
+a, b = 1, 'x'
+local c = a --: integer
+local d = b --: string
+--! ok
+
+--8<-- assign-named-types-error
+--v a, b: integer, string
+a, b = 'x', 1
+--@^^ Error: Cannot assign `"x"` into `integer`
+--@^^ Note: The other type originates here
+--@^^^^ Error: Cannot assign `1` into `string`
+--@^^^^ Note: The other type originates here
+--! error
+
 --8<-- assign-from-seq-with-nil-1
 local function p()
     return 3, 4
@@ -1821,7 +2162,7 @@ local q = p * 3
 --! error
 
 --8<-- invalid-open
---# open `internal kailua_dummy` --@< Error: Cannot find the built-in library name given to `--# open` directive
+--# open `internal kailua_dummy` --@< Error: Cannot find the built-in library or module name given to `--# open` directive
 --! error
 
 --8<-- duplicate-open
@@ -1829,6 +2170,87 @@ local q = p * 3
 --# open `internal kailua_test`
 --! ok
 
+--8<-- open-module
+--# open a
+local x = foo + 1 --: integer
+local y = bar('hello') --: string
+local z = baz --: string --@< Error: Cannot assign `integer` into `string`
+                         --@^ Note: The other type originates here
+
+--& a
+local M = {}
+M.foo = 42
+M.baz = 54
+--v function(s: string) --> string
+function M.bar(s) return s .. '!' end
+return M
+
+--! error
+
+--8<-- open-module-types
+--# open a
+local x = 42 --: Int
+
+--& a
+--# type Int = integer
+return {}
+
+--! ok
+
+--8<-- open-module-non-table
+--# open a
+local x = a --@< Error: Global or local variable `a` is not defined
+
+--& a
+return 42
+
+--! error
+
+--8<-- open-module-and-require
+--# assume global `require`: [require] function(string) --> any
+--# open a
+local M = require 'a'
+local x = M.foo + foo --: integer
+
+--& a
+return { foo = 42 }
+
+--! ok
+
+--8<-- open-module-not-global
+--# assume global `require`: [require] function(string) --> any
+--# open a
+local x = foo --: integer
+require 'b'
+
+--& a
+return { foo = 42 }
+
+--& b
+local y = foo --@< Error: Global or local variable `foo` is not defined
+
+--! error
+
+--8<-- open-module-scoped
+do
+    --# open a
+    local x = foo --: integer
+end
+local y = foo --@< Error: Global or local variable `foo` is not defined
+
+--& a
+return { foo = 42 }
+
+--! error
+
+--8<-- open-module-unknown
+--# open b --@< Error: Cannot find the built-in library or module name given to `--# open` directive
+
+--& a
+return { foo = 42 }
+
+--! error
+
 --8<-- assign-identical
 --# assume x: WHATEVER
 x = x
@@ -1854,6 +2276,26 @@ if x then x = 42 end
 while x do x = 42 end
 --! ok
 
+--8<-- repeat-until-local
+local x = 0
+repeat x = x + 1 until x == 10
+--! ok
+
+--8<-- repeat-until-local-in-block
+--# assume f: function() --> integer
+repeat
+    local y = f()
+until y >= 10
+--! ok
+
+--8<-- repeat-until-local-out-of-block
+--# assume f: function() --> integer
+repeat
+    local y = f()
+until true
+local z = y --@< Error: Global or local variable `y` is not defined
+--! error
+
 --8<-- for-assign-identical
 --# assume x: WHATEVER
 for i = 1, x do x = 42 end
@@ -2162,6 +2604,93 @@ return 42
 
 --! ok
 
+--8<-- require-global-var
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+local x = foo --: integer
+
+--& a
+foo = 42 --: integer
+
+--! ok
+
+--8<-- require-global-var-type
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+foo = 'string' --@< Error: Cannot assign `"string"` into `integer`
+               --@^ Note: The other type originates here
+
+--& a
+foo = 42 --: integer
+
+--! error
+
+--8<-- require-global-var-before-require
+--# assume global `require`: [require] function(string) --> any
+local x = foo --@< Error: Global or local variable `foo` is not defined
+require 'a'
+
+--& a
+foo = 42 --: integer
+
+--! error
+
+--8<-- require-global-var-across-modules
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+require 'b'
+
+--& a
+foo = 42 --: integer
+
+--& b
+local x = foo + 1 --: integer
+
+--! ok
+
+--8<-- require-assume-global
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+local x = baz --: string
+
+--& a
+--# assume global baz: string
+
+--! ok
+
+--8<-- require-assume-local-no-leak
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+local x = baz --@< Error: Global or local variable `baz` is not defined
+
+--& a
+--# assume baz: string
+
+--! error
+
+--8<-- require-local-var-no-leak
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+local x = bar --@< Error: Global or local variable `bar` is not defined
+
+--& a
+local bar = 42 --: integer
+
+--! error
+
+--8<-- require-local-var-no-leak-across-modules
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+require 'b'
+
+--& a
+local bar = 42 --: integer
+
+--& b
+local x = bar --@< Error: Global or local variable `bar` is not defined
+
+--! error
+
 --8<-- require-type-local
 --# assume global `require`: [require] function(string) --> any
 local x = require('x')
@@ -2723,6 +3252,117 @@ function p.a:b() end
 p.a:b()
 --! ok
 
+--8<-- method-decl-self-called-with-dot -- exact
+local p = {}
+function p:a() end
+local q = p
+p.a(q) --@< Warning: Method `a` is called with `.`; did you mean to use `:`?
+--! ok
+
+--8<-- method-decl-self-called-with-dot-receiver -- exact
+local p = {}
+function p:a() end
+p.a(p)
+--! ok
+
+--8<-- method-decl-self-called-with-dot-explicit-self -- exact
+local p = {}
+function p:a() end
+function p:b() p.a(self) end
+p:b()
+--! ok
+
+--8<-- method-decl-no-self-called-with-dot -- exact
+local p = {}
+--v function(x: table)
+function p.a(x) end
+p.a(p)
+--! ok
+
+--8<-- self-outside-method
+--# assume global self: table
+local p = {}
+--v function() --> table
+function p.a()
+    return self --@< Warning: `self` is used outside of a method; did you mean to declare the function with `:`?
+end
+--! ok
+
+--8<-- self-outside-method-assign
+--# assume global self: table
+self = {} --@< Warning: `self` is used outside of a method; did you mean to declare the function with `:`?
+--! ok
+
+--8<-- self-inside-method -- exact
+local p = {}
+function p:a() return self end
+--! ok
+
+--8<-- self-as-first-arg -- exact
+local p = {}
+--v function(self: table) --> table
+function p.a(self) return self end
+--! ok
+
+--8<-- self-outside-method-pragma -- exact
+--# assume global self: table
+--# pragma disable self-outside-method
+local x = self
+--! ok
+
+--8<-- pragma-strict-nil -- exact
+--# assume x: {a: integer}
+local p = x.a
+--# pragma strict-nil
+local q = x.a --@< Error: The type `{a: integer}` can be `nil` and should be checked for `nil` before indexing under the `strict-nil` pragma
+--! error
+
+--8<-- pragma-strict-nil-method -- exact
+--# pragma strict-nil
+--# assume x: {f: function(any)}
+x:f() --@< Error: The type `{f: function(any) --> ()}` can be `nil` and should be checked for `nil` before indexing under the `strict-nil` pragma
+--! error
+
+--8<-- pragma-strict-nil-noisy-nil -- exact
+--# pragma strict-nil
+--# assume x: {a: integer}?
+local p = x.a --@< Error: Tried to index a non-table type `{a: integer}?`
+--! error
+
+--8<-- pragma-strict-nil-non-nil -- exact
+--# pragma strict-nil
+--# assume x: {a: integer}!
+local p = x.a
+local y = {a = 42} --: {a: integer}!
+local q = y.a
+--! ok
+
+--8<-- pragma-strict-nil-assert -- exact
+--# pragma strict-nil
+--# assume global assert: [assert] function(v: any, message: string?)
+--# assume x: {a: integer}
+assert(x)
+local p = x.a
+--! ok
+
+--8<-- pragma-strict-nil-and-or -- exact
+--# pragma strict-nil
+--# assume x: {a: integer}
+local p = x and x.a
+local q = not x or x.a
+local r = x.a --@< Error: The type `{a: integer}` can be `nil` and should be checked for `nil` before indexing under the `strict-nil` pragma
+--! error
+
+--8<-- pragma-strict-nil-string-literal -- exact
+--# pragma strict-nil
+--# assume global string: [string_meta] { upper: function(string) --> string }
+local s = ('foo'):upper()
+--! ok
+
+--8<-- pragma-unknown-option
+--# pragma strict-everything --@< Warning: The option `strict-everything` in the pragma does not exist
+--! ok
+
 --8<-- method-decl-nontable
 local p = 42
 function p.a() end --@< Error: Tried to index a non-table type `integer`
@@ -2914,6 +3554,50 @@ do
 end
 --! ok
 
+--8<-- local-func-literal-recursive
+local f = function() --@< Note: The local function is defined here
+    f()
+    --@^ Warning: `f` does not refer to the local function being defined here; use `local function f(...)` for recursive references
+    --@^^ Error: Global or local variable `f` is not defined
+    f() -- warned only once
+    --@^ Error: Global or local variable `f` is not defined
+end
+--! error
+
+--8<-- local-func-literal-recursive-global
+function f() end
+local f = function() --@< Note: The local function is defined here
+    f()
+    --@^ Warning: `f` does not refer to the local function being defined here; use `local function f(...)` for recursive references
+end
+f()
+--! ok
+
+--8<-- local-func-literal-recursive-outer-local
+local f = 42
+local f = function() --@< Note: The local function is defined here
+    return f
+    --@^ Warning: `f` does not refer to the local function being defined here; use `local function f(...)` for recursive references
+end
+--! ok
+
+--8<-- local-func-literal-shadowed-param
+local f = function(f) --: integer
+    return f
+end
+--! ok
+
+--8<-- local-func-literal-shadowed-local
+local g = function() local g = 5 return g end
+--! ok
+
+--8<-- local-func-literal-non-recursive
+local g = function() end
+local h = function() g() end
+local f --: function?
+f = function() return f end
+--! ok
+
 --8<-- void-arbitrary
 x = 42
 y = "foo"
@@ -3208,6 +3892,78 @@ local x = nil --: thread
 local a = {[x] = 2, [f()] = x, f()} --: table
 --! ok
 
+--8<-- table-lit-infer-record
+local a = {x = 1, y = 'hello'}
+local b = a.x --: integer
+local c = a.y --: string
+local d = a.z --@< Error: Missing key "z" in `{x: 1, y: "hello", ...}`
+--! error
+
+--8<-- table-lit-hint-dynamic
+-- a table literal assigned to `WHATEVER` is inferred as a record
+local a --: WHATEVER
+a = {x = 1, y = 'hello'}
+local b = a.x --: integer
+local c = a.y --: string
+local d = a.z --@< Error: Missing key "z" in `{x: integer, y: string, ...}`
+--! error
+
+--8<-- table-lit-hint-dynamic-update
+-- fields of the inferred record can be updated like other table literals
+local a --: WHATEVER
+a = {x = 1, y = 'hello'}
+a.x = 2
+a.y = 'world'
+local b = a.x --: integer
+--! ok
+
+--8<-- table-lit-hint-dynamic-wrong-type
+local a --: WHATEVER
+a = {x = 1}
+local b = a.x --: string
+--@^ Error: Cannot assign `integer` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- table-lit-hint-dynamic-reassign
+-- the declared `WHATEVER` is restored on the next assignment
+local a --: WHATEVER
+a = {x = 1}
+a = 42
+local b = a.z --: boolean
+a = {y = 'hello'}
+local c = a.y --: string
+--! ok
+
+--8<-- table-lit-hint-dynamic-branch
+-- the inferred record does not outlive the scope of the assignment
+--# assume c: boolean
+local a --: WHATEVER
+if c then
+    a = {x = 1}
+    local b = a.x --: integer
+end
+local d = a.y --: string
+--! ok
+
+--8<-- table-lit-hint-dynamic-func
+-- an assignment in a function does not affect the outer scope
+local a --: WHATEVER
+local function f()
+    a = {q = 1}
+end
+local b = a.w --: string
+--! ok
+
+--8<-- table-lit-hint-dynamic-closure
+-- the inferred record is not visible from functions, which can be called later
+local a --: WHATEVER
+a = {x = 1}
+local function f()
+    local y = a.y --: string
+end
+--! ok
+
 --8<-- table-lit-hint-duplicate-key
 -- duplicate keys are checked as much as possible
 -- even when the hint doesn't require integral or stringy keys
@@ -3577,6 +4333,7 @@ local x = kailua_test.gen_tvar()
 --# assume x.y.z: integer
 --@^ Error: `--# assume` directive tried to access a field from a type not yet known enough
 local z = x.y.z + 42 --: integer --@< Error: The type `<unknown type>` is tabular but not known enough to index
+                                 --@^ Note: Consider annotating this value with a table type
 --! error
 
 --8<-- assume-field-table-or-nil
@@ -3825,3 +4582,233 @@ function f(...) --: string
 end
 --! error
 
+--8<-- ignore-next-line -- exact
+--# ignore-next-line
+local x = 42 --: string
+--! ok
+
+--8<-- ignore-next-line-only-next -- exact
+--# ignore-next-line
+local x = 42 --: string
+local y = 42 --: string
+--@^ Error: Cannot assign `42` into `string`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- ignore-next-line-lint -- exact
+--# assume global self: table
+--# ignore-next-line
+local x = self
+--! ok
+
+--8<-- ignore-next-line-require -- exact
+--# assume global `require`: [require] function(string) --> any
+require 'a'
+--& a
+--# ignore-next-line
+local x = 42 --: string
+--! ok
+
+--8<-- string-pack
+--# assume global pack: [string_pack] function(fmt: string, any...) --> string
+local s = pack('>I4Bd', 1, 2, 3.5) --: string
+local t = pack('z s2', 'foo', 'bar') --: string
+--# assume fmt: string
+local u = pack(fmt, true, {}) --: string
+--! ok
+
+--8<-- string-pack-wrong-type
+--# assume global pack: [string_pack] function(fmt: string, any...) --> string
+local s = pack('>I4B', 1, 'foo')
+--@^ Error: The type `[string_pack] function(fmt: string, any...) --> string` cannot be called
+--@^^ Cause: Third function argument `"foo"` is not a subtype of `integer`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- string-pack-too-many
+--# assume global pack: [string_pack] function(fmt: string, any...) --> string
+local s = pack('i', 1, 2)
+--@^ Error: The type `[string_pack] function(fmt: string, any...) --> string` cannot be called
+--@^^ Cause: Cannot give more than 2 argument(s) to the function
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- string-unpack
+--# assume global unpack: [string_unpack] function(fmt: string, s: string, pos: integer?) --> (any...)
+--# assume s: string
+local a, b, c, d = unpack('<i2 n z', s) --: integer, number, string, integer
+--! ok
+
+--8<-- string-unpack-non-literal
+--# assume global unpack: [string_unpack] function(fmt: string, s: string, pos: integer?) --> (any...)
+--# assume s: string
+--# assume fmt: string
+local a, b, c = unpack(fmt, s) --: any, any, any
+--! ok
+
+--8<-- table-move
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local a = {1, 2, 3} --: vector<integer>
+local b = {} --: vector<integer>
+local m = {} --: map<integer, string>
+local x = move(a, 1, 3, 2) --: vector<integer>
+local y = move(a, 1, 3, 1, b) --: vector<integer>
+local z = move(a, 1, 3, 1, nil) --: vector<integer>
+local w = move(m, 1, 3, 2) --: map<integer, string>
+--! ok
+
+--8<-- table-move-incompatible
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local a = {1, 2, 3} --: vector<integer>
+local b = {} --: vector<string>
+move(a, 1, 3, 1, b)
+--@^ Error: `table.move` cannot move elements of the type `integer` to a table with elements of the type `string`
+--! error
+
+--8<-- table-move-compatible-elements
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local a = {1, 2, 3} --: vector<integer>
+local m = {} --: map<integer, integer>
+local b = {} --: vector<number>
+local x = move(a, 1, 3, 1, b) --: vector<number>
+local y = move(m, 1, 3, 1, b) --: vector<number>
+--! ok
+
+--8<-- table-move-incompatible-tables
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local a = {1, 2, 3} --: vector<integer>
+local b = {} --: map<string, integer>
+move(a, 1, 3, 1, b)
+--@^ Error: `table.move` cannot move elements from the type `vector<integer>` to the type `map<string, integer>`
+--@^^ Cause: `vector<integer>` is not a subtype of `map<string, integer>`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- table-move-non-integer-keys
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local m = {} --: map<string, integer>
+move(m, 1, 3, 2) --@< Error: `table.move` cannot move elements from the type `map<string, integer>` with non-integer keys
+--! error
+
+--8<-- table-move-non-integer-index
+--# assume global move: [table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table
+local a = {1, 2, 3} --: vector<integer>
+move(a, 1, 'x', 2)
+--@^ Error: The type `[table_move] function(a1: table, f: integer, e: integer, t: integer, a2: table?) --> table` cannot be called
+--@^^ Cause: Third function argument `"x"` is not a subtype of `integer`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- math-type
+--# assume global mtype: [math_type] function(x: any) --> string?
+--# assume i: integer
+--# assume n: number
+--# assume s: string
+--# assume u: integer | string
+local a = mtype(i) --: 'integer'
+local b = mtype(n) --: 'integer' | 'float'
+local c = mtype(s) --: nil
+local d = mtype(u) --: 'integer'?
+--! ok
+
+--8<-- math-type-number
+--# assume global mtype: [math_type] function(x: any) --> string?
+--# assume n: number
+local a = mtype(n) --: 'integer'
+--@^ Error: Cannot assign `("float"|"integer")` into `"integer"`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- math-type-narrowing
+--# assume global assert: [assert] function(v: any, message: string?)
+--# assume global assert_not: [assert_not] function(v: any, message: string?)
+--# assume global mtype: [math_type] function(x: any) --> string?
+--# assume x: number
+--# assume y: integer | string
+--# assume z: integer | string
+assert(mtype(x) == 'integer')
+local a = x --: integer
+assert(mtype(y) == 'integer')
+local b = y --: integer
+assert_not(mtype(z) == 'integer')
+local c = z --: string
+--! ok
+
+--8<-- math-type-narrowing-float
+--# assume global assert: [assert] function(v: any, message: string?)
+--# assume global mtype: [math_type] function(x: any) --> string?
+--# assume x: number | string
+assert('float' == mtype(x))
+local a = x --: integer
+--@^ Error: Cannot assign `number` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- math-type-unknown-literal
+--# assume global assert: [assert] function(v: any, message: string?)
+--# assume global mtype: [math_type] function(x: any) --> string?
+--# assume x: number
+assert(mtype(x) == 'number') --@< Error: The literal cannot appear as a return value of `math.type`
+--! error
+
+--8<-- pcall
+--# assume global pcall: [pcall] function(f: function, any...) --> (boolean, any...)
+--v function(x: integer) --> (integer, string)
+local function f(x) return x, 'a' end
+local ok, a, b = pcall(f, 3)
+local ok = ok --: boolean
+local a = a --: integer | string
+local b = b --: string?
+--! ok
+
+--8<-- pcall-no-returns -- exact
+--# assume global pcall: [pcall] function(f: function, any...) --> (boolean, any...)
+local ok, e = pcall(function() end)
+local e = e --: string?
+local n = e --: integer?
+--@^ Error: Cannot assign `string?` into `integer?`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- pcall-diverging -- exact
+--# assume global pcall: [pcall] function(f: function, any...) --> (boolean, any...)
+--# assume global error: function(message: string, level: integer?) --> !
+local ok, e = pcall(function() error('failed') end)
+local e = e --: string
+local n = e --: integer
+--@^ Error: Cannot assign `string` into `integer`
+--@^^ Note: The other type originates here
+--! error
+
+--8<-- pcall-wrong-args
+--# assume global pcall: [pcall] function(f: function, any...) --> (boolean, any...)
+--v function(x: integer) --> integer
+local function f(x) return x end
+local ok, a = pcall(f, 'x')
+--@^ Error: The type `function(x: integer) --> integer` cannot be called
+--@^^ Cause: First function argument `"x"` is not a subtype of `integer`
+--@^^^ Note: The other type originates here
+--! error
+
+--8<-- pcall-dynamic
+--# assume global pcall: [pcall] function(f: function, any...) --> (boolean, any...)
+--# assume f: WHATEVER
+local ok, a = pcall(f, 1, 2)
+local a = a --: integer
+--! ok
+
+--8<-- local-close-attr
+local a <close> = 42
+--@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+--@^^ Error: A variable with the `<close>` attribute should have a `__close` metamethod, but the type `integer` does not have it
+--! error
+
+--8<-- local-close-attr-nil-or-dynamic
+--# assume f: function() --> WHATEVER
+local a <close> = nil
+--@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+local b <close> = f()
+--@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+local c <const> = 42
+--@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+--! ok
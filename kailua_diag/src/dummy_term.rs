@@ -4,22 +4,23 @@ use term;
 use term::color::Color;
 use term::{Attr, Terminal, StderrTerminal};
 
-struct DummyTerminal<T: Write> {
+/// A `Terminal` adapter for arbitrary writers, which ignores colors and other attributes.
+pub struct WriterTerminal<T: Write> {
     writer: T,
 }
 
-impl<T: Write> DummyTerminal<T> {
-    pub fn new(writer: T) -> DummyTerminal<T> {
-        DummyTerminal { writer: writer }
+impl<T: Write> WriterTerminal<T> {
+    pub fn new(writer: T) -> WriterTerminal<T> {
+        WriterTerminal { writer: writer }
     }
 }
 
-impl<T: Write> Write for DummyTerminal<T> {
+impl<T: Write> Write for WriterTerminal<T> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.writer.write(buf) }
     fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
 }
 
-impl<T: Write> Terminal for DummyTerminal<T> {
+impl<T: Write> Terminal for WriterTerminal<T> {
     type Output = T;
     fn fg(&mut self, _color: Color) -> term::Result<()> { Err(term::Error::NotSupported) }
     fn bg(&mut self, _color: Color) -> term::Result<()> { Err(term::Error::NotSupported) }
@@ -36,12 +37,78 @@ impl<T: Write> Terminal for DummyTerminal<T> {
     fn into_inner(self) -> Self::Output where Self: Sized { self.writer }
 }
 
-unsafe impl<T: Write + Sync> Sync for DummyTerminal<T> {}
+unsafe impl<T: Write + Sync> Sync for WriterTerminal<T> {}
+
+/// A minimal terminal for arbitrary writers, which always emits ANSI escape sequences for colors.
+///
+/// Used to force colors even when the terminal information is not available.
+pub struct AnsiTerminal<T: Write> {
+    writer: T,
+}
+
+impl<T: Write> AnsiTerminal<T> {
+    pub fn new(writer: T) -> AnsiTerminal<T> {
+        AnsiTerminal { writer: writer }
+    }
+}
+
+impl<T: Write> Write for AnsiTerminal<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.writer.write(buf) }
+    fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
+}
 
 pub fn stderr_or_dummy() -> Box<StderrTerminal> {
     match term::stderr() {
         Some(t) => t,
-        None => Box::new(DummyTerminal::new(io::stderr())),
+        None => Box::new(WriterTerminal::new(io::stderr())),
     }
 }
 
+// an object-safe subset of `Terminal` used by `ConsoleReport`,
+// so that terminals with different output types can be used interchangeably
+pub trait ReportTerminal: Write {
+    fn fg(&mut self, color: Color) -> term::Result<()>;
+    fn reset(&mut self) -> term::Result<()>;
+}
+
+impl<T: Terminal + ?Sized> ReportTerminal for Box<T> {
+    fn fg(&mut self, color: Color) -> term::Result<()> { (**self).fg(color) }
+    fn reset(&mut self) -> term::Result<()> { (**self).reset() }
+}
+
+impl<T: Write> ReportTerminal for WriterTerminal<T> {
+    fn fg(&mut self, color: Color) -> term::Result<()> { Terminal::fg(self, color) }
+    fn reset(&mut self) -> term::Result<()> { Terminal::reset(self) }
+}
+
+impl<T: Write> ReportTerminal for AnsiTerminal<T> {
+    fn fg(&mut self, color: Color) -> term::Result<()> {
+        // 8 normal colors and 8 bright colors are supported
+        let code = match color {
+            0...7 => 30 + color,
+            8...15 => 90 + (color - 8),
+            _ => return Err(term::Error::ColorOutOfRange),
+        };
+        write!(self.writer, "\x1b[{}m", code)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> term::Result<()> {
+        write!(self.writer, "\x1b[0m")?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ansi_terminal() {
+    use term::color;
+
+    let mut term = AnsiTerminal::new(Vec::new());
+    term.fg(color::RED).unwrap();
+    write!(term, "red").unwrap();
+    term.fg(color::BRIGHT_WHITE).unwrap();
+    write!(term, "white").unwrap();
+    term.reset().unwrap();
+    assert!(term.fg(16).is_err());
+    assert_eq!(term.writer, b"\x1b[31mred\x1b[97mwhite\x1b[0m".to_vec());
+}
@@ -10,7 +10,10 @@ extern crate kailua_env;
 
 pub use message::{Locale, Localize, Localized};
 pub use report::{Kind, Stop, Result, Report, Reporter};
-pub use report::{ConsoleReport, CollectedReport, NoReport, TrackMaxKind};
+pub use report::{ConsoleReport, ColorMode, CollectedReport, NoReport, TrackMaxKind, IgnoreSpans};
+
+// re-exported for the reporters that need the resolved locations
+pub use kailua_env::SourceLocation;
 
 pub mod message;
 pub mod report;
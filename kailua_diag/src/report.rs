@@ -1,17 +1,23 @@
 //! Diagnostics.
 
-use std::char;
+use std::io;
+use std::env;
 use std::str;
 use std::cmp;
 use std::result;
+use std::io::Write;
+use std::iter::FromIterator;
+use std::convert::TryFrom;
 use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use unicode_width::UnicodeWidthChar;
-use kailua_env::{Source, SourceSlice, Span, Pos};
+use kailua_env::{Source, SourceSlice, Span, LineColRange};
 
-use dummy_term::{stderr_or_dummy};
-use term::{color, StderrTerminal};
+use dummy_term::{stderr_or_dummy, WriterTerminal, AnsiTerminal, ReportTerminal};
+use term::color;
 use message::{Locale, Localize, Localized, get_message_locale};
 
 /// The diagnostic category.
@@ -70,6 +76,39 @@ impl Kind {
     }
 }
 
+/// A stable mapping from `Kind` to a single byte, used for compact serialization.
+///
+/// `Info` and `Cause` come after other kinds, so the mapping doesn't follow the ordering of kinds.
+impl From<Kind> for u8 {
+    fn from(kind: Kind) -> u8 {
+        match kind {
+            Kind::Note => 0,
+            Kind::Warning => 1,
+            Kind::Error => 2,
+            Kind::Fatal => 3,
+            Kind::Info => 4,
+            Kind::Cause => 5,
+        }
+    }
+}
+
+/// The reverse of `From<Kind> for u8`. Returns the original byte on an unknown value.
+impl TryFrom<u8> for Kind {
+    type Error = u8;
+
+    fn try_from(v: u8) -> result::Result<Kind, u8> {
+        match v {
+            0 => Ok(Kind::Note),
+            1 => Ok(Kind::Warning),
+            2 => Ok(Kind::Error),
+            3 => Ok(Kind::Fatal),
+            4 => Ok(Kind::Info),
+            5 => Ok(Kind::Cause),
+            v => Err(v),
+        }
+    }
+}
+
 /// The error type for any procedure that may have to stop after the reporting.
 #[must_use]
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -85,31 +124,74 @@ pub type Result<T> = result::Result<T, Stop>;
 pub trait Report {
     fn message_locale(&self) -> Locale;
     fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> Result<()>;
+
+    /// Returns the most severe message category reported so far, if any.
+    fn max_kind(&self) -> Option<Kind>;
+
+    /// Returns true if no error (or fatal error) has been reported so far.
+    fn can_continue(&self) -> bool {
+        self.max_kind() < Some(Kind::Error)
+    }
+
+    /// Marks given deduplication key as seen and returns true if it was not seen before.
+    ///
+    /// The default implementation does not track anything and always returns true.
+    fn mark_once(&self, _key: &str) -> bool { true }
+
+    /// Returns the span of the most recent error (`Kind::Error` or worse) if any.
+    ///
+    /// The default implementation does not track anything and always returns `None`.
+    fn last_error_span(&self) -> Option<Span> { None }
+
+    /// Returns true if no reports of any kind have been made so far.
+    ///
+    /// The default implementation derives it from `max_kind`.
+    fn is_empty(&self) -> bool { self.max_kind().is_none() }
 }
 
 impl<'a, R: Report + ?Sized> Report for &'a R {
     fn message_locale(&self) -> Locale { (**self).message_locale() }
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> { (**self).add_span(k, s, m) }
+    fn max_kind(&self) -> Option<Kind> { (**self).max_kind() }
+    fn mark_once(&self, key: &str) -> bool { (**self).mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { (**self).last_error_span() }
+    fn is_empty(&self) -> bool { (**self).is_empty() }
 }
 
 impl<'a, R: Report + ?Sized> Report for &'a mut R {
     fn message_locale(&self) -> Locale { (**self).message_locale() }
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> { (**self).add_span(k, s, m) }
+    fn max_kind(&self) -> Option<Kind> { (**self).max_kind() }
+    fn mark_once(&self, key: &str) -> bool { (**self).mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { (**self).last_error_span() }
+    fn is_empty(&self) -> bool { (**self).is_empty() }
 }
 
 impl<'a, R: Report + ?Sized> Report for Box<R> {
     fn message_locale(&self) -> Locale { (**self).message_locale() }
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> { (**self).add_span(k, s, m) }
+    fn max_kind(&self) -> Option<Kind> { (**self).max_kind() }
+    fn mark_once(&self, key: &str) -> bool { (**self).mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { (**self).last_error_span() }
+    fn is_empty(&self) -> bool { (**self).is_empty() }
 }
 
 impl<'a, R: Report + ?Sized> Report for Rc<R> {
     fn message_locale(&self) -> Locale { (**self).message_locale() }
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> { (**self).add_span(k, s, m) }
+    fn max_kind(&self) -> Option<Kind> { (**self).max_kind() }
+    fn mark_once(&self, key: &str) -> bool { (**self).mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { (**self).last_error_span() }
+    fn is_empty(&self) -> bool { (**self).is_empty() }
 }
 
 impl<'a, R: Report + ?Sized> Report for Arc<R> {
     fn message_locale(&self) -> Locale { (**self).message_locale() }
     fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> { (**self).add_span(k, s, m) }
+    fn max_kind(&self) -> Option<Kind> { (**self).max_kind() }
+    fn mark_once(&self, key: &str) -> bool { (**self).mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { (**self).last_error_span() }
+    fn is_empty(&self) -> bool { (**self).is_empty() }
 }
 
 /// Extension methods for `Report`. This is what you normally want to use.
@@ -139,6 +221,26 @@ pub trait Reporter: Report + Sized {
         ReportMore::new(self, ret)
     }
 
+    /// Same to `warn` but only reports the first time for each unique `dedup_key`.
+    /// Any additional reports chained to the suppressed warning are also suppressed.
+    fn warn_once<Loc: Into<Span>, Msg: Localize>(&self, dedup_key: &str,
+                                                 loc: Loc, msg: Msg) -> ReportMore<()> {
+        if self.mark_once(dedup_key) {
+            self.warn(loc, msg)
+        } else {
+            info!("suppressing duplicate warning: {:?}", msg);
+            ReportMore::suppressed(Ok(()))
+        }
+    }
+
+    /// Returns the span of the most recently reported error (`Kind::Error` or worse) if any.
+    ///
+    /// Useful for the error recovery to avoid duplicate errors at the same location.
+    /// Not every `Report` tracks errors; `None` is returned in that case.
+    fn span_of_last_error(&self) -> Option<Span> {
+        self.last_error_span()
+    }
+
     /// Reports an additional information with given location and message.
     /// Additional errors can be chained and should finish with `.done()` call.
     fn info<Loc: Into<Span>, Msg: Localize>(&self, loc: Loc, msg: Msg) -> ReportMore<()> {
@@ -153,27 +255,38 @@ impl<T: Report> Reporter for T {}
 /// A helper type for additional reports to the root message.
 #[must_use]
 pub struct ReportMore<'a, T> {
-    report: &'a Report,
+    report: Option<&'a Report>, // None if the root message has been suppressed
     result: Result<T>,
 }
 
 impl<'a, T> ReportMore<'a, T> {
     fn new(report: &'a Report, result: Result<T>) -> ReportMore<'a, T> {
-        ReportMore { report: report, result: result }
+        ReportMore { report: Some(report), result: result }
+    }
+
+    fn suppressed(result: Result<T>) -> ReportMore<'a, T> {
+        ReportMore { report: None, result: result }
+    }
+
+    fn add_span(self, kind: Kind, loc: Span, msg: &Localize) -> ReportMore<'a, T> {
+        if let Some(report) = self.report {
+            let ret = report.add_span(kind, loc, msg);
+            ReportMore { report: self.report, result: if let Err(e) = ret { Err(e) } else { self.result } }
+        } else {
+            self
+        }
     }
 
     /// Reports a cause of the root message with given location and message.
     pub fn cause<Loc: Into<Span>, Msg: Localize>(self, loc: Loc, msg: Msg) -> ReportMore<'a, T> {
         info!("reporting cause: {:?}", msg);
-        let ret = self.report.add_span(Kind::Cause, loc.into(), &msg);
-        ReportMore::new(self.report, if let Err(e) = ret { Err(e) } else { self.result })
+        self.add_span(Kind::Cause, loc.into(), &msg)
     }
 
     /// Reports a note for the root message with given location and message.
     pub fn note<Loc: Into<Span>, Msg: Localize>(self, loc: Loc, msg: Msg) -> ReportMore<'a, T> {
         info!("reporting note: {:?}", msg);
-        let ret = self.report.add_span(Kind::Note, loc.into(), &msg);
-        ReportMore::new(self.report, if let Err(e) = ret { Err(e) } else { self.result })
+        self.add_span(Kind::Note, loc.into(), &msg)
     }
 
     /// Same to `note` but only reports for non-dummy spans.
@@ -184,8 +297,7 @@ impl<'a, T> ReportMore<'a, T> {
             self
         } else {
             info!("reporting note: {:?}", msg);
-            let ret = self.report.add_span(Kind::Note, loc.into(), &msg);
-            ReportMore::new(self.report, if let Err(e) = ret { Err(e) } else { self.result })
+            self.add_span(Kind::Note, loc, &msg)
         }
     }
 
@@ -193,32 +305,62 @@ impl<'a, T> ReportMore<'a, T> {
     pub fn done(self) -> Result<T> { self.result }
 }
 
-fn strip_newline(mut s: SourceSlice) -> SourceSlice {
-    match s {
-        SourceSlice::U8(ref mut s) => loop {
-            match s.last() {
-                Some(&b'\r') | Some(&b'\n') => { *s = &s[..s.len()-1]; }
-                _ => { break; }
-            }
-        },
-        SourceSlice::U16(ref mut s) => loop {
-            match s.last() {
-                Some(&0x0a) | Some(&0x0d) => { *s = &s[..s.len()-1]; }
-                _ => { break; }
-            }
-        },
+/// Whether `ConsoleReport` should color its output.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    /// Always colors the output, even when the terminal is not detected.
+    Always,
+
+    /// Never colors the output.
+    Never,
+
+    /// Colors the output if the terminal supports colors.
+    ///
+    /// The `NO_COLOR` environment variable, if set to a non-empty value, disables colors.
+    /// Otherwise the `FORCE_COLOR` environment variable, if set to a non-empty value
+    /// other than `0`, forces colors.
+    Auto,
+}
+
+impl ColorMode {
+    // resolves `Auto` with environment variables, returning `Auto` if none is set
+    fn resolve_with_env(self) -> ColorMode {
+        self.resolve_with(|name| env::var(name).ok())
+    }
+
+    // same to `resolve_with_env` but reads variables from given function
+    fn resolve_with<F: Fn(&str) -> Option<String>>(self, getenv: F) -> ColorMode {
+        let env_set = |name| getenv(name).and_then(|v| if v.is_empty() { None } else { Some(v) });
+
+        match self {
+            ColorMode::Auto if env_set("NO_COLOR").is_some() => ColorMode::Never,
+            ColorMode::Auto if env_set("FORCE_COLOR").map_or(false, |v| v != "0") =>
+                ColorMode::Always,
+            mode => mode,
+        }
+    }
+
+    // returns a terminal for given writer, which is colored only for `Always`
+    fn terminal_for<W: Write + 'static>(self, writer: W) -> Box<ReportTerminal> {
+        match self {
+            ColorMode::Always => Box::new(AnsiTerminal::new(writer)),
+            ColorMode::Never | ColorMode::Auto => Box::new(WriterTerminal::new(writer)),
+        }
     }
-    s
 }
 
 /// An implementation of `Report` that reports to stderr, optionally colored.
+/// It can also report to any other writer (without colors) via `ConsoleReport::new_with_writer`.
 ///
 /// This will also give the correct context for the reports from given `Source`,
 /// with mostly correct line and column numbers (may be inaccurate with strange encodings).
 pub struct ConsoleReport {
     source: Rc<RefCell<Source>>,
-    term: RefCell<Box<StderrTerminal>>,
+    term: RefCell<Box<ReportTerminal>>,
     locale: Locale,
+    seen: RefCell<HashSet<String>>,
+    last_error: Cell<Option<Span>>,
+    max_kind: Cell<Option<Kind>>,
 }
 
 impl ConsoleReport {
@@ -228,92 +370,52 @@ impl ConsoleReport {
     }
 
     pub fn with_locale(source: Rc<RefCell<Source>>, locale: Locale) -> ConsoleReport {
-        ConsoleReport {
-            source: source,
-            term: RefCell::new(stderr_or_dummy()),
-            locale: locale,
-        }
+        ConsoleReport::with_terminal(source, locale, Box::new(stderr_or_dummy()))
     }
 
-    // column number starts from 0
-    // the final newlines are ignored and not counted towards columns
-    fn calculate_column(&self, linespan: Span, pos: Pos) -> usize {
-        assert!(linespan.contains_or_end(pos));
-        let off = pos.to_usize() - linespan.begin().to_usize();
-
-        let source = self.source.borrow();
-        let line = strip_newline(source.slice_from_span(linespan).unwrap());
-
-        fn seek<T, Iter, Width>(off: usize, iter: Iter, len: usize, tab: T, width: Width) -> usize
-            where T: PartialEq, Iter: Iterator<Item=(usize, T)>, Width: Fn(&T) -> usize
-        {
-            let mut lastcol = 0;
-            let mut col = 0;
-
-            for (i, c) in iter {
-                if off < i {
-                    // previous start offset <= off < current start offset
-                    return lastcol;
-                }
-                lastcol = col;
-                if c == tab {
-                    // assume 8-space tabs (common in terminals)
-                    col = (col + 8) & !7; // 0..7->8, 8..15->16, ...
-                } else {
-                    col += width(&c);
-                }
-            }
-
-            if off < len {
-                return lastcol;
-            }
-
-            // the offset *may* exceed `len` (the entire end offset),
-            // when the iterator has stripped a newline and the offset points past that newline
-            col
-        }
+    /// Same to `ConsoleReport::new` but colors the output as requested.
+    ///
+    /// `ColorMode::Auto` is same to `ConsoleReport::new` except for environment variables.
+    pub fn new_with_color(source: Rc<RefCell<Source>>, color: ColorMode) -> ConsoleReport {
+        let locale = get_message_locale().unwrap_or_else(|| Locale::dummy());
+        let term = match color.resolve_with_env() {
+            ColorMode::Auto => Box::new(stderr_or_dummy()),
+            color => color.terminal_for(io::stderr()),
+        };
+        ConsoleReport::with_terminal(source, locale, term)
+    }
 
-        match line {
-            SourceSlice::U8(line) => {
-                if let Ok(line) = str::from_utf8(line) {
-                    // it is a UTF-8 string, use unicode-width
-                    seek(off, line.char_indices(), line.len(), '\t',
-                         |c| c.width_cjk().unwrap_or(1))
-                } else {
-                    // otherwise it is in the legacy encodings.
-                    // fortunately for us the column width and byte width for those encodings
-                    // generally agrees to each other, so we just use the byte offset
-                    seek(off, line.iter().cloned().enumerate(), line.len(), b'\t', |_| 1)
-                }
-            }
+    /// Same to `ConsoleReport::new` but writes to given writer instead of stderr.
+    ///
+    /// The output is never colored.
+    pub fn new_with_writer<W: Write + 'static>(source: Rc<RefCell<Source>>,
+                                               writer: W) -> ConsoleReport {
+        let locale = get_message_locale().unwrap_or_else(|| Locale::dummy());
+        ConsoleReport::with_writer_and_locale(source, writer, locale)
+    }
 
-            SourceSlice::U16(line) => {
-                // we assume that the U16 lines are almost UTF-16.
-
-                // char::decode_utf16 itself doesn't directly give an u16 offset...
-                type DecodeUtf16Result = ::std::result::Result<char, char::DecodeUtf16Error>;
-                struct Iter<I> { iter: I, cur: usize }
-                impl<I: Iterator<Item=DecodeUtf16Result>> Iterator for Iter<I> {
-                    type Item = (usize, DecodeUtf16Result);
-                    fn next(&mut self) -> Option<(usize, DecodeUtf16Result)> {
-                        let cur = self.cur;
-                        if let Some(c) = self.iter.next() {
-                            self.cur += if let Ok('\u{10000}'...'\u{10ffff}') = c { 2 } else { 1 };
-                            Some((cur, c))
-                        } else {
-                            None
-                        }
-                    }
-                }
+    /// Same to `ConsoleReport::with_locale` but writes to given writer instead of stderr.
+    ///
+    /// The output is never colored.
+    pub fn with_writer_and_locale<W: Write + 'static>(source: Rc<RefCell<Source>>,
+                                                      writer: W,
+                                                      locale: Locale) -> ConsoleReport {
+        ConsoleReport::with_terminal(source, locale, Box::new(WriterTerminal::new(writer)))
+    }
 
-                let iter = Iter { iter: char::decode_utf16(line.iter().cloned()), cur: 0 };
-                seek(off, iter, line.len(), Ok('\t'),
-                     |c| c.as_ref().ok().unwrap_or(&'\u{fffd}').width_cjk().unwrap_or(1))
-            }
+    fn with_terminal(source: Rc<RefCell<Source>>, locale: Locale,
+                     term: Box<ReportTerminal>) -> ConsoleReport {
+        ConsoleReport {
+            source: source,
+            term: RefCell::new(term),
+            locale: locale,
+            seen: RefCell::new(HashSet::new()),
+            last_error: Cell::new(None),
+            max_kind: Cell::new(None),
         }
     }
 
-    // similar to calculate_column but expands tab in `line`
+    // similar to the column calculation (`Span::to_line_col_range`) but expands tab in `line`
     fn expand_tab_in_str(&self, line: &str, next_col: &mut usize) -> Vec<u8> {
         let mut col = *next_col;
         let mut ret = String::new();
@@ -355,23 +457,27 @@ impl Report for ConsoleReport {
     }
 
     fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> Result<()> {
+        self.max_kind.set(cmp::max(self.max_kind.get(), Some(kind)));
+        if kind >= Kind::Error {
+            self.last_error.set(Some(span));
+        }
+
         let mut term = self.term.borrow_mut();
         let term = &mut *term;
         let source = self.source.borrow();
 
         let mut codeinfo = None;
-        if let Some(f) = source.get_file(span.unit()) {
-            if let Some((beginline, mut spans, endline)) = f.lines_from_span(span) {
-                let beginspan = spans.next().unwrap();
-                let begincol = self.calculate_column(beginspan, span.begin());
-                let endspan = spans.next_back().unwrap_or(beginspan);
-                let endcol = self.calculate_column(endspan, span.end());
-                let _ = write!(term, "{}:{}:{}: ", f.path(), beginline + 1, begincol + 1);
-                if span.begin() != span.end() {
-                    let _ = write!(term, "{}:{} ", endline + 1, endcol + 1);
-                }
-                codeinfo = Some((beginline, begincol, beginspan, endline, endcol, endspan));
+        if let (Some(beginspan), Some(range)) = (span.enclosing_line(&source),
+                                                 span.to_line_col_range(&source)) {
+            let endspan = Span::from(span.end()).enclosing_line(&source).unwrap_or(beginspan);
+            let LineColRange { start_line: beginline, start_col: begincol,
+                               end_line: endline, end_col: endcol } = range;
+            let _ = write!(term, "{}:{}:{}: ", span.unit().display_full_path(&source),
+                           beginline + 1, begincol + 1);
+            if span.begin() != span.end() {
+                let _ = write!(term, "{}:{} ", endline + 1, endcol + 1);
             }
+            codeinfo = Some((beginline, begincol, beginspan, endline, endcol, endspan));
         }
 
         let (dim, bright) = kind.colors();
@@ -394,7 +500,7 @@ impl Report for ConsoleReport {
                 d
             }
 
-            type Term<'a> = &'a mut Box<StderrTerminal>;
+            type Term<'a> = &'a mut Box<ReportTerminal>;
 
             let write_newline = |term: Term| {
                 let _ = writeln!(term, "");
@@ -450,7 +556,7 @@ impl Report for ConsoleReport {
             };
 
             if beginline == endline {
-                let slice = strip_newline(source.slice_from_span(beginspan).unwrap());
+                let slice = source.slice_from_span(beginspan).unwrap().strip_newline();
                 let beginoff = span.begin().to_usize() - beginspan.begin().to_usize();
                 let endoff = span.end().to_usize() - beginspan.begin().to_usize();
 
@@ -474,7 +580,7 @@ impl Report for ConsoleReport {
             } else {
                 // 123 | aaaaXXXXXXXX
                 //     |     ^ from here...
-                let beginslice = strip_newline(source.slice_from_span(beginspan).unwrap());
+                let beginslice = source.slice_from_span(beginspan).unwrap().strip_newline();
                 let beginlen = beginslice.len();
                 let beginoff = span.begin().to_usize() - beginspan.begin().to_usize();
                 write_lineno(term, beginline);
@@ -498,7 +604,7 @@ impl Report for ConsoleReport {
                 //
                 // 321 | XXXXXbbbbb     endcol > 0
                 //     |     ^ to here
-                let endslice = strip_newline(source.slice_from_span(endspan).unwrap());
+                let endslice = source.slice_from_span(endspan).unwrap().strip_newline();
                 let endoff = span.end().to_usize() - endspan.begin().to_usize();
                 write_lineno(term, endline);
                 write_slice(term, endslice, 0, endoff);
@@ -519,6 +625,22 @@ impl Report for ConsoleReport {
 
         if kind == Kind::Fatal { Err(Stop) } else { Ok(()) }
     }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.seen.borrow_mut().insert(key.to_owned())
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.last_error.get()
+    }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.max_kind.get()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.max_kind.get().is_none()
+    }
 }
 
 /// An implementation of `Report` that simply collects reports for later uses.
@@ -527,16 +649,45 @@ impl Report for ConsoleReport {
 pub struct CollectedReport {
     collected: RefCell<Vec<(Kind, Span, String)>>,
     locale: Locale,
+    seen: RefCell<HashSet<String>>,
 }
 
 impl CollectedReport {
     pub fn new(locale: Locale) -> CollectedReport {
-        CollectedReport { collected: RefCell::new(Vec::new()), locale: locale }
+        CollectedReport {
+            collected: RefCell::new(Vec::new()),
+            locale: locale,
+            seen: RefCell::new(HashSet::new()),
+        }
     }
 
     pub fn into_reports(self) -> Vec<(Kind, Span, String)> {
         self.collected.into_inner()
     }
+
+    /// Appends all reports from another `CollectedReport`.
+    ///
+    /// The keys marked by `Report::mark_once` are merged as well.
+    /// Messages are kept as is, even when two reports have different locales.
+    pub fn merge(&mut self, other: CollectedReport) {
+        self.collected.get_mut().extend(other.collected.into_inner());
+        self.seen.get_mut().extend(other.seen.into_inner());
+    }
+}
+
+impl Extend<(Kind, Span, String)> for CollectedReport {
+    fn extend<I: IntoIterator<Item=(Kind, Span, String)>>(&mut self, iter: I) {
+        self.collected.get_mut().extend(iter);
+    }
+}
+
+/// The resulting report has a dummy locale.
+impl FromIterator<(Kind, Span, String)> for CollectedReport {
+    fn from_iter<I: IntoIterator<Item=(Kind, Span, String)>>(iter: I) -> CollectedReport {
+        let mut report = CollectedReport::new(Locale::dummy());
+        report.extend(iter);
+        report
+    }
 }
 
 impl Report for CollectedReport {
@@ -549,6 +700,22 @@ impl Report for CollectedReport {
         self.collected.borrow_mut().push((kind, span, msg));
         if kind == Kind::Fatal { Err(Stop) } else { Ok(()) }
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.collected.borrow().iter().map(|r| r.0).max()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.seen.borrow_mut().insert(key.to_owned())
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.collected.borrow().iter().rev().find(|r| r.0 >= Kind::Error).map(|r| r.1)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.collected.borrow().is_empty()
+    }
 }
 
 /// An implementation of `Report` that panics on reports.
@@ -562,15 +729,24 @@ impl Report for NoReport {
     fn add_span(&self, _kind: Kind, _span: Span, _msg: &Localize) -> Result<()> {
         Err(Stop)
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        None
+    }
 }
 
 /// A wrapper for `Report` implementations that also tracks the most severe message category.
+///
+/// Every report is forwarded to the inner `Report` as is.
+/// This is useful for determining if the processing has been successful
+/// after all reports have been issued, e.g. for the exit code or test runners.
 pub struct TrackMaxKind<R: Report> {
     report: R,
     maxkind: Cell<Option<Kind>>,
 }
 
 impl<R: Report> TrackMaxKind<R> {
+    /// Wraps the given `Report`.
     pub fn new(report: R) -> TrackMaxKind<R> {
         TrackMaxKind {
             report: report,
@@ -578,10 +754,12 @@ impl<R: Report> TrackMaxKind<R> {
         }
     }
 
-    pub fn can_continue(&self) -> bool {
-        self.maxkind.get() < Some(Kind::Error)
+    /// Returns true if any error (or fatal error) has been reported so far.
+    pub fn has_errors(&self) -> bool {
+        !self.can_continue()
     }
 
+    /// Returns the inner `Report`.
     pub fn into_inner(self) -> R {
         self.report
     }
@@ -593,12 +771,336 @@ impl<R: Report> Report for TrackMaxKind<R> {
     }
 
     fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> Result<()> {
-        if let Some(maxkind) = self.maxkind.get() {
-            self.maxkind.set(Some(cmp::max(maxkind, kind)));
+        self.maxkind.set(cmp::max(self.maxkind.get(), Some(kind)));
+        self.report.add_span(kind, span, msg)
+    }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.maxkind.get()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.report.mark_once(key)
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.report.last_error_span()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.maxkind.get().is_none()
+    }
+}
+
+/// A wrapper for `Report` implementations that ignores reports starting within given spans.
+///
+/// Notes and causes following an ignored report are also ignored.
+/// Fatal reports are never ignored, as the caller relies on them to stop.
+pub struct IgnoreSpans<R> {
+    report: R,
+    spans: Vec<Span>,
+    ignoring: AtomicBool, // should be `Sync` as it is used in the checker context
+}
+
+impl<R> IgnoreSpans<R> {
+    pub fn new(report: R) -> IgnoreSpans<R> {
+        IgnoreSpans {
+            report: report,
+            spans: Vec::new(),
+            ignoring: AtomicBool::new(false),
+        }
+    }
+
+    pub fn ignore(&mut self, span: Span) {
+        self.spans.push(span);
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.report
+    }
+
+    pub fn into_inner(self) -> R {
+        self.report
+    }
+}
+
+impl<R: Report> Report for IgnoreSpans<R> {
+    fn message_locale(&self) -> Locale {
+        self.report.message_locale()
+    }
+
+    fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> Result<()> {
+        match kind {
+            Kind::Note | Kind::Cause => {}
+            Kind::Fatal => self.ignoring.store(false, Ordering::Relaxed),
+            _ => {
+                let ignoring = self.spans.iter().any(|s| s.contains(span.begin()));
+                self.ignoring.store(ignoring, Ordering::Relaxed);
+            }
+        }
+        if self.ignoring.load(Ordering::Relaxed) {
+            Ok(())
         } else {
-            self.maxkind.set(Some(kind));
+            self.report.add_span(kind, span, msg)
         }
-        self.report.add_span(kind, span, msg)
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        self.report.max_kind()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.report.mark_once(key)
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.report.last_error_span()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.report.is_empty()
+    }
+}
+
+#[test]
+fn test_warn_once() {
+    let report = CollectedReport::new(Locale::dummy());
+    report.warn_once("a", Span::dummy(), "first").note(Span::dummy(), "note").done().unwrap();
+    report.warn_once("a", Span::dummy(), "second").note(Span::dummy(), "note").done().unwrap();
+    report.warn_once("b", Span::dummy(), "third").done().unwrap();
+    let msgs: Vec<_> = report.into_reports().into_iter().map(|(k, _, m)| (k, m)).collect();
+    assert_eq!(msgs, vec![(Kind::Warning, "first".to_string()),
+                          (Kind::Note, "note".to_string()),
+                          (Kind::Warning, "third".to_string())]);
+}
+
+#[test]
+fn test_collected_report_merge() {
+    let mut report = CollectedReport::new(Locale::dummy());
+    report.warn_once("a", Span::dummy(), "first").done().unwrap();
+
+    let other: CollectedReport = vec![(Kind::Error, Span::dummy(), "second".to_string())]
+        .into_iter().collect();
+    other.warn_once("b", Span::dummy(), "third").done().unwrap();
+    report.merge(other);
+    report.extend(vec![(Kind::Note, Span::dummy(), "fourth".to_string())]);
+
+    // both "a" and "b" have been marked
+    report.warn_once("a", Span::dummy(), "ignored").done().unwrap();
+    report.warn_once("b", Span::dummy(), "ignored").done().unwrap();
+
+    let msgs: Vec<_> = report.into_reports().into_iter().map(|(k, _, m)| (k, m)).collect();
+    assert_eq!(msgs, vec![(Kind::Warning, "first".to_string()),
+                          (Kind::Error, "second".to_string()),
+                          (Kind::Warning, "third".to_string()),
+                          (Kind::Note, "fourth".to_string())]);
+}
+
+#[test]
+fn test_ignore_spans() {
+    use kailua_env::SourceFile;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"aaaa\nbbbb\ncccc".to_vec()));
+    let lines: Vec<Span> = source.get_file(span.unit()).unwrap().line_spans().collect();
+
+    let mut report = IgnoreSpans::new(CollectedReport::new(Locale::dummy()));
+    report.ignore(lines[1]);
+    report.warn(lines[0], "first").note(lines[1], "note").done().unwrap();
+    report.error(lines[1], "second").note(lines[0], "ignored note").done().unwrap();
+    report.error(lines[2], "third").done().unwrap();
+    assert!(report.fatal::<_, _, ()>(lines[1], "fatal").done().is_err());
+
+    let msgs: Vec<_> = report.into_inner().into_reports().into_iter()
+                                                         .map(|(k, _, m)| (k, m)).collect();
+    assert_eq!(msgs, vec![(Kind::Warning, "first".to_string()),
+                          (Kind::Note, "note".to_string()),
+                          (Kind::Error, "third".to_string()),
+                          (Kind::Fatal, "fatal".to_string())]);
+}
+
+#[test]
+fn test_kind_to_u8() {
+    let kinds = [Kind::Note, Kind::Info, Kind::Cause, Kind::Warning, Kind::Error, Kind::Fatal];
+    for &kind in &kinds {
+        assert_eq!(Kind::try_from(u8::from(kind)), Ok(kind));
+    }
+
+    assert_eq!(u8::from(Kind::Note), 0);
+    assert_eq!(u8::from(Kind::Warning), 1);
+    assert_eq!(u8::from(Kind::Error), 2);
+    assert_eq!(u8::from(Kind::Fatal), 3);
+    assert_eq!(Kind::try_from(6), Err(6));
 }
 
+// a writer whose output can be inspected after being moved into the report
+#[cfg(test)]
+#[derive(Clone)]
+struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.borrow_mut().write(buf) }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[test]
+fn test_console_report_with_writer() {
+    use kailua_env::SourceFile;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"aaaa\nbbbb".to_vec()));
+    let lines: Vec<Span> = source.get_file(span.unit()).unwrap().line_spans().collect();
+
+    let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+    let source = Rc::new(RefCell::new(source));
+    let report = ConsoleReport::with_writer_and_locale(source, buf.clone(), Locale::dummy());
+    report.error(lines[1], "oops").done().unwrap();
+
+    let output = String::from_utf8(buf.0.borrow().clone()).unwrap();
+    assert_eq!(output, concat!("foo:2:1: 2:5 [Error] oops\n",
+                               "2 | bbbb\n",
+                               "  | ^^^^\n"));
+}
+
+#[test]
+fn test_console_report_with_color() {
+    use kailua_env::SourceFile;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"aaaa\nbbbb".to_vec()));
+    let lines: Vec<Span> = source.get_file(span.unit()).unwrap().line_spans().collect();
+    let source = Rc::new(RefCell::new(source));
+
+    let output = |color: ColorMode| {
+        let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+        let term = color.terminal_for(buf.clone());
+        let report = ConsoleReport::with_terminal(source.clone(), Locale::dummy(), term);
+        report.error(lines[1], "oops").done().unwrap();
+        String::from_utf8(buf.0.replace(Vec::new())).unwrap()
+    };
+
+    let plain = concat!("foo:2:1: 2:5 [Error] oops\n",
+                        "2 | bbbb\n",
+                        "  | ^^^^\n");
+    assert_eq!(output(ColorMode::Never), plain);
+    let colored = output(ColorMode::Always);
+    assert!(colored.contains("\x1b["));
+    assert_ne!(colored, plain);
+
+    // the stderr-backed reports can be created in any mode
+    for &color in &[ColorMode::Always, ColorMode::Never, ColorMode::Auto] {
+        let report = ConsoleReport::new_with_color(source.clone(), color);
+        assert!(report.is_empty());
+    }
+}
+
+#[test]
+fn test_color_mode_env() {
+    fn resolve(mode: ColorMode, vars: &[(&str, &str)]) -> ColorMode {
+        mode.resolve_with(|name| {
+            vars.iter().find(|&&(k, _)| k == name).map(|&(_, v)| v.to_owned())
+        })
+    }
+
+    assert_eq!(resolve(ColorMode::Auto, &[]), ColorMode::Auto);
+    assert_eq!(resolve(ColorMode::Auto, &[("NO_COLOR", "1")]), ColorMode::Never);
+    assert_eq!(resolve(ColorMode::Auto, &[("NO_COLOR", "")]), ColorMode::Auto);
+    assert_eq!(resolve(ColorMode::Auto, &[("FORCE_COLOR", "1")]), ColorMode::Always);
+    assert_eq!(resolve(ColorMode::Auto, &[("FORCE_COLOR", "0")]), ColorMode::Auto);
+    assert_eq!(resolve(ColorMode::Auto, &[("FORCE_COLOR", "")]), ColorMode::Auto);
+    assert_eq!(resolve(ColorMode::Auto, &[("NO_COLOR", "1"), ("FORCE_COLOR", "1")]),
+               ColorMode::Never);
+
+    // explicit modes are not affected by environment variables
+    assert_eq!(resolve(ColorMode::Always, &[("NO_COLOR", "1")]), ColorMode::Always);
+    assert_eq!(resolve(ColorMode::Never, &[("FORCE_COLOR", "1")]), ColorMode::Never);
+}
+
+#[test]
+fn test_span_of_last_error() {
+    use kailua_env::SourceFile;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"aaaa\nbbbb\ncccc".to_vec()));
+    let lines: Vec<Span> = source.get_file(span.unit()).unwrap().line_spans().collect();
+
+    let report = CollectedReport::new(Locale::dummy());
+    assert_eq!(report.span_of_last_error(), None);
+    report.warn(lines[0], "warning").done().unwrap();
+    assert_eq!(report.span_of_last_error(), None);
+    report.error(lines[1], "error").note(lines[0], "note").done().unwrap();
+    report.warn(lines[2], "warning").done().unwrap();
+    assert_eq!(report.span_of_last_error(), Some(lines[1]));
+
+    // ignored errors are not visible to the wrapped report
+    let mut report = TrackMaxKind::new(IgnoreSpans::new(CollectedReport::new(Locale::dummy())));
+    report.report.ignore(lines[2]);
+    report.error(lines[0], "error").done().unwrap();
+    report.error(lines[2], "ignored error").done().unwrap();
+    assert_eq!(report.span_of_last_error(), Some(lines[0]));
+
+    let source = Rc::new(RefCell::new(source));
+    let report = ConsoleReport::with_writer_and_locale(source, Vec::new(), Locale::dummy());
+    assert_eq!(report.span_of_last_error(), None);
+    report.error(lines[2], "error").done().unwrap();
+    report.warn(lines[0], "warning").done().unwrap();
+    assert_eq!(report.span_of_last_error(), Some(lines[2]));
+
+    assert_eq!(NoReport.span_of_last_error(), None);
+}
+
+#[test]
+fn test_report_is_empty() {
+    assert!(NoReport.is_empty());
+
+    let report = TrackMaxKind::new(CollectedReport::new(Locale::dummy()));
+    assert!(report.is_empty());
+    report.info(Span::dummy(), "info").done().unwrap();
+    assert!(!report.is_empty());
+    assert!(!report.into_inner().is_empty());
+}
+
+#[test]
+fn test_track_max_kind() {
+    let report = TrackMaxKind::new(CollectedReport::new(Locale::dummy()));
+    assert_eq!(report.max_kind(), None);
+    assert!(!report.has_errors());
+
+    report.warn(Span::dummy(), "warning").done().unwrap();
+    report.info(Span::dummy(), "info").done().unwrap();
+    assert_eq!(report.max_kind(), Some(Kind::Warning));
+    assert!(!report.has_errors());
+    assert!(report.can_continue());
+
+    report.error(Span::dummy(), "error").done().unwrap();
+    assert_eq!(report.max_kind(), Some(Kind::Error));
+    assert!(report.has_errors());
+    assert!(!report.can_continue());
+    assert_eq!(report.into_inner().into_reports().len(), 3);
+}
+
+#[test]
+fn test_max_kind() {
+    assert_eq!(NoReport.max_kind(), None);
+    assert!(NoReport.can_continue());
+
+    let report = CollectedReport::new(Locale::dummy());
+    assert_eq!(report.max_kind(), None);
+    report.warn(Span::dummy(), "warning").note(Span::dummy(), "note").done().unwrap();
+    assert_eq!(report.max_kind(), Some(Kind::Warning));
+    assert!(report.can_continue());
+    report.error(Span::dummy(), "error").done().unwrap();
+    assert_eq!(report.max_kind(), Some(Kind::Error));
+    assert!(!report.can_continue());
+
+    let source = Rc::new(RefCell::new(Source::new()));
+    let report = ConsoleReport::with_writer_and_locale(source, Vec::new(), Locale::dummy());
+    assert_eq!(report.max_kind(), None);
+    report.info(Span::dummy(), "info").done().unwrap();
+    assert_eq!(report.max_kind(), Some(Kind::Info));
+    assert!(report.can_continue());
+    assert!(report.fatal::<_, _, ()>(Span::dummy(), "fatal").done().is_err());
+    assert_eq!(report.max_kind(), Some(Kind::Fatal));
+    assert!(!report.can_continue());
+}
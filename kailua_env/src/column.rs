@@ -0,0 +1,128 @@
+//! Column calculation for the line-column ranges (`Span::to_line_col_range`).
+
+use std::char;
+use unicode_width::UnicodeWidthChar;
+use loc::{Pos, Span};
+use source::{Source, SourceSlice};
+
+// filters out ANSI escape sequences (`\x1b[...m`) from the `(offset, char)` iterator,
+// so that they do not count towards columns (they are not visible from the terminal).
+// `to_char` converts the item to a character if possible.
+struct StripAnsiEscapes<I, F> {
+    iter: I,
+    to_char: F,
+    state: AnsiState,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum AnsiState {
+    Normal, // outside of escape sequences
+    Escape, // after `\x1b`
+    Csi,    // after `\x1b[`, expecting digits, `;` or `m`
+}
+
+fn strip_ansi_escapes<T, I, F>(iter: I, to_char: F) -> StripAnsiEscapes<I, F>
+    where I: Iterator<Item=(usize, T)>, F: Fn(&T) -> Option<char>
+{
+    StripAnsiEscapes { iter: iter, to_char: to_char, state: AnsiState::Normal }
+}
+
+impl<T, I, F> Iterator for StripAnsiEscapes<I, F>
+    where I: Iterator<Item=(usize, T)>, F: Fn(&T) -> Option<char>
+{
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<(usize, T)> {
+        while let Some((i, c)) = self.iter.next() {
+            let ch = (self.to_char)(&c);
+            let (state, strip) = match (self.state, ch) {
+                (AnsiState::Normal, Some('\x1b')) => (AnsiState::Escape, true),
+                (AnsiState::Normal, _) => (AnsiState::Normal, false),
+                (AnsiState::Escape, Some('[')) => (AnsiState::Csi, true),
+                (AnsiState::Escape, _) => (AnsiState::Normal, false),
+                (AnsiState::Csi, Some('0'...'9')) | (AnsiState::Csi, Some(';')) =>
+                    (AnsiState::Csi, true),
+                (AnsiState::Csi, Some('m')) => (AnsiState::Normal, true),
+                (AnsiState::Csi, _) => (AnsiState::Normal, false), // malformed, bail out
+            };
+            self.state = state;
+            if !strip {
+                return Some((i, c));
+            }
+        }
+        None
+    }
+}
+
+// column number starts from 0
+// the final newlines are ignored and not counted towards columns
+pub fn calculate_column(source: &Source, linespan: Span, pos: Pos) -> usize {
+    assert!(linespan.contains_or_end(pos));
+    let off = pos.to_usize() - linespan.begin().to_usize();
+
+    let line = source.slice_from_span(linespan).unwrap().strip_newline();
+
+    fn seek<T, Iter, Width>(off: usize, iter: Iter, len: usize, tab: T, width: Width) -> usize
+        where T: PartialEq, Iter: Iterator<Item=(usize, T)>, Width: Fn(&T) -> usize
+    {
+        let mut lastcol = 0;
+        let mut col = 0;
+
+        for (i, c) in iter {
+            if off < i {
+                // previous start offset <= off < current start offset
+                return lastcol;
+            }
+            lastcol = col;
+            if c == tab {
+                // assume 8-space tabs (common in terminals)
+                col = (col + 8) & !7; // 0..7->8, 8..15->16, ...
+            } else {
+                col += width(&c);
+            }
+        }
+
+        if off < len {
+            return lastcol;
+        }
+
+        // the offset *may* exceed `len` (the entire end offset),
+        // when the iterator has stripped a newline and the offset points past that newline
+        col
+    }
+
+    // the byte source is mostly UTF-8, and invalid sequences mostly come from
+    // the legacy encodings, whose column width generally agrees to the byte width.
+    // since each invalid byte mostly results in its own replacement character,
+    // we count a replacement character from the byte source as a single column.
+    let legacy_width = match line {
+        SourceSlice::U8(_) => true,
+        SourceSlice::U16(_) => false,
+    };
+
+    let begin = linespan.begin().to_usize();
+    let len = line.len();
+    let iter = linespan.chars_in(source).unwrap()
+                       .map(|(pos, c)| (pos.to_usize() - begin, c))
+                       .take_while(|&(i, _)| i < len);
+    seek(off, strip_ansi_escapes(iter, |&c| Some(c)), len, '\t', |&c| {
+        if legacy_width && c == char::REPLACEMENT_CHARACTER {
+            1
+        } else {
+            c.width_cjk().unwrap_or(1)
+        }
+    })
+}
+
+#[test]
+fn test_strip_ansi_escapes() {
+    let strip = |s: &str| -> Vec<(usize, char)> {
+        strip_ansi_escapes(s.char_indices(), |&c| Some(c)).collect()
+    };
+    assert_eq!(strip("ab"), vec![(0, 'a'), (1, 'b')]);
+    assert_eq!(strip("a\x1b[1;31mb\x1b[0m"), vec![(0, 'a'), (8, 'b')]);
+    assert_eq!(strip("a\x1b[mb"), vec![(0, 'a'), (4, 'b')]);
+    // malformed sequences only strip the recognized prefix
+    assert_eq!(strip("\x1bx"), vec![(1, 'x')]);
+    assert_eq!(strip("\x1b[1x"), vec![(3, 'x')]);
+}
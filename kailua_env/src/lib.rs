@@ -13,12 +13,15 @@
 //! * An arbitrary mapping from location ranges to values
 //!   ([`kailua_env::spanmap`](./spanmap/index.html))
 
+extern crate unicode_width;
+
 mod loc;
+mod column;
 pub mod scope;
 pub mod source;
 pub mod spanmap;
 
-pub use loc::{Unit, Pos, Span, Spanned, WithLoc};
+pub use loc::{Unit, UnitDisplayPath, Pos, Span, Spanned, WithLoc, LineColRange, SourceLocation};
 pub use scope::{Scope, ScopedId, ScopeMap};
 pub use source::{Source, SourceFile, SourceSlice, SourceData};
 pub use spanmap::SpanMap;
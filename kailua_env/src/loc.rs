@@ -3,7 +3,12 @@
 use std::ops;
 use std::cmp;
 use std::fmt;
+use std::str;
+use std::char;
 use std::borrow::Borrow;
+use std::path::{Path, PathBuf};
+use source::{Source, SourceSlice};
+use column;
 
 /// An identifier for the code *unit*, unique in the originating `Source`.
 ///
@@ -44,6 +49,18 @@ impl Unit {
     pub fn to_usize(&self) -> usize {
         self.unit as usize
     }
+
+    /// Returns a displayable file name of the unit, resolved from given `Source`.
+    ///
+    /// Only the last component of the registered path is displayed.
+    pub fn display_path<'a>(&self, source: &'a Source) -> UnitDisplayPath<'a> {
+        UnitDisplayPath { unit: *self, source: source, full: false }
+    }
+
+    /// Same to `display_path` but displays the full registered path.
+    pub fn display_full_path<'a>(&self, source: &'a Source) -> UnitDisplayPath<'a> {
+        UnitDisplayPath { unit: *self, source: source, full: true }
+    }
 }
 
 /// In the debugging output the unit is denoted `@_` or <code>@<i>unit</i></code>.
@@ -64,6 +81,40 @@ impl fmt::Debug for Unit {
     }
 }
 
+/// A displayable path of the unit, returned by `Unit::display_path` and
+/// `Unit::display_full_path`.
+///
+/// The source-independent units and units not in the `Source` are displayed as
+/// `<builtin>` or `<unknown>`.
+pub struct UnitDisplayPath<'a> {
+    unit: Unit,
+    source: &'a Source,
+    full: bool,
+}
+
+impl<'a> fmt::Display for UnitDisplayPath<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.unit.unit == BUILTIN_UNIT {
+            return write!(f, "<builtin>");
+        }
+
+        if let Some(file) = self.source.get_file(self.unit) {
+            let path = file.path();
+            if self.full {
+                write!(f, "{}", path)
+            } else {
+                // fall back to the full path if it has no file name (e.g. `..`)
+                match Path::new(path).file_name() {
+                    Some(name) => write!(f, "{}", name.to_string_lossy()),
+                    None => write!(f, "{}", path),
+                }
+            }
+        } else {
+            write!(f, "<unknown>")
+        }
+    }
+}
+
 /// A *position* in the originating `Source`.
 ///
 /// The position is composed of the `Unit` and an offset to the corresponding source.
@@ -106,6 +157,48 @@ impl Pos {
     pub fn to_usize(&self) -> usize {
         self.pos as usize
     }
+
+    /// Advances the position by given offset, returning `None` on the overflow.
+    ///
+    /// Source-independent positions cannot be advanced (except by zero).
+    pub fn checked_add(&self, n: usize) -> Option<Pos> {
+        if n == 0 {
+            return Some(*self);
+        }
+        if !self.is_source_dependent() || n > u32::MAX as usize {
+            return None;
+        }
+        self.pos.checked_add(n as u32).map(|pos| Pos { unit: self.unit, pos: pos })
+    }
+
+    /// Moves the position back by given offset, returning `None` on the underflow.
+    ///
+    /// Source-independent positions cannot be moved back (except by zero).
+    pub fn checked_sub(&self, n: usize) -> Option<Pos> {
+        if n == 0 {
+            return Some(*self);
+        }
+        if !self.is_source_dependent() || n > u32::MAX as usize {
+            return None;
+        }
+        self.pos.checked_sub(n as u32).map(|pos| Pos { unit: self.unit, pos: pos })
+    }
+}
+
+/// Advances the position by given offset. Panics on the overflow.
+impl ops::Add<usize> for Pos {
+    type Output = Pos;
+    fn add(self, n: usize) -> Pos {
+        self.checked_add(n).expect("Pos overflow")
+    }
+}
+
+/// Moves the position back by given offset. Panics on the underflow.
+impl ops::Sub<usize> for Pos {
+    type Output = Pos;
+    fn sub(self, n: usize) -> Pos {
+        self.checked_sub(n).expect("Pos underflow")
+    }
 }
 
 /// In the debugging output the position is denoted `@_` or <code>@<i>unit</i>/<i>off</i></code>.
@@ -213,6 +306,14 @@ impl Span {
         Pos { unit: self.unit, pos: self.end }
     }
 
+    /// Returns the smallest span covering all given spans.
+    ///
+    /// This is same to folding spans with `|` starting from `Span::dummy()`,
+    /// so dummy spans are ignored and spans from different units result in a dummy span.
+    pub fn merge_all<I: IntoIterator<Item=Span>>(spans: I) -> Span {
+        spans.into_iter().fold(Span::dummy(), |acc, span| acc | span)
+    }
+
     pub fn len(&self) -> usize {
         if self.is_source_dependent() {
             (self.end - self.begin) as usize
@@ -228,6 +329,144 @@ impl Span {
     pub fn contains_or_end(&self, pos: Pos) -> bool {
         self.unit > 0 && self.unit == pos.unit && self.begin <= pos.pos && pos.pos <= self.end
     }
+
+    /// Iterates over the characters in the span, with the starting position for each character.
+    ///
+    /// The byte source is decoded as UTF-8 and the two-byte word source is decoded as UTF-16.
+    /// Each invalid sequence yields `char::REPLACEMENT_CHARACTER` instead.
+    /// Returns `None` if the span cannot be resolved from given `Source`.
+    pub fn chars_in<'a>(&self, source: &'a Source)
+            -> Option<impl Iterator<Item=(Pos, char)> + 'a> {
+        let data = match source.slice_from_span(*self) {
+            Some(SourceSlice::U8(data)) => CharsInData::U8(data),
+            Some(SourceSlice::U16(data)) => CharsInData::U16(data),
+            None => return None,
+        };
+        Some(CharsIn { data: data, unit: self.unit, pos: self.begin })
+    }
+
+    /// Returns the span of the entire line containing the beginning of the span,
+    /// including the trailing newline if any.
+    /// Returns `None` if the span cannot be resolved from given `Source`.
+    pub fn enclosing_line(&self, source: &Source) -> Option<Span> {
+        let file = source.get_file(self.unit())?;
+        file.line_from_pos(self.begin()).map(|(_, span)| span)
+    }
+
+    /// Resolves the span into line and column numbers from given `Source`.
+    ///
+    /// Columns are counted in the display width, with 8-column tab stops,
+    /// and do not count the trailing newlines nor ANSI escape sequences.
+    /// Returns `None` if the span cannot be resolved from given `Source`.
+    pub fn to_line_col_range(&self, source: &Source) -> Option<LineColRange> {
+        let file = source.get_file(self.unit())?;
+        let (start_line, mut spans, end_line) = file.lines_from_span(*self)?;
+        let start_span = spans.next().unwrap();
+        let end_span = spans.next_back().unwrap_or(start_span);
+        Some(LineColRange {
+            start_line: start_line,
+            start_col: column::calculate_column(source, start_span, self.begin()),
+            end_line: end_line,
+            end_col: column::calculate_column(source, end_span, self.end()),
+        })
+    }
+
+    /// Resolves the span into the path and line and column numbers from given `Source`.
+    ///
+    /// Same to `to_line_col_range` but also fills in the path of the file.
+    /// The path is `None` and all numbers are 0 if the span cannot be resolved.
+    pub fn to_source_location(&self, source: &Source) -> SourceLocation {
+        let path = source.get_file(self.unit()).map(|f| PathBuf::from(f.path()));
+        match (path, self.to_line_col_range(source)) {
+            (Some(path), Some(range)) => SourceLocation {
+                path: Some(path),
+                start: (range.start_line, range.start_col),
+                end: (range.end_line, range.end_col),
+            },
+            _ => SourceLocation { path: None, start: (0, 0), end: (0, 0) },
+        }
+    }
+}
+
+/// A span resolved into line and column numbers, returned by `Span::to_line_col_range`.
+///
+/// All numbers are 0-based, and the end is exclusive as in `Span`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LineColRange {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+/// A span resolved into the path and line and column numbers,
+/// returned by `Span::to_source_location`.
+///
+/// `start` and `end` are `(line, column)` pairs with the same meaning as `LineColRange`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SourceLocation {
+    pub path: Option<PathBuf>,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+enum CharsInData<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+}
+
+// an iterator returned by `Span::chars_in`
+struct CharsIn<'a> {
+    data: CharsInData<'a>, // remaining data
+    unit: u32,
+    pos: u32,
+}
+
+impl<'a> Iterator for CharsIn<'a> {
+    type Item = (Pos, char);
+
+    fn next(&mut self) -> Option<(Pos, char)> {
+        let (c, len) = match self.data {
+            CharsInData::U8(ref mut data) => {
+                if data.is_empty() {
+                    return None;
+                }
+
+                // a single UTF-8 sequence is at most 4 bytes long
+                let window = &data[..cmp::min(data.len(), 4)];
+                let (c, len) = match str::from_utf8(window) {
+                    Ok(s) => {
+                        let c = s.chars().next().unwrap();
+                        (c, c.len_utf8())
+                    }
+                    Err(e) => if e.valid_up_to() > 0 {
+                        let c = str::from_utf8(&window[..e.valid_up_to()]).unwrap()
+                                                                         .chars().next().unwrap();
+                        (c, c.len_utf8())
+                    } else {
+                        // the sequence can be only truncated by the end of data
+                        (char::REPLACEMENT_CHARACTER, e.error_len().unwrap_or(window.len()))
+                    },
+                };
+                *data = &data[len..];
+                (c, len)
+            }
+
+            CharsInData::U16(ref mut data) => {
+                let (c, len) = match char::decode_utf16(data.iter().cloned()).next() {
+                    Some(Ok(c)) => (c, c.len_utf16()),
+                    Some(Err(_)) => (char::REPLACEMENT_CHARACTER, 1),
+                    None => return None,
+                };
+                *data = &data[len..];
+                (c, len)
+            }
+        };
+
+        let pos = Pos { unit: self.unit, pos: self.pos };
+        self.pos += len as u32;
+        Some((pos, c))
+    }
 }
 
 impl ops::BitAnd for Span {
@@ -365,6 +604,28 @@ impl<T> Spanned<T> {
     }
 }
 
+impl<T: Ord> Spanned<T> {
+    /// Compares two values while ignoring the span.
+    ///
+    /// `Spanned` does not implement `Ord` since the equality does take the span into account.
+    /// Use `slice.sort_by(Spanned::cmp_base)` to sort values by their bases.
+    pub fn cmp_base(&self, other: &Spanned<T>) -> cmp::Ordering {
+        self.base.cmp(&other.base)
+    }
+}
+
+impl From<(Unit, u32)> for Pos {
+    fn from((unit, pos): (Unit, u32)) -> Pos {
+        pos_from_u32(unit, pos)
+    }
+}
+
+impl From<(Unit, u32, u32)> for Span {
+    fn from((unit, begin, end): (Unit, u32, u32)) -> Span {
+        Span::new(pos_from_u32(unit, begin), pos_from_u32(unit, end))
+    }
+}
+
 impl From<Pos> for Span {
     fn from(pos: Pos) -> Span {
         Span { unit: pos.unit, begin: pos.pos, end: pos.pos }
@@ -431,3 +692,55 @@ pub trait WithLoc: Sized {
 
 impl<T> WithLoc for T {}
 
+#[test]
+fn test_pos_arith() {
+    let unit = unit_from_u32(1);
+    let pos = pos_from_u32(unit, 10);
+    assert_eq!(pos + 5, pos_from_u32(unit, 15));
+    assert_eq!(pos - 3, pos_from_u32(unit, 7));
+    assert_eq!((pos + 5).unit(), unit);
+    assert_eq!(pos.checked_sub(10), Some(pos_from_u32(unit, 0)));
+    assert_eq!(pos.checked_sub(11), None);
+    assert_eq!(pos_from_u32(unit, u32::MAX).checked_add(1), None);
+    assert_eq!(Pos::dummy().checked_add(0), Some(Pos::dummy()));
+    assert_eq!(Pos::dummy().checked_add(1), None);
+    assert_eq!(Pos::builtin().checked_sub(1), None);
+}
+
+#[test]
+fn test_span_merge_all() {
+    let unit1 = unit_from_u32(1);
+    let unit2 = unit_from_u32(2);
+    let span = |unit, begin, end| span_from_u32(unit, begin, end);
+
+    assert_eq!(Span::merge_all(vec![]), Span::dummy());
+    assert_eq!(Span::merge_all(vec![span(unit1, 3, 5)]), span(unit1, 3, 5));
+    assert_eq!(Span::merge_all(vec![span(unit1, 8, 9), Span::dummy(), span(unit1, 3, 5)]),
+               span(unit1, 3, 9));
+    assert_eq!(Span::merge_all(vec![span(unit1, 3, 5), span(unit2, 3, 5)]), Span::dummy());
+}
+
+#[test]
+fn test_from_tuples() {
+    let unit = unit_from_u32(1);
+    assert_eq!(Pos::from((unit, 10)), pos_from_u32(unit, 10));
+    assert_eq!(Span::from((unit, 3, 5)), span_from_u32(unit, 3, 5));
+    assert_eq!(Span::from((unit, 3, 5)), Span::new((unit, 3).into(), (unit, 5).into()));
+    assert_eq!(Span::from((unit, 7, 7)), Span::from(Pos::from((unit, 7))));
+    assert_eq!(Span::from((unit, 5, 3)), span_from_u32(unit, 3, 5));
+    assert_eq!(Span::from((Unit::dummy(), 3, 5)), Span::dummy());
+}
+
+#[test]
+fn test_spanned_cmp_base() {
+    let unit = unit_from_u32(1);
+    let a = "a".with_loc(span_from_u32(unit, 5, 6));
+    let b = "b".with_loc(span_from_u32(unit, 1, 2));
+    assert_eq!(a.cmp_base(&b), cmp::Ordering::Less);
+    assert_eq!(a.cmp_base(&"a".without_loc()), cmp::Ordering::Equal);
+    assert!(a != "a".without_loc());
+
+    let mut names = vec![b, a, "c".without_loc()];
+    names.sort_by(Spanned::cmp_base);
+    assert_eq!(names.iter().map(|s| s.base).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+}
@@ -351,6 +351,25 @@ impl<'a> SourceSlice<'a> {
             SourceSlice::U16(s) => s.len(),
         }
     }
+
+    /// Strips any trailing newlines (`\r` or `\n`) from the slice.
+    pub fn strip_newline(mut self) -> SourceSlice<'a> {
+        match self {
+            SourceSlice::U8(ref mut s) => loop {
+                match s.last() {
+                    Some(&b'\r') | Some(&b'\n') => { *s = &s[..s.len()-1]; }
+                    _ => { break; }
+                }
+            },
+            SourceSlice::U16(ref mut s) => loop {
+                match s.last() {
+                    Some(&0x0a) | Some(&0x0d) => { *s = &s[..s.len()-1]; }
+                    _ => { break; }
+                }
+            },
+        }
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -507,3 +526,83 @@ impl Source {
     }
 }
 
+#[test]
+fn test_span_chars_in() {
+    let mut source = Source::new();
+
+    let span = source.add(SourceFile::from_u8("foo".into(), b"a\xea\xb0\x80\xffb\xe0\xa0".to_vec()));
+    let chars = span.chars_in(&source).unwrap()
+                    .map(|(pos, c)| (pos.to_usize() - span.begin().to_usize(), c))
+                    .collect::<Vec<_>>();
+    assert_eq!(chars, vec![(0, 'a'), (1, '\u{ac00}'), (4, '\u{fffd}'),
+                           (5, 'b'), (6, '\u{fffd}')]);
+
+    let span = source.add(SourceFile::from_u16("bar".into(),
+                                               vec![0x61, 0xd83d, 0xde00, 0xdc00, 0x62]));
+    let chars = span.chars_in(&source).unwrap()
+                    .map(|(pos, c)| (pos.to_usize() - span.begin().to_usize(), c))
+                    .collect::<Vec<_>>();
+    assert_eq!(chars, vec![(0, 'a'), (1, '\u{1f600}'), (3, '\u{fffd}'), (4, 'b')]);
+
+    assert!(Span::dummy().chars_in(&source).is_none());
+}
+
+#[test]
+fn test_span_to_line_col_range() {
+    use loc::LineColRange;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(),
+                                              "ab\n\tcd\n\u{ac00}x".as_bytes().to_vec()));
+    let pos = |ch| span.chars_in(&source).unwrap().find(|&(_, c)| c == ch).unwrap().0;
+
+    let range = |begin, end| Span::new(begin, end).to_line_col_range(&source);
+    assert_eq!(range(pos('b'), pos('c')),
+               Some(LineColRange { start_line: 0, start_col: 1, end_line: 1, end_col: 8 }));
+    assert_eq!(range(pos('x'), span.end()),
+               Some(LineColRange { start_line: 2, start_col: 2, end_line: 2, end_col: 3 }));
+    assert_eq!(Span::dummy().to_line_col_range(&source), None);
+}
+
+#[test]
+fn test_span_to_source_location() {
+    use std::path::PathBuf;
+    use loc::SourceLocation;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo/bar.lua".into(), b"ab\n\tcd".to_vec()));
+    let pos = |off: usize| span.begin() + off;
+
+    assert_eq!(Span::new(pos(1), pos(5)).to_source_location(&source),
+               SourceLocation { path: Some(PathBuf::from("foo/bar.lua")),
+                                start: (0, 1), end: (1, 9) });
+    assert_eq!(Span::dummy().to_source_location(&source),
+               SourceLocation { path: None, start: (0, 0), end: (0, 0) });
+}
+
+#[test]
+fn test_span_enclosing_line() {
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"ab
+cde
+f".to_vec()));
+    let pos = |off: usize| span.begin() + off;
+    let line = |begin: usize, end: usize| Some(Span::new(pos(begin), pos(end)));
+
+    assert_eq!(Span::from(pos(0)).enclosing_line(&source), line(0, 3));
+    assert_eq!(Span::new(pos(1), pos(5)).enclosing_line(&source), line(0, 3));
+    assert_eq!(Span::new(pos(3), pos(4)).enclosing_line(&source), line(3, 7));
+    assert_eq!(Span::new(pos(8), pos(9)).enclosing_line(&source), line(7, 8));
+    assert_eq!(Span::dummy().enclosing_line(&source), None);
+}
+
+#[test]
+fn test_unit_display_path() {
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo/bar.lua".into(), b"x".to_vec()));
+
+    assert_eq!(span.unit().display_path(&source).to_string(), "bar.lua");
+    assert_eq!(span.unit().display_full_path(&source).to_string(), "foo/bar.lua");
+    assert_eq!(Unit::dummy().display_path(&source).to_string(), "<unknown>");
+    assert_eq!(Unit::builtin().display_full_path(&source).to_string(), "<builtin>");
+}
@@ -1,5 +1,6 @@
 use std::ops;
 use std::fmt;
+use std::cmp;
 use std::hash;
 use std::sync::Arc;
 use std::collections::HashSet;
@@ -80,6 +81,15 @@ struct ReportTreeInner {
     parents: Mutex<HashSet<Arc<ReportTreeInner>>>,
 
     collected: Mutex<Vec<(String, Diagnostic)>>,
+
+    // the most severe kind reported to this tree, including untranslated reports
+    max_kind: Mutex<Option<Kind>>,
+
+    // the span of the most recent error, including untranslated reports
+    last_error: Mutex<Option<Span>>,
+
+    // deduplication keys for `Report::mark_once`
+    seen: Mutex<HashSet<String>>,
 }
 
 impl PartialEq for ReportTreeInner {
@@ -106,6 +116,9 @@ impl ReportTree {
                 locale: locale,
                 parents: Mutex::new(HashSet::new()),
                 collected: Mutex::new(Vec::new()),
+                max_kind: Mutex::new(None),
+                last_error: Mutex::new(None),
+                seen: Mutex::new(HashSet::new()),
             })
         }
     }
@@ -162,6 +175,14 @@ impl<F> Report for ReportTreeReport<F>
     }
 
     fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> kailua_diag::Result<()> {
+        {
+            let mut max_kind = self.inner.max_kind.lock();
+            *max_kind = cmp::max(*max_kind, Some(kind));
+        }
+        if kind >= Kind::Error {
+            *self.inner.last_error.lock() = Some(span);
+        }
+
         let msg = Localized::new(msg, self.inner.locale).to_string();
 
         // TODO span should be translated _after_ deciding whether to put a new diagnostic,
@@ -207,6 +228,22 @@ impl<F> Report for ReportTreeReport<F>
 
         if kind == Kind::Fatal { Err(kailua_diag::Stop) } else { Ok(()) }
     }
+
+    fn max_kind(&self) -> Option<Kind> {
+        *self.inner.max_kind.lock()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.inner.seen.lock().insert(key.to_owned())
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        *self.inner.last_error.lock()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.max_kind.lock().is_none()
+    }
 }
 
 pub struct Diagnostics<'a> {
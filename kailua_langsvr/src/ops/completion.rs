@@ -138,7 +138,7 @@ fn is_name_completion_disabled(tokens: &[NestedToken], name_idx: usize) -> bool
         // name_decl_possible can continue to func_sig_possible in place, so this should be first
         if func_sig_possible {
             match tok.tok.base {
-                Tok::Comment |
+                Tok::Comment(_) |
                 Tok::Name(_) |
                 Tok::Punct(Punct::Dot) |
                 Tok::Punct(Punct::Colon) => {},
@@ -151,7 +151,7 @@ fn is_name_completion_disabled(tokens: &[NestedToken], name_idx: usize) -> bool
 
         if name_decl_possible {
             match tok.tok.base {
-                Tok::Comment |
+                Tok::Comment(_) |
                 Tok::Name(_) |
                 Tok::Punct(Punct::Comma) |
                 // Newline to account for meta comments (other tokens are nested)
@@ -12,7 +12,7 @@ pub mod definition; // also contains rename
 
 fn last_non_comment(tokens: &[NestedToken]) -> Option<(usize, &NestedToken)> {
     tokens.iter().enumerate().rev().find(|&(_, tok)| {
-        match tok.tok.base { Tok::Comment => false, _ => true }
+        match tok.tok.base { Tok::Comment(_) => false, _ => true }
     })
 }
 
@@ -130,10 +130,12 @@ fn collect_tokens(source: &Source, span: Span, report: &Report) -> Vec<NestedTok
     tokens
 }
 
-fn parse_to_chunk(tokens: Vec<NestedToken>, report: &Report) -> kailua_diag::Result<Chunk> {
+fn parse_to_chunk(tokens: Vec<NestedToken>, source: &Source,
+                  report: &Report) -> kailua_diag::Result<Chunk> {
     let mut tokens = tokens.into_iter();
-    let chunk = Parser::new(&mut tokens, report).into_chunk();
-    chunk
+    let mut chunk = Parser::new(&mut tokens, report).into_chunk()?;
+    chunk.resolve_ignored_lines(source);
+    Ok(chunk)
 }
 
 #[derive(Clone, Debug)]
@@ -380,12 +382,10 @@ impl WorkspaceFile {
                 let diags = ReportTree::new(inner.message_locale, None);
                 diags.add_parent(parent_diags);
 
-                // in this future source access is only needed for reporting
                 let chunk = {
-                    let report = diags.report(|span| {
-                        diags::translate_span(span, &inner.source.read())
-                    });
-                    parse_to_chunk(tokens, &report)
+                    let source = inner.source.read();
+                    let report = diags.report(|span| diags::translate_span(span, &source));
+                    parse_to_chunk(tokens, &source, &report)
                 };
                 match chunk {
                     Ok(chunk) => {
@@ -596,7 +596,7 @@ impl FsSource for WorkspaceFsSource {
             let source = fssource.source.read();
             let report = diags.report(|span| diags::translate_span(span, &source));
             let tokens = collect_tokens(&source, span, &report);
-            parse_to_chunk(tokens, &report)
+            parse_to_chunk(tokens, &source, &report)
         };
         match chunk {
             Ok(chunk) => {
@@ -8,7 +8,7 @@
 use std::fmt;
 use std::cell::Cell;
 use std::collections::HashMap;
-use kailua_env::{Span, Spanned, Scope, ScopedId, ScopeMap};
+use kailua_env::{Span, Spanned, Scope, ScopedId, ScopeMap, Source};
 
 use string::{Str, Name};
 
@@ -28,6 +28,34 @@ impl fmt::Display for Comma {
     }
 }
 
+/// Any AST node with an associated span.
+///
+/// Most AST nodes do not have their own spans and are wrapped in `Spanned` instead,
+/// so this is primarily implemented for `Spanned<T>`.
+/// Other nodes get the smallest span covering all of their spanned components,
+/// which can be a dummy span if no component has a span.
+pub trait AstNode {
+    /// Returns the span of this node.
+    fn span(&self) -> Span;
+}
+
+impl<T> AstNode for Spanned<T> {
+    fn span(&self) -> Span { self.span }
+}
+
+impl<T: AstNode> AstNode for Box<T> {
+    fn span(&self) -> Span { (**self).span() }
+}
+
+impl<T: AstNode> AstNode for Option<T> {
+    fn span(&self) -> Span { self.as_ref().map_or(Span::dummy(), |node| node.span()) }
+}
+
+/// The span of the sequence of nodes like `Block`.
+impl<T: AstNode> AstNode for Vec<T> {
+    fn span(&self) -> Span { Span::merge_all(self.iter().map(|node| node.span())) }
+}
+
 /// A resolved reference to the name, either local or global.
 //
 // why don't we use scoped ids everywhere? scoped ids are bound to the scope map,
@@ -84,6 +112,10 @@ pub struct Attr {
     pub values: Option<Spanned<Vec<Spanned<AttrValue>>>>,
 }
 
+impl AstNode for Attr {
+    fn span(&self) -> Span { self.name.span | self.values.span() }
+}
+
 impl fmt::Debug for Attr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(ref values) = self.values {
@@ -129,6 +161,10 @@ impl<Head, Tail> Seq<Head, Tail> {
     }
 }
 
+impl<Head: AstNode, Tail: AstNode> AstNode for Seq<Head, Tail> {
+    fn span(&self) -> Span { self.head.span() | self.tail.span() }
+}
+
 impl<Head: fmt::Debug, Tail: fmt::Debug> fmt::Debug for Seq<Head, Tail> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if !f.sign_minus() { write!(f, "[")?; }
@@ -185,6 +221,10 @@ impl<T> TypeSpec<T> {
     }
 }
 
+impl<T: AstNode> AstNode for TypeSpec<T> {
+    fn span(&self) -> Span { self.base.span() | self.kind.span() }
+}
+
 impl<T: fmt::Debug> fmt::Debug for TypeSpec<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self.base)?;
@@ -199,23 +239,57 @@ impl<T: fmt::Debug> fmt::Debug for TypeSpec<T> {
 /// A return type of a function in the Kailua type.
 #[derive(Clone, PartialEq)]
 pub enum Returns {
-    /// `--> type` or `--> (type, type...)`.
-    Seq(Seq<Spanned<Kind>>),
+    /// `--> type` or `--> (type, type...)`, where each type can be optionally named
+    /// (`--> (name: type, name: type)`).
+    ///
+    /// As like function arguments, the name is purely for description.
+    Seq(Seq<(Option<Spanned<Name>>, Spanned<Kind>), Spanned<Kind>>),
 
-    /// `--> !`.
+    /// `--> !` or `--> never`.
     ///
-    /// The span points to a token `!`.
+    /// The span points to a token `!` or `never`.
     Never(Span),
 }
 
+impl AstNode for Returns {
+    fn span(&self) -> Span {
+        match *self {
+            Returns::Seq(ref seq) => {
+                let head = seq.head.iter().map(|&(ref n, ref k)| n.span() | k.span);
+                Span::merge_all(head) | seq.tail.span()
+            },
+            Returns::Never(span) => span,
+        }
+    }
+}
+
+// prints a sequence of optionally named types without surrounding parentheses
+fn fmt_named_kind_seq(seq: &Seq<(Option<Spanned<Name>>, Spanned<Kind>), Spanned<Kind>>,
+                      f: &mut fmt::Formatter) -> fmt::Result {
+    let comma = Comma::new();
+    for &(ref name, ref kind) in &seq.head {
+        write!(f, "{}", comma)?;
+        if let &Some(ref name) = name {
+            write!(f, "{:?}: ", name)?;
+        }
+        write!(f, "{:?}", kind)?;
+    }
+    if let Some(ref kind) = seq.tail {
+        write!(f, "{}{:?}...", comma, kind)?;
+    }
+    Ok(())
+}
+
 impl fmt::Debug for Returns {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Returns::Seq(ref seq) => {
-                if seq.head.len() == 1 && seq.tail.is_none() {
-                    write!(f, "{:?}", seq.head[0])
+                if seq.head.len() == 1 && seq.tail.is_none() && seq.head[0].0.is_none() {
+                    write!(f, "{:?}", seq.head[0].1)
                 } else {
-                    write!(f, "({:-?})", seq)
+                    write!(f, "(")?;
+                    fmt_named_kind_seq(seq, f)?;
+                    write!(f, ")")
                 }
             },
             Returns::Never(span) => {
@@ -237,6 +311,10 @@ pub struct Varargs {
     pub legacy_arg: Option<Spanned<ScopedId>>,
 }
 
+impl AstNode for Varargs {
+    fn span(&self) -> Span { self.kind.span() | self.legacy_arg.span() }
+}
+
 impl fmt::Debug for Varargs {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "...")?;
@@ -266,6 +344,10 @@ pub struct Sig {
     pub returns: Option<Returns>,
 }
 
+impl AstNode for Sig {
+    fn span(&self) -> Span { self.attrs.span() | self.args.span | self.returns.span() }
+}
+
 impl fmt::Debug for Sig {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for attr in &self.attrs {
@@ -282,11 +364,14 @@ impl fmt::Debug for Sig {
         write!(f, "]")?;
         match self.returns {
             Some(Returns::Seq(Seq { ref head, tail: None })) if head.is_empty() => Ok(()),
-            Some(Returns::Seq(Seq { ref head, tail: None })) if head.len() == 1 => {
-                write!(f, " --> {:?}", head[0])
+            Some(Returns::Seq(Seq { ref head, tail: None }))
+                    if head.len() == 1 && head[0].0.is_none() => {
+                write!(f, " --> {:?}", head[0].1)
             },
             Some(Returns::Seq(ref seq)) => {
-                write!(f, " --> {:?}", seq)
+                write!(f, " --> [")?;
+                fmt_named_kind_seq(seq, f)?;
+                write!(f, "]")
             },
             Some(Returns::Never(span)) => {
                 write!(f, " --> !")?;
@@ -306,6 +391,12 @@ pub struct Table {
     pub items: Vec<(Option<Spanned<Exp>>, Spanned<Exp>)>,
 }
 
+impl AstNode for Table {
+    fn span(&self) -> Span {
+        Span::merge_all(self.items.iter().map(|&(ref k, ref v)| k.span() | v.span))
+    }
+}
+
 impl fmt::Debug for Table {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{{")?;
@@ -415,6 +506,17 @@ pub enum Ex {
     Bin(Spanned<Exp>, Spanned<BinOp>, Spanned<Exp>),
 }
 
+impl Ex {
+    /// Returns true if the expression can be converted to `Var`,
+    /// i.e. it can appear in the left side of assignments.
+    pub fn is_lvalue(&self) -> bool {
+        match *self {
+            Ex::Var(_) | Ex::Index(..) | Ex::IndexName(..) => true,
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Debug for Ex {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -532,6 +634,16 @@ impl fmt::Debug for SelfParam {
     }
 }
 
+/// An attribute to the local variable (`local name <attr> = ...`), available since Lua 5.4.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LocalAttr {
+    /// `<const>`.
+    Const,
+
+    /// `<close>`. The value should have a `__close` metamethod, or be `nil` or `false`.
+    Close,
+}
+
 /// A scope of the named Kailua type (from `--# type`).
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TypeScope {
@@ -600,8 +712,11 @@ pub enum St {
     MethodDecl(Spanned<(Spanned<NameRef>, Vec<Spanned<Name>>)>,
                Option<Spanned<SelfParam>>, Sig, Scope, Spanned<Block>),
 
-    /// `local name, ... = exp, ...`.
-    Local(Spanned<Vec<TypeSpec<Spanned<ScopedId>>>>, Spanned<Vec<Spanned<Exp>>>, Scope),
+    /// `local name [<attr>], ... = exp, ...`.
+    ///
+    /// The second field has an optional attribute for each name.
+    Local(Spanned<Vec<TypeSpec<Spanned<ScopedId>>>>, Vec<Option<Spanned<LocalAttr>>>,
+          Spanned<Vec<Spanned<Exp>>>, Scope),
 
     /// `return exp, ...`.
     Return(Spanned<Vec<Spanned<Exp>>>),
@@ -638,6 +753,14 @@ pub enum St {
     /// `--# assume [global] class[(...)] ClassName[: ParentClassName]`.
     KailuaAssumeClass(Option<Spanned<Name>> /*system*/, Spanned<RenameRef> /*variable & type name*/,
                       Option<Spanned<Name>> /*parent type name*/, Option<Scope>),
+
+    /// `--# pragma enable lint-name` or `--# pragma disable lint-name`.
+    ///
+    /// The `bool` is true when the lint is enabled.
+    KailuaPragma(bool /*enable*/, Spanned<Name>),
+
+    /// `--# pragma option-name`, e.g. `--# pragma strict-nil`.
+    KailuaPragmaOption(Spanned<Name>),
 }
 
 /// In the debugging output scopes are printed in two ways:
@@ -684,7 +807,11 @@ impl fmt::Debug for St {
                 for i in ii { write!(f, ".{:?}", i)?; }
                 write!(f, "){:?}, {:?}, {:?}, {:?}{:?})", span, selfparam, sig, bs, b)
             },
-            St::Local(ref ii, ref ee, is) => write!(f, "Local({:?}, {:?}){:?}", ii, ee, is),
+            St::Local(ref ii, ref aa, ref ee, is) => {
+                write!(f, "Local({:?}, ", ii)?;
+                if aa.iter().any(|a| a.is_some()) { write!(f, "{:?}, ", aa)?; }
+                write!(f, "{:?}){:?}", ee, is)
+            },
             St::Return(ref ee) => write!(f, "Return({:?})", ee),
             St::Break => write!(f, "Break"),
 
@@ -712,6 +839,8 @@ impl fmt::Debug for St {
                 if let Some(is) = is { write!(f, "{:?}", is)?; }
                 Ok(())
             },
+            St::KailuaPragma(enable, ref name) => write!(f, "KailuaPragma({}, {:?})", enable, name),
+            St::KailuaPragmaOption(ref name) => write!(f, "KailuaPragmaOption({:?})", name),
         }
     }
 }
@@ -748,9 +877,14 @@ impl fmt::Debug for M {
 /// This is a superset of `M` used only for variables.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum MM {
-    /// Mutable (default, no separate keyword exists).
+    /// Mutable (default, no modifier written).
     None,
 
+    /// Mutable, explicitly written (`var`).
+    ///
+    /// This is semantically identical to `MM::None` but retained for tools.
+    Var,
+
     /// Immutable (`const`).
     Const,
 
@@ -760,14 +894,22 @@ pub enum MM {
     /// and assignments to resulting fields are stored to the current scope (and not checked).
     /// Stored nodes are checked at the end of the scope where variables were registered.
     Module,
+
+    /// Assignable only once (`once`).
+    ///
+    /// The variable becomes immutable (`const`) after the first assignment,
+    /// which may be its initialization or the first assignment to an uninitialized variable.
+    Once,
 }
 
 impl fmt::Debug for MM {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             MM::None => write!(f, "_"),
+            MM::Var => write!(f, "Var"),
             MM::Const => write!(f, "Const"),
             MM::Module => write!(f, "Module"),
+            MM::Once => write!(f, "Once"),
         }
     }
 }
@@ -782,15 +924,47 @@ pub struct SlotKind {
     pub kind: Spanned<Kind>,
 }
 
+impl AstNode for SlotKind {
+    fn span(&self) -> Span { self.kind.span }
+}
+
 impl fmt::Debug for SlotKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} {:?}", self.modf, self.kind)
     }
 }
 
+/// A type parameter in the function type for Kailua.
+#[derive(Clone, PartialEq)]
+pub struct TyParam {
+    /// A name of the type parameter.
+    pub name: Spanned<Name>,
+
+    /// A name of the bound (e.g. `Stringy`) if any.
+    ///
+    /// The parser accepts any name, and the checker will issue an error on unknown bounds.
+    pub bound: Option<Spanned<Name>>,
+}
+
+impl fmt::Debug for TyParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name)?;
+        if let Some(ref bound) = self.bound {
+            write!(f, ": {:?}", bound)?;
+        }
+        Ok(())
+    }
+}
+
 /// A function type for Kailua.
 #[derive(Clone, PartialEq)]
 pub struct FuncKind {
+    /// A list of type parameters with optional bounds.
+    ///
+    /// Both `function<T: Stringy>(...)` and `function<T where T: Stringy>(...)` result in
+    /// the same list.
+    pub typarams: Vec<TyParam>,
+
     /// A list of argument types with optional names.
     ///
     /// The name is purely for description and has no effect in the type.
@@ -802,8 +976,23 @@ pub struct FuncKind {
     pub returns: Returns,
 }
 
+impl AstNode for FuncKind {
+    fn span(&self) -> Span {
+        let args = Span::merge_all(self.args.head.iter().map(|&(ref n, ref k)| n.span() | k.span));
+        args | self.args.tail.span() | self.returns.span()
+    }
+}
+
 impl fmt::Debug for FuncKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.typarams.is_empty() {
+            write!(f, "<")?;
+            let comma = Comma::new();
+            for typaram in &self.typarams {
+                write!(f, "{}{:?}", comma, typaram)?;
+            }
+            write!(f, ">")?;
+        }
         write!(f, "(")?;
         let comma = Comma::new();
         for &(ref name, ref arg) in &self.args.head {
@@ -1035,5 +1224,79 @@ pub struct Chunk {
 
     /// Auxiliary informations for each input token (including `Tok::EOF`), in the order.
     pub token_aux: Vec<TokenAux>,
+
+    /// Spans of `--# ignore-next-line` directives, in the order.
+    pub ignore_next_line: Vec<Span>,
+
+    /// Spans of lines where any diagnostics should be ignored.
+    ///
+    /// The parser doesn't know where each line ends,
+    /// so this is empty until `Chunk::resolve_ignored_lines` is called.
+    /// `kailua_syntax::parse_chunk` does this automatically.
+    pub ignored_lines: Vec<Span>,
+}
+
+impl AstNode for Chunk {
+    fn span(&self) -> Span { self.block.span }
+}
+
+impl Chunk {
+    /// Calculates `ignored_lines` from `ignore_next_line` directives and the source.
+    pub fn resolve_ignored_lines(&mut self, source: &Source) {
+        self.ignored_lines.clear();
+        for directive in &self.ignore_next_line {
+            if let Some(file) = source.get_file(directive.unit()) {
+                if let Some((line, _)) = file.line_from_pos(directive.begin()) {
+                    if let Some(nextline) = file.line_spans().nth(line + 1) {
+                        self.ignored_lines.push(nextline);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ast_node_span() {
+    use kailua_env::SourceFile;
+    use kailua_diag::{CollectedReport, Locale};
+
+    let mut source = Source::new();
+    let code = b"local a = 1\nlocal b = {2, x = 3}\n";
+    let span = source.add(SourceFile::from_u8("foo".into(), code.to_vec()));
+    let report = CollectedReport::new(Locale::dummy());
+    let chunk = ::parse_chunk(&source, span, &report).unwrap();
+
+    let slice = |node: &AstNode| {
+        let span = node.span();
+        code[span.begin().to_usize()..span.end().to_usize()].to_vec()
+    };
+
+    assert_eq!(slice(&chunk.block.base), b"local a = 1\nlocal b = {2, x = 3}".to_vec());
+    assert_eq!(slice(&chunk.block.base[0]), b"local a = 1".to_vec());
+    if let St::Local(_, _, ref exps, _) = *chunk.block.base[1].base {
+        if let Ex::Table(ref table) = *exps[0].base {
+            assert_eq!(slice(table), b"2, x = 3".to_vec());
+        } else {
+            panic!("not a table constructor");
+        }
+    } else {
+        panic!("not a local statement");
+    }
+    assert_eq!(Vec::<Spanned<Stmt>>::new().span(), Span::dummy());
 }
 
+#[test]
+fn test_ex_is_lvalue() {
+    use kailua_env::WithLoc;
+
+    let exp = |ex: Ex| Box::new(ex).without_loc();
+    let name = || Name::from(b"a"[..].to_owned()).without_loc();
+
+    assert!(Ex::Var(NameRef::Global(name().base).without_loc()).is_lvalue());
+    assert!(Ex::Index(exp(Ex::Oops), exp(Ex::Num(1.0))).is_lvalue());
+    assert!(Ex::IndexName(exp(Ex::Oops), name()).is_lvalue());
+    assert!(!Ex::Nil.is_lvalue());
+    assert!(!Ex::Exp(exp(Ex::Var(NameRef::Global(name().base).without_loc()))).is_lvalue());
+    assert!(!Ex::FuncCall(exp(Ex::Oops), Args::List(Vec::new()).without_loc()).is_lvalue());
+}
@@ -82,6 +82,9 @@ pub enum Lua {
 
     /// Lua 5.3.
     Lua53 = 0x53,
+
+    /// Lua 5.4.
+    Lua54 = 0x54,
 }
 
 impl Lua {
@@ -90,6 +93,7 @@ impl Lua {
             0x51 => Some(Lua::Lua51),
             0x52 => Some(Lua::Lua52),
             0x53 => Some(Lua::Lua53),
+            0x54 => Some(Lua::Lua54),
             _ => None,
         }
     }
@@ -99,8 +103,29 @@ impl Lua {
             Lua::Lua51 => "Lua 5.1",
             Lua::Lua52 => "Lua 5.2",
             Lua::Lua53 => "Lua 5.3",
+            Lua::Lua54 => "Lua 5.4",
         }
     }
+
+    /// Returns true if `goto` is a keyword and labels (`::name::`) are available.
+    pub fn supports_goto(&self) -> bool {
+        *self >= Lua::Lua52
+    }
+
+    /// Returns true if bitwise operators (`&`, `|`, `~`, `<<`, `>>` and `//`) are available.
+    pub fn supports_bitwise(&self) -> bool {
+        *self >= Lua::Lua53
+    }
+
+    /// Returns true if integers are distinct from floating-point numbers at runtime.
+    pub fn supports_integer_type(&self) -> bool {
+        *self >= Lua::Lua53
+    }
+
+    /// Returns true if local variables can have the `<close>` attribute.
+    pub fn supports_close_attr(&self) -> bool {
+        *self >= Lua::Lua54
+    }
 }
 
 impl fmt::Debug for Lua {
@@ -154,3 +179,20 @@ impl Localize for Kailua {
     }
 }
 
+#[test]
+fn test_lua_capabilities() {
+    let versions = [Lua::Lua51, Lua::Lua52, Lua::Lua53, Lua::Lua54];
+    let caps: Vec<_> = versions.iter().map(|v| {
+        (v.supports_goto(), v.supports_bitwise(),
+         v.supports_integer_type(), v.supports_close_attr())
+    }).collect();
+    assert_eq!(caps, [(false, false, false, false),
+                      (true, false, false, false),
+                      (true, true, true, false),
+                      (true, true, true, true)]);
+
+    for &v in &versions {
+        assert_eq!(Lua::from_u32(v as u32), Some(v));
+    }
+    assert_eq!(Lua::from_u32(0x55), None);
+}
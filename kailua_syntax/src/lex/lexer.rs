@@ -13,6 +13,24 @@ fn is_digit(c: SourceData) -> bool {
     match c { U8(b'0'...b'9') => true, _ => false }
 }
 
+fn hex_digit(c: SourceData) -> Option<u8> {
+    match c { U8(c) => (c as char).to_digit(16).map(|d| d as u8), _ => None }
+}
+
+// appends the UTF-8 representation of given data to the buffer.
+// surrogates are individually replaced with U+FFFD, so this is lossy for non-BMP UTF-16 data.
+fn push_data(buf: &mut Vec<u8>, c: SourceData) {
+    match c {
+        U8(c) => buf.push(c),
+        U16(c) => {
+            let c = ::std::char::from_u32(c as u32).unwrap_or('\u{fffd}');
+            let mut encoded = [0; 4];
+            buf.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+        }
+        EOF => {}
+    }
+}
+
 fn normalize_data(c: SourceData) -> SourceData {
     // normalize ASCII letters to U8, so that we can easily check against them
     match c {
@@ -34,7 +52,11 @@ pub struct Lexer<'a> {
     lookahead: bool,
     meta: bool,
     meta_span: Span,
-    shebang: Option<Span>,
+    shebang: Option<Spanned<Vec<u8>>>,
+    // the raw data read so far for the current comment, if any;
+    // maintained by `read` and `unread` so that the lookahead is excluded
+    recorded: Option<Vec<u8>>,
+    last_recorded_len: usize,
     eof: bool,
     report: &'a Report,
 }
@@ -50,7 +72,9 @@ impl<'a> Lexer<'a> {
         let shebang = if first.base == U8(b'#') || first.base == U16(b'#' as u16) {
             let begin = first.span.begin();
             let mut end = first.span.end();
+            let mut text = Vec::new();
             loop {
+                push_data(&mut text, normalize_data(first.base));
                 first = bytes.next().expect("no EOF after the end of input stream");
                 if first.base == U8(b'\n') || first.base == U16(b'\n' as u16) {
                     // the next byte is the beginning of the second line
@@ -63,7 +87,7 @@ impl<'a> Lexer<'a> {
                     end = first.span.end();
                 }
             }
-            Some(Span::new(begin, end))
+            Some(text.with_loc(Span::new(begin, end)))
         } else {
             None
         };
@@ -77,6 +101,8 @@ impl<'a> Lexer<'a> {
             meta: false,
             meta_span: Span::dummy(),
             shebang: shebang,
+            recorded: None,
+            last_recorded_len: 0,
             eof: false,
             report: report,
         }
@@ -91,7 +117,7 @@ impl<'a> Lexer<'a> {
     }
 
     fn read(&mut self) -> SourceData {
-        if self.lookahead {
+        let c = if self.lookahead {
             self.lookahead = false;
             self.last_data
         } else {
@@ -104,7 +130,12 @@ impl<'a> Lexer<'a> {
             } else {
                 EOF
             }
+        };
+        if let Some(ref mut recorded) = self.recorded {
+            self.last_recorded_len = recorded.len();
+            push_data(recorded, c);
         }
+        c
     }
 
     fn unread(&mut self, last: SourceData) {
@@ -112,6 +143,9 @@ impl<'a> Lexer<'a> {
         assert!(!self.last_pos.is_dummy());
         assert_eq!(self.last_data, last);
         self.lookahead = true;
+        if let Some(ref mut recorded) = self.recorded {
+            recorded.truncate(self.last_recorded_len);
+        }
     }
 
     fn try<Cond>(&mut self, mut cond: Cond) -> Option<SourceData>
@@ -197,6 +231,24 @@ impl<'a> Lexer<'a> {
         Ok(())
     }
 
+    // assumes that the leading byte `lead` of UTF-8 sequence is already read.
+    // reads as many continuation bytes as required by the leading byte (and no more),
+    // and returns true only if the sequence was complete.
+    fn scan_utf8_continuation(&mut self, lead: u8) -> bool {
+        let expected = match lead {
+            0xc0...0xdf => 1,
+            0xe0...0xef => 2,
+            0xf0...0xf7 => 3,
+            _ => unreachable!("invalid UTF-8 leading byte {:#x}", lead),
+        };
+        for _ in 0..expected {
+            if self.try(|c| match c { U8(0x80...0xbf) => true, _ => false }).is_none() {
+                return false;
+            }
+        }
+        true
+    }
+
     fn count_equals(&mut self) -> i32 {
         let mut v = 0;
         self.scan_while(|c| c == U8(b'='), |_| v += 1);
@@ -305,6 +357,27 @@ impl<'a> Lexer<'a> {
                         }
                         f(n)
                     },
+                    // exactly two hexadecimal digits. this is not in Lua 5.1 but
+                    // accepted as in Lua 5.2 and later; without any digit it is unrecognized.
+                    // the malformed escape is reported and skipped, like unrecognized ones.
+                    U8(b'x') => {
+                        match self.try(|c| hex_digit(c).is_some()) {
+                            Some(hi) => match self.try(|c| hex_digit(c).is_some()) {
+                                Some(lo) => f(hex_digit(hi).unwrap() << 4 |
+                                              hex_digit(lo).unwrap()),
+                                None => {
+                                    self.report.error(lastpos..self.pos(),
+                                                      m::InvalidHexEscapeInString {})
+                                               .done()?;
+                                }
+                            },
+                            None => {
+                                self.report.error(lastpos..self.pos(),
+                                                  m::UnrecognizedEscapeInString {})
+                                           .done()?;
+                            }
+                        }
+                    },
                     U8(_) | U16(_) => {
                         self.report.error(lastpos..self.pos(), m::UnrecognizedEscapeInString {})
                                    .done()?;
@@ -341,8 +414,8 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn next_token(&mut self) -> diag::Result<Option<Spanned<Tok>>> {
-        if let Some(span) = self.shebang.take() {
-            return Ok(Some(Tok::Comment.with_loc(span)));
+        if let Some(shebang) = self.shebang.take() {
+            return Ok(Some(shebang.map(Tok::Comment)));
         }
 
         loop {
@@ -359,7 +432,8 @@ impl<'a> Lexer<'a> {
             let begin = self.pos();
 
             macro_rules! tok {
-                (@token Comment)          => (Tok::Comment);
+                (@token Error)            => (Tok::Error);
+                (@token Comment)          => (Tok::Comment(self.recorded.take().unwrap()));
                 (@token Keyword($e:expr)) => (Tok::Keyword($e));
                 (@token Name($e:expr))    => (Tok::Name($e));
                 (@token Num($e:expr))     => (Tok::Num($e));
@@ -368,6 +442,7 @@ impl<'a> Lexer<'a> {
 
                 (meta: $($t:tt)*) => ({
                     let span = Span::new(begin, self.pos());
+                    self.recorded = None;
                     self.meta = true;
                     self.meta_span = span;
                     Ok(Some(tok!(@token $($t)*).with_loc(span)))
@@ -478,6 +553,8 @@ impl<'a> Lexer<'a> {
 
                 U8(b'-') => match self.read() {
                     U8(b'-') => {
+                        // this is either a comment or a meta block, record it for the former
+                        self.recorded = Some(b"--".to_vec());
                         match self.read() {
                             U8(b'[') => {
                                 if let Some(c) = self.try(|c| c == U8(b'[') || c == U8(b'=')) {
@@ -574,7 +651,31 @@ impl<'a> Lexer<'a> {
                 U8(b'!') if self.meta => return tok!(Bang),
                 U8(b'|') if self.meta => return tok!(Pipe),
 
-                U8(_) | U16(_) => {
+                // a continuation byte without any leading byte
+                U8(0x80...0xbf) => {
+                    // skip to the next character boundary
+                    self.scan_while(|c| match c { U8(0x80...0xbf) => true, _ => false }, |_| {});
+                    self.report.error(begin..self.pos(), m::StrayUtf8ContinuationByte {}).done()?;
+                    return tok!(Error);
+                },
+
+                // a byte which can never start any UTF-8 sequence
+                U8(0xf8...0xff) => {
+                    self.scan_while(|c| match c { U8(0x80...0xbf) => true, _ => false }, |_| {});
+                    self.report.error(begin..self.pos(), m::InvalidUtf8LeadingByte {}).done()?;
+                    return tok!(Error);
+                },
+
+                c @ U8(_) | c @ U16(_) => {
+                    // a leading byte possibly followed by too few continuation bytes
+                    if let U8(lead @ 0xc0...0xf7) = c {
+                        if !self.scan_utf8_continuation(lead) {
+                            self.report.error(begin..self.pos(), m::IncompleteUtf8Sequence {})
+                                       .done()?;
+                            return tok!(Error);
+                        }
+                    }
+
                     // try to consume more invalid multi-byte characters in a row
                     self.scan_while(
                         |c| match c { U8(0x80...0xff) | U16(_) => true, _ => false },
@@ -609,3 +710,129 @@ impl<'a> Iterator for Lexer<'a> {
     }
 }
 
+/// The lexical analyzer that produces plain `(Tok, Span)` pairs.
+///
+/// This is a thin wrapper around `Lexer`, for embedding into other tools
+/// (e.g. syntax highlighters) which do not need `Spanned` values nor the nesting information.
+/// Like `Lexer`, the stream ends with the EOF token and reports are generated out of band.
+pub struct RawLexer<'a> {
+    lexer: Lexer<'a>,
+}
+
+impl<'a> RawLexer<'a> {
+    /// Creates a lexer with given stream of spanned source data and the report receiver.
+    pub fn new(bytes: &'a mut Iterator<Item=Spanned<SourceData>>,
+               report: &'a Report) -> RawLexer<'a> {
+        RawLexer { lexer: Lexer::new(bytes, report) }
+    }
+}
+
+impl<'a> Iterator for RawLexer<'a> {
+    type Item = (Tok, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next().map(|tok| (tok.base, tok.span))
+    }
+}
+
+#[test]
+fn test_incomplete_utf8() {
+    use kailua_env::{Source, SourceFile};
+    use kailua_diag::{CollectedReport, Kind, Locale};
+
+    fn lex(data: &[u8]) -> (Vec<Tok>, Vec<Kind>) {
+        let mut source = Source::new();
+        let span = source.add(SourceFile::from_u8("foo".into(), data.to_owned()));
+        let report = CollectedReport::new(Locale::dummy());
+        let tokens = {
+            let mut iter = source.iter_from_span(span).unwrap();
+            let lexer = Lexer::new(&mut iter, &report);
+            lexer.map(|tok| tok.base).collect()
+        };
+        let kinds = report.into_reports().into_iter().map(|(kind, _, _)| kind).collect();
+        (tokens, kinds)
+    }
+
+    let a = || Tok::Name(b"a"[..].into());
+    let b = || Tok::Name(b"b"[..].into());
+
+    // a complete (but otherwise unexpected) character
+    assert_eq!(lex(b"a \xea\xb0\x80 b"), (vec![a(), b(), Tok::EOF], vec![Kind::Error]));
+    // continuation bytes without a leading byte
+    assert_eq!(lex(b"a \x80\xbf b"), (vec![a(), Tok::Error, b(), Tok::EOF], vec![Kind::Error]));
+    // too few continuation bytes
+    assert_eq!(lex(b"a \xea\xb0 b"), (vec![a(), Tok::Error, b(), Tok::EOF], vec![Kind::Error]));
+    // too few continuation bytes at the end of file
+    assert_eq!(lex(b"a \xea\xb0"), (vec![a(), Tok::Error, Tok::EOF], vec![Kind::Error]));
+    // invalid leading bytes, with following continuation bytes skipped
+    assert_eq!(lex(b"a \xf8\x80 b"), (vec![a(), Tok::Error, b(), Tok::EOF], vec![Kind::Error]));
+    assert_eq!(lex(b"a \xff b"), (vec![a(), Tok::Error, b(), Tok::EOF], vec![Kind::Error]));
+}
+
+#[test]
+fn test_raw_lexer() {
+    use kailua_env::{Source, SourceFile};
+    use kailua_diag::{CollectedReport, Locale};
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"a = 1".to_vec()));
+    let report = CollectedReport::new(Locale::dummy());
+    let mut iter = source.iter_from_span(span).unwrap();
+    let tokens: Vec<_> = RawLexer::new(&mut iter, &report).collect();
+
+    assert_eq!(tokens.iter().map(|&(ref tok, _)| tok.clone()).collect::<Vec<_>>(),
+               vec![Tok::Name(b"a"[..].into()), Tok::Punct(Punct::Eq), Tok::Num(1.0), Tok::EOF]);
+    assert_eq!(tokens[0].1.begin(), span.begin());
+}
+
+#[test]
+fn test_comment_text() {
+    use kailua_env::{Source, SourceFile};
+    use kailua_diag::{CollectedReport, Locale};
+
+    fn comments(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut source = Source::new();
+        let span = source.add(SourceFile::from_u8("foo".into(), data.to_owned()));
+        let report = CollectedReport::new(Locale::dummy());
+        let mut iter = source.iter_from_span(span).unwrap();
+        let lexer = Lexer::new(&mut iter, &report);
+        lexer.filter_map(|tok| if let Tok::Comment(text) = tok.base { Some(text) } else { None })
+             .collect()
+    }
+
+    assert_eq!(comments(b"#!/usr/bin/lua\na -- short\r\nb"),
+               vec![b"#!/usr/bin/lua".to_vec(), b"-- short".to_vec()]);
+    assert_eq!(comments(b"--[==[ long\n]] ]==] a --[x\n--[=x"),
+               vec![b"--[==[ long\n]] ]==]".to_vec(), b"--[x".to_vec(), b"--[=x".to_vec()]);
+    assert_eq!(comments(b"--# assume x: integer -- trailing\n--"),
+               vec![b"-- trailing".to_vec(), b"--".to_vec()]);
+}
+
+#[test]
+fn test_hex_escape() {
+    use kailua_env::{Source, SourceFile};
+    use kailua_diag::{CollectedReport, Kind, Locale};
+
+    fn lex(data: &[u8]) -> (Vec<Tok>, Vec<Kind>) {
+        let mut source = Source::new();
+        let span = source.add(SourceFile::from_u8("foo".into(), data.to_owned()));
+        let report = CollectedReport::new(Locale::dummy());
+        let tokens = {
+            let mut iter = source.iter_from_span(span).unwrap();
+            let lexer = Lexer::new(&mut iter, &report);
+            lexer.map(|tok| tok.base).collect()
+        };
+        let kinds = report.into_reports().into_iter().map(|(kind, _, _)| kind).collect();
+        (tokens, kinds)
+    }
+
+    let s = |s: &[u8]| Tok::Str(s.into());
+
+    assert_eq!(lex(br#""\x41""#), (vec![s(b"A"), Tok::EOF], vec![]));
+    assert_eq!(lex(br#"'\x7a\xfF\x00'"#), (vec![s(b"z\xff\x00"), Tok::EOF], vec![]));
+    assert_eq!(lex(br#""\x414""#), (vec![s(b"A4"), Tok::EOF], vec![]));
+    assert_eq!(lex(br#""\x4""#), (vec![s(b""), Tok::EOF], vec![Kind::Error]));
+    assert_eq!(lex(br#""\x4g""#), (vec![s(b"g"), Tok::EOF], vec![Kind::Error]));
+    assert_eq!(lex(br#""\xg1""#), (vec![s(b"g1"), Tok::EOF], vec![Kind::Error]));
+    assert_eq!(lex(br#""\x""#), (vec![s(b""), Tok::EOF], vec![Kind::Error]));
+}
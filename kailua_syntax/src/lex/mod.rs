@@ -16,8 +16,11 @@ pub enum Tok {
 
     /// A comment token. The parser should ignore this.
     ///
+    /// The raw comment text is retained, including the leading `--` (and long brackets if any)
+    /// but excluding the trailing newline.
+    ///
     /// The shebang line (the first line starting with `#`) is also considered as a comment.
-    Comment,
+    Comment(Vec<u8>),
 
     /// A punctuation.
     Punct(Punct),
@@ -45,8 +48,8 @@ impl Localize for Tok {
         match (&locale[..], self) {
             ("ko", &Tok::Error)      => write!(f, "잘못된 문자"),
             (_,    &Tok::Error)      => write!(f, "an invalid character"),
-            ("ko", &Tok::Comment)    => write!(f, "주석"),
-            (_,    &Tok::Comment)    => write!(f, "a comment"),
+            ("ko", &Tok::Comment(_)) => write!(f, "주석"),
+            (_,    &Tok::Comment(_)) => write!(f, "a comment"),
             (_,    &Tok::Punct(p))   => write!(f, "{}", Localized::new(&p, locale)),
             (_,    &Tok::Keyword(w)) => write!(f, "{}", Localized::new(&w, locale)),
             ("ko", &Tok::Num(_))     => write!(f, "숫자"),
@@ -229,6 +232,9 @@ impl Localize for Keyword {
 mod lexer;
 mod nesting;
 
-pub use self::lexer::Lexer;
+pub use self::lexer::{Lexer, RawLexer};
 pub use self::nesting::{Nest, NestedToken, NestingCategory, NestingSerial};
 
+// the lexer itself is version-agnostic, but the version determines which tokens are meaningful
+pub use lang::Lua as LuaVersion;
+
@@ -4,9 +4,10 @@
 /// the autocompletion engine and parser (for error recovery).
 
 use std::cmp;
+use std::borrow::Cow;
 use std::cell::Cell;
 
-use kailua_env::Spanned;
+use kailua_env::{Spanned, Source, SourceSlice};
 use super::{Tok, Punct, Keyword};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -61,6 +62,17 @@ impl NestingSerial {
     pub fn dummy() -> NestingSerial { NestingSerial(0) }
 
     pub fn to_usize(&self) -> usize { self.0 as usize }
+
+    /// Restores a serial number from `NestingSerial::to_u32`.
+    ///
+    /// The serial number is only meaningful within the same lexing session;
+    /// a restored value can be only compared to other serials from that session.
+    pub fn from_u32(serial: u32) -> NestingSerial { NestingSerial(serial) }
+
+    /// Returns the serial number as an integer, suitable for serialization.
+    ///
+    /// The serial number is only meaningful within the same lexing session.
+    pub fn to_u32(&self) -> u32 { self.0 }
 }
 
 /// A token with the nesting information.
@@ -83,6 +95,20 @@ pub struct NestedToken {
     pub serial: NestingSerial,
 }
 
+impl NestedToken {
+    /// Returns the original source text for this token.
+    ///
+    /// Invalid UTF-8 (or UTF-16) sequences are replaced with U+FFFD.
+    /// Returns an empty string if the token span is not in given source.
+    pub fn source_text<'a>(&self, source: &'a Source) -> Cow<'a, str> {
+        match source.slice_from_span(self.tok.span) {
+            Some(SourceSlice::U8(s)) => String::from_utf8_lossy(s),
+            Some(SourceSlice::U16(s)) => Cow::Owned(String::from_utf16_lossy(s)),
+            None => Cow::Borrowed(""),
+        }
+    }
+}
+
 /// The nesting analyzer.
 ///
 /// Externally this is a simple iterator adapter that converts spanned tokens
@@ -368,3 +394,29 @@ impl<'a> Iterator for Nest<'a> {
     }
 }
 
+#[test]
+fn test_nested_token_source_text() {
+    use kailua_env::SourceFile;
+    use kailua_diag::{CollectedReport, Locale};
+    use super::Lexer;
+
+    let mut source = Source::new();
+    let span = source.add(SourceFile::from_u8("foo".into(), b"x = 'a\xffb' -- c".to_vec()));
+    let report = CollectedReport::new(Locale::dummy());
+    let tokens: Vec<_> = {
+        let mut iter = source.iter_from_span(span).unwrap();
+        let mut lexer = Lexer::new(&mut iter, &report);
+        Nest::new(&mut lexer).collect()
+    };
+
+    let texts: Vec<_> = tokens.iter().map(|tok| tok.source_text(&source)).collect();
+    assert_eq!(texts, ["x", "=", "'a\u{fffd}b'", "-- c", ""]);
+}
+
+#[test]
+fn test_nesting_serial_u32() {
+    assert_eq!(NestingSerial::from_u32(0), NestingSerial::dummy());
+    assert_eq!(NestingSerial::from_u32(42).to_u32(), 42);
+    assert_eq!(NestingSerial::from_u32(0xffffffff).to_u32(), 0xffffffff);
+    assert!(NestingSerial::from_u32(3) < NestingSerial::from_u32(4));
+}
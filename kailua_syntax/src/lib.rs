@@ -39,7 +39,7 @@
 #[macro_use] extern crate log;
 extern crate kailua_env;
 
-use kailua_env::{Source, Span};
+use kailua_env::{Source, Span, Spanned, SourceData, WithLoc};
 use kailua_diag::Report;
 
 pub use string::{Str, Name};
@@ -64,10 +64,86 @@ pub fn parse_chunk(source: &Source, span: Span, report: &Report) -> kailua_diag:
         let mut lexer = Lexer::new(&mut iter, &report);
         let mut nest = Nest::new(&mut lexer);
         let parser = Parser::new(&mut nest, &report);
-        parser.into_chunk()
+        let mut chunk = parser.into_chunk()?;
+        chunk.resolve_ignored_lines(source);
+        Ok(chunk)
     } else {
         use kailua_diag::Reporter;
         report.fatal(span, message::NoFileForSpan {}).done()
     }
 }
 
+/// An one-off function to parse a single type from a given span in the `Source`.
+///
+/// The span should contain the type only, without the leading meta comment (`--:`).
+/// As with `parse_chunk`, the caller should also determine if it can continue
+/// in spite of reported errors.
+pub fn parse_type(source: &Source, span: Span,
+                  report: &Report) -> kailua_diag::Result<Spanned<ast::Kind>> {
+    if let Some(iter) = source.iter_from_span(span) {
+        // the type is only recognized in the meta comment, so we supply a virtual `--:`
+        let begin = Span::from(span.begin());
+        let mut iter = b"--:".iter().map(|&c| SourceData::U8(c).with_loc(begin)).chain(iter);
+        let mut lexer = Lexer::new(&mut iter, &report);
+        let mut nest = Nest::new(&mut lexer);
+        let parser = Parser::new(&mut nest, &report);
+        parser.into_type()
+    } else {
+        use kailua_diag::Reporter;
+        report.fatal(span, message::NoFileForSpan {}).done()
+    }
+}
+
+#[test]
+fn test_parse_type() {
+    use kailua_env::SourceFile;
+    use kailua_diag::{CollectedReport, Locale};
+
+    fn parse(code: &str) -> Option<(String, String)> {
+        let mut source = Source::new();
+        let span = source.add(SourceFile::from_u8("<type>".into(), code.as_bytes().to_vec()));
+        let report = CollectedReport::new(Locale::dummy());
+        let kind = match parse_type(&source, span, &report) {
+            Ok(ref kind) if report.is_empty() => kind.clone(),
+            _ => return None,
+        };
+        let begin = kind.span.begin().to_usize() - span.begin().to_usize();
+        let end = kind.span.end().to_usize() - span.begin().to_usize();
+        Some((format!("{:?}", kind.base), code[begin..end].to_owned()))
+    }
+
+    let (kind, text) = parse("integer | string").unwrap();
+    assert_eq!(kind, "Union([Integer, String])");
+    assert_eq!(text, "integer | string");
+    let (kind, text) = parse("  function(integer) --> string\n").unwrap();
+    assert_eq!(kind, "Func((Integer) --> String)");
+    assert_eq!(text, "function(integer) --> string");
+
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("integer string"), None);
+    assert_eq!(parse("integer\nstring"), None);
+}
+
+#[test]
+fn test_parse_chunk_invalid_utf8() {
+    use kailua_env::SourceFile;
+    use kailua_diag::{CollectedReport, Kind, Locale};
+
+    fn parse(code: &[u8]) -> Vec<(Kind, String)> {
+        let mut source = Source::new();
+        let span = source.add(SourceFile::from_u8("<chunk>".into(), code.to_vec()));
+        let report = CollectedReport::new(Locale::dummy());
+        let _ = parse_chunk(&source, span, &report);
+        report.into_reports().into_iter().map(|(kind, _, msg)| (kind, msg)).collect()
+    }
+
+    // the lexer reports each invalid sequence once, and the parser skips it silently
+    let error = |msg: &str| vec![(Kind::Error, msg.to_owned())];
+    assert_eq!(parse(b"local a = 1 \x80\xbf\nlocal b = 2"),
+               error("A UTF-8 continuation byte appeared without a leading byte"));
+    assert_eq!(parse(b"local a = 1 \xea\xb0\nlocal b = 2"),
+               error("Incomplete UTF-8 byte sequence"));
+    assert_eq!(parse(b"local a = 1 \xf8\x80\nlocal b = 2"),
+               error("A byte which cannot start a UTF-8 sequence appeared"));
+    assert_eq!(parse(b"local a = 1 \xea\xb0"), error("Incomplete UTF-8 byte sequence"));
+}
@@ -55,6 +55,11 @@ define_msg! { pub UnrecognizedEscapeInString:
     _    => "Unrecognized escape sequence in a string",
 }
 
+define_msg! { pub InvalidHexEscapeInString:
+    "ko" => "문자열 안의 `\\x` 탈출열 뒤에는 16진수 숫자 두 개가 와야 합니다",
+    _    => "`\\x` escape sequence in a string should be followed by two hexadecimal digits",
+}
+
 define_msg! { pub StringStart:
     "ko" => "문자열 리터럴은 여기서 시작되었습니다",
     _    => "The string started here",
@@ -80,6 +85,21 @@ define_msg! { pub InvalidNumber:
     _    => "Invalid number",
 }
 
+define_msg! { pub StrayUtf8ContinuationByte:
+    "ko" => "UTF-8 연속 바이트가 선행 바이트 없이 나왔습니다",
+    _    => "A UTF-8 continuation byte appeared without a leading byte",
+}
+
+define_msg! { pub InvalidUtf8LeadingByte:
+    "ko" => "UTF-8 바이트열을 시작할 수 없는 바이트가 나왔습니다",
+    _    => "A byte which cannot start a UTF-8 sequence appeared",
+}
+
+define_msg! { pub IncompleteUtf8Sequence:
+    "ko" => "UTF-8 바이트열이 완전하지 않습니다",
+    _    => "Incomplete UTF-8 byte sequence",
+}
+
 define_msg! { pub UnexpectedChar:
     "ko" => "알 수 없는 문자가 나왔습니다",
     _    => "Unexpected character",
@@ -197,6 +217,22 @@ define_msg! { pub NoFuncOrNameAfterLocal<'a> { read: &'a Tok }:
     _    => "Expected a name or `function` after `local`, got {read}",
 }
 
+define_msg! { pub NoLocalAttr<'a> { read: &'a Tok }:
+    "ko" => "`<` 뒤에 `const`나 `close` 속성이 나와야 하는데 {read}이(가) 나왔습니다",
+    _    => "Expected a `const` or `close` attribute after `<`, got {read}",
+}
+
+define_msg! { pub UnknownLocalAttr<'a> { name: &'a Name }:
+    "ko" => "{name}은(는) 알 수 없는 지역 변수 속성입니다",
+    _    => "{name} is not a known local variable attribute",
+}
+
+define_msg! { pub FutureLocalAttr { current: Lua, future: Lua }:
+    "ko" => "지역 변수 속성은 {current}에서 쓸 수 없으며 {future}부터 쓸 수 있습니다",
+    _    => "Local variable attributes are not available in {current}, \
+             they are available since {future}",
+}
+
 define_msg! { pub NoFunctionOrMethodBeforeSig<'a> { read: &'a Tok }:
     "ko" => "함수 명세 앞에 `function`이나 `method`가 나와야 하는데 {read}이(가) 나왔습니다",
     _    => "Expected a `function` or `method` before the function specification, got {read}",
@@ -265,6 +301,27 @@ define_msg! { pub MissingFuncLitAfterFuncSpec:
     _    => "No function literal after the function specification",
 }
 
+define_msg! { pub MissingAssignAfterAssignSpec:
+    "ko" => "이름이 붙은 타입 뒤에 대입문이 없습니다",
+    _    => "No assignment after the named type specification",
+}
+
+define_msg! { pub MismatchedNamesAndTypesInAssignSpec:
+    "ko" => "이름이 붙은 타입에서 이름과 타입의 개수가 다릅니다",
+    _    => "The number of names and types in the named type specification differ",
+}
+
+define_msg! { pub MismatchedNamesInAssignSpec:
+    "ko" => "이름이 붙은 타입의 이름이 대입문의 좌항과 일치하지 않습니다",
+    _    => "Names in the named type specification do not match the left hand side \
+            of the assignment",
+}
+
+define_msg! { pub AssignAfterAssignSpec:
+    "ko" => "대입문의 좌항은 여기에 있습니다",
+    _    => "The left hand side of the assignment is here",
+}
+
 define_msg! { pub NoModfAllowedInVarargs:
     "ko" => "가변 인자에는 변수 종류를 사용할 수 없습니다",
     _    => "Variadic argument specifier cannot have modifiers",
@@ -362,11 +419,36 @@ define_msg! { pub FirstArgNameInFuncKind:
     _    => "The first duplicate appeared here",
 }
 
+define_msg! { pub DuplicateTyParamInFuncKind<'a> { name: &'a Name }:
+    "ko" => "타입에서 타입 인자 이름 {name}이 중복됩니다",
+    _    => "Duplicate type parameter {name} in the type specification",
+}
+
+define_msg! { pub FirstTyParamInFuncKind:
+    "ko" => "여기서 처음 나왔습니다",
+    _    => "The first duplicate appeared here",
+}
+
+define_msg! { pub DuplicateBoundInFuncKind<'a> { name: &'a Name }:
+    "ko" => "타입 인자 {name}에 이미 제약이 붙어 있습니다",
+    _    => "The type parameter {name} already has a bound",
+}
+
+define_msg! { pub NoTyParamForBound<'a> { name: &'a Name }:
+    "ko" => "`where` 절에 지정된 {name}이 타입 인자가 아닙니다",
+    _    => "{name} in the `where` clause is not a type parameter",
+}
+
 define_msg! { pub PartiallyNamedFieldsInFuncKind:
     "ko" => "타입에서 일부 인자에만 이름이 붙어 있습니다",
     _    => "Not all but only some arguments in the type are named",
 }
 
+define_msg! { pub PartiallyNamedReturnsInFuncKind:
+    "ko" => "타입에서 일부 반환값에만 이름이 붙어 있습니다",
+    _    => "Not all but only some return values in the type are named",
+}
+
 define_msg! { pub FunctionWithMethodSig:
     "ko" => "일반 함수의 명세는 `function`으로 시작해야 합니다",
     _    => "A function specification for ordinary functions should start with `function`",
@@ -392,6 +474,11 @@ define_msg! { pub CannotRedefineBuiltin:
     _    => "Cannot redefine a builtin type",
 }
 
+define_msg! { pub NeverAliasShadowedInReturns:
+    "ko" => "`never` 타입 별칭은 반환 타입 자리에서는 `!`로 해석되어 가려집니다",
+    _    => "The type alias `never` is shadowed by `!` at the return type position",
+}
+
 define_msg! { pub AttrToKindSeq:
     "ko" => "[name] 꼴의 타입 속성 선언은 타입열에는 붙일 수 없습니다",
     _    => "Cannot attach the type attribute (like [name]) to the type sequence",
@@ -402,6 +489,11 @@ define_msg! { pub StmtAfterReturnOrBreak:
     _    => "`return` or `break` cannot be followed by other statements",
 }
 
+define_msg! { pub BreakOutsideLoop:
+    "ko" => "`break`는 반복문 안에서만 쓸 수 있습니다",
+    _    => "`break` cannot be used outside of a loop",
+}
+
 define_msg! { pub WrongVectorParamsArity:
     "ko" => "`vector` 타입에는 타입 인자가 하나 있어야 합니다",
     _    => "`vector` type needs a single type parameter",
@@ -4,6 +4,7 @@ use std::u8;
 use std::i32;
 use std::usize;
 use std::fmt;
+use std::mem;
 use std::result;
 use std::collections::{hash_map, HashMap};
 use kailua_env::{Pos, Span, Spanned, WithLoc, Scope, ScopedId, ScopeMap};
@@ -15,8 +16,8 @@ use lex::{Tok, Punct, Keyword, NestedToken, NestingCategory, NestingSerial};
 use string::{Str, Name};
 use ast::{NameRef, RenameRef, Var, Seq, Sig, Attr, AttrValue, Args, Table};
 use ast::{Ex, Exp, UnOp, BinOp, SelfParam, TypeScope, St, Stmt, Block};
-use ast::{M, MM, K, Kind, SlotKind, FuncKind, TypeSpec, Varargs, Returns};
-use ast::{LocalName, LocalNameKind, TokenAux, Chunk};
+use ast::{M, MM, K, Kind, SlotKind, FuncKind, TyParam, TypeSpec, Varargs, Returns};
+use ast::{LocalName, LocalNameKind, LocalAttr, TokenAux, Chunk};
 
 /// The parser.
 pub struct Parser<'a> {
@@ -53,8 +54,14 @@ pub struct Parser<'a> {
     scope_stack: Vec<(Scope, Pos)>,
     block_depth: usize,
 
+    // the number of enclosing loops in the current function, used to check `break`
+    loop_depth: usize,
+
     // auxiliary info for each *input* token (i.e. including elided tokens)
     token_aux: Vec<TokenAux>,
+
+    // spans of `--# ignore-next-line` directives
+    ignore_next_line: Vec<Span>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -255,6 +262,15 @@ struct Presig {
 
 enum AtomicKind { One(Spanned<Kind>), Seq(Seq<Spanned<Kind>>) }
 
+// a type sequence spec returned from `--:` or `--v NAME, ... :` (without names)
+type TypeSeqSpec = Spanned<Vec<Spanned<(MM, Option<Spanned<Kind>>)>>>;
+
+// the contents of `--v`, which is also used to type names in assignments
+enum VSpec {
+    Func(Vec<Spanned<Attr>>, Option<Spanned<Presig>>),
+    Assign(Vec<Spanned<NameRef>>, TypeSeqSpec),
+}
+
 impl<'a> Report for Parser<'a> {
     fn message_locale(&self) -> Locale {
         self.report.message_locale()
@@ -263,6 +279,22 @@ impl<'a> Report for Parser<'a> {
     fn add_span(&self, k: report::Kind, s: Span, m: &Localize) -> report::Result<()> {
         self.report.add_span(k, s, m)
     }
+
+    fn max_kind(&self) -> Option<report::Kind> {
+        self.report.max_kind()
+    }
+
+    fn mark_once(&self, key: &str) -> bool {
+        self.report.mark_once(key)
+    }
+
+    fn last_error_span(&self) -> Option<Span> {
+        self.report.last_error_span()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.report.is_empty()
+    }
 }
 
 // wrappers around kailua_diag::report::{ReportMore, Reporter}, used to remap `done` method
@@ -372,7 +404,9 @@ impl<'a> Parser<'a> {
             global_scope: HashMap::new(),
             scope_stack: Vec::new(),
             block_depth: 0,
+            loop_depth: 0,
             token_aux: Vec::new(),
+            ignore_next_line: Vec::new(),
         };
 
         // read the first token and fill the last_span
@@ -398,11 +432,14 @@ impl<'a> Parser<'a> {
                 self.token_aux.push(TokenAux::None);
 
                 // comments should be ignored in the parser
-                if let Tok::Comment = t.tok.base { continue; }
+                if let Tok::Comment(_) = t.tok.base { continue; }
+
+                // invalid characters have been already reported by the lexer
+                if let Tok::Error = t.tok.base { continue; }
 
                 // `goto` is converted to a name on Lua 5.1
                 let lua = self.language.lua();
-                if lua < Lua::Lua52 {
+                if !lua.supports_goto() {
                     if let Tok::Keyword(kw @ Keyword::Goto) = t.tok.base {
                         // XXX don't want to make this failable
                         let _ = self.warn(t.tok.span,
@@ -950,6 +987,50 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // `<const>` or `<close>` after the local name (Lua 5.4)
+    fn try_parse_local_attr(&mut self) -> Result<Option<Spanned<LocalAttr>>> {
+        let begin = self.pos();
+        if !self.may_expect(Punct::Lt) {
+            return Ok(None);
+        }
+
+        let attr = match_next! { self;
+            Tok::Name(name) in span => match &name[..] {
+                b"const" => Some(LocalAttr::Const),
+                b"close" => Some(LocalAttr::Close),
+                _ => {
+                    self.error(span, m::UnknownLocalAttr { name: &name }).done()?;
+                    None
+                }
+            };
+            // the attribute is missing, continue as if there were no `<`
+            'unread: _, m::NoLocalAttr => return Ok(None);
+        };
+        self.expect(Punct::Gt)?;
+        let span = Span::new(begin, self.last_pos());
+
+        let lua = self.language.lua();
+        if !lua.supports_close_attr() {
+            self.warn(span, m::FutureLocalAttr { current: lua, future: Lua::Lua54 }).done()?;
+        }
+
+        Ok(attr.map(|attr| attr.with_loc(span)))
+    }
+
+    // a lint name is a list of names or keywords delimited by `-`, e.g. `unused-locals`
+    fn parse_lint_name(&mut self) -> Result<Spanned<Name>> {
+        let first = self.try_name_or_keyword()?;
+        let mut span = first.span;
+        let mut name = first.base.to_vec();
+        while self.may_expect(Punct::Dash) {
+            let next = self.try_name_or_keyword()?;
+            span |= next.span;
+            name.push(b'-');
+            name.extend_from_slice(&next.base);
+        }
+        Ok(Name::from(name).with_loc(span))
+    }
+
     fn try_name_or_keyword(&mut self) -> Result<Spanned<Name>> {
         match_next! { self;
             Tok::Name(name) in span => Ok(name.with_loc(span));
@@ -1082,16 +1163,16 @@ impl<'a> Parser<'a> {
         } else if oldspecs.len() > specs.base.len() {
             let span = {
                 let excess = &oldspecs[specs.base.len()..];
-                excess.iter().fold(Span::dummy(), |span, i| {
-                    span | i.base.span | i.kind.as_ref().map_or(Span::dummy(), |k| k.span)
-                })
+                Span::merge_all(excess.iter().map(|i| {
+                    i.base.span | i.kind.as_ref().map_or(Span::dummy(), |k| k.span)
+                }))
             };
             self.error(span, note_on_less).done()?;
             Ok(oldspecs)
         } else if oldspecs.len() < specs.base.len() {
             let span = {
                 let excess = &specs.base[oldspecs.len()..];
-                excess.iter().fold(Span::dummy(), |span, i| span | i.span)
+                Span::merge_all(excess.iter().map(|i| i.span))
             };
             self.error(span, note_on_more).done()?;
             Ok(oldspecs)
@@ -1109,7 +1190,15 @@ impl<'a> Parser<'a> {
         trace!("parsing stmt");
         let begin = self.pos();
 
-        let funcspec = self.try_parse_kailua_func_spec()?;
+        let (funcspec, mut assignspec) = match self.try_parse_kailua_v_spec(true)? {
+            Some(Spanned { base: VSpec::Func(attrs, sig), span }) => {
+                (Some((attrs, sig).with_loc(span)), None)
+            }
+            Some(Spanned { base: VSpec::Assign(names, specs), span }) => {
+                (None, Some((names, specs).with_loc(span)))
+            }
+            None => (None, None),
+        };
         if let Some(ref funcspec) = funcspec {
             // limit the possible lookahead
             let allowed = match self.peek().base {
@@ -1121,6 +1210,14 @@ impl<'a> Parser<'a> {
                 self.error(funcspec.span, m::MissingFuncDeclAfterFuncSpec {}).done()?;
             }
         }
+        if let Some(spec) = assignspec.take() {
+            // the assignment should start with a name, which is ensured later
+            if let Tok::Name(_) = self.peek().base {
+                assignspec = Some(spec);
+            } else {
+                self.error(spec.span, m::MissingAssignAfterAssignSpec {}).done()?;
+            }
+        }
 
         // if there exists a spec stmt return it first.
         // a spec may be empty, so loop until no spec exists or a spec is found.
@@ -1140,13 +1237,28 @@ impl<'a> Parser<'a> {
 
             Tok::Keyword(Keyword::While) => {
                 let cond = self.recover(Self::parse_exp, Keyword::Do)?;
-                let block = self.recover(Self::parse_block, Keyword::End)?;
-                Box::new(St::While(cond, block))
+                self.loop_depth += 1;
+                let block = self.recover(Self::parse_block, Keyword::End);
+                self.loop_depth -= 1;
+                Box::new(St::While(cond, block?))
             };
 
             Tok::Keyword(Keyword::Repeat) => {
-                let block = self.recover(Self::parse_block, Keyword::Until)?;
-                let cond = self.parse_exp()?;
+                // same to `self.parse_block()` followed by `self.expect(Keyword::Until)`,
+                // but the condition can refer to local variables in the block
+                // so the scope should be kept until the condition is parsed.
+                let nscopes = self.scope_stack.len();
+                self.block_depth += 1;
+                self.loop_depth += 1;
+                let block = self._parse_block();
+                self.loop_depth -= 1;
+                self.block_depth -= 1;
+                let blockcond = self.recover(|_| block, Keyword::Until).and_then(|block| {
+                    let cond = self.parse_exp()?;
+                    Ok((block, cond))
+                });
+                self.pop_scope_upto(nscopes);
+                let (block, cond) = blockcond?;
                 Box::new(St::Repeat(block, cond))
             };
 
@@ -1194,9 +1306,12 @@ impl<'a> Parser<'a> {
                             None
                         };
                         self.expect(Keyword::Do)?;
-                        let (id, scope, block) = self.parse_block_end_with_scope(|parser, scope| {
+                        self.loop_depth += 1;
+                        let ret = self.parse_block_end_with_scope(|parser, scope| {
                             parser.add_spanned_local_name(scope, name)
-                        })?;
+                        });
+                        self.loop_depth -= 1;
+                        let (id, scope, block) = ret?;
                         Box::new(St::For(id, start, end, step, scope, block))
                     };
 
@@ -1295,9 +1410,13 @@ impl<'a> Parser<'a> {
                         }
 
                         let mut names = Vec::new();
+                        let mut attrs = Vec::new();
                         let (span, eq) =
-                            self.scan_list_with_spec(Self::parse_name,
-                                                     |namespec| names.push(namespec))?;
+                            self.scan_list_with_spec(|parser| {
+                                let name = parser.parse_name()?;
+                                attrs.push(parser.try_parse_local_attr()?);
+                                Ok(name)
+                            }, |namespec| names.push(namespec))?;
                         let mut names = names.with_loc(span);
 
                         let exps = if eq {
@@ -1325,7 +1444,7 @@ impl<'a> Parser<'a> {
                             namerefs.push(TypeSpec { base: name, modf: namespec.modf,
                                                      kind: namespec.kind });
                         }
-                        Box::new(St::Local(namerefs, exps, sibling_scope))
+                        Box::new(St::Local(namerefs, attrs, exps, sibling_scope))
                     };
 
                     'unread: _, m::NoFuncOrNameAfterLocal => Box::new(St::Oops);
@@ -1338,7 +1457,12 @@ impl<'a> Parser<'a> {
                 Box::new(St::Return(exps.with_loc(span)))
             };
 
-            Tok::Keyword(Keyword::Break) => Box::new(St::Break);
+            Tok::Keyword(Keyword::Break) in span => {
+                if self.loop_depth == 0 {
+                    self.error(span, m::BreakOutsideLoop {}).done()?;
+                }
+                Box::new(St::Break)
+            };
 
             'unread: _ => {
                 // only prefixexp can appear at this position, but it is very common that
@@ -1400,6 +1524,29 @@ impl<'a> Parser<'a> {
 
                         // the equal sign is mandatory, so ensure it here
                         let mut lhs = lhs.with_loc(span);
+
+                        // apply `--v NAME, ... : ...` before the statement if any
+                        if let Some(spec) = assignspec.take() {
+                            let (names, specs) = spec.base;
+                            let matches = names.len() == lhs.len() &&
+                                names.iter().zip(lhs.iter()).all(|(name, varspec)| {
+                                    match varspec.base.base {
+                                        Var::Name(ref varname) => varname.base == name.base,
+                                        _ => false,
+                                    }
+                                });
+                            if matches {
+                                lhs = self.update_type_specs_with_typeseq_spec(
+                                    lhs, specs,
+                                    &m::DuplicateTypeSpecInAssign {},
+                                    &m::ExcessLvaluesInAssign {},
+                                    &m::ExcessTypeSpecsInAssign {})?;
+                            } else {
+                                self.error(spec.span, m::MismatchedNamesInAssignSpec {})
+                                    .note(lhs.span, m::AssignAfterAssignSpec {})
+                                    .done()?;
+                            }
+                        }
                         if !eq && !self.may_expect(Punct::Eq) {
                             error_with!(self, m::NoEq);
 
@@ -1436,6 +1583,10 @@ impl<'a> Parser<'a> {
             };
         };
 
+        if let Some(spec) = assignspec {
+            self.error(spec.span, m::MissingAssignAfterAssignSpec {}).done()?;
+        }
+
         Ok(Some(stmt.with_loc(begin..self.last_pos())))
     }
 
@@ -1444,13 +1595,16 @@ impl<'a> Parser<'a> {
         let span = self.scan_list(Self::parse_exp, |exp| exps.push(exp))?;
         let exps = exps.with_loc(span);
         self.expect(Keyword::Do)?;
-        let (names, scope, block) = self.parse_block_end_with_scope(move |parser, scope| {
+        self.loop_depth += 1;
+        let ret = self.parse_block_end_with_scope(move |parser, scope| {
             let mut names_ = Vec::new().with_loc(names.span);
             for name in names.base {
                 names_.push(parser.add_spanned_local_name(scope, name)?);
             }
             Ok(names_)
-        })?;
+        });
+        self.loop_depth -= 1;
+        let (names, scope, block) = ret?;
         Ok(Box::new(St::ForIn(names, exps, scope, block)))
     }
 
@@ -1561,11 +1715,11 @@ impl<'a> Parser<'a> {
                 // inline argument type spec is an error
                 if args.len() > presig.base.args.head.len() {
                     let excess = &args[presig.base.args.head.len()..];
-                    let span = excess.iter().fold(Span::dummy(), |span, i| span | i.0.span);
+                    let span = Span::merge_all(excess.iter().map(|i| i.0.span));
                     self.error(span, m::ExcessArgsInFuncDecl {}).done()?;
                 } else if args.len() < presig.base.args.head.len() {
                     let excess = &presig.base.args.head[args.len()..];
-                    let span = excess.iter().fold(Span::dummy(), |span, i| span | i.span);
+                    let span = Span::merge_all(excess.iter().map(|i| i.span));
                     self.error(span, m::ExcessArgsInFuncSpec {}).done()?;
                 }
 
@@ -1646,9 +1800,10 @@ impl<'a> Parser<'a> {
             }.with_loc(begin..end)
         });
 
-        // resolve every parameter (including self)
-        let ((selfparam, args), scope, block) = self.parse_block_end_with_scope(move |parser,
-                                                                                      scope| {
+        // resolve every parameter (including self).
+        // the function body is not inside any loop even when the function itself is.
+        let loop_depth = mem::replace(&mut self.loop_depth, 0);
+        let ret = self.parse_block_end_with_scope(move |parser, scope| {
             // attach all arguments to the function body scope
             // XXX should also mention all excess arguments
             // TODO should we add varargs?
@@ -1693,7 +1848,9 @@ impl<'a> Parser<'a> {
 
             let args = Seq { head: head, tail: tail }.with_loc(args.span);
             Ok((selfparam, args))
-        })?;
+        });
+        self.loop_depth = loop_depth;
+        let ((selfparam, args), scope, block) = ret?;
 
         let sig = Sig { attrs: attrs, args: args, returns: returns };
         Ok(Some((selfparam, sig, scope, block)))
@@ -1881,6 +2038,10 @@ impl<'a> Parser<'a> {
     fn convert_and_register_var_from_exp(&mut self, exp: Spanned<Exp>)
         -> result::Result<Spanned<Var>, Spanned<Exp>>
     {
+        if !exp.is_lvalue() {
+            return Err(exp);
+        }
+
         let span = exp.span;
         let base = *exp.base;
         match base {
@@ -2245,11 +2406,15 @@ impl<'a> Parser<'a> {
 
     fn parse_kailua_modf_with_module(&mut self) -> Result<Spanned<MM>> {
         let begin = self.pos();
-        let (modf, end) = if self.may_expect(Keyword::Const) {
+        let (modf, end) = if self.may_expect(Keyword::Var) {
+            (MM::Var, self.last_pos())
+        } else if self.may_expect(Keyword::Const) {
             (MM::Const, self.last_pos())
         } else if self.may_expect(Keyword::Module) {
             // this gets ignored in the checker unless at proper places
             (MM::Module, self.last_pos())
+        } else if self.may_expect(Keyword::Once) {
+            (MM::Once, self.last_pos())
         } else {
             (MM::None, begin)
         };
@@ -2426,9 +2591,65 @@ impl<'a> Parser<'a> {
         Ok(Seq { head: specs, tail: tail }.with_loc(begin..end))
     }
 
+    // parses "<" NAME [":" NAME] {"," NAME [":" NAME]} ["where" NAME ":" NAME {"," ...}] ">".
+    // the caller should have checked that the next token is "<".
+    fn parse_kailua_typarams(&mut self) -> Result<Vec<TyParam>> {
+        let mut typarams: Vec<TyParam> = Vec::new();
+
+        self.expect(Punct::Lt)?;
+        loop {
+            let name = self.parse_name()?.map(|n| n.name);
+            if let Some(prev) = typarams.iter().find(|p| p.name.base == name.base) {
+                self.error(&name, m::DuplicateTyParamInFuncKind { name: &name })
+                    .note(&prev.name, m::FirstTyParamInFuncKind {})
+                    .done()?;
+            }
+            let bound = if self.may_expect(Punct::Colon) {
+                Some(self.parse_name()?.map(|n| n.name))
+            } else {
+                None
+            };
+            typarams.push(TyParam { name: name, bound: bound });
+            if !self.may_expect(Punct::Comma) {
+                break;
+            }
+        }
+
+        if self.may_expect(FixedName("where")) {
+            loop {
+                let name = self.parse_name()?.map(|n| n.name);
+                self.expect(Punct::Colon)?;
+                let bound = self.parse_name()?.map(|n| n.name);
+                match typarams.iter_mut().find(|p| p.name.base == name.base) {
+                    Some(ref p) if p.bound.is_some() => {
+                        self.error(&name, m::DuplicateBoundInFuncKind { name: &name }).done()?;
+                    }
+                    Some(p) => {
+                        p.bound = Some(bound);
+                    }
+                    None => {
+                        self.error(&name, m::NoTyParamForBound { name: &name }).done()?;
+                    }
+                }
+                if !self.may_expect(Punct::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.expect(Punct::Gt)?;
+        Ok(typarams)
+    }
+
     fn parse_kailua_funckind(&mut self) -> Result<Spanned<FuncKind>> {
         let begin = self.pos();
 
+        let typarams = if self.lookahead(Punct::Lt) {
+            self.parse_kailua_typarams()?
+        } else {
+            Vec::new()
+        };
+
         self.expect(Punct::LParen)?;
         let args = self.parse_kailua_kindlist(true)?;
         self.expect(Punct::RParen)?;
@@ -2449,7 +2670,7 @@ impl<'a> Parser<'a> {
         };
 
         let span = begin..self.last_pos();
-        Ok(FuncKind { args: args, returns: returns }.with_loc(span))
+        Ok(FuncKind { typarams: typarams, args: args, returns: returns }.with_loc(span))
     }
 
     fn try_parse_kailua_kind_params(&mut self)
@@ -2542,8 +2763,8 @@ impl<'a> Parser<'a> {
         let kind = match_next! { self;
             Tok::Keyword(Keyword::Function) in span => {
                 // either a "function" type or a function signature
-                if self.lookahead(Punct::LParen) {
-                    // function `(` ... `)` [`-->` ...]
+                if self.lookahead(Punct::LParen) || self.lookahead(Punct::Lt) {
+                    // function [`<` ... `>`] `(` ... `)` [`-->` ...]
                     let func = self.parse_kailua_funckind()?;
                     // cannot be followed by postfix operators
                     let kind = Box::new(K::Func(func)).with_loc(begin..self.last_pos());
@@ -2838,11 +3059,42 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_kailua_returns(&mut self) -> Result<Returns> {
+        // `never` is an alias to `!`, only recognized at the return position
+        let begin = self.pos();
+        if self.may_expect(FixedName("never")) {
+            return Ok(Returns::Never(Span::new(begin, self.last_pos())));
+        }
+
         match_next! { self;
             Tok::Punct(Punct::Bang) in span => Ok(Returns::Never(span));
+
+            // "(" [NAME ":"] KIND {"," [NAME ":"] KIND} ["," KIND "..."] ")", where names are
+            // only allowed here; we cannot defer to the kind sequence parser as in arguments
+            Tok::Punct(Punct::LParen) in span => {
+                let begin = span.begin();
+                let mut seq = self.parse_kailua_kindlist(true)?;
+                self.expect(Punct::RParen)?;
+
+                if seq.head.len() == 1 && seq.tail.is_none() && seq.head[0].0.is_none() {
+                    // a parenthesized single type, which may be followed by operators
+                    let kind = seq.head.pop().unwrap().1;
+                    let kind = self.parse_kailua_kind_suffix(begin, kind);
+                    let kind = self.parse_kailua_kind_after_kind(begin, kind)?;
+                    return Ok(Returns::Seq(Seq { head: vec![(None, kind)], tail: None }));
+                }
+
+                let namedcount = seq.head.iter().filter(|&&(ref name, _)| name.is_some()).count();
+                if namedcount > 0 && namedcount < seq.head.len() {
+                    let span = begin..self.last_pos();
+                    self.error(span, m::PartiallyNamedReturnsInFuncKind {}).done()?;
+                }
+                Ok(Returns::Seq(seq))
+            };
+
             'unread: _ => {
                 let seq = self.parse_kailua_kind_seq()?;
-                Ok(Returns::Seq(seq))
+                let head = seq.head.into_iter().map(|kind| (None, kind)).collect();
+                Ok(Returns::Seq(Seq { head: head, tail: seq.tail }))
             };
         }
     }
@@ -2893,6 +3145,23 @@ impl<'a> Parser<'a> {
         Ok(spec.map(|spec| spec.map(|(m,k)| (m.base, k))))
     }
 
+    // MODF [KIND] in the type sequence spec
+    fn parse_kailua_typeseq_spec_item(&mut self)
+        -> Result<Spanned<(Spanned<MM>, Option<Spanned<Kind>>)>>
+    {
+        let begin = self.pos();
+        let modf = self.parse_kailua_modf_with_module()?;
+        let mut kind = self.recover_upto(Self::try_parse_kailua_kind)?;
+        if kind.is_none() && !(self.lookahead(Punct::DashDashGt) ||
+                               self.lookahead(Punct::Newline) ||
+                               self.lookahead(Punct::Comma)) {
+            // the next `end_meta_comment` call is guaranteed to fail, which leaves
+            // the kind empty (i.e. inferred later). better to make it an oops.
+            kind = Some(Recover::recover());
+        }
+        Ok((modf, kind).with_loc(begin..self.last_pos()))
+    }
+
     fn try_parse_kailua_typeseq_spec(&mut self)
         -> Result<Option<Spanned<Vec<Spanned<(MM, Option<Spanned<Kind>>)>>>>>
     {
@@ -2904,19 +3173,7 @@ impl<'a> Parser<'a> {
             let metaend = self.recover_meta(|parser| {
                 parser.begin_meta_comment(Punct::DashDashColon);
 
-                parser.scan_list(|parser| {
-                    let begin = parser.pos();
-                    let modf = parser.parse_kailua_modf_with_module()?;
-                    let mut kind = parser.recover_upto(Self::try_parse_kailua_kind)?;
-                    if kind.is_none() && !(parser.lookahead(Punct::DashDashGt) ||
-                                           parser.lookahead(Punct::Newline) ||
-                                           parser.lookahead(Punct::Comma)) {
-                        // the next `end_meta_comment` call is guaranteed to fail, which leaves
-                        // the kind empty (i.e. inferred later). better to make it an oops.
-                        kind = Some(Recover::recover());
-                    }
-                    Ok((modf, kind).with_loc(begin..parser.last_pos()))
-                }, |spec| {
+                parser.scan_list(Self::parse_kailua_typeseq_spec_item, |spec| {
                     specs.push(spec.map(|(m,k)| (m.base, k)));
                 })?;
 
@@ -2959,6 +3216,17 @@ impl<'a> Parser<'a> {
     // it may supply only attributes, so we need to return attributes and pre-signatures separately
     fn try_parse_kailua_func_spec(&mut self)
             -> Result<Option<Spanned<(Vec<Spanned<Attr>>, Option<Spanned<Presig>>)>>> {
+        match self.try_parse_kailua_v_spec(false)? {
+            Some(Spanned { base: VSpec::Func(attrs, sig), span }) => {
+                Ok(Some((attrs, sig).with_loc(span)))
+            }
+            Some(Spanned { base: VSpec::Assign(..), .. }) => unreachable!(),
+            None => Ok(None),
+        }
+    }
+
+    // parses either a function spec or (if `allow_assign` is set) an assignment spec
+    fn try_parse_kailua_v_spec(&mut self, allow_assign: bool) -> Result<Option<Spanned<VSpec>>> {
         trace!("parsing kailua function spec");
         let metabegin = self.pos();
 
@@ -2966,6 +3234,33 @@ impl<'a> Parser<'a> {
             self.recover_meta(|parser| {
                 parser.begin_meta_comment(Punct::DashDashV);
 
+                // NAME {"," NAME} ":" MODF KIND {"," MODF KIND}
+                if let (true, &Tok::Name(_)) = (allow_assign, &parser.peek().base) {
+                    let mut names = Vec::new();
+                    parser.scan_list(Self::parse_name, |name| names.push(name))?;
+                    parser.expect(Punct::Colon)?;
+
+                    let mut specs = Vec::new();
+                    let begin = parser.pos();
+                    parser.scan_list(Self::parse_kailua_typeseq_spec_item, |spec| {
+                        specs.push(spec.map(|(m,k)| (m.base, k)));
+                    })?;
+                    let specs = specs.with_loc(begin..parser.last_pos());
+                    if names.len() != specs.len() {
+                        parser.error(specs.span, m::MismatchedNamesAndTypesInAssignSpec {})
+                              .done()?;
+                    }
+
+                    let names = names.into_iter().map(|name| {
+                        let span = name.span;
+                        parser.resolve_name(name.base).with_loc(span)
+                    }).collect();
+
+                    let metaend = parser.last_pos();
+                    parser.end_meta_comment(Punct::DashDashV)?;
+                    return Ok(Some(VSpec::Assign(names, specs).with_loc(metabegin..metaend)));
+                }
+
                 let mut attrs = Vec::new();
                 let mut attrs_seen = false;
                 loop {
@@ -3009,7 +3304,7 @@ impl<'a> Parser<'a> {
 
                 let metaend = parser.last_pos();
                 parser.end_meta_comment(Punct::DashDashV)?;
-                Ok(Some((attrs, sig).with_loc(metabegin..metaend)))
+                Ok(Some(VSpec::Func(attrs, sig).with_loc(metabegin..metaend)))
             }, || None)
         } else {
             Ok(None)
@@ -3211,6 +3506,31 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // (enable | disable) LINT-NAME, or OPTION-NAME
+    // the newline is not consumed, and is not skipped even if the name is missing
+    fn parse_kailua_pragma(&mut self) -> Result<St> {
+        let enable = if self.may_expect(FixedName("enable")) {
+            Some(true)
+        } else if self.may_expect(FixedName("disable")) {
+            Some(false)
+        } else {
+            None
+        };
+
+        if self.lookahead(Punct::Newline) {
+            let next = self.peek().clone();
+            self.error(next.span, m::NoName { read: &next.base }).done()?;
+            return Ok(St::Oops);
+        }
+
+        let name = self.parse_lint_name()?;
+        if let Some(enable) = enable {
+            Ok(St::KailuaPragma(enable, name))
+        } else {
+            Ok(St::KailuaPragmaOption(name))
+        }
+    }
+
     fn try_parse_kailua_spec(&mut self) -> Result<Option<Option<Spanned<Stmt>>>> {
         trace!("parsing kailua spec");
         let begin = self.pos();
@@ -3220,6 +3540,37 @@ impl<'a> Parser<'a> {
                 parser.begin_meta_comment(Punct::DashDashHash);
 
                 let mut sibling_scope = None;
+
+                // ignore-next-line
+                if parser.may_expect(FixedName("ignore")) {
+                    parser.expect(Punct::Dash)?;
+                    parser.expect(FixedName("next"))?;
+                    parser.expect(Punct::Dash)?;
+                    parser.expect(FixedName("line"))?;
+                    let end = parser.last_pos();
+                    parser.ignore_next_line.push(Span::new(begin, end));
+                    parser.end_meta_comment(Punct::DashDashHash)?;
+                    return Ok((None, Some(end)));
+                }
+
+                // pragma (enable | disable) LINT-NAME
+                // pragma OPTION-NAME
+                // (not a keyword, so that `pragma` can be still used as a name)
+                if parser.may_expect(FixedName("pragma")) {
+                    // the pragma should fit in a single line, so that the following `--#` line
+                    // is never read as a part of the pragma (e.g. when the name is missing)
+                    let meta = parser.ignore_after_newline.take();
+                    let stmt = parser.parse_kailua_pragma();
+                    let end = parser.last_pos();
+                    if stmt.is_ok() && parser.may_expect(Punct::Newline) {
+                        return Ok((Some(Box::new(stmt?)), Some(end)));
+                    }
+                    parser.ignore_after_newline = meta;
+                    let stmt = stmt?;
+                    parser.end_meta_comment(Punct::DashDashHash)?;
+                    return Ok((Some(Box::new(stmt)), Some(end)));
+                }
+
                 let stmt = match_next! { parser;
                     // assume ...
                     Tok::Keyword(Keyword::Assume) => {
@@ -3261,6 +3612,11 @@ impl<'a> Parser<'a> {
                             parser.error(name.span, m::CannotRedefineBuiltin {}).done()?;
                         }
 
+                        // `never` remains a valid alias, but `--> never` still means `--> !`
+                        if &*name.base.name == &b"never"[..] {
+                            parser.warn(name.span, m::NeverAliasShadowedInReturns {}).done()?;
+                        }
+
                         // error on module-level type definitions in the local scope
                         let end = parser.last_pos();
                         if parser.block_depth != 0 {
@@ -3299,6 +3655,27 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_kailua_kind_until_eof(&mut self) -> Result<Spanned<Kind>> {
+        trace!("parsing kailua type then EOF");
+
+        self.expect(Punct::DashDashColon)?;
+        self.begin_meta_comment(Punct::DashDashColon);
+        let kind = self.parse_kailua_kind()?;
+        self.end_meta_comment(Punct::DashDashColon)?;
+        self.expect(EOF)?;
+        Ok(kind)
+    }
+
+    /// Parses a single type in the meta comment (`--: TYPE`) which should span
+    /// the entire file, and returns that type (while generating reports).
+    ///
+    /// As with `into_chunk`, the caller should also determine if it can continue
+    /// in spite of reported errors.
+    /// `kailua_syntax::parse_type` is a convenient wrapper which omits the leading `--:`.
+    pub fn into_type(mut self) -> report::Result<Spanned<Kind>> {
+        self.parse_kailua_kind_until_eof().map_err(|_| report::Stop)
+    }
+
     /// Parses the entire file and returns a chunk (while generating reports).
     ///
     /// Most parsing errors can be recovered, so the caller should also determine if
@@ -3307,6 +3684,7 @@ impl<'a> Parser<'a> {
     pub fn into_chunk(mut self) -> report::Result<Chunk> {
         self.scope_stack = vec![];
         self.block_depth = 0;
+        self.loop_depth = 0;
         let ret = self.parse_block_until_eof();
 
         // any remaining scope is considered to end at the last token read
@@ -3323,6 +3701,8 @@ impl<'a> Parser<'a> {
                 map: self.scope_map,
                 local_names: self.local_names,
                 token_aux: self.token_aux,
+                ignore_next_line: self.ignore_next_line,
+                ignored_lines: Vec::new(),
             })
         } else {
             Err(report::Stop)
@@ -11,8 +11,30 @@
 --! []
 --8<-- do-and-break
 do break; end; break
+--@^ Error: `break` cannot be used outside of a loop
+--@^^ Error: `break` cannot be used outside of a loop
 --! [Do([Break]), Break]
 
+--8<-- loop-and-break
+while a do break end
+repeat break until b
+for i = c, d do break end
+for k, v in e do do break end end
+--! [While(`a`_, [Break]), \
+--!  Repeat([Break], `b`_), \
+--!  For(`i`$1, `c`_, `d`_, None, $1[Break]), \
+--!  ForIn([`k`$2, `v`$2], [`e`_], $2[Do([Break])])]
+
+--8<-- loop-and-break-in-func
+while a do
+    local f = function() break end --@< Error: `break` cannot be used outside of a loop
+    function g() while b do break end end
+    break
+end
+--! [While(`a`_, [Local([`f`$2], [Func([] --> _, $1[Break])])$2, \
+--!                FuncDecl(`g`_, [] --> _, $3[While(`b`_, [Break])]), \
+--!                Break])]
+
 --8<-- do-recover
 do
     @ --@< Error: Unexpected character
@@ -67,6 +89,15 @@ end
 f()
 --! [While(`a`_, []), Void(`f`_())]
 
+--8<-- repeat
+repeat a() until b c()
+--! [Repeat([Void(`a`_())], `b`_), Void(`c`_())]
+
+--8<-- repeat-local-scope
+repeat local x = a until x
+local y = x
+--! [Repeat([Local([`x`$1], [`a`_])$1], `x`$1), Local([`y`$2], [`x`_])$2]
+
 --8<-- top-level-end
 f() end g() --@< Error: Expected a statement, got a keyword `end`
 --! [Void(`f`_()), Oops, Void(`g`_())]
@@ -146,6 +177,20 @@ local a, --: const table
       b
 --! [Local([`a`$1: Const Table, `b`$1], [])$1]
 
+--8<-- local-attr
+local a <close>, b, c <const> = f() --@< Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+                                    --@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+--! [Local([`a`$1, `b`$1, `c`$1], [Some(Close), None, Some(Const)], [`f`_()])$1]
+
+--8<-- local-attr-unknown
+local a <foo> = f() --@< Error: `foo` is not a known local variable attribute
+                    --@^ Warning: Local variable attributes are not available in Lua 5.1, they are available since Lua 5.4
+--! [Local([`a`$1], [`f`_()])$1]
+
+--8<-- local-attr-incomplete
+local a < = f() --@< Error: Expected a `const` or `close` attribute after `<`, got `=`
+--! [Local([`a`$1], [`f`_()])$1]
+
 --8<-- local-assign-1
 local a = --: integer
           f()
@@ -236,6 +281,18 @@ local b = {} --: module {}
 local a, b, c = {} --: module, {}, module {}
 --! [Local([`a`$1: Module, `b`$1: _ EmptyTable, `c`$1: Module EmptyTable], [{}])$1]
 
+--8<-- local-once
+local a = 'x' --: once
+local b, c = f() --: once integer, integer
+--! [Local([`a`$1: Once], ["x"])$1, \
+--!  Local([`b`$2: Once Integer, `c`$2: _ Integer], [`f`_()])$2]
+
+--8<-- local-var
+local a = 'x' --: var
+local b, c = f() --: var integer, const integer
+--! [Local([`a`$1: Var], ["x"])$1, \
+--!  Local([`b`$2: Var Integer, `c`$2: Const Integer], [`f`_()])$2]
+
 --8<-- local-module-recover
 local a = {} --: module { --@<-v Error: Expected a single type, got a newline
 --! [Local([`a`$1: Module Oops], [{}])$1]
@@ -396,6 +453,56 @@ a = f() --: integer,
         --: const {} --@^-< Error: Excess type specifications after the assignment
 --! [Assign([`a`_], [`f`_()])]
 
+--8<-- assign-named-types
+--v a, b: integer, const string
+a, b = f(), g()
+--! [Assign([`a`_: _ Integer, `b`_: Const String], [`f`_(), `g`_()])]
+
+--8<-- assign-named-types-local
+local a, b
+--v a, b: integer, string
+a, b = f()
+--! [Local([`a`$1, `b`$1], [])$1, Assign([`a`$1: _ Integer, `b`$1: _ String], [`f`_()])]
+
+--8<-- assign-named-types-duplicate
+--v a, b: integer, string
+a, b = f() --: integer, string
+--@^ Error: The type specification cannot appear both at the left hand side and after the assignment
+--! [Assign([`a`_: _ Integer, `b`_: _ String], [`f`_()])]
+
+--8<-- assign-named-types-mismatch
+--v a, c: integer, string
+--@^ Error: Names in the named type specification do not match the left hand side of the assignment
+a, b = f()
+--@^ Note: The left hand side of the assignment is here
+--! [Assign([`a`_, `b`_], [`f`_()])]
+
+--8<-- assign-named-types-index
+--v a: integer
+--@^ Error: Names in the named type specification do not match the left hand side of the assignment
+a.x = f()
+--@^ Note: The left hand side of the assignment is here
+--! [Assign([`a`_.`x`], [`f`_()])]
+
+--8<-- assign-named-types-count
+--v a, b: integer
+--@^ Error: The number of names and types in the named type specification differ
+a, b = f()
+--@^ Error: Excess type specifications in the left hand side
+--! [Assign([`a`_, `b`_], [`f`_()])]
+
+--8<-- assign-named-types-no-assign
+--v a: integer
+--@^ Error: No assignment after the named type specification
+a()
+--! [Void(`a`_())]
+
+--8<-- assign-named-types-no-assign-2
+--v a: integer
+--@^ Error: No assignment after the named type specification
+local a = f()
+--! [Local([`a`$1], [`f`_()])$1]
+
 --8<-- assign-type-index-1
 a.x, b.y = 42, 54 --: integer, integer
 --! [Assign([`a`_.`x`: _ Integer, `b`_.`y`: _ Integer], [42, 54])]
@@ -1039,11 +1146,43 @@ local b --: function(a: string, b:) --@< Error: Expected a single type, got `)`
 --! [Local([`a`$1: _ Oops], [])$1, \
 --!  Local([`b`$2: _ Oops], [])$2]
 
---8<-- kind-func-no-named-returns
+--8<-- kind-func-named-returns
 local x --: function(string, integer) --> (a: integer, b: string)
---@^ Error: Expected `)`, got `:`
---@^^ Error: Expected a newline, got `)`
---! [Local([`x`$1: _ Oops], [])$1]
+local y --: function() --> (a: integer)
+local z --: function() --> (integer, string...)
+--! [Local([`x`$1: _ Func((String, Integer) --> (`a`: Integer, `b`: String))], [])$1, \
+--!  Local([`y`$2: _ Func(() --> (`a`: Integer))], [])$2, \
+--!  Local([`z`$3: _ Func(() --> (Integer, String...))], [])$3]
+
+--8<-- kind-func-named-returns-partial
+local x --: function() --> (a: integer, string)
+--@^ Error: Not all but only some return values in the type are named
+--! [Local([`x`$1: _ Func(() --> (`a`: Integer, String))], [])$1]
+
+--8<-- kind-func-typarams
+local x --: function<T>(a: T) --> T
+local y --: function<T: Stringy, U>(T, U) --> string
+local z --: function<T, U where T: Stringy, U: Integral>(a: T, b: U)
+--! [Local([`x`$1: _ Func(<`T`>(`a`: `T`) --> `T`)], [])$1, \
+--!  Local([`y`$2: _ Func(<`T`: `Stringy`, `U`>(`T`, `U`) --> String)], [])$2, \
+--!  Local([`z`$3: _ Func(<`T`: `Stringy`, `U`: `Integral`>(`a`: `T`, `b`: `U`) --> ())], [])$3]
+
+--8<-- kind-func-typarams-dup
+local x --: function<T, --@< Note: The first duplicate appeared here
+        --:          T>(T)  --@< Error: Duplicate type parameter `T` in the type specification
+--! [Local([`x`$1: _ Func(<`T`, `T`>(`T`) --> ())], [])$1]
+
+--8<-- kind-func-typarams-where
+local x --: function<T: Stringy where T: Integral>(T)
+--@^ Error: The type parameter `T` already has a bound
+local y --: function<T where U: Integral>(T)
+--@^ Error: `U` in the `where` clause is not a type parameter
+--! [Local([`x`$1: _ Func(<`T`: `Stringy`>(`T`) --> ())], [])$1, \
+--!  Local([`y`$2: _ Func(<`T`>(`T`) --> ())], [])$2]
+
+--8<-- kind-func-parenthesized-return
+local x --: function() --> (integer)?
+--! [Local([`x`$1: _ Func(() --> Integer?)], [])$1]
 
 --8<-- kind-func-diverging
 local x --: function() --> !
@@ -1051,6 +1190,12 @@ local y --: function(string, integer) --> !
 --! [Local([`x`$1: _ Func(() --> !)], [])$1, \
 --!  Local([`y`$2: _ Func((String, Integer) --> !)], [])$2]
 
+--8<-- kind-func-diverging-never
+local x --: function() --> never
+local y --: function(string, integer) --> never
+--! [Local([`x`$1: _ Func(() --> !)], [])$1, \
+--!  Local([`y`$2: _ Func((String, Integer) --> !)], [])$2]
+
 --8<-- kind-thread
 local x --: thread
 --! [Local([`x`$1: _ Thread], [])$1]
@@ -1492,6 +1637,11 @@ function foo() end
 function error(msg) end
 --! [FuncDecl(`error`_, [`msg`$1: _ String] --> !, $1[])]
 
+--8<-- funcspec-diverging-never
+--v function(msg: string) --> never
+function error(msg) end
+--! [FuncDecl(`error`_, [`msg`$1: _ String] --> !, $1[])]
+
 --8<-- funcspec-modf-only
 --v function(x: const) --@< Error: Expected a single type, got `)`
 function foo(x) end
@@ -1814,6 +1964,19 @@ f('foo\xyz', 'bar\zyx') --@< Error: Unrecognized escape sequence in a string
                         --@^ Error: Unrecognized escape sequence in a string
 --! [Void(`f`_("fooyz", "baryx"))]
 
+--8<-- string-hex-escape
+f('\x41\x62c', "\x7e")
+--! [Void(`f`_("Abc", "~"))]
+
+--8<-- string-wrong-hex-escape
+f('foo\x4yz') --@< Error: `\x` escape sequence in a string should be followed by two hexadecimal digits
+--! [Void(`f`_("fooyz"))]
+
+--8<-- string-wrong-hex-escape-recover
+f('\x4', 'bar\x') --@< Error: `\x` escape sequence in a string should be followed by two hexadecimal digits
+                   --@^ Error: Unrecognized escape sequence in a string
+--! [Void(`f`_("", "bar"))]
+
 --8<-- string-incomplete-escape
 f('foo\ --@< Error: Premature end of file in a string
         --@^ Note: The string started here
@@ -2194,6 +2357,49 @@ f(                                      --@< Error: Expected `)`, got the end of
 f()
 --! [Oops, Void(`f`_())]
 
+--8<-- pragma
+--# pragma disable unused-locals
+f()
+--# pragma enable unused-locals
+--! [KailuaPragma(false, `unused-locals`), Void(`f`_()), KailuaPragma(true, `unused-locals`)]
+
+--8<-- pragma-option
+--# pragma strict-nil
+f()
+--! [KailuaPragmaOption(`strict-nil`), Void(`f`_())]
+
+--8<-- pragma-option-followed-by-meta
+--# pragma strict-nil
+--# type x = integer
+--! [KailuaPragmaOption(`strict-nil`), KailuaType(Exported, `x`, Integer)]
+
+--8<-- pragma-incomplete
+--# pragma --@<-v Error: Expected a name, got a newline
+--# pragma whatever
+f()
+--! [Oops, KailuaPragmaOption(`whatever`), Void(`f`_())]
+
+--8<-- pragma-not-keyword
+--# type pragma = integer
+--# assume x: pragma
+--! [KailuaType(Exported, `pragma`, Integer), \
+--!  KailuaAssume(`x`_ => `x`$1, _, `pragma`)$1]
+
+--8<-- pragma-no-name
+--# pragma enable --@<-v Error: Expected a name, got a newline
+f()
+--! [Oops, Void(`f`_())]
+
+--8<-- ignore-next-line
+--# ignore-next-line
+f()
+--! [Void(`f`_())]
+
+--8<-- ignore-next-line-incomplete
+--# ignore-next --@<-v Error: Expected `-`, got a newline
+f()
+--! [Oops, Void(`f`_())]
+
 --8<-- alias
 --# type Int = integer
 --# type local integral = integer
@@ -2215,6 +2421,18 @@ f()
 --# assume x: vector<any>
 --! [KailuaType(Exported, `any`, Integer), KailuaAssume(`x`_ => `x`$1, _, Array(_ Any))$1]
 
+--8<-- alias-never
+--# type never = integer --@< Warning: The type alias `never` is shadowed by `!` at the return type position
+--! [KailuaType(Exported, `never`, Integer)]
+
+--8<-- alias-never-usage
+--# type never = integer --@< Warning: The type alias `never` is shadowed by `!` at the return type position
+--# assume x: vector<never>
+--# assume f: function() --> never
+--! [KailuaType(Exported, `never`, Integer), \
+--!  KailuaAssume(`x`_ => `x`$1, _, Array(_ `never`))$1, \
+--!  KailuaAssume(`f`_ => `f`$2, _, Func(() --> !))$2]
+
 --8<-- alias-incomplete
 --# type Int =
 --# assume x: vector<Int> --@< Error: Expected a single type, got a keyword `assume`
@@ -2267,8 +2485,8 @@ f()
 --! [KailuaOpen(`lua51`), KailuaType(Exported, `goto`, Integer)]
 
 --8<-- type-spec-recover-negative-span
-local a = {} --: var { var { } } --@< Error: Expected a newline, got a keyword `var`
-local b --: var { var { } }      --@< Error: Expected a newline, got a keyword `var`
+local a = {} --: static { static { } } --@< Error: Expected a newline, got a keyword `static`
+local b --: static { static { } }      --@< Error: Expected a newline, got a keyword `static`
 --! [Local([`a`$1: _ Oops], [{}])$1, Local([`b`$2: _ Oops], [])$2]
 
 --8<-- paren-ambiguity-1
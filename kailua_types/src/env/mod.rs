@@ -3,6 +3,7 @@
 use std::mem;
 use std::str;
 use std::fmt;
+use std::sync::Arc;
 use std::collections::{HashMap, HashSet};
 use vec_map::{self, VecMap};
 use atomic::Atomic;
@@ -10,7 +11,7 @@ use atomic::Ordering::Relaxed;
 
 use kailua_diag::Locale;
 use diag::{Origin, TypeReport, TypeResult};
-use ty::{Ty, T, Slot, TVar, RVar, Lattice, Key};
+use ty::{Ty, T, Slot, TVar, TVarStats, RVar, Lattice, Key};
 use ty::{TypeContext, ClassId, ClassSystemId, DisplayState};
 use ty::flags::*;
 use self::partitions::{Partition, Partitions};
@@ -24,8 +25,15 @@ struct Bound {
     bound: Option<Ty>,
 }
 
+impl Clone for Bound {
+    fn clone(&self) -> Bound {
+        Bound { parent: Atomic::new(self.parent.load(Relaxed)),
+                rank: self.rank, bound: self.bound.clone() }
+    }
+}
+
 // a set of constraints that can be organized as a tree
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Constraints {
     op: &'static str,
     bounds: Partitions<Box<Bound>>,
@@ -86,6 +94,34 @@ impl Constraints {
         None
     }
 
+    // unlike add_bound, this unconditionally overwrites the existing bound
+    fn replace_bound(&mut self, lhs: TVar, rhs: &Ty) {
+        let lhs_ = self.bounds.find(lhs.0 as usize);
+        let b = self.bounds.entry(lhs_).or_insert_with(|| Partition::create(lhs_, 0));
+        b.bound = Some(rhs.clone());
+    }
+
+    // checks if `add_relation` would succeed without changing anything
+    fn can_add_relation(&self, lhs: TVar, rhs: TVar) -> bool {
+        if lhs == rhs { return true; }
+
+        let lhs_ = self.bounds.find(lhs.0 as usize);
+        let rhs_ = self.bounds.find(rhs.0 as usize);
+        if lhs_ == rhs_ { return true; }
+
+        let lhsbound = self.bounds.get(lhs_).and_then(|b| b.bound.clone());
+        let rhsbound = self.bounds.get(rhs_).and_then(|b| b.bound.clone());
+        match (is_bound_trivial(&lhsbound), is_bound_trivial(&rhsbound)) {
+            (true, true) if lhsbound != rhsbound => {
+                info!("variables {:?}/{:?} cannot have multiple bounds \
+                       (left {} {:?}, right {} {:?})",
+                      lhs, rhs, self.op, lhsbound, self.op, rhsbound);
+                false
+            },
+            (_, _) => true,
+        }
+    }
+
     fn add_relation(&mut self, lhs: TVar, rhs: TVar) -> bool {
         if lhs == rhs { return true; }
 
@@ -93,6 +129,11 @@ impl Constraints {
         let rhs_ = self.bounds.find(rhs.0 as usize);
         if lhs_ == rhs_ { return true; }
 
+        // check first, so that the failure does not lose existing bounds
+        if !self.can_add_relation(lhs, rhs) {
+            return false;
+        }
+
         fn take_bound(bounds: &mut VecMap<Box<Bound>>, i: usize) -> Option<Ty> {
             if let Some(b) = bounds.get_mut(i) {
                 mem::replace(&mut b.bound, None)
@@ -108,13 +149,7 @@ impl Constraints {
         let bound = match (is_bound_trivial(&lhsbound), is_bound_trivial(&rhsbound)) {
             (false, _) => lhsbound,
             (true, false) => rhsbound,
-            (true, true) if lhsbound == rhsbound => lhsbound,
-            (true, true) => {
-                info!("variables {:?}/{:?} cannot have multiple bounds \
-                       (left {} {:?}, right {} {:?})",
-                      lhs, rhs, self.op, lhsbound, self.op, rhsbound);
-                return false;
-            },
+            (true, true) => lhsbound,
         };
 
         // update the shared bound to the merged representative
@@ -128,7 +163,7 @@ impl Constraints {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct RowInfo {
     // the hashmap being None indicates that it is currently recursing;
     // the value can be Some(slot) for "positive" fields, which the row variable contains that key,
@@ -214,16 +249,23 @@ pub struct Types {
 
     // type variable information
     next_tvar: TVar,
-    tvar_sub: Constraints, // upper bound
-    tvar_sup: Constraints, // lower bound
-    tvar_eq: Constraints, // tight bound
+    // (constraints and row infos are copied on write, so that snapshots are cheap to make)
+    tvar_sub: Arc<Constraints>, // upper bound
+    tvar_sup: Arc<Constraints>, // lower bound
+    tvar_eq: Arc<Constraints>, // tight bound
+    // initial upper bounds from `gen_tvar_constrained`, which later constraints can tighten
+    tvar_flag_bounds: Arc<VecMap<Ty>>,
 
     // row variable information
     next_rvar: RVar,
-    row_infos: VecMap<Box<RowInfo>>,
+    row_infos: Arc<VecMap<Box<RowInfo>>>,
 
     // classes and class systems are handled in a separate subsystem, encapsulated as ClassProvider
-    classes: Box<ClassProvider>,
+    // shared with snapshots, which never change classes
+    classes: Arc<ClassProvider>,
+
+    // true if this is a temporary snapshot made by `subtype_of`
+    snapshot: bool,
 }
 
 impl Types {
@@ -232,12 +274,14 @@ impl Types {
         Types {
             message_locale: locale,
             next_tvar: TVar(1), // TVar(0) for the top-level return
-            tvar_sub: Constraints::new("<:"),
-            tvar_sup: Constraints::new(":>"),
-            tvar_eq: Constraints::new("="),
+            tvar_sub: Arc::new(Constraints::new("<:")),
+            tvar_sup: Arc::new(Constraints::new(":>")),
+            tvar_eq: Arc::new(Constraints::new("=")),
+            tvar_flag_bounds: Arc::new(VecMap::new()),
             next_rvar: RVar::new(1), // RVar::new(0) == RVar::empty()
-            row_infos: VecMap::new(),
-            classes: classes,
+            row_infos: Arc::new(VecMap::new()),
+            classes: classes.into(),
+            snapshot: false,
         }
     }
 
@@ -251,6 +295,11 @@ impl Types {
         self.message_locale = locale;
     }
 
+    // true if the upper bound of `tvar` is still the initial bound from `gen_tvar_constrained`
+    fn has_initial_flag_bound(&self, tvar: TVar, ub: &Ty) -> bool {
+        self.tvar_flag_bounds.get(tvar.0 as usize) == Some(ub)
+    }
+
     fn assert_rvar_rel(&mut self, lhs: RVar, rhs: RVar, is_sub: bool) -> TypeResult<()> {
         trace!("{:?} should be {} {:?}", lhs, if is_sub { "<:" } else { "=" }, rhs);
 
@@ -277,7 +326,7 @@ impl Types {
                     return Ok((present, r));
                 }
 
-                match ctx.row_infos.entry(r.to_usize()) {
+                match Arc::make_mut(&mut ctx.row_infos).entry(r.to_usize()) {
                     vec_map::Entry::Occupied(row) => {
                         let row = row.get();
 
@@ -378,15 +427,17 @@ impl Types {
         // finally two row variables should be linked by setting the common last row variable
         let last = self.gen_rvar();
         if lnext != RVar::empty() {
-            let mut row =
-                self.row_infos.entry(lnext.to_usize()).or_insert_with(|| Box::new(RowInfo::new()));
+            let row_infos = Arc::make_mut(&mut self.row_infos);
+            let mut row = row_infos.entry(lnext.to_usize())
+                                   .or_insert_with(|| Box::new(RowInfo::new()));
             assert_eq!(row.next, None);
             row.next = Some(last.clone());
         }
         if rnext != RVar::empty() && rnext != lnext {
             // avoid setting the next twice, which breaks the assertion
-            let mut row =
-                self.row_infos.entry(rnext.to_usize()).or_insert_with(|| Box::new(RowInfo::new()));
+            let row_infos = Arc::make_mut(&mut self.row_infos);
+            let mut row = row_infos.entry(rnext.to_usize())
+                                   .or_insert_with(|| Box::new(RowInfo::new()));
             assert_eq!(row.next, None);
             row.next = Some(last);
         }
@@ -412,7 +463,8 @@ impl Types {
 
         // take fields out, so that we can detect an infinite recursion
         let fields_and_next = {
-            let row = self.row_infos.entry(lhs_).or_insert_with(|| Box::new(RowInfo::new()));
+            let row_infos = Arc::make_mut(&mut self.row_infos);
+            let row = row_infos.entry(lhs_).or_insert_with(|| Box::new(RowInfo::new()));
             row.fields.take().map(|fields| (fields, row.next.clone()))
         };
         let (mut fields, next) = if let Some(fields_and_next) = fields_and_next {
@@ -423,7 +475,7 @@ impl Types {
         trace!("{:?} already had {:?} and {:?}", lhs, fields, next);
 
         let e = inner(self, lhs, includes, nilable, &mut fields, next);
-        self.row_infos.get_mut(lhs_).unwrap().fields = Some(fields);
+        Arc::make_mut(&mut self.row_infos).get_mut(lhs_).unwrap().fields = Some(fields);
         return e;
 
         fn inner(ctx: &mut Types, _lhs: RVar, includes: &[(Key, Slot)], nilable: bool,
@@ -468,6 +520,24 @@ impl Types {
             Ok(())
         }
     }
+
+    // filters the tight bound of the type variable by given flags and replaces it.
+    // `assert_tvar_eq` cannot be used here, as it only checks the consistency
+    // against the existing tight bound and never updates it.
+    fn constrain_tvar_by_flags(&mut self, tvar: TVar, flags: Flags) -> TypeResult<()> {
+        let ty = match self.get_tvar_exact_type(tvar) {
+            Some(ty) => ty,
+            None => return Ok(()),
+        };
+
+        let narrowed = ty.clone().filter_by_flags(flags, self)?;
+        debug!("narrowing a constraint {:?} = {:?} to {:?}", tvar, ty, narrowed);
+        if let Some(lb) = self.tvar_sup.get_bound(tvar).and_then(|b| b.bound.clone()) {
+            lb.assert_sub(&narrowed, self)?;
+        }
+        Arc::make_mut(&mut self.tvar_eq).replace_bound(tvar, &narrowed);
+        Ok(())
+    }
 }
 
 impl TypeContext for Types {
@@ -494,26 +564,46 @@ impl TypeContext for Types {
             let tvar_ = self.gen_tvar();
             trace!("copied {:?} to {:?}", tvar, tvar_);
             if let Some(ub) = self.tvar_sub.get_bound(tvar).and_then(|b| b.bound.clone()) {
-                let oldub = self.tvar_sub.add_bound(tvar_, &ub);
+                let oldub = Arc::make_mut(&mut self.tvar_sub).add_bound(tvar_, &ub);
                 assert!(oldub.is_none(), "bounding fresh tvar should not fail");
+                if self.has_initial_flag_bound(tvar, &ub) {
+                    Arc::make_mut(&mut self.tvar_flag_bounds).insert(tvar_.0 as usize, ub);
+                }
             }
             if let Some(lb) = self.tvar_sup.get_bound(tvar).and_then(|b| b.bound.clone()) {
-                let oldlb = self.tvar_sup.add_bound(tvar_, &lb);
+                let oldlb = Arc::make_mut(&mut self.tvar_sup).add_bound(tvar_, &lb);
                 assert!(oldlb.is_none(), "bounding fresh tvar should not fail");
             }
             tvar_
         }
     }
 
+    fn gen_tvar_constrained(&mut self, flags: Flags) -> TVar {
+        let tvar = self.gen_tvar();
+        let ub = Ty::new(T::ubound_from_flags(flags & !T_DYNAMIC));
+        trace!("generated {:?} <: {:?}", tvar, ub);
+        let oldub = Arc::make_mut(&mut self.tvar_sub).add_bound(tvar, &ub);
+        assert!(oldub.is_none(), "bounding fresh tvar should not fail");
+        Arc::make_mut(&mut self.tvar_flag_bounds).insert(tvar.0 as usize, ub);
+        tvar
+    }
+
     fn assert_tvar_sub(&mut self, lhs: TVar, rhs0: &Ty) -> TypeResult<()> {
         let rhs = rhs0.clone().coerce();
         debug!("adding a constraint {:?} <: {:?} (coerced to {:?})", lhs, rhs0, rhs);
         if let Some(eb) = self.tvar_eq.get_bound(lhs).and_then(|b| b.bound.clone()) {
             eb.assert_sub(&rhs, self)?;
         } else {
-            if let Some(ub) = self.tvar_sub.add_bound(lhs, &rhs).map(|b| b.clone()) {
-                // the original bound is not consistent, bound <: rhs still has to hold
-                if let Err(e) = ub.assert_sub(&rhs, self) {
+            let ub = Arc::make_mut(&mut self.tvar_sub).add_bound(lhs, &rhs).map(|b| b.clone());
+            if let Some(ub) = ub {
+                // the original bound is not consistent, bound <: rhs still has to hold.
+                // the exception is a bounded type parameter (e.g. `T: Stringy`)
+                // whose instantiation is further constrained by its uses:
+                // its initial bound is replaced by rhs if rhs is tighter.
+                if self.has_initial_flag_bound(lhs, &ub) && self.subtype_of(&rhs, &ub) {
+                    debug!("tightening a constraint {:?} <: {:?} to {:?}", lhs, ub, rhs);
+                    Arc::make_mut(&mut self.tvar_sub).replace_bound(lhs, &rhs);
+                } else if let Err(e) = ub.assert_sub(&rhs, self) {
                     info!("variable {:?} cannot have multiple possibly disjoint \
                            bounds (original <: {:?}, later <: {:?}): {:?}", lhs, ub, rhs, e);
                     return Err(e);
@@ -532,7 +622,8 @@ impl TypeContext for Types {
         if let Some(eb) = self.tvar_eq.get_bound(lhs).and_then(|b| b.bound.clone()) {
             rhs.assert_sub(&eb, self)?;
         } else {
-            if let Some(lb) = self.tvar_sup.add_bound(lhs, rhs).map(|b| b.clone()) {
+            let lb = Arc::make_mut(&mut self.tvar_sup).add_bound(lhs, rhs).map(|b| b.clone());
+            if let Some(lb) = lb {
                 // the original bound is not consistent, bound :> rhs still has to hold
                 if let Err(e) = rhs.assert_sub(&lb, self) {
                     info!("variable {:?} cannot have multiple possibly disjoint \
@@ -550,7 +641,7 @@ impl TypeContext for Types {
     fn assert_tvar_eq(&mut self, lhs: TVar, rhs0: &Ty) -> TypeResult<()> {
         let rhs = rhs0.clone().coerce();
         debug!("adding a constraint {:?} = {:?} (coerced to {:?})", lhs, rhs0, rhs);
-        if let Some(eb) = self.tvar_eq.add_bound(lhs, &rhs).map(|b| b.clone()) {
+        if let Some(eb) = Arc::make_mut(&mut self.tvar_eq).add_bound(lhs, &rhs).map(|b| b.clone()) {
             // the original bound is not consistent, bound = rhs still has to hold
             if let Err(e) = eb.assert_eq(&rhs, self) {
                 info!("variable {:?} cannot have multiple possibly disjoint \
@@ -571,14 +662,15 @@ impl TypeContext for Types {
     fn assert_tvar_sub_tvar(&mut self, lhs: TVar, rhs: TVar) -> TypeResult<()> {
         debug!("adding a constraint {:?} <: {:?}", lhs, rhs);
         if !self.tvar_eq.is(lhs, rhs) {
-            if !self.tvar_sub.add_relation(lhs, rhs) {
-                // TODO
-                return Err(self.gen_report().not_sub(Origin::TVar, "<tvar>", "<tvar>", self));
-            }
-            if !self.tvar_sup.add_relation(rhs, lhs) {
+            // both relations should be checked before adding either of them
+            if !self.tvar_sub.can_add_relation(lhs, rhs) ||
+               !self.tvar_sup.can_add_relation(rhs, lhs) {
                 // TODO
                 return Err(self.gen_report().not_sub(Origin::TVar, "<tvar>", "<tvar>", self));
             }
+            let sub_added = Arc::make_mut(&mut self.tvar_sub).add_relation(lhs, rhs);
+            let sup_added = Arc::make_mut(&mut self.tvar_sup).add_relation(rhs, lhs);
+            debug_assert!(sub_added && sup_added);
         }
         Ok(())
     }
@@ -586,13 +678,21 @@ impl TypeContext for Types {
     fn assert_tvar_eq_tvar(&mut self, lhs: TVar, rhs: TVar) -> TypeResult<()> {
         debug!("adding a constraint {:?} = {:?}", lhs, rhs);
         // do not update tvar_sub & tvar_sup, tvar_eq will be consulted first
-        if !self.tvar_eq.add_relation(lhs, rhs) {
+        if !Arc::make_mut(&mut self.tvar_eq).add_relation(lhs, rhs) {
             // TODO
             return Err(self.gen_report().not_eq(Origin::TVar, "<tvar>", "<tvar>", self));
         }
         Ok(())
     }
 
+    fn constrain_to_truthy(&mut self, tvar: TVar) -> TypeResult<()> {
+        self.constrain_tvar_by_flags(tvar, T_TRUTHY)
+    }
+
+    fn constrain_to_falsy(&mut self, tvar: TVar) -> TypeResult<()> {
+        self.constrain_tvar_by_flags(tvar, T_FALSY)
+    }
+
     fn get_tvar_bounds(&self, tvar: TVar) -> (Flags /*lb*/, Flags /*ub*/) {
         if let Some(b) = self.tvar_eq.get_bound(tvar).and_then(|b| b.bound.as_ref()) {
             let flags = b.flags();
@@ -611,6 +711,121 @@ impl TypeContext for Types {
         self.tvar_eq.get_bound(tvar).and_then(|b| b.bound.as_ref()).cloned()
     }
 
+    fn tvar_stats(&self) -> TVarStats {
+        let mut stats = TVarStats::default();
+
+        // `gen_tvar` increments `next_tvar` first, so generated variables start at TVar(2)
+        for i in 2..self.next_tvar.0 + 1 {
+            let tvar = TVar(i);
+            let has_bound = |c: &Constraints| {
+                c.get_bound(tvar).map_or(false, |b| !is_bound_trivial(&b.bound))
+            };
+
+            stats.total += 1;
+            if has_bound(&self.tvar_eq) {
+                stats.resolved += 1;
+            } else {
+                match (has_bound(&self.tvar_sub), has_bound(&self.tvar_sup)) {
+                    (true, false) => stats.unresolved_upper_only += 1,
+                    (false, true) => stats.unresolved_lower_only += 1,
+                    (true, true) => stats.unresolved_both += 1,
+                    (false, false) => stats.unresolved_free += 1,
+                }
+            }
+        }
+
+        stats
+    }
+
+    fn check_invariants(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        // every bound should refer to the type variable generated so far
+        for &(name, c) in &[("upper", &self.tvar_sub), ("lower", &self.tvar_sup),
+                            ("tight", &self.tvar_eq)] {
+            for (i, b) in c.bounds.iter() {
+                let (parent, _) = b.read();
+                if i > self.next_tvar.0 as usize || parent > self.next_tvar.0 as usize {
+                    violations.push(format!("{} bound for <#{}> (parent <#{}>) refers to \
+                                             a type variable not yet generated",
+                                            name, i, parent));
+                }
+            }
+        }
+
+        // the bounds for each type variable should be consistent: lower <: tight <: upper.
+        // the full subtyping check may alter the environment, so this only checks type flags.
+        // bounds involving type variables have no meaningful flags and are skipped.
+        let is_flags_sub = |lhs: &Ty, rhs: &Ty| {
+            let lflags = lhs.flags();
+            let rflags = rhs.flags();
+            lhs.get_tvar().is_some() || rhs.get_tvar().is_some() ||
+                lflags.is_dynamic() || rflags.is_dynamic() ||
+                !lflags.intersects(!rflags)
+        };
+        for i in 0..self.next_tvar.0 + 1 {
+            let tvar = TVar(i);
+            let get_bound = |c: &Constraints| {
+                c.get_bound(tvar).and_then(|b| b.bound.clone()).and_then(|t| {
+                    if is_bound_trivial(&Some(t.clone())) { None } else { Some(t) }
+                })
+            };
+            let ub = get_bound(&self.tvar_sub);
+            let lb = get_bound(&self.tvar_sup);
+            let eb = get_bound(&self.tvar_eq);
+
+            let mut check = |lhs: &Option<Ty>, rhs: &Option<Ty>, lname, rname| {
+                if let (&Some(ref lhs), &Some(ref rhs)) = (lhs, rhs) {
+                    if !is_flags_sub(lhs, rhs) {
+                        violations.push(format!("{} bound {:?} of {:?} is not a subtype of \
+                                                 its {} bound {:?}",
+                                                lname, lhs, tvar, rname, rhs));
+                    }
+                }
+            };
+            check(&lb, &ub, "lower", "upper");
+            check(&lb, &eb, "lower", "tight");
+            check(&eb, &ub, "tight", "upper");
+        }
+
+        // every row variable should be fully instantiated (not in the middle of recursion),
+        // and its chain should be finite and have no duplicate positive fields
+        for (i, info) in self.row_infos.iter() {
+            let rvar = RVar::new(i);
+            if i >= self.next_rvar.to_usize() {
+                violations.push(format!("{:?} is instantiated but not yet generated", rvar));
+            }
+            if info.fields.is_none() {
+                violations.push(format!("{:?} is still being extended", rvar));
+            }
+
+            let mut seen_rvars = HashSet::new();
+            let mut seen_keys = HashSet::new();
+            let mut r = rvar.clone();
+            while let Some(info) = self.row_infos.get(r.to_usize()) {
+                if !seen_rvars.insert(r.clone()) {
+                    violations.push(format!("{:?} has a cyclic chain through {:?}", rvar, r));
+                    break;
+                }
+                if let Some(ref fields) = info.fields {
+                    for (k, v) in fields {
+                        if v.is_some() && !seen_keys.insert(k.clone()) {
+                            violations.push(format!("{:?} has a duplicate field {:?} in {:?}",
+                                                    rvar, k, r));
+                        }
+                    }
+                }
+                match info.next {
+                    Some(ref next) if *next == RVar::empty() => break,
+                    Some(ref next) => r = next.clone(),
+                    None => break,
+                }
+            }
+        }
+
+        violations
+    }
+
     fn gen_rvar(&mut self) -> RVar {
         let rvar = self.next_rvar.clone();
         self.next_rvar = RVar::new(rvar.to_usize() + 1);
@@ -655,7 +870,7 @@ impl TypeContext for Types {
         // we know that rvar0 contains `fields` plus an unspecified non-empty row variable,
         // which should be replaced with an (uninstantiated) fresh row variable.
         let rvar = self.gen_rvar();
-        self.row_infos.insert(rvar.to_usize(),
+        Arc::make_mut(&mut self.row_infos).insert(rvar.to_usize(),
                               Box::new(RowInfo { fields: Some(fields), next: None }));
         rvar
     }
@@ -696,7 +911,8 @@ impl TypeContext for Types {
 
             {
                 let rvar_ = rvar.to_usize();
-                let info = self.row_infos.entry(rvar_).or_insert_with(|| Box::new(RowInfo::new()));
+                let row_infos = Arc::make_mut(&mut self.row_infos);
+                let info = row_infos.entry(rvar_).or_insert_with(|| Box::new(RowInfo::new()));
                 if let Some(ref next) = info.next {
                     rvar = next.clone();
                 } else {
@@ -757,6 +973,26 @@ impl TypeContext for Types {
     fn is_subclass_of(&self, lhs: ClassId, rhs: ClassId) -> bool {
         self.classes.is_subclass_of(lhs, rhs)
     }
+
+    fn subtype_of(&self, a: &Ty, b: &Ty) -> bool {
+        let mut snapshot = Types {
+            message_locale: self.message_locale,
+            next_tvar: self.next_tvar,
+            tvar_sub: self.tvar_sub.clone(),
+            tvar_sup: self.tvar_sup.clone(),
+            tvar_eq: self.tvar_eq.clone(),
+            tvar_flag_bounds: self.tvar_flag_bounds.clone(),
+            next_rvar: self.next_rvar.clone(),
+            row_infos: self.row_infos.clone(),
+            classes: self.classes.clone(),
+            snapshot: true,
+        };
+        a.assert_sub(b, &mut snapshot).is_ok()
+    }
+
+    fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
 }
 
 #[test]
@@ -779,6 +1015,13 @@ fn test_types_tvar() {
         assert!(types.assert_tvar_sub(v1, &Ty::new(T::String)).is_err());
     }
 
+    { // a looser upper bound is not replaced by a tighter one
+        let v1 = types.gen_tvar();
+        assert!(types.assert_tvar_sub(v1, &Ty::new(T::Number | T::String)).is_ok());
+        assert!(types.assert_tvar_sub(v1, &Ty::new(T::Integer)).is_err());
+        assert!(types.assert_tvar_sup(v1, &Ty::new(T::String)).is_ok());
+    }
+
     { // empty bounds (lb & ub = bottom)
         let v1 = types.gen_tvar();
         assert!(types.assert_tvar_sub(v1, &Ty::new(T::Integer)).is_ok());
@@ -828,5 +1071,220 @@ fn test_types_tvar() {
         assert!(types.assert_tvar_sub(v3, &Ty::new(T::Number)).is_ok());
         assert!(types.assert_tvar_eq(v3, &Ty::new(T::String)).is_err());
     }
+
+    { // unification
+        let v1 = types.gen_tvar();
+        let v2 = types.gen_tvar();
+        assert!(types.unify(v1, v2).is_ok());
+        assert!(types.assert_tvar_eq(v1, &Ty::new(T::Integer)).is_ok());
+        assert!(types.assert_tvar_sup(v2, &Ty::new(T::String)).is_err());
+
+        let v3 = types.gen_tvar();
+        let v4 = types.gen_tvar();
+        assert!(types.assert_tvar_eq(v3, &Ty::new(T::Number)).is_ok());
+        assert!(types.assert_tvar_eq(v4, &Ty::new(T::Integer)).is_ok());
+        assert!(types.unify(v3, v4).is_ok());
+
+        // a failed equality should not drop the existing bounds before the fallback
+        let v5 = types.gen_tvar();
+        let v6 = types.gen_tvar();
+        assert!(types.assert_tvar_eq(v5, &Ty::new(T::None)).is_ok());
+        assert!(types.assert_tvar_eq_tvar(v5, v6).is_err());
+        assert_eq!(types.get_tvar_exact_type(v5), Some(Ty::new(T::None)));
+        assert!(types.unify(v5, v6).is_ok());
+        assert_eq!(types.get_tvar_exact_type(v5), Some(Ty::new(T::None)));
+        assert!(!types.tvar_eq.is(v5, v6));
+    }
+}
+
+#[test]
+fn test_types_constrain_tvar() {
+    use ty::Nil;
+
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+
+    let v1 = types.gen_tvar();
+    assert!(types.assert_tvar_eq(v1, &Ty::new(T::Integer).or_nil(Nil::Noisy)).is_ok());
+    assert!(types.constrain_to_truthy(v1).is_ok());
+    assert_eq!(types.get_tvar_exact_type(v1), Some(Ty::new(T::Integer)));
+
+    let v2 = types.gen_tvar();
+    assert!(types.assert_tvar_eq(v2, &Ty::new(T::Boolean)).is_ok());
+    assert!(types.constrain_to_falsy(v2).is_ok());
+    assert_eq!(types.get_tvar_exact_type(v2), Some(Ty::new(T::False)));
+
+    // no tight bound, nothing to narrow
+    let v3 = types.gen_tvar();
+    assert!(types.constrain_to_truthy(v3).is_ok());
+    assert_eq!(types.get_tvar_exact_type(v3), None);
+}
+
+#[test]
+fn test_types_gen_tvar_constrained() {
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+
+    let v1 = types.gen_tvar_constrained(T_STRINGY);
+    assert!(types.assert_tvar_sup(v1, &Ty::new(T::Integer | T::String)).is_ok());
+    let v2 = types.gen_tvar_constrained(T_STRINGY);
+    assert!(types.assert_tvar_sup(v2, &Ty::new(T::Boolean)).is_err());
+
+    let v3 = types.gen_tvar_constrained(T_INTEGRAL);
+    assert!(types.assert_tvar_sup(v3, &Ty::new(T::Integer)).is_ok());
+    let v4 = types.gen_tvar_constrained(T_INTEGRAL);
+    assert!(types.assert_tvar_sup(v4, &Ty::new(T::Number)).is_err());
+
+    // the bound is kept by `copy_tvar`
+    let v5 = types.gen_tvar_constrained(T_STRINGY);
+    let v6 = types.copy_tvar(v5);
+    assert!(types.assert_tvar_sup(v6, &Ty::new(T::table())).is_err());
+
+    // the initial bound can be tightened once, but not loosened
+    let v7 = types.gen_tvar_constrained(T_STRINGY);
+    assert!(types.assert_tvar_sub(v7, &Ty::new(T::Boolean)).is_err());
+    assert!(types.assert_tvar_sub(v7, &Ty::new(T::Integer)).is_ok());
+    assert!(types.assert_tvar_sup(v7, &Ty::new(T::String)).is_err());
+    assert!(types.assert_tvar_sub(v7, &Ty::new(T::Int(42))).is_err());
+    let v8 = types.copy_tvar(v5);
+    assert!(types.assert_tvar_sub(v8, &Ty::new(T::String)).is_ok());
+}
+
+#[test]
+fn test_types_has_close_metamethod() {
+    use ty::{Dyn, Nil};
+
+    let types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+    assert!(types.has_close_metamethod(&Ty::new(T::Dynamic(Dyn::User))));
+    assert!(types.has_close_metamethod(&Ty::new(T::None)));
+    assert!(types.has_close_metamethod(&Ty::new(T::False).or_nil(Nil::Noisy)));
+    assert!(!types.has_close_metamethod(&Ty::new(T::Integer)));
+    assert!(!types.has_close_metamethod(&Ty::new(T::Boolean)));
 }
 
+#[test]
+fn test_types_tvar_stats() {
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+    assert_eq!(types.tvar_stats(), TVarStats::default());
+
+    let v1 = types.gen_tvar();
+    let v2 = types.gen_tvar();
+    let v3 = types.gen_tvar();
+    let v4 = types.gen_tvar();
+    let _v5 = types.gen_tvar();
+    assert!(types.assert_tvar_eq(v1, &Ty::new(T::Integer)).is_ok());
+    assert!(types.assert_tvar_sub(v2, &Ty::new(T::Number)).is_ok());
+    assert!(types.assert_tvar_sup(v3, &Ty::new(T::Integer)).is_ok());
+    assert!(types.assert_tvar_sub(v4, &Ty::new(T::Number)).is_ok());
+    assert!(types.assert_tvar_sup(v4, &Ty::new(T::Integer)).is_ok());
+
+    assert_eq!(types.tvar_stats(), TVarStats {
+        total: 5,
+        resolved: 1,
+        unresolved_upper_only: 1,
+        unresolved_lower_only: 1,
+        unresolved_both: 1,
+        unresolved_free: 1,
+    });
+}
+
+#[test]
+fn test_types_check_invariants() {
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+
+    let v1 = types.gen_tvar();
+    let v2 = types.gen_tvar();
+    assert!(types.assert_tvar_sub(v1, &Ty::new(T::Number)).is_ok());
+    assert!(types.assert_tvar_sup(v1, &Ty::new(T::Integer)).is_ok());
+    assert!(types.assert_tvar_eq(v2, &Ty::new(T::String)).is_ok());
+
+    let r1 = types.gen_rvar();
+    let r2 = types.gen_rvar();
+    let foo = Key::Str(b"foo"[..].into());
+    assert!(types.assert_rvar_includes(r1.clone(),
+                                       &[(foo.clone(), Slot::just(Ty::new(T::Integer)))]).is_ok());
+    assert!(types.assert_rvar_sub(r1.clone(), r2.clone()).is_ok());
+    assert_eq!(types.check_invariants(), Vec::<String>::new());
+
+    // bypass the usual checks to break the invariants
+    let v1_ = types.tvar_sup.bounds.find(v1.0 as usize);
+    Arc::make_mut(&mut types.tvar_sup).bounds.get_mut(v1_).unwrap().bound =
+        Some(Ty::new(T::String));
+    Arc::make_mut(&mut types.row_infos).get_mut(r1.to_usize()).unwrap().next = Some(r1.clone());
+    assert_eq!(types.check_invariants(), vec![
+        format!("lower bound string of {:?} is not a subtype of its upper bound number", v1),
+        format!("{:?} has a cyclic chain through {:?}", r1, r1),
+    ]);
+}
+
+#[test]
+fn test_assert_sub_reporting() {
+    use kailua_env::Span;
+    use kailua_diag::{Report, CollectedReport, Kind};
+    use diag::TypeReportHint;
+
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+    let report = CollectedReport::new(Locale::dummy());
+
+    let integer = Ty::new(T::Integer);
+    let number = Ty::new(T::Number);
+    let ret = integer.assert_sub_reporting(&number, Span::dummy(), TypeReportHint::None,
+                                           &mut types, &report, |_| panic!("no error expected"));
+    assert_eq!(ret, Ok(true));
+    assert!(report.is_empty());
+
+    let ret = number.assert_sub_reporting(&integer, Span::dummy(), TypeReportHint::None,
+                                          &mut types, &report, |_| Box::new("not a subtype"));
+    assert_eq!(ret, Ok(false));
+    let reports = report.into_reports();
+    assert_eq!(reports[0], (Kind::Error, Span::dummy(), "not a subtype".to_string()));
+}
+
+#[test]
+fn test_types_subtype_of() {
+    use std::borrow::Cow;
+    use ty::{F, Tables};
+
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+
+    let integer = Ty::new(T::Integer);
+    let number = Ty::new(T::Number);
+    assert!(types.subtype_of(&integer, &number));
+    assert!(!types.subtype_of(&number, &integer));
+
+    // the type variable is not bound by the query
+    let v1 = types.gen_tvar();
+    let bounds = types.get_tvar_bounds(v1);
+    assert!(types.subtype_of(&Ty::new(T::TVar(v1)), &integer));
+    assert!(types.subtype_of(&Ty::new(T::TVar(v1)), &Ty::new(T::String)));
+    assert_eq!(types.get_tvar_bounds(v1), bounds);
+
+    // but the existing bounds are respected
+    assert!(types.assert_tvar_sub(v1, &integer).is_ok());
+    assert!(types.subtype_of(&Ty::new(T::TVar(v1)), &number));
+    assert!(!types.subtype_of(&Ty::new(T::TVar(v1)), &Ty::new(T::String)));
+
+    // slots are shared with the snapshot, so the unknown flex should not be resolved
+    let unknown = Slot::new(F::Unknown, integer.clone());
+    let lhs = Ty::new(T::Tables(Cow::Owned(Tables::Array(unknown.clone()))));
+    let rhs = Ty::new(T::Tables(Cow::Owned(Tables::Array(Slot::var(integer.clone())))));
+    assert!(types.subtype_of(&lhs, &rhs));
+    assert_eq!(unknown.flex(), F::Unknown);
+    assert!(lhs.assert_sub(&rhs, &mut types).is_ok());
+    assert_eq!(unknown.flex(), F::Var);
+}
+
+#[test]
+fn test_types_remove_ty_does_not_bind() {
+    use ty::{Unioned, F};
+
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+    let v1 = types.gen_tvar();
+    let bounds = types.get_tvar_bounds(v1);
+
+    let lhs = T::array(Slot::new(F::Var, Ty::new(T::TVar(v1))));
+    let rhs = Ty::new(T::array(Slot::new(F::Var, Ty::new(T::Integer))));
+    let orig = Unioned::from(&lhs, &mut types).unwrap();
+    let mut u = orig.clone();
+    u.remove_ty(&rhs, &mut types).unwrap();
+    assert_eq!(u, orig); // only removable by binding `v1`, so kept
+    assert_eq!(types.get_tvar_bounds(v1), bounds);
+}
@@ -219,3 +219,8 @@ define_msg! { pub UnsupportedUnionTypeSpec:
     _    => "This union type is not supported in the specification",
 }
 
+define_msg! { pub UnknownTyParamBound<'a> { name: &'a Name }:
+    "ko" => "타입 인자에 알 수 없는 제약 {name}이 붙었습니다",
+    _    => "Unknown bound {name} for the type parameter",
+}
+
@@ -17,6 +17,7 @@ pub struct DisplayState<'a> {
     disambiguators: RefCell<HashMap<Name, HashMap<Span, usize>>>,
     slots_seen: RefCell<HashSet<*const S>>,
     tvars_seen: RefCell<HashSet<u32>>,
+    tvar_names: RefCell<HashMap<u32, Name>>,
     rvars_seen: RefCell<HashSet<u32>>,
 
     // rvars are one of the biggest source of type display bloats,
@@ -32,6 +33,7 @@ impl<'a> DisplayState<'a> {
             disambiguators: RefCell::new(HashMap::new()),
             slots_seen: RefCell::new(HashSet::new()),
             tvars_seen: RefCell::new(HashSet::new()),
+            tvar_names: RefCell::new(HashMap::new()),
             rvars_seen: RefCell::new(HashSet::new()),
             max_rvar_level: f.precision(),
         }
@@ -57,6 +59,15 @@ impl<'a> DisplayState<'a> {
         !self.rvars_seen.borrow_mut().insert(rvar.to_u32())
     }
 
+    /// Makes given type variable to be displayed as given name (of the type parameter).
+    pub fn name_tvar(&self, tvar: TVar, name: &Name) {
+        self.tvar_names.borrow_mut().insert(tvar.0, name.clone());
+    }
+
+    pub fn tvar_name(&self, tvar: TVar) -> Option<Name> {
+        self.tvar_names.borrow().get(&tvar.0).cloned()
+    }
+
     pub fn unmark_slot(&self, slot: &S) {
         self.slots_seen.borrow_mut().remove(&(slot as *const S));
     }
@@ -172,6 +183,13 @@ impl Display for DisplayName {
 pub trait Display: fmt::Debug + Sized {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result;
 
+    /// Returns a short category of the value (e.g. `table` for any table type) if any.
+    ///
+    /// Used by `Displayed::brief`. The default implementation has no such category.
+    fn brief_displayed(&self, _ctx: &TypeContext) -> Option<String> {
+        None
+    }
+
     fn display<'b, C>(&'b self, ctx: C) -> Displayed<'b, Self, C> {
         Displayed { base: self, ctx: ctx }
     }
@@ -181,24 +199,40 @@ impl<T: Display> Display for Spanned<T> {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         self.base.fmt_displayed(f, st)
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        self.base.brief_displayed(ctx)
+    }
 }
 
 impl<T: Display + ?Sized> Display for Box<T> {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         (**self).fmt_displayed(f, st)
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        (**self).brief_displayed(ctx)
+    }
 }
 
 impl<'a, T: Display + ?Sized> Display for &'a T {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         (**self).fmt_displayed(f, st)
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        (**self).brief_displayed(ctx)
+    }
 }
 
 impl<'a, T: Display + ?Sized> Display for &'a mut T {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         (**self).fmt_displayed(f, st)
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        (**self).brief_displayed(ctx)
+    }
 }
 
 impl Display for String {
@@ -231,6 +265,24 @@ impl<'b, 'c, T: Display + 'b> Displayed<'b, T, &'c TypeContext> {
                          locale: Locale) -> Localized<'a, Displayed<'b, T, &'c TypeContext>> {
         Localized::new(self, locale)
     }
+
+    /// Renders the value into a string in given locale.
+    ///
+    /// The type context is already in `Displayed`, so only the locale has to be given.
+    pub fn to_string_with_locale(&self, locale: Locale) -> String {
+        self.localized(locale).to_string()
+    }
+
+    /// Renders a short category of the value, like `table` for `{x: integer}`
+    /// or `function` for `function(integer) --> string`.
+    ///
+    /// Falls back to the full display in the default locale when there is no such category
+    /// (e.g. the error type or an unresolved type variable).
+    pub fn brief(&self) -> String {
+        self.base.brief_displayed(self.ctx).unwrap_or_else(|| {
+            self.to_string_with_locale(Locale::dummy())
+        })
+    }
 }
 
 impl<'b, 'c, T: Display + 'b> Localize for Displayed<'b, T, &'c TypeContext> {
@@ -246,3 +298,47 @@ impl<'b, T: Display + fmt::Debug + 'b, C> fmt::Debug for Displayed<'b, T, C> {
     }
 }
 
+#[test]
+fn test_displayed_to_string_with_locale() {
+    use ty::{T, Dyn, NoTypeContext};
+
+    let ty = T::Dynamic(Dyn::Oops);
+    let displayed = ty.display(&NoTypeContext as &TypeContext);
+    assert_eq!(displayed.to_string_with_locale(Locale::new("en").unwrap()), "<error>");
+    assert_eq!(displayed.to_string_with_locale(Locale::new("ko").unwrap()), "<오류>");
+}
+
+#[test]
+fn test_displayed_brief() {
+    use std::borrow::Cow;
+    use env::{Types, DummyClassProvider};
+    use ty::{T, Ty, Dyn, Nil, Key, Tables, Function, TySeq, F, Slot};
+
+    let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+    let rvar = types.gen_rvar();
+    let key = Key::Str(b"x"[..].into());
+    types.assert_rvar_includes(rvar.clone(), &[(key, Slot::new(F::Var, Ty::new(T::Integer)))])
+         .unwrap();
+    let record = Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar, None))));
+    let func = Ty::new(T::func(Function {
+        typarams: Vec::new(),
+        args: TySeq { head: vec![Ty::new(T::Integer)], tail: None },
+        argnames: Vec::new(),
+        returns: Some(TySeq { head: vec![Ty::new(T::String)], tail: None }),
+        returnnames: Vec::new(),
+    }));
+    let tvar = Ty::new(T::TVar(types.gen_tvar()));
+
+    let brief = |ty: &Ty| ty.display(&types as &TypeContext).brief();
+    assert_eq!(brief(&record), "table");
+    assert_eq!(brief(&func), "function");
+    assert_eq!(brief(&Ty::new(T::Int(42))), "integer");
+    assert_eq!(brief(&Ty::new(T::Number)), "number");
+    assert_eq!(brief(&Ty::new(T::True)), "boolean");
+    assert_eq!(brief(&Ty::new(T::Dynamic(Dyn::User))), "WHATEVER");
+    assert_eq!(brief(&Ty::new(T::None)), "nil");
+    assert_eq!(brief(&Ty::new(T::String).or_nil(Nil::Noisy)), "string?");
+    assert_eq!(brief(&Ty::new(T::Int(1) | T::String).or_nil(Nil::Noisy)), "(integer|string)?");
+    assert_eq!(brief(&Ty::new(T::Dynamic(Dyn::Oops))), "<error>");
+    assert_eq!(brief(&tvar), "<unknown type>");
+}
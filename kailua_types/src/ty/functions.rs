@@ -1,15 +1,125 @@
 use std::fmt;
-use kailua_env::Spanned;
-use kailua_diag::Result;
+use std::collections::HashMap;
+use kailua_env::{Span, Spanned};
+use kailua_diag::{Result, Kind, Locale, Localize, Report, Reporter};
 use kailua_syntax::Name;
 use kailua_syntax::ast::{FuncKind, Returns};
 
 use diag::{Origin, TypeReport, TypeResult};
-use super::{Display, DisplayState, Ty, TySeq, TypeContext, TypeResolver, Lattice};
+use message as m;
+use super::{Display, DisplayState, Ty, T, TySeq, TVar, TypeContext, TypeResolver, Lattice};
+use super::ClassSystemId;
+use super::flags::*;
+
+/// A type parameter of the function type.
+#[derive(Clone, PartialEq)]
+pub struct TyParam {
+    /// A name of the type parameter, used for diagnostics only.
+    pub name: Spanned<Name>,
+
+    /// A type variable standing for the type parameter.
+    pub tvar: TVar,
+
+    /// The bound of the type parameter, or `T_ALL` if unbounded.
+    pub bound: Flags,
+}
+
+impl TyParam {
+    /// Returns the type flags for given bound name (e.g. `T_STRINGY` for `Stringy`).
+    pub fn bound_from_name(name: &[u8]) -> Option<Flags> {
+        match name {
+            b"Integral" => Some(T_INTEGRAL),
+            b"Numeric" => Some(T_NUMERIC),
+            b"Stringy" => Some(T_STRINGY),
+            b"Tabular" => Some(T_TABULAR),
+            b"Callable" => Some(T_CALLABLE),
+            _ => None,
+        }
+    }
+
+    /// Returns the bound name for given type flags, if the flags came from `bound_from_name`.
+    pub fn bound_name(flags: Flags) -> Option<&'static str> {
+        match flags {
+            T_INTEGRAL => Some("Integral"),
+            T_NUMERIC => Some("Numeric"),
+            T_STRINGY => Some("Stringy"),
+            T_TABULAR => Some("Tabular"),
+            T_CALLABLE => Some("Callable"),
+            _ => None,
+        }
+    }
+
+    // generates a fresh type variable with given bound
+    fn gen_tvar(bound: Flags, ctx: &mut TypeContext) -> TVar {
+        if bound == T_ALL {
+            ctx.gen_tvar()
+        } else {
+            ctx.gen_tvar_constrained(bound)
+        }
+    }
+
+    fn fmt_generic(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:+}", self.name)?;
+        if let Some(bound) = TyParam::bound_name(self.bound) {
+            write!(f, ": {}", bound)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TyParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_generic(f)?;
+        write!(f, " = {:?}", self.tvar)
+    }
+}
+
+// a type resolver which resolves type parameters before any other type names
+struct TyParamResolver<'a> {
+    typarams: &'a [TyParam],
+    resolv: &'a mut TypeResolver,
+}
+
+impl<'a> Report for TyParamResolver<'a> {
+    fn message_locale(&self) -> Locale { self.resolv.message_locale() }
+    fn add_span(&self, k: Kind, s: Span, m: &Localize) -> Result<()> {
+        self.resolv.add_span(k, s, m)
+    }
+    fn max_kind(&self) -> Option<Kind> { self.resolv.max_kind() }
+    fn mark_once(&self, key: &str) -> bool { self.resolv.mark_once(key) }
+    fn last_error_span(&self) -> Option<Span> { self.resolv.last_error_span() }
+    fn is_empty(&self) -> bool { self.resolv.is_empty() }
+}
+
+impl<'a> TypeResolver for TyParamResolver<'a> {
+    fn context(&self) -> &TypeContext {
+        self.resolv.context()
+    }
+    fn context_mut(&mut self) -> &mut TypeContext {
+        self.resolv.context_mut()
+    }
+    fn ty_from_name(&self, name: &Spanned<Name>) -> Result<Ty> {
+        if let Some(typaram) = self.typarams.iter().find(|p| p.name.base == name.base) {
+            Ok(Ty::new(T::TVar(typaram.tvar)))
+        } else {
+            self.resolv.ty_from_name(name)
+        }
+    }
+    fn class_system_from_name(&self, name: &Spanned<Name>) -> Result<Option<ClassSystemId>> {
+        self.resolv.class_system_from_name(name)
+    }
+}
 
 /// A function type.
 #[derive(Clone, PartialEq)]
 pub struct Function {
+    /// Type parameters, used for diagnostics only.
+    ///
+    /// Each type parameter is represented as a (possibly bounded) type variable,
+    /// which gets replaced to a fresh type variable with the same bound at each call site.
+    /// See `Function::generalize` for details.
+    pub typarams: Vec<TyParam>,
+
     /// Argument types.
     pub args: TySeq,
 
@@ -18,23 +128,88 @@ pub struct Function {
 
     /// Return types, or `None` if the function diverges.
     pub returns: Option<TySeq>,
+
+    /// Optional return value names, used for diagnostics only.
+    pub returnnames: Vec<Option<Spanned<Name>>>,
 }
 
 impl Function {
     pub fn from_kind(func: &Spanned<FuncKind>, resolv: &mut TypeResolver) -> Result<Function> {
+        let mut typarams = Vec::new();
+        for typaram in &func.typarams {
+            let bound = if let Some(ref bound) = typaram.bound {
+                if let Some(flags) = TyParam::bound_from_name(&bound.base) {
+                    flags
+                } else {
+                    resolv.error(bound, m::UnknownTyParamBound { name: &bound.base }).done()?;
+                    T_ALL
+                }
+            } else {
+                T_ALL
+            };
+            let tvar = TyParam::gen_tvar(bound, resolv.context_mut());
+            typarams.push(TyParam { name: typaram.name.clone(), tvar: tvar, bound: bound });
+        }
+
+        let mut resolv = TyParamResolver { typarams: &typarams, resolv: resolv };
+        let resolv = &mut resolv;
+
         let args = TySeq::from_kind_seq(&func.args, |namekind| &namekind.1, resolv)?;
-        let mut argnames = Vec::new();
-        for (i, &(ref name, _)) in func.args.head.iter().enumerate() {
+        let argnames = Function::names_from_kind_seq(&func.args.head);
+        let (returns, returnnames) = match func.returns {
+            Returns::Seq(ref seq) => {
+                let returns = TySeq::from_kind_seq(seq, |namekind| &namekind.1, resolv)?;
+                (Some(returns), Function::names_from_kind_seq(&seq.head))
+            },
+            Returns::Never(_span) => (None, Vec::new()),
+        };
+        Ok(Function { typarams: typarams, args: args, argnames: argnames,
+                      returns: returns, returnnames: returnnames })
+    }
+
+    /// Collects argument or return value names from the sequence of named kinds,
+    /// in the form of `Function::argnames` or `Function::returnnames`.
+    ///
+    /// Trailing `None`s are omitted.
+    pub fn names_from_kind_seq<T>(head: &[(Option<Spanned<Name>>, T)])
+            -> Vec<Option<Spanned<Name>>> {
+        let mut names = Vec::new();
+        for (i, &(ref name, _)) in head.iter().enumerate() {
             if let Some(ref name) = *name {
-                argnames.resize(i, None);
-                argnames.push(Some(name.clone()));
+                names.resize(i, None);
+                names.push(Some(name.clone()));
             }
         }
-        let returns = match func.returns {
-            Returns::Seq(ref seq) => Some(TySeq::from_kind_seq(seq, |kind| kind, resolv)?),
-            Returns::Never(_span) => None,
+        names
+    }
+
+    /// Returns argument and return types with type and row variables made fresh for each call.
+    ///
+    /// Every occurrence of each type parameter is replaced to the same fresh type variable,
+    /// so the type parameter gets instantiated per call and is not shared across calls.
+    pub fn generalize(&self, ctx: &mut TypeContext) -> (TySeq, Option<TySeq>) {
+        let substs: HashMap<TVar, TVar> = self.typarams.iter().map(|typaram| {
+            (typaram.tvar, TyParam::gen_tvar(typaram.bound, ctx))
+        }).collect();
+
+        let mut generalize_tyseq = |seq: &TySeq| {
+            let head = seq.head.iter().map(|t| t.clone().generalize(&substs, ctx)).collect();
+            let tail = seq.tail.as_ref().map(|t| t.clone().generalize(&substs, ctx));
+            TySeq { head: head, tail: tail }
         };
-        Ok(Function { args: args, argnames: argnames, returns: returns })
+
+        let args = generalize_tyseq(&self.args);
+        let returns = self.returns.as_ref().map(|returns| generalize_tyseq(returns));
+        (args, returns)
+    }
+
+    /// Returns true if the first argument is named `self`,
+    /// i.e. the function is meant to be called with the method call syntax (`a:b(...)`).
+    pub fn is_method(&self) -> bool {
+        match self.argnames.first() {
+            Some(&Some(ref name)) => &name.base[..] == b"self",
+            _ => false,
+        }
     }
 
     fn assert_sub(&self, other: &Self, ctx: &mut TypeContext) -> TypeResult<()> {
@@ -71,8 +246,18 @@ impl Function {
                                         mut write_tyseq: WriteTySeq) -> fmt::Result
             where WriteTy: FnMut(&Ty, &mut fmt::Formatter, bool) -> fmt::Result,
                   WriteTySeq: FnMut(&TySeq, &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "function")?;
+        if !self.typarams.is_empty() {
+            write!(f, "<")?;
+            for (i, typaram) in self.typarams.iter().enumerate() {
+                if i > 0 { write!(f, ", ")?; }
+                typaram.fmt_generic(f)?;
+            }
+            write!(f, ">")?;
+        }
+
         // we cannot directly print self.args as they should be interleaved with self.argnames
-        write!(f, "function(")?;
+        write!(f, "(")?;
         let mut first = true;
         let mut names = self.argnames.iter();
         for t in &self.args.head {
@@ -94,14 +279,33 @@ impl Function {
         match self.returns {
             Some(ref returns) => match (returns.head.len(), returns.tail.is_some()) {
                 (0, false) => write!(f, " --> ()"),
-                (1, false) => {
+                (1, false) if self.returnnames.is_empty() => {
                     write!(f, " --> ")?;
                     write_ty(&returns.head[0], f, false)
                 },
-                (_, _) => {
+                (_, _) if self.returnnames.is_empty() => {
                     write!(f, " --> ")?;
                     write_tyseq(returns, f)
                 },
+                (_, _) => {
+                    // same to arguments, the names should be interleaved
+                    write!(f, " --> (")?;
+                    let mut first = true;
+                    let mut names = self.returnnames.iter();
+                    for t in &returns.head {
+                        if first { first = false; } else { write!(f, ", ")?; }
+                        if let Some(&Some(ref name)) = names.next() {
+                            write!(f, "{:+}: ", name)?;
+                        }
+                        write_ty(t, f, false)?;
+                    }
+                    if let Some(ref t) = returns.tail {
+                        if !first { write!(f, ", ")?; }
+                        write_ty(t, f, true)?;
+                        write!(f, "...")?;
+                    }
+                    write!(f, ")")
+                },
             },
             None => write!(f, " --> !"),
         }
@@ -110,6 +314,9 @@ impl Function {
 
 impl Display for Function {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
+        for typaram in &self.typarams {
+            st.name_tvar(typaram.tvar, &typaram.name.base);
+        }
         self.fmt_generic(
             f,
             |t, f, without_nil| {
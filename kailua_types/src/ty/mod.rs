@@ -2,15 +2,16 @@
 
 use std::fmt;
 use std::result;
-use diag::{TypeReport, TypeResult};
-use kailua_env::Spanned;
-use kailua_diag::{Result, Locale, Report};
+use diag::{TypeReport, TypeResult, TypeReportHint, TypeReportMore};
+use kailua_env::{Span, Spanned};
+use kailua_diag::{Result, Locale, Localize, Report, Reporter};
 use kailua_syntax::Name;
+use self::flags::Flags;
 
 pub use self::display::{Display, Displayed, DisplayState, DisplayName};
 pub use self::literals::{Numbers, Strings};
 pub use self::tables::{Key, Tables};
-pub use self::functions::{Function, Functions};
+pub use self::functions::{Function, Functions, TyParam};
 pub use self::union::Unioned;
 pub use self::value::{Dyn, Nil, T, Ty};
 pub use self::slot::{F, S, Slot};
@@ -38,6 +39,30 @@ impl fmt::Debug for TVar {
     }
 }
 
+/// Statistics about type variables, returned by `TypeContext::tvar_stats`.
+///
+/// Every generated type variable belongs to exactly one of `resolved` and `unresolved_*` fields.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TVarStats {
+    /// The total number of type variables.
+    pub total: usize,
+
+    /// The number of type variables with a tight bound.
+    pub resolved: usize,
+
+    /// The number of unresolved type variables only with an upper bound.
+    pub unresolved_upper_only: usize,
+
+    /// The number of unresolved type variables only with a lower bound.
+    pub unresolved_lower_only: usize,
+
+    /// The number of unresolved type variables with both upper and lower bounds.
+    pub unresolved_both: usize,
+
+    /// The number of unresolved type variables without any bound.
+    pub unresolved_free: usize,
+}
+
 /// Row variables generated by `TypeContext`.
 ///
 /// A row variable #0 (`RVar::empty()`) denotes a special, inextensible "empty" row variable.
@@ -193,6 +218,12 @@ pub trait TypeContext {
     /// Mainly used for generalization.
     fn copy_tvar(&mut self, tvar: TVar) -> TVar;
 
+    /// Generates a new fresh type variable with an upper bound covering given flags.
+    ///
+    /// For example, `T_STRINGY` results in a type variable bounded by `number | string`.
+    /// `T_DYNAMIC` in the flags is ignored.
+    fn gen_tvar_constrained(&mut self, flags: Flags) -> TVar;
+
     /// Asserts that the type variable has given upper bound.
     fn assert_tvar_sub(&mut self, lhs: TVar, rhs: &Ty) -> TypeResult<()>;
 
@@ -208,12 +239,56 @@ pub trait TypeContext {
     /// Asserts that the first type variable is equal to the second.
     fn assert_tvar_eq_tvar(&mut self, lhs: TVar, rhs: TVar) -> TypeResult<()>;
 
+    /// Unifies two type variables in a single step.
+    ///
+    /// Unlike `assert_tvar_eq_tvar`, this does not fail when two variables cannot be equal;
+    /// it falls back to `assert_tvar_sub_tvar` in either direction, so that the tightest
+    /// relation between them (equality, then `a <: b`, then `b <: a`) is recorded.
+    /// Only fails when two variables are not related at all.
+    ///
+    /// Each failed attempt leaves the variables unchanged,
+    /// so the bounds are never partially added before the fallback.
+    fn unify(&mut self, a: TVar, b: TVar) -> TypeResult<()> {
+        if self.assert_tvar_eq_tvar(a, b).is_ok() {
+            return Ok(());
+        }
+        if self.assert_tvar_sub_tvar(a, b).is_ok() {
+            return Ok(());
+        }
+        self.assert_tvar_sub_tvar(b, a)
+    }
+
+    /// Narrows the type variable to the truthy portion of its tight bound.
+    ///
+    /// Mainly used for narrowing the type in the `if` condition.
+    /// Does nothing if the type variable has no tight bound yet.
+    fn constrain_to_truthy(&mut self, tvar: TVar) -> TypeResult<()>;
+
+    /// Narrows the type variable to the falsy portion (`nil` or `false`) of its tight bound.
+    ///
+    /// Does nothing if the type variable has no tight bound yet.
+    fn constrain_to_falsy(&mut self, tvar: TVar) -> TypeResult<()>;
+
     /// Returns lower and upper bounds of given type variable as type flags.
     fn get_tvar_bounds(&self, tvar: TVar) -> (flags::Flags /*lb*/, flags::Flags /*ub*/);
 
     /// Resolves a given type variable if there is a tight bound.
     fn get_tvar_exact_type(&self, tvar: TVar) -> Option<Ty>;
 
+    /// Collects statistics about all type variables generated so far.
+    ///
+    /// This is mainly useful for profiling.
+    fn tvar_stats(&self) -> TVarStats;
+
+    /// Verifies the internal consistency of type and row variables,
+    /// and returns a list of human-readable descriptions for each violation found.
+    ///
+    /// This is a debugging aid and is not used by the type checker itself.
+    /// The default implementation has nothing to check and returns an empty list.
+    fn check_invariants(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     /// Generates a new fresh row variable.
     fn gen_rvar(&mut self) -> RVar;
 
@@ -273,6 +348,20 @@ pub trait TypeContext {
     /// Returns true if given nominal instance type is a subtype of another nominal instance type.
     fn is_subclass_of(&self, lhs: ClassId, rhs: ClassId) -> bool;
 
+    /// Returns true if the first type is a subtype of the second.
+    ///
+    /// This is a non-mutating version of `Lattice::assert_sub`: the assertion is made against
+    /// a snapshot of the context which is then discarded, so no type variable gets new bounds.
+    fn subtype_of(&self, a: &Ty, b: &Ty) -> bool;
+
+    /// Returns true if this context is a snapshot made by `subtype_of`.
+    ///
+    /// Slots are shared with the original context, so they should not be changed in place
+    /// (e.g. resolving the unknown flexibility) while this is true.
+    fn is_snapshot(&self) -> bool {
+        false
+    }
+
     /// Returns a pair of type flags that is an exact lower and upper bound for that type.
     ///
     /// Used as an approximate type bound testing like arithmetics.
@@ -284,6 +373,16 @@ pub trait TypeContext {
         (flags | lb, flags | ub)
     }
 
+    /// Returns true if given type is known to have a `__close` metamethod,
+    /// as required by Lua 5.4 to-be-closed variables (`local x <close> = ...`).
+    ///
+    /// Kailua does not track metatables yet, so this only accepts dynamic types and
+    /// `nil` or `false` (which Lua 5.4 allows and ignores).
+    fn has_close_metamethod(&self, ty: &Ty) -> bool {
+        let (_, ub) = self.get_type_bounds(ty);
+        ub.is_dynamic() || !ub.intersects(!flags::T_FALSY)
+    }
+
     /// Exactly resolves the type variable inside `ty` if possible.
     ///
     /// This is a requirement for table indexing and function calls.
@@ -328,6 +427,26 @@ pub trait Lattice<Other = Self> {
 
     /// Asserts that `self` is a consistent type equal to `other` under the type context.
     fn assert_eq(&self, other: &Other, ctx: &mut TypeContext) -> TypeResult<()>;
+
+    /// Same to `assert_sub` but reports an error to given `Report` on the failure.
+    ///
+    /// The error message is made from `msg` only when the assertion has failed,
+    /// and is followed by the detailed type reports (see `TypeReportMore::report_types`).
+    /// Returns `Ok(false)` if the error has been reported and the caller can continue.
+    fn assert_sub_reporting<'a, R, Msg>(&self, other: &Other, span: Span, hint: TypeReportHint,
+                                        ctx: &'a mut TypeContext, report: &R,
+                                        msg: Msg) -> Result<bool>
+        where R: Report, Msg: FnOnce(&'a TypeContext) -> Box<Localize + 'a>
+    {
+        match self.assert_sub(other, ctx) {
+            Ok(()) => Ok(true),
+            Err(r) => {
+                let msg = msg(ctx);
+                report.error(span, &*msg).report_types(r, hint).done()?;
+                Ok(false)
+            }
+        }
+    }
 }
 
 impl<A: Union<B>, B> Union<Box<B>> for Box<A> {
@@ -385,6 +504,12 @@ pub trait Dummy {
 /// Useful for ensuring that no operations involve type variables or row variables.
 pub struct NoTypeContext;
 
+impl NoTypeContext {
+    pub fn new() -> NoTypeContext {
+        NoTypeContext
+    }
+}
+
 impl TypeContext for NoTypeContext {
     fn gen_report(&self) -> TypeReport {
         TypeReport::new(Locale::dummy())
@@ -398,6 +523,9 @@ impl TypeContext for NoTypeContext {
     fn copy_tvar(&mut self, tvar: TVar) -> TVar {
         panic!("copy_tvar({:?}) is not supposed to be called here", tvar);
     }
+    fn gen_tvar_constrained(&mut self, flags: Flags) -> TVar {
+        panic!("gen_tvar_constrained({:?}) is not supposed to be called here", flags);
+    }
     fn assert_tvar_sub(&mut self, lhs: TVar, rhs: &Ty) -> TypeResult<()> {
         panic!("assert_tvar_sub({:?}, {:?}) is not supposed to be called here", lhs, rhs);
     }
@@ -413,12 +541,21 @@ impl TypeContext for NoTypeContext {
     fn assert_tvar_eq_tvar(&mut self, lhs: TVar, rhs: TVar) -> TypeResult<()> {
         panic!("assert_tvar_eq_tvar({:?}, {:?}) is not supposed to be called here", lhs, rhs);
     }
+    fn constrain_to_truthy(&mut self, tvar: TVar) -> TypeResult<()> {
+        panic!("constrain_to_truthy({:?}) is not supposed to be called here", tvar);
+    }
+    fn constrain_to_falsy(&mut self, tvar: TVar) -> TypeResult<()> {
+        panic!("constrain_to_falsy({:?}) is not supposed to be called here", tvar);
+    }
     fn get_tvar_bounds(&self, tvar: TVar) -> (flags::Flags /*lb*/, flags::Flags /*ub*/) {
         panic!("get_tvar_bounds({:?}) is not supposed to be called here", tvar);
     }
     fn get_tvar_exact_type(&self, tvar: TVar) -> Option<Ty> {
         panic!("get_tvar_exact_type({:?}) is not supposed to be called here", tvar);
     }
+    fn tvar_stats(&self) -> TVarStats {
+        TVarStats::default()
+    }
 
     fn gen_rvar(&mut self) -> RVar {
         panic!("gen_rvar is not supposed to be called here");
@@ -455,6 +592,9 @@ impl TypeContext for NoTypeContext {
     fn is_subclass_of(&self, lhs: ClassId, rhs: ClassId) -> bool {
         panic!("is_subclass_of({:?}, {:?}) is not supposed to be called here", lhs, rhs);
     }
+    fn subtype_of(&self, a: &Ty, b: &Ty) -> bool {
+        a.assert_sub(b, &mut NoTypeContext).is_ok()
+    }
 }
 
 impl Lattice for TVar {
@@ -593,6 +733,14 @@ pub mod flags {
             self.intersects(T_FALSY) && !self.intersects(!T_FALSY)
         }
 
+        /// Returns false if values with these flags can never equal to values with `other`.
+        ///
+        /// Dynamic types can equal to anything. Note that a silent nil is not in the flags,
+        /// so the caller should add `T_NOISY_NIL` for any type that can be nil.
+        pub fn can_equal(&self, other: Flags) -> bool {
+            self.is_dynamic() || other.is_dynamic() || self.intersects(other & T_ALL)
+        }
+
         pub fn get_dynamic(&self) -> Option<Dyn> {
             if self.contains(T_DYNAMIC) {
                 Some(Dyn::Oops)
@@ -746,3 +746,25 @@ impl SpannedSlotSeq {
     }
 }
 
+#[test]
+fn test_seq_eq() {
+    use ty::F;
+
+    let seq = |head: Vec<T<'static>>, tail: Option<T<'static>>| {
+        TySeq { head: head.into_iter().map(Ty::new).collect(), tail: tail.map(Ty::new) }
+    };
+
+    // structural, not by identity
+    assert_eq!(seq(vec![T::Integer, T::String], None), seq(vec![T::Integer, T::String], None));
+    assert_eq!(seq(vec![], Some(T::Boolean)), seq(vec![], Some(T::Boolean)));
+    assert!(seq(vec![T::Integer], None) != seq(vec![T::Integer], Some(T::Integer)));
+    assert!(seq(vec![T::Integer], None) != seq(vec![T::Number], None));
+    assert!(seq(vec![T::Integer], None) != seq(vec![T::Integer, T::Integer], None));
+
+    let slots = |flex: F, head: Vec<T<'static>>| {
+        SlotSeq { head: head.into_iter().map(|t| Slot::new(flex, Ty::new(t))).collect(),
+                  tail: None }
+    };
+    assert_eq!(slots(F::Var, vec![T::Integer]), slots(F::Var, vec![T::Integer]));
+    assert!(slots(F::Var, vec![T::Integer]) != slots(F::Const, vec![T::Integer]));
+}
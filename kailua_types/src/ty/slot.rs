@@ -2,6 +2,7 @@ use std::fmt;
 use std::mem;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::collections::HashMap;
 use std::sync::atomic::{Ordering, AtomicUsize};
 use take_mut::take;
 use parking_lot::{RwLock, RwLockReadGuard};
@@ -9,7 +10,8 @@ use parking_lot::{RwLock, RwLockReadGuard};
 use kailua_env::{Span, Spanned};
 use kailua_syntax::ast::{M, MM};
 use diag::Origin;
-use super::{Dyn, Nil, T, Ty, TypeContext, Lattice, Union, Dummy, TVar, Tag};
+use kailua_diag::Locale;
+use super::{Dyn, Nil, T, Ty, TypeContext, NoTypeContext, Lattice, Union, Dummy, TVar, Tag};
 use super::{TypeReport, TypeResult};
 use super::display::{Display, DisplayState, DisplayName};
 use super::flags::Flags;
@@ -41,6 +43,35 @@ pub enum F {
     /// This is a variant of `Var` but only allows for indexing (no subtyping), and any types
     /// assigned via indexing will be marked as type-checked later.
     Module,
+
+    /// Write-once slot.
+    ///
+    /// Behaves like `Var` until the first assignment, and gets updated to `Const` in place
+    /// after that, so that any subsequent assignment is rejected.
+    Once,
+}
+
+impl F {
+    /// Returns true if the slot with this flexibility can be assigned to.
+    ///
+    /// Dynamic slots are writable, as they silently accept (and ignore) any assignment.
+    /// This does not consider the initialization, which is also allowed for `Const`.
+    pub fn is_writable(&self) -> bool {
+        match *self {
+            F::Dynamic(_) | F::Just | F::Var | F::Module | F::Once => true,
+            F::Unknown | F::Const => false,
+        }
+    }
+
+    /// Returns true if the slot with this flexibility can be read from.
+    ///
+    /// Only the uninitialized (`Unknown`) slot cannot be read.
+    pub fn is_readable(&self) -> bool {
+        match *self {
+            F::Unknown => false,
+            F::Dynamic(_) | F::Just | F::Const | F::Var | F::Module | F::Once => true,
+        }
+    }
 }
 
 impl From<M> for F {
@@ -55,13 +86,18 @@ impl From<M> for F {
 impl From<MM> for F {
     fn from(modf: MM) -> F {
         match modf {
-            MM::None => F::Var,
+            MM::None | MM::Var => F::Var,
             MM::Const => F::Const,
             MM::Module => F::Module,
+            MM::Once => F::Once,
         }
     }
 }
 
+impl Default for F {
+    fn default() -> F { F::Just }
+}
+
 impl<'a> fmt::Debug for F {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -72,6 +108,7 @@ impl<'a> fmt::Debug for F {
             F::Const              => write!(f, "const"),
             F::Var                => write!(f, "var"),
             F::Module             => write!(f, "module"),
+            F::Once               => write!(f, "once"),
         }
     }
 }
@@ -83,6 +120,7 @@ const JUST_BITS: usize = 0x03;
 const CONST_BITS: usize = 0x04;
 const VAR_BITS: usize = 0x05;
 const MODULE_BITS: usize = 0x06;
+const ONCE_BITS: usize = 0x07;
 const FLEX_MASK: usize = 0x07;
 
 fn usize_from_flex(flex: F) -> usize {
@@ -94,6 +132,7 @@ fn usize_from_flex(flex: F) -> usize {
         F::Const              => CONST_BITS,
         F::Var                => VAR_BITS,
         F::Module             => MODULE_BITS,
+        F::Once               => ONCE_BITS,
     }
 }
 
@@ -106,6 +145,7 @@ fn flex_from_usize(v: usize) -> F {
         CONST_BITS        => F::Const,
         VAR_BITS          => F::Var,
         MODULE_BITS       => F::Module,
+        ONCE_BITS         => F::Once,
         _                 => panic!("unknown flex bits {:#x}", v),
     }
 }
@@ -246,15 +286,25 @@ impl S {
         S::new(flex, self.ty.into_inner().coerce())
     }
 
-    pub fn generalize(self, ctx: &mut TypeContext) -> S {
-        self.map_ty(|t| t.generalize(ctx))
+    pub fn generalize(self, substs: &HashMap<TVar, TVar>, ctx: &mut TypeContext) -> S {
+        self.map_ty(|t| t.generalize(substs, ctx))
     }
 
-    fn resolve_unknown_flex(&self, other: &S) -> (Bits, Bits) {
+    fn resolve_unknown_flex(&self, other: &S, ctx: &TypeContext) -> (Bits, Bits) {
         // if one flex is unknown, use the other's flex (this should be atomic)
         let mut lbits = self.bits();
         let mut rbits = other.bits();
         if lbits.flex() != rbits.flex() {
+            // the snapshot should only see the resolved flex without changing slots
+            if ctx.is_snapshot() {
+                if lbits.flex() == F::Unknown {
+                    lbits = lbits.with_flex(rbits.flex());
+                } else if rbits.flex() == F::Unknown {
+                    rbits = rbits.with_flex(lbits.flex());
+                }
+                return (lbits, rbits);
+            }
+
             while lbits.flex() == F::Unknown {
                 lbits.try_set_flex(rbits.flex(), &self.bits);
             }
@@ -271,14 +321,15 @@ impl S {
         (|| {
             let (flex, ty) = match (self.flex(), other.flex()) {
                 (F::Dynamic(dyn1), F::Dynamic(dyn2)) => {
-                    let dyn = dyn1.union(dyn2);
+                    let dyn = dyn1.union(&dyn2, explicit, ctx)?;
                     (F::Dynamic(dyn), Ty::new(T::Dynamic(dyn)))
                 },
                 (F::Dynamic(dyn), _) | (_, F::Dynamic(dyn)) =>
                     (F::Dynamic(dyn), Ty::new(T::Dynamic(dyn))),
 
-                // modules and unknowns cannot be unioned (even to itself)
+                // modules, write-once slots and unknowns cannot be unioned (even to itself)
                 (F::Module, _) | (_, F::Module) |
+                (F::Once, _) | (_, F::Once) |
                 (F::Unknown, _) | (_, F::Unknown) =>
                     return Err(ctx.gen_report()),
 
@@ -317,12 +368,14 @@ impl S {
         debug!("asserting a constraint {:?} <: {:?}", *self, *other);
 
         (|| {
-            let (lbits, rbits) = self.resolve_unknown_flex(other);
+            let (lbits, rbits) = self.resolve_unknown_flex(other, ctx);
 
             match (lbits.flex(), rbits.flex()) {
                 (_, F::Dynamic(_)) | (F::Dynamic(_), _) => Ok(()),
 
-                (F::Just, _) | (_, F::Const) =>
+                (_, F::Const) =>
+                    assert_const_sub(&*self.ty.read(), &*other.ty.read(), ctx),
+                (F::Just, _) =>
                     self.ty.read().assert_sub(&*other.ty.read(), ctx),
                 (F::Var, F::Var) =>
                     self.ty.read().assert_eq(&*other.ty.read(), ctx),
@@ -336,13 +389,13 @@ impl S {
         debug!("asserting a constraint {:?} = {:?}", *self, *other);
 
         (|| {
-            let (lbits, rbits) = self.resolve_unknown_flex(other);
+            let (lbits, rbits) = self.resolve_unknown_flex(other, ctx);
 
             match (lbits.flex(), rbits.flex()) {
                 (_, F::Dynamic(_)) | (F::Dynamic(_), _) => Ok(()),
 
                 (F::Just, F::Just) | (F::Const, F::Const) |
-                (F::Var, F::Var) | (F::Module, F::Module) =>
+                (F::Var, F::Var) | (F::Module, F::Module) | (F::Once, F::Once) =>
                     self.ty.read().assert_eq(&*other.ty.read(), ctx),
 
                 (_, _) => Err(ctx.gen_report()),
@@ -351,6 +404,28 @@ impl S {
     }
 }
 
+// asserts `lhs <: rhs` where `rhs` is in the Const slot.
+// the elements of vectors in the Const slot cannot be updated either,
+// so `vector<integer> <: const vector<number>` holds unlike Var slots.
+// the element itself can be read out and updated, so this does not apply to nested vectors.
+fn assert_const_sub(lhs: &Ty, rhs: &Ty, ctx: &mut TypeContext) -> TypeResult<()> {
+    match (lhs.to_covariant_elements(), rhs.to_covariant_elements()) {
+        (Some(lhs), Some(rhs)) => lhs.assert_sub(&rhs, ctx),
+        (_, _) => lhs.assert_sub(rhs, ctx),
+    }
+}
+
+impl Default for S {
+    /// Returns a dynamic slot (`WHATEVER`) which accepts any assignment.
+    ///
+    /// Unlike `Slot::dummy`, this uses `Dyn::User` instead of `Dyn::Oops`:
+    /// the default slot is a legitimate value rather than a result of prior errors,
+    /// so it should not absorb other types into the error type nor silence diagnostics.
+    fn default() -> S {
+        S::new(F::Dynamic(Dyn::User), Ty::new(T::Dynamic(Dyn::User)))
+    }
+}
+
 impl PartialEq for S {
     fn eq(&self, other: &S) -> bool {
         Bits::load(&self.bits) == Bits::load(&other.bits) && *self.ty.read() == *other.ty.read()
@@ -384,6 +459,8 @@ impl Display for S {
 
             (F::Module, "ko") => (true, "<초기화중> "),
             (F::Module, _)    => (true, "<initializing> "),
+
+            (F::Once, _) => (true, "once "),
         };
 
         let ret = if write_ty {
@@ -439,6 +516,13 @@ impl Slot {
         self.0.unlift()
     }
 
+    /// Returns the value type of the slot, same to `unlift`.
+    ///
+    /// The returned proxy dereferences to `Ty` and keeps the slot locked until dropped.
+    pub fn ty<'a>(&'a self) -> UnliftedSlot<'a> {
+        self.unlift()
+    }
+
     // one tries to assign to `self` through parent with `flex`. how should `self` change?
     // (only makes sense when `self` is a Just slot, otherwise no-op)
     pub fn adapt(&self, flex: F, _ctx: &mut TypeContext) {
@@ -485,9 +569,18 @@ impl Slot {
                     (F::Module, _, false) =>
                         return rhs.0.ty.read().assert_sub(&*self.0.ty.read(), ctx),
 
+                    // Once becomes Const after the first assignment
+                    (F::Once, _, _) => {
+                        rhs.0.ty.read().assert_sub(&*self.0.ty.read(), ctx)?;
+                        while bits.flex() == F::Once {
+                            bits.try_set_flex(F::Const, &self.0.bits);
+                        }
+                        return Ok(());
+                    },
+
                     // assignment to Const slot is for initialization only
                     (F::Const, _, true) =>
-                        return rhs.0.ty.read().assert_sub(&*self.0.ty.read(), ctx),
+                        return assert_const_sub(&*rhs.0.ty.read(), &*self.0.ty.read(), ctx),
 
                     // Just becomes Var when assignment happens
                     (F::Just, _, _) => {
@@ -509,17 +602,15 @@ impl Slot {
         let mut bits = self.0.bits();
         loop {
             match bits.flex() {
-                F::Dynamic(_) => return Ok(()),
-                F::Unknown | F::Const => {
-                    return Err(ctx.gen_report().cannot_assign_in_place(Origin::Slot, &*self, ctx));
-                },
-                F::Var => return Ok(()),
                 F::Just => {
                     bits.try_set_flex(F::Var, &self.0.bits);
                     // retry until the flex _really_ changes to Var
                 },
-                // this requires an additional processing
-                F::Module => return Ok(()),
+                // Module requires an additional processing
+                flex if flex.is_writable() => return Ok(()),
+                _ => {
+                    return Err(ctx.gen_report().cannot_assign_in_place(Origin::Slot, &*self, ctx));
+                },
             }
         }
     }
@@ -531,6 +622,17 @@ impl Slot {
         }
     }
 
+    /// Turns the `once` slot into `const` as if it has been assigned, or reverts that.
+    ///
+    /// This is used to give each branch of a conditional statement its own first assignment.
+    pub fn set_once_assigned(&self, assigned: bool) {
+        let (from, to) = if assigned { (F::Once, F::Const) } else { (F::Const, F::Once) };
+        let mut bits = self.0.bits();
+        while bits.flex() == from {
+            bits.try_set_flex(to, &self.0.bits);
+        }
+    }
+
     pub fn filter_by_flags(&self, flags: Flags, ctx: &mut TypeContext) -> TypeResult<()> {
         // when filter_by_flags fails, the slot itself has no valid type
         let mut ty = self.0.ty.write();
@@ -541,6 +643,24 @@ impl Slot {
         Ok(())
     }
 
+    /// Replaces the value type in place and returns the previous type.
+    ///
+    /// This is used to revert the temporary narrowing by `filter_by_flags`.
+    pub fn replace_ty(&self, ty: Ty) -> Ty {
+        mem::replace(&mut *self.0.ty.write(), ty)
+    }
+
+    /// Marks the implicitly nilable value type as not containing `nil` in place.
+    ///
+    /// This should be only used after the slot has been checked for `nil`.
+    pub fn remove_implicit_nil(&self) {
+        let mut ty = self.0.ty.write();
+        if ty.nil() == Nil::Silent {
+            let t = mem::replace(&mut *ty, Ty::dummy());
+            *ty = t.or_nil(Nil::Absent);
+        }
+    }
+
     // following methods are direct analogues to value type's ones, whenever applicable
 
     pub fn flex(&self) -> F { self.0.flex() }
@@ -572,8 +692,24 @@ impl Slot {
         Slot::from((*self.0).clone().coerce())
     }
 
-    pub fn generalize(&self, ctx: &mut TypeContext) -> Slot {
-        Slot::from((*self.0).clone().generalize(ctx))
+    pub fn generalize(&self, substs: &HashMap<TVar, TVar>, ctx: &mut TypeContext) -> Slot {
+        Slot::from((*self.0).clone().generalize(substs, ctx))
+    }
+
+    /// Returns a new `Just` slot with the same type, as if it's used as an r-value
+    /// (e.g. passed to a function argument).
+    pub fn to_rvalue(&self) -> Slot {
+        Slot::just(self.unlift().clone())
+    }
+
+    /// Returns a new `Const` slot with the same type.
+    pub fn to_const(&self) -> Slot {
+        Slot::new(F::Const, self.unlift().clone())
+    }
+
+    /// Returns a new `Var` slot with the same type.
+    pub fn to_var(&self) -> Slot {
+        Slot::var(self.unlift().clone())
     }
 
     // should *not* create a new slot! (the resulting slot is not a different type,
@@ -588,6 +724,11 @@ impl Dummy for Slot {
     fn dummy() -> Slot { Self::dummy() }
 }
 
+impl Default for Slot {
+    /// Returns a dynamic slot (`WHATEVER`) which accepts any assignment.
+    fn default() -> Slot { Slot::from(S::default()) }
+}
+
 impl Union for Slot {
     type Output = Slot;
 
@@ -657,6 +798,26 @@ impl Display for Slot {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         self.0.fmt_displayed(f, st)
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        self.unlift().brief_displayed(ctx)
+    }
+}
+
+/// Displays the slot without any type context, mainly for debugging.
+///
+/// Types requiring a type context to display (type variables, records and classes)
+/// fall back to the `Debug` output.
+/// Use `Display::display` with an appropriate context for the full output.
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.unlift().is_context_free() {
+            return fmt::Debug::fmt(self, f);
+        }
+        let ctx = NoTypeContext::new();
+        let st = DisplayState::new(f, Locale::dummy(), &ctx);
+        Display::fmt_displayed(self, f, &st)
+    }
 }
 
 impl fmt::Debug for Slot {
@@ -697,5 +858,62 @@ mod tests {
         assert!(cnst(T::Integer).assert_sub(&cnst(T::Number), &mut NoTypeContext).is_ok());
         assert!(cnst(T::Number).assert_sub(&cnst(T::Integer), &mut NoTypeContext).is_err());
     }
-}
 
+    #[test]
+    fn test_default() {
+        assert_eq!(F::default(), F::Just);
+
+        let slot = Slot::default();
+        assert_eq!(slot.flex(), F::Dynamic(Dyn::User));
+        assert!(Slot::just(Ty::new(T::Integer)).assert_sub(&slot, &mut NoTypeContext).is_ok());
+    }
+
+    #[test]
+    fn test_flex_coercions() {
+        let var = Slot::var(Ty::new(T::Integer));
+
+        let rvalue = var.to_rvalue();
+        assert_eq!(rvalue.flex(), F::Just);
+        assert_eq!(*rvalue.unlift(), Ty::new(T::Integer));
+        assert_eq!(var.flex(), F::Var); // the original slot is left intact
+
+        let cnst = rvalue.to_const();
+        assert_eq!(cnst.flex(), F::Const);
+        assert_eq!(*cnst.unlift(), Ty::new(T::Integer));
+
+        let var2 = cnst.to_var();
+        assert_eq!(var2.flex(), F::Var);
+        assert_eq!(*var2.unlift(), Ty::new(T::Integer));
+    }
+
+    #[test]
+    fn test_flex_access() {
+        assert!(F::Just.is_writable() && F::Just.is_readable());
+        assert!(F::Var.is_writable() && F::Var.is_readable());
+        assert!(F::Module.is_writable() && F::Module.is_readable());
+        assert!(F::Dynamic(Dyn::User).is_writable() && F::Dynamic(Dyn::Oops).is_readable());
+        assert!(!F::Const.is_writable() && F::Const.is_readable());
+        assert!(!F::Unknown.is_writable() && !F::Unknown.is_readable());
+    }
+
+    #[test]
+    fn test_fmt_display() {
+        assert_eq!(Slot::new(F::Var, Ty::new(T::Integer)).to_string(), "integer");
+        assert_eq!(Slot::new(F::Const, Ty::new(T::String)).to_string(), "const string");
+        assert_eq!(format!("{}", Slot::new(F::Just, Ty::new(T::Integer | T::String))),
+                   "(integer|string)");
+
+        // types requiring a type context fall back to the debug output
+        use std::borrow::Cow;
+        use ty::{Tables, TVar, RVar, Class, ClassId, ClassSystemId};
+        let tvar = Slot::new(F::Var, Ty::new(T::TVar(TVar(1))));
+        assert_eq!(tvar.to_string(), format!("{:?}", tvar));
+        let record = Slot::new(F::Var, Ty::new(T::Tables(Cow::Owned(Tables::Fields(RVar::empty(),
+                                                                                   None)))));
+        assert_eq!(record.to_string(), format!("{:?}", record));
+        let class = Class::Instance(ClassId(ClassSystemId(0), 0));
+        let class = Slot::new(F::Var, Ty::new(T::Class(class)));
+        let nested = Slot::new(F::Var, Ty::new(T::array(class)));
+        assert_eq!(nested.to_string(), format!("{:?}", nested));
+    }
+}
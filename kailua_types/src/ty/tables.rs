@@ -1,11 +1,12 @@
 use std::fmt;
 use std::i32;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, HashMap};
 
 use kailua_syntax::Str;
 use diag::{Origin, TypeReport, TypeResult};
-use super::{Display, DisplayState, T, Ty, Slot, TypeContext, Union, Lattice, RVar};
+use super::{Display, DisplayState, T, Ty, F, Slot, TypeContext, Union, Lattice, RVar, TVar};
 
 /// A key allowed in the row variable.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -70,10 +71,13 @@ impl fmt::Debug for Key {
 /// Table types.
 #[derive(Clone)]
 pub enum Tables {
-    /// A tuple or record type, represented by a row variable.
+    /// A tuple or record type, represented by a row variable and an optional metatable.
     ///
-    /// An empty inextensible table is represented as `Tables::Fields(RVar::empty())`.
-    Fields(RVar),
+    /// The metatable is set by `setmetatable` and used as a fallback for indexing and calls.
+    /// When indexing a missing field, a table `__index` is searched recursively and
+    /// a function `__index` (called as `__index(t, key)`) results in its first return type.
+    /// An empty inextensible table is represented as `Tables::Fields(RVar::empty(), None)`.
+    Fields(RVar, Option<Ty>),
 
     /// An array type `vector<T>`, where `T` is implicitly nilable.
     ///
@@ -102,20 +106,44 @@ fn nslot(v: &Slot) -> Slot {
 }
 
 impl Tables {
-    pub fn generalize(self, ctx: &mut TypeContext) -> Tables {
+    pub fn generalize(self, substs: &HashMap<TVar, TVar>, ctx: &mut TypeContext) -> Tables {
         match self {
-            Tables::Fields(r) => Tables::Fields(ctx.copy_rvar(r)),
-            Tables::Array(v) => Tables::Array(v.generalize(ctx)),
-            Tables::ArrayN(v) => Tables::ArrayN(v.generalize(ctx)),
+            // the metatable is kept as is, it is not a part of the structural type
+            Tables::Fields(r, meta) => Tables::Fields(ctx.copy_rvar(r), meta),
+            Tables::Array(v) => Tables::Array(v.generalize(substs, ctx)),
+            Tables::ArrayN(v) => Tables::ArrayN(v.generalize(substs, ctx)),
             Tables::Map(k, v) => {
-                let k = k.generalize(ctx);
-                let v = v.generalize(ctx);
+                let k = k.generalize(substs, ctx);
+                let v = v.generalize(substs, ctx);
                 Tables::Map(k, v)
             },
             Tables::All => Tables::All,
         }
     }
 
+    /// Returns a copy of the table with its element slot made `Just`,
+    /// if the table only has integer keys (i.e. `vector<T>` or `vector<T>` with `n`).
+    ///
+    /// A table in the `const` slot cannot be updated through that slot,
+    /// so its elements can be compared covariantly. This makes `const vector<T>` covariant to `T`.
+    /// Only the outermost element is affected; the element can still be read out
+    /// and updated on its own, so any table inside the element type remains invariant
+    /// unless the element slot is `const` as well.
+    pub fn to_covariant_elements(&self) -> Option<Tables> {
+        fn covariant(v: &Slot) -> Slot {
+            match v.flex() {
+                F::Dynamic(_) | F::Const => v.clone(),
+                _ => Slot::just(v.unlift().clone()),
+            }
+        }
+
+        match *self {
+            Tables::Array(ref v) => Some(Tables::Array(covariant(v))),
+            Tables::ArrayN(ref v) => Some(Tables::ArrayN(covariant(v))),
+            _ => None,
+        }
+    }
+
     fn fmt_generic<WriteTy, WriteSlot>(&self, f: &mut fmt::Formatter,
                                        st: Option<&DisplayState>,
                                        mut write_ty: WriteTy,
@@ -126,7 +154,7 @@ impl Tables {
         match *self {
             Tables::All => write!(f, "table"),
 
-            Tables::Fields(ref rvar) => {
+            Tables::Fields(ref rvar, ref meta) => {
                 if let Some(st) = st {
                     if !st.can_recurse() {
                         return match &st.locale[..] {
@@ -204,6 +232,12 @@ impl Tables {
                     }
 
                     write!(f, "}}")?;
+
+                    // the metatable is only shown in the debugging output
+                    if let (true, &Some(ref meta)) = (expose_rvar, meta) {
+                        write!(f, " with metatable ")?;
+                        write_ty(meta, f)?;
+                    }
                     Ok(())
                 })();
 
@@ -268,18 +302,20 @@ impl Union for Tables {
                     av.assert_eq(bv, ctx)?;
                     Ok(Tables::Map(ak.clone(), av.clone()))
                 },
-                (&Tables::Fields(ref ar), &Tables::Fields(ref br)) => {
+                (&Tables::Fields(ref ar, ref am), &Tables::Fields(ref br, ref bm)) => {
                     ar.assert_eq(&br, ctx)?;
-                    Ok(Tables::Fields(ar.clone()))
+                    // conflicting metatables are forgotten, which is safe but less useful
+                    let meta = if *am == *bm { am.clone() } else { None };
+                    Ok(Tables::Fields(ar.clone(), meta))
                 },
 
                 // for the records and non-records, records should be a subtype of non-records
                 // (and should be no longer extensible)
-                (lhs @ &Tables::Fields(_), rhs) => {
+                (lhs @ &Tables::Fields(..), rhs) => {
                     lhs.assert_sub(rhs, ctx)?;
                     Ok(rhs.clone())
                 },
-                (lhs, rhs @ &Tables::Fields(_)) => {
+                (lhs, rhs @ &Tables::Fields(..)) => {
                     rhs.assert_sub(lhs, ctx)?;
                     Ok(lhs.clone())
                 },
@@ -297,11 +333,17 @@ impl Lattice for Tables {
                 (_, &Tables::All) => true,
                 (&Tables::All, _) => false,
 
-                (&Tables::Fields(ref ar), &Tables::Fields(ref br)) => {
-                    return ar.assert_sub(&br, ctx);
+                (&Tables::Fields(ref ar, ref am), &Tables::Fields(ref br, ref bm)) => {
+                    ar.assert_sub(&br, ctx)?;
+                    // the metatable can be forgotten but cannot be made up
+                    match (am, bm) {
+                        (_, &None) => true,
+                        (&Some(ref am), &Some(ref bm)) => { am.assert_sub(bm, ctx)?; true },
+                        (&None, &Some(_)) => false,
+                    }
                 },
 
-                (&Tables::Fields(ref rvar), &Tables::Map(ref key, ref value)) => {
+                (&Tables::Fields(ref rvar, _), &Tables::Map(ref key, ref value)) => {
                     // subtyping should hold for existing fields
                     for (k, v) in ctx.get_rvar_fields(rvar.clone()) {
                         k.to_type().assert_sub(&**key, ctx)?;
@@ -313,8 +355,8 @@ impl Lattice for Tables {
                     return ctx.assert_rvar_closed(rvar.clone());
                 },
 
-                (&Tables::Fields(ref rvar), &Tables::Array(ref value)) |
-                (&Tables::Fields(ref rvar), &Tables::ArrayN(ref value)) => {
+                (&Tables::Fields(ref rvar, _), &Tables::Array(ref value)) |
+                (&Tables::Fields(ref rvar, _), &Tables::ArrayN(ref value)) => {
                     let has_n = if let Tables::ArrayN(_) = *other { true } else { false };
 
                     // the fields should have consecutive integer keys.
@@ -400,7 +442,14 @@ impl Lattice for Tables {
                     av.assert_eq(bv, ctx)?;
                     true
                 }
-                (&Tables::Fields(ref ar), &Tables::Fields(ref br)) => return ar.assert_eq(&br, ctx),
+                (&Tables::Fields(ref ar, ref am), &Tables::Fields(ref br, ref bm)) => {
+                    ar.assert_eq(&br, ctx)?;
+                    match (am, bm) {
+                        (&None, &None) => true,
+                        (&Some(ref am), &Some(ref bm)) => return am.assert_eq(bm, ctx),
+                        (_, _) => false,
+                    }
+                }
                 (_, _) => false,
             };
 
@@ -417,12 +466,35 @@ impl PartialEq for Tables {
             (&Tables::ArrayN(ref a), &Tables::ArrayN(ref b)) => *a == *b,
             (&Tables::Map(ref ak, ref av), &Tables::Map(ref bk, ref bv)) =>
                 *ak == *bk && *av == *bv,
-            (&Tables::Fields(ref ar), &Tables::Fields(ref br)) => *ar == *br,
+            (&Tables::Fields(ref ar, ref am), &Tables::Fields(ref br, ref bm)) =>
+                *ar == *br && *am == *bm,
             (_, _) => false,
         }
     }
 }
 
+impl Eq for Tables {}
+
+/// Hashes the row variable or the flags of component types.
+///
+/// This is coarser than `PartialEq` but consistent with it,
+/// since equal types always have equal flags.
+impl Hash for Tables {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Tables::All => 0u8.hash(state),
+            Tables::Fields(ref r, _) => { 1u8.hash(state); r.hash(state); }
+            Tables::Array(ref v) => { 2u8.hash(state); v.flags().bits().hash(state); }
+            Tables::ArrayN(ref v) => { 3u8.hash(state); v.flags().bits().hash(state); }
+            Tables::Map(ref k, ref v) => {
+                4u8.hash(state);
+                k.flags().bits().hash(state);
+                v.flags().bits().hash(state);
+            }
+        }
+    }
+}
+
 impl Display for Tables {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         self.fmt_generic(
@@ -448,3 +520,17 @@ impl fmt::Debug for Tables {
     }
 }
 
+#[test]
+fn test_tables_as_hash_key() {
+    use std::collections::HashMap;
+
+    let mut map = HashMap::new();
+    map.insert(Tables::All, 1);
+    map.insert(Tables::Array(Slot::just(Ty::new(T::Integer))), 2);
+    map.insert(Tables::Fields(RVar::empty(), None), 3);
+    assert_eq!(map.get(&Tables::All), Some(&1));
+    assert_eq!(map.get(&Tables::Array(Slot::just(Ty::new(T::Integer)))), Some(&2));
+    assert_eq!(map.get(&Tables::Array(Slot::just(Ty::new(T::String)))), None);
+    assert_eq!(map.get(&Tables::Fields(RVar::empty(), None)), Some(&3));
+    assert_eq!(map.get(&Tables::Fields(RVar::any(), None)), None);
+}
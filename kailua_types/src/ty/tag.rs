@@ -33,7 +33,7 @@ pub enum Tag {
     ///
     /// - `<expr>` asserts that the corresponding type is truthy.
     /// - `not <expr>` asserts that the corresponding type is falsy.
-    /// - `<type>(<expr>) == <string>`, where `<type>` is a value with `Type` tag.
+    /// - `<type>(<expr>) == <string>`, where `<type>` is a value with `Type` or `MathType` tag.
     ///
     /// Expressions can be chained by `and` or `or`, subject to De Morgan's law.
     /// Any unrecognized expression or non-definitive conditions are ignored.
@@ -53,6 +53,26 @@ pub enum Tag {
     /// - `"integer"` for an integer.
     AssertType,
 
+    /// `function(number, ...) -> number`
+    ///
+    /// The first return type is refined to `integer` when all arguments are integral,
+    /// or to `WHATEVER` when any argument is dynamic (e.g. `math.max` or `math.abs`).
+    PreserveInteger,
+
+    /// `function(any) -> string?`
+    ///
+    /// `math.type` (Lua 5.3). The return type is refined from the argument type:
+    /// `"integer"` for integers, `"float"` for other numbers and `nil` for non-numbers.
+    /// Also recognized by `Assert` like `Type`, but with `"integer"` and `"float"` instead.
+    MathType,
+
+    /// `function(function, ...) -> (boolean, ...)`
+    ///
+    /// `pcall`. When the first argument is a function, the remaining arguments are checked
+    /// against it and the return types are refined from its return types `(T, U...)`
+    /// to `(boolean, T | string, U?...)`, where `string` is an error message from `error`.
+    Pcall,
+
     /// `function(table, ...) -> (function(table, any) -> (any?, any), table, any, ...)`
     ///
     /// A hack for supporting generic `pairs` and `ipairs` functions.
@@ -106,6 +126,88 @@ pub enum Tag {
     /// there is no other valid way to get a table with such a type.
     StringMeta,
 
+    /// `function(string, string, ...) -> (...)`
+    ///
+    /// `string.find`, `string.match` and `string.gmatch` respectively.
+    /// When the pattern (the second argument) is a string literal,
+    /// the return types are refined from the captures in that pattern.
+    StringFind,
+    StringMatch,
+    StringGmatch,
+
+    /// `function(string, ...) -> string` and `function(string, string, integer?) -> (...)`
+    ///
+    /// `string.pack` and `string.unpack` respectively (Lua 5.3).
+    /// When the format (the first argument) is a string literal,
+    /// the values to be packed are checked against and the unpacked values are inferred from
+    /// the options in that format.
+    StringPack,
+    StringUnpack,
+
+    /// `function(table, table?) -> table`
+    ///
+    /// When the first argument is a record, the return type is that record
+    /// with the second argument as its metatable. The metatable is then used for
+    /// the `__index` fallback on missing fields and for the `__call` metamethod.
+    SetMetatable,
+
+    /// `function(any) -> table`
+    ///
+    /// When the argument is a record with a known metatable, the return type is that metatable.
+    GetMetatable,
+
+    /// `function(table, any) -> any`
+    ///
+    /// `rawget`. When the first argument is a table, the return type is refined
+    /// by indexing the table with the second argument, ignoring its metatable.
+    RawGet,
+
+    /// `function(table, any, any) -> table`
+    ///
+    /// `rawset`. When the first argument is a table, the third argument is assigned
+    /// to the table indexed with the second argument, as if `table[index] = value`
+    /// but ignoring its metatable. The return type is refined to the first argument.
+    RawSet,
+
+    /// `function(vector<T>, (function(T, T) -> boolean)?)`
+    ///
+    /// `table.sort`. When the first argument is a vector with a known element type,
+    /// the comparator (the second argument) is checked against that element type.
+    TableSort,
+
+    /// `function(vector<T>, integer, integer, integer, vector<T>?) -> vector<T>`
+    ///
+    /// `table.move` (Lua 5.3). The source (the first argument) should not be a map with
+    /// non-integer keys, and the destination (the fifth argument) if any should be
+    /// compatible to the source; for vectors, the source elements should be subtypes of
+    /// the destination elements. The return type is refined to the destination.
+    TableMove,
+
+    /// `function(<format>...) -> (any...)` and `function(<format>...) -> function() -> (any...)`
+    ///
+    /// `io.read` (also `file:read`) and `io.lines` (also `file:lines`) respectively.
+    /// Formats are the variadic arguments following fixed ones (e.g. `self` or a file name).
+    /// When each format is a literal, a corresponding value is refined to
+    /// `number?` for `*n`, `string` for `*a` and `string?` for `*l` or an integer;
+    /// no formats are same to a single `*l`. `io.lines` returns an iterator for such values.
+    /// Lua 5.1 ignores formats to `io.lines`, so the Lua 5.1 definitions do not use `IoLines`.
+    IoRead,
+    IoLines,
+
+    /// `function(any...) -> (any...)`
+    ///
+    /// `coroutine.yield`. The (widened) argument types are collected into the current function,
+    /// so that `coroutine.wrap` can use them as the return types of the wrapper function.
+    CoroutineYield,
+
+    /// `function(function) -> function`
+    ///
+    /// `coroutine.wrap`. When the argument is a function literal which calls `coroutine.yield`,
+    /// the return type is refined to a function returning the types yielded from that literal.
+    /// The literal should not call other functions that may yield, e.g. functions defined
+    /// outside of the literal or iterators not from the library; the type is kept otherwise.
+    CoroutineWrap,
+
     /// `function(<class prototype type>?) -> <class prototype type>`
     ///
     /// A function that makes a class prototype. If the argument is given, it should be
@@ -169,12 +271,30 @@ impl Tag {
             b"assert_not"    => no_values(resolv, Tag::AssertNot),
             b"assert_type"   => no_values(resolv, Tag::AssertType),
             b"generic_pairs" => no_values(resolv, Tag::GenericPairs),
+            b"preserve_integer" => no_values(resolv, Tag::PreserveInteger),
+            b"math_type"     => no_values(resolv, Tag::MathType),
+            b"pcall"         => no_values(resolv, Tag::Pcall),
             b"genv"          => no_values(resolv, Tag::GlobalEnv),
             b"geval"         => no_values(resolv, Tag::GlobalEval),
             b"become_module" => no_values(resolv, Tag::BecomeModule),
             b"package_path"  => no_values(resolv, Tag::PackagePath),
             b"package_cpath" => no_values(resolv, Tag::PackageCpath),
             b"string_meta"   => no_values(resolv, Tag::StringMeta),
+            b"string_find"   => no_values(resolv, Tag::StringFind),
+            b"string_match"  => no_values(resolv, Tag::StringMatch),
+            b"string_gmatch" => no_values(resolv, Tag::StringGmatch),
+            b"string_pack"   => no_values(resolv, Tag::StringPack),
+            b"string_unpack" => no_values(resolv, Tag::StringUnpack),
+            b"setmetatable"  => no_values(resolv, Tag::SetMetatable),
+            b"getmetatable"  => no_values(resolv, Tag::GetMetatable),
+            b"rawget"        => no_values(resolv, Tag::RawGet),
+            b"rawset"        => no_values(resolv, Tag::RawSet),
+            b"table_sort"    => no_values(resolv, Tag::TableSort),
+            b"table_move"    => no_values(resolv, Tag::TableMove),
+            b"io_read"       => no_values(resolv, Tag::IoRead),
+            b"io_lines"      => no_values(resolv, Tag::IoLines),
+            b"coroutine_yield" => no_values(resolv, Tag::CoroutineYield),
+            b"coroutine_wrap" => no_values(resolv, Tag::CoroutineWrap),
 
             b"make_class" => {
                 let values = values(resolv, 1)?;
@@ -204,12 +324,30 @@ impl Tag {
             Tag::AssertNot    => "assert_not",
             Tag::AssertType   => "assert_type",
             Tag::GenericPairs => "generic_pairs",
+            Tag::PreserveInteger => "preserve_integer",
+            Tag::MathType     => "math_type",
+            Tag::Pcall        => "pcall",
             Tag::GlobalEnv    => "genv",
             Tag::GlobalEval   => "geval",
             Tag::BecomeModule => "become_module",
             Tag::PackagePath  => "package_path",
             Tag::PackageCpath => "package_cpath",
             Tag::StringMeta   => "string_meta",
+            Tag::StringFind   => "string_find",
+            Tag::StringMatch  => "string_match",
+            Tag::StringGmatch => "string_gmatch",
+            Tag::StringPack   => "string_pack",
+            Tag::StringUnpack => "string_unpack",
+            Tag::SetMetatable => "setmetatable",
+            Tag::GetMetatable => "getmetatable",
+            Tag::RawGet       => "rawget",
+            Tag::RawSet       => "rawset",
+            Tag::TableSort    => "table_sort",
+            Tag::TableMove    => "table_move",
+            Tag::IoRead       => "io_read",
+            Tag::IoLines      => "io_lines",
+            Tag::CoroutineYield => "coroutine_yield",
+            Tag::CoroutineWrap => "coroutine_wrap",
             Tag::MakeClass(_) => "make_class",
 
             Tag::_Subtype         => "internal subtype",
@@ -228,6 +366,7 @@ impl Tag {
     pub fn scope_local(&self) -> bool {
         match *self {
             Tag::Type |
+            Tag::MathType |
             Tag::Assert |
             Tag::AssertNot |
             Tag::AssertType |
@@ -273,6 +412,25 @@ impl fmt::Debug for Tag {
     }
 }
 
+/// Displays the user-visible tag name as used in the attribute.
+///
+/// Class systems in `[make_class]` cannot be resolved without a type context,
+/// so they are shown as their identifiers; `Display` should be preferred if possible.
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+
+        match *self {
+            Tag::MakeClass(csid) => {
+                write!(f, "({:?})", csid)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
 impl Display for Tag {
     fn fmt_displayed(&self, f: &mut fmt::Formatter, st: &DisplayState) -> fmt::Result {
         write!(f, "{}", self.name())?;
@@ -290,3 +448,18 @@ impl Display for Tag {
     }
 }
 
+#[test]
+fn test_display() {
+    use kailua_diag::Locale;
+    use ty::{Ty, T, TypeContext, NoTypeContext};
+
+    assert_eq!(Tag::StringFind.to_string(), "string_find");
+    assert_eq!(Tag::MakeClass(ClassSystemId(1)).to_string(), "make_class(%1)");
+
+    let ctx = &NoTypeContext as &TypeContext;
+    assert_eq!(Tag::GenericPairs.display(ctx).to_string_with_locale(Locale::dummy()),
+               "generic_pairs");
+    let ty = Ty::new(T::Integer).with_tag(Tag::PreserveInteger);
+    assert_eq!(ty.display(ctx).to_string_with_locale(Locale::dummy()),
+               "[preserve_integer] integer");
+}
@@ -5,7 +5,7 @@ use std::collections::BTreeSet;
 use kailua_syntax::Str;
 use diag::{TypeReport, TypeResult, Origin};
 use super::display::{Display, DisplayState, DisplayName};
-use super::{T, TypeContext, Lattice, Union};
+use super::{T, Ty, TypeContext, Lattice, Union};
 use super::{Numbers, Strings, Tables, Functions, Class};
 use super::flags::*;
 
@@ -195,6 +195,117 @@ impl Unioned {
         self.display_hints.retain(|&(flags, _)| flags != T_NONE);
     }
 
+    /// Removes any component which is a subtype of given type.
+    ///
+    /// The union type can only lose a component as a whole,
+    /// so `number` minus `integer` is still `number` (there is no type for non-integers).
+    /// The nil-ness of given type is ignored.
+    /// Subtyping is only queried, so no type variable gets new bounds;
+    /// components involving unbound type variables are kept, as they are not known to be subtypes.
+    pub fn remove_ty(&mut self, ty: &Ty, ctx: &mut TypeContext) -> TypeResult<()> {
+        let other = match **ty {
+            // everything is a subtype of them
+            T::Dynamic(_) | T::All => {
+                *self = Unioned::empty();
+                return Ok(());
+            }
+            ref t => Unioned::from(t, ctx)?,
+        };
+
+        let prevflags = self.flags();
+
+        self.simple &= !other.simple;
+
+        self.numbers = match (self.numbers.take(), other.numbers) {
+            (num, None) => num,
+            (_, Some(Numbers::All)) => None,
+            (Some(Numbers::All), Some(_)) => Some(Numbers::All),
+            (Some(_), Some(Numbers::Int)) => None,
+            (Some(Numbers::Int), Some(_)) => Some(Numbers::Int),
+            (Some(Numbers::One(v)), Some(rhs)) => {
+                if rhs.contains_literal(&v) { None } else { Some(Numbers::One(v)) }
+            }
+            (Some(Numbers::Some(vv)), Some(rhs)) => {
+                subtract_literals(vv, &rhs).map(|vv| {
+                    if vv.len() == 1 {
+                        Numbers::One(vv.iter().cloned().next().unwrap())
+                    } else {
+                        Numbers::Some(vv)
+                    }
+                })
+            }
+            (None, _) => None,
+        };
+
+        self.strings = match (self.strings.take(), other.strings) {
+            (s, None) => s,
+            (_, Some(Strings::All)) => None,
+            (Some(Strings::All), Some(_)) => Some(Strings::All),
+            (Some(Strings::One(s)), Some(rhs)) => {
+                if rhs.contains_literal(&s) { None } else { Some(Strings::One(s)) }
+            }
+            (Some(Strings::Some(ss)), Some(rhs)) => {
+                subtract_literals(ss, &rhs).map(|ss| {
+                    if ss.len() == 1 {
+                        Strings::One(ss.into_iter().next().unwrap())
+                    } else {
+                        Strings::Some(ss)
+                    }
+                })
+            }
+            (None, _) => None,
+        };
+
+        // the query may succeed only by binding type variables, which is not a real subtyping
+        let removable = |lhs: &Ty, rhs: &Ty, ctx: &mut TypeContext| {
+            !lhs.has_unbound_tvar(ctx) && !rhs.has_unbound_tvar(ctx) && ctx.subtype_of(lhs, rhs)
+        };
+
+        if let (Some(tab), Some(rhs)) = (self.tables.take(), other.tables) {
+            let lhs = Ty::new(T::Tables(Cow::Owned(tab.clone())));
+            if !removable(&lhs, &Ty::new(T::Tables(Cow::Owned(rhs))), ctx) {
+                self.tables = Some(tab);
+            }
+        }
+
+        if let (Some(func), Some(rhs)) = (self.functions.take(), other.functions) {
+            let lhs = Ty::new(T::Functions(Cow::Owned(func.clone())));
+            if !removable(&lhs, &Ty::new(T::Functions(Cow::Owned(rhs))), ctx) {
+                self.functions = Some(func);
+            }
+        }
+
+        self.classes = &self.classes - &other.classes;
+
+        self.filter_display_hints(prevflags & !self.flags());
+        Ok(())
+    }
+
+    /// Returns a new union type with all components matching given flags removed.
+    ///
+    /// Like `remove_ty`, removing `T_INTEGER` from `number` still results in `number`.
+    pub fn remove_flags(&self, flags: Flags) -> Unioned {
+        let removed = flags & self.flags();
+        let mut u = self.clone();
+        if removed.is_empty() {
+            return u;
+        }
+
+        u.simple &= !UnionedSimple::from_bits_truncate(removed.bits());
+        if removed.intersects(T_NUMBER) {
+            u.numbers = match (u.numbers.take(), removed & T_NUMBER) {
+                (Some(Numbers::All), T_INTEGER) => Some(Numbers::All),
+                (Some(Numbers::All), T_NONINTEGER) => Some(Numbers::Int),
+                (_, _) => None,
+            };
+        }
+        if removed.contains(T_STRING) { u.strings = None; }
+        if removed.contains(T_TABLE) { u.tables = None; u.classes.clear(); }
+        if removed.contains(T_FUNCTION) { u.functions = None; }
+        u.filter_display_hints(removed & !u.flags());
+        u
+    }
+
     pub fn simplify(self) -> T<'static> {
         let single = {
             let mut single = None;
@@ -274,6 +385,38 @@ impl Unioned {
     }
 }
 
+// removes literals in `rhs` from `lits`, returning `None` if nothing remains
+fn subtract_literals<L, Rhs>(lits: BTreeSet<L>, rhs: &Rhs) -> Option<BTreeSet<L>>
+    where L: Ord, Rhs: LiteralSet<L>
+{
+    let remaining: BTreeSet<L> = lits.into_iter().filter(|l| !rhs.contains_literal(l)).collect();
+    if remaining.is_empty() { None } else { Some(remaining) }
+}
+
+trait LiteralSet<L> {
+    fn contains_literal(&self, lit: &L) -> bool;
+}
+
+impl LiteralSet<i32> for Numbers {
+    fn contains_literal(&self, lit: &i32) -> bool {
+        match *self {
+            Numbers::One(v) => v == *lit,
+            Numbers::Some(ref vv) => vv.contains(lit),
+            Numbers::Int | Numbers::All => true,
+        }
+    }
+}
+
+impl LiteralSet<Str> for Strings {
+    fn contains_literal(&self, lit: &Str) -> bool {
+        match *self {
+            Strings::One(ref s) => *s == *lit,
+            Strings::Some(ref ss) => ss.contains(lit),
+            Strings::All => true,
+        }
+    }
+}
+
 impl Union for Unioned {
     type Output = Unioned;
 
@@ -448,3 +591,52 @@ fn test_unioned_simplify() {
                T::Number);
 }
 
+#[test]
+fn test_unioned_remove() {
+    use super::NoTypeContext;
+
+    let ctx = &mut NoTypeContext;
+    let strs = |ss: &[&[u8]]| Some(Strings::Some(ss.iter().map(|&s| s.into()).collect()));
+
+    let u = Unioned {
+        simple: U_BOOLEAN | U_THREAD,
+        numbers: Some(Numbers::Some([1, 2, 3].iter().cloned().collect())),
+        strings: strs(&[b"a", b"b"]),
+        ..Unioned::empty()
+    };
+
+    let mut v = u.clone();
+    v.remove_ty(&Ty::new(T::True), ctx).unwrap();
+    assert_eq!(v.simple, U_FALSE | U_THREAD);
+    v.remove_ty(&Ty::new(T::Int(2)), ctx).unwrap();
+    assert_eq!(v.numbers, Some(Numbers::Some([1, 3].iter().cloned().collect())));
+    v.remove_ty(&Ty::new(T::Int(3)), ctx).unwrap();
+    assert_eq!(v.numbers, Some(Numbers::One(1)));
+    v.remove_ty(&Ty::new(T::Integer), ctx).unwrap();
+    assert_eq!(v.numbers, None);
+    v.remove_ty(&Ty::new(T::Str(Cow::Owned(b"a"[..].into()))), ctx).unwrap();
+    assert_eq!(v.strings, Some(Strings::One(b"b"[..].into())));
+    v.remove_ty(&Ty::new(T::String), ctx).unwrap();
+    assert_eq!(v.strings, None);
+    assert_eq!(v.clone().simplify(), T::Union(Cow::Owned(Unioned { simple: U_FALSE | U_THREAD,
+                                                                     ..Unioned::empty() })));
+
+    let mut v = u.clone();
+    v.remove_ty(&Ty::new(T::Number), ctx).unwrap();
+    assert_eq!(v, Unioned { numbers: None, ..u.clone() });
+
+    let mut v = Unioned { numbers: Some(Numbers::All), ..u.clone() };
+    v.remove_ty(&Ty::new(T::Integer), ctx).unwrap();
+    assert_eq!(v.numbers, Some(Numbers::All));
+
+    let mut v = u.clone();
+    v.remove_ty(&Ty::new(T::All), ctx).unwrap();
+    assert_eq!(v, Unioned::empty());
+
+    let v = u.remove_flags(T_STRING | T_TRUE);
+    assert_eq!(v, Unioned { simple: U_FALSE | U_THREAD, strings: None, ..u.clone() });
+    let v = Unioned { numbers: Some(Numbers::All), ..u.clone() }.remove_flags(T_NONINTEGER);
+    assert_eq!(v.numbers, Some(Numbers::Int));
+    let v = u.remove_flags(T_INTEGER);
+    assert_eq!(v.numbers, None);
+}
@@ -1,9 +1,11 @@
 use std::fmt;
 use std::ops;
+use std::cmp;
 use std::mem;
 use std::borrow::Cow;
 use std::result;
 use std::collections::{hash_map, HashMap};
+use std::sync::Arc;
 
 use kailua_env::{Spanned, WithLoc};
 use kailua_syntax::{Str, Name};
@@ -12,7 +14,7 @@ use kailua_diag::{Result, Reporter};
 use diag::{Origin, TypeReport, TypeResult, TypeReportHint, TypeReportMore};
 use super::display::{Display, DisplayState, DisplayName};
 use super::{TypeContext, NoTypeContext, TypeResolver};
-use super::{F, Slot, Lattice, Union, Dummy};
+use super::{F, Slot, Lattice, Union, Dummy, RVar};
 use super::{Numbers, Strings, Key, Tables, Function, Functions, Unioned, TVar, Tag, Class};
 use super::flags::*;
 use message as m;
@@ -38,11 +40,16 @@ impl Dyn {
             (None, None) => None,
         }
     }
+}
+
+impl Union for Dyn {
+    type Output = Dyn;
 
-    pub fn union(&self, rhs: Dyn) -> Dyn {
-        match (*self, rhs) {
-            (Dyn::Oops, _) | (_, Dyn::Oops) => Dyn::Oops,
-            (Dyn::User, Dyn::User) => Dyn::User,
+    // the weaker (i.e. more error-prone) variant wins
+    fn union(&self, other: &Dyn, _explicit: bool, _ctx: &mut TypeContext) -> TypeResult<Dyn> {
+        match (*self, *other) {
+            (Dyn::Oops, _) | (_, Dyn::Oops) => Ok(Dyn::Oops),
+            (Dyn::User, Dyn::User) => Ok(Dyn::User),
         }
     }
 }
@@ -116,6 +123,12 @@ pub enum T<'a> {
     Union(Cow<'a, Unioned>),
 }
 
+// records being visited and the depths of records already visited, for `T::depth`
+struct DepthState {
+    seen: Vec<RVar>,
+    memo: HashMap<RVar, usize>,
+}
+
 impl<'a> T<'a> {
     pub fn dummy() -> T<'a> { T::Dynamic(Dyn::Oops) }
 
@@ -148,6 +161,55 @@ impl<'a> T<'a> {
         T::Tables(Cow::Owned(Tables::Fields(fields.collect(), RVar::fresh())))
     }
     */
+    /// Converts flags back to the type, the inverse of `T::flags`.
+    ///
+    /// Returns `None` when no single type has exactly given flags,
+    /// e.g. `T_NONINTEGER` or a union like `T_INTEGER | T_STRING`.
+    /// Subtypes with the same flags (e.g. `3` for `T_INTEGER`) are never returned.
+    pub fn from_flags(flags: Flags) -> Option<T<'a>> {
+        match flags {
+            _ if flags == T_ALL | T_WHATEVER => Some(T::Dynamic(Dyn::User)),
+            _ if flags == T_ALL | T_DYNAMIC => Some(T::Dynamic(Dyn::Oops)),
+
+            T_ALL      => Some(T::All),
+            T_NONE     => Some(T::None),
+            T_BOOLEAN  => Some(T::Boolean),
+            T_TRUE     => Some(T::True),
+            T_FALSE    => Some(T::False),
+            T_THREAD   => Some(T::Thread),
+            T_USERDATA => Some(T::UserData),
+
+            T_NUMBER   => Some(T::Number),
+            T_INTEGER  => Some(T::Integer),
+            T_STRING   => Some(T::String),
+
+            T_TABLE    => Some(T::table()),
+            T_FUNCTION => Some(T::function()),
+
+            _ => None,
+        }
+    }
+
+    /// Returns the least type that covers every value with given flags.
+    ///
+    /// `T_DYNAMIC` (and therefore `T_STRINGY` and so on) should be masked out beforehand,
+    /// as the dynamic type cannot be used as an upper bound.
+    pub fn ubound_from_flags(flags: Flags) -> T<'a> {
+        assert!(!flags.intersects(T_DYNAMIC));
+
+        let mut t = T::None;
+        if flags.contains(T_TRUE)       { t = t | T::True; }
+        if flags.contains(T_FALSE)      { t = t | T::False; }
+        if flags.contains(T_NONINTEGER) { t = t | T::Number; }
+        if flags.contains(T_INTEGER)    { t = t | T::Integer; }
+        if flags.contains(T_STRING)     { t = t | T::String; }
+        if flags.contains(T_TABLE)      { t = t | T::table(); }
+        if flags.contains(T_FUNCTION)   { t = t | T::function(); }
+        if flags.contains(T_THREAD)     { t = t | T::Thread; }
+        if flags.contains(T_USERDATA)   { t = t | T::UserData; }
+        t
+    }
+
     pub fn array(v: Slot) -> T<'a> {
         T::Tables(Cow::Owned(Tables::Array(v)))
     }
@@ -304,6 +366,195 @@ impl<'a> T<'a> {
         }
     }
 
+    /// Returns true if the type can be displayed without any type context,
+    /// i.e. it has no type variables, records (row variables) or classes.
+    pub fn is_context_free(&self) -> bool {
+        fn tables_context_free(tab: &Tables) -> bool {
+            match *tab {
+                Tables::Fields(..) => false,
+                Tables::Array(ref v) | Tables::ArrayN(ref v) => v.unlift().is_context_free(),
+                Tables::Map(ref k, ref v) => k.is_context_free() && v.unlift().is_context_free(),
+                Tables::All => true,
+            }
+        }
+
+        fn functions_context_free(func: &Functions) -> bool {
+            match *func {
+                Functions::Simple(ref f) => {
+                    let returns = f.returns.as_ref().into_iter();
+                    f.args.head.iter().chain(f.args.tail.iter())
+                        .chain(returns.flat_map(|r| r.head.iter().chain(r.tail.iter())))
+                        .all(|t| t.is_context_free())
+                }
+                Functions::All => true,
+            }
+        }
+
+        match *self {
+            T::Tables(ref tab) => tables_context_free(tab),
+            T::Functions(ref func) => functions_context_free(func),
+            T::Class(_) | T::TVar(_) => false,
+            T::Union(ref u) => {
+                u.classes.is_empty() &&
+                u.tables.as_ref().map_or(true, |tab| tables_context_free(tab)) &&
+                u.functions.as_ref().map_or(true, |func| functions_context_free(func))
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns true if the type has any type variable without a tight bound,
+    /// so that the subtyping assertion against the type may bind that variable.
+    ///
+    /// Fields of records and exact types of type variables are followed through the context.
+    pub fn has_unbound_tvar(&self, ctx: &TypeContext) -> bool {
+        fn tables_unbound(tab: &Tables, ctx: &TypeContext, seen: &mut Vec<RVar>) -> bool {
+            match *tab {
+                Tables::Fields(ref rvar, ref meta) => {
+                    if seen.contains(rvar) {
+                        return false;
+                    }
+                    if meta.as_ref().map_or(false, |meta| unbound(meta, ctx, seen)) {
+                        return true;
+                    }
+                    seen.push(rvar.clone());
+                    let mut found = false;
+                    let _ = ctx.list_rvar_fields(rvar.clone(), &mut |_k, v| {
+                        found = unbound(&v.unlift(), ctx, seen);
+                        if found { Err(()) } else { Ok(()) }
+                    });
+                    seen.pop();
+                    found
+                }
+                Tables::Array(ref v) | Tables::ArrayN(ref v) => unbound(&v.unlift(), ctx, seen),
+                Tables::Map(ref k, ref v) => {
+                    unbound(k, ctx, seen) || unbound(&v.unlift(), ctx, seen)
+                }
+                Tables::All => false,
+            }
+        }
+
+        fn functions_unbound(func: &Functions, ctx: &TypeContext, seen: &mut Vec<RVar>) -> bool {
+            match *func {
+                Functions::Simple(ref f) => {
+                    let returns = f.returns.as_ref().into_iter();
+                    f.args.head.iter().chain(f.args.tail.iter())
+                        .chain(returns.flat_map(|r| r.head.iter().chain(r.tail.iter())))
+                        .any(|t| unbound(t, ctx, seen))
+                }
+                Functions::All => false,
+            }
+        }
+
+        fn unbound(t: &T, ctx: &TypeContext, seen: &mut Vec<RVar>) -> bool {
+            match *t {
+                T::Tables(ref tab) => tables_unbound(tab, ctx, seen),
+                T::Functions(ref func) => functions_unbound(func, ctx, seen),
+                T::TVar(tv) => match ctx.get_tvar_exact_type(tv) {
+                    Some(ty) => unbound(&ty, ctx, seen),
+                    None => true,
+                },
+                T::Union(ref u) => {
+                    u.tables.as_ref().map_or(false, |tab| tables_unbound(tab, ctx, seen)) ||
+                    u.functions.as_ref().map_or(false, |func| functions_unbound(func, ctx, seen))
+                }
+                _ => false,
+            }
+        }
+
+        unbound(self, ctx, &mut Vec::new())
+    }
+
+    /// Returns the structural depth of the type, where non-composite types have the depth 1.
+    ///
+    /// Fields of records and exact types of type variables are followed through the context.
+    /// Like the display, a record referring to itself is not followed again,
+    /// so recursive types still have a finite depth.
+    /// Each record is visited only once, so records shared by multiple fields
+    /// do not make the computation exponential.
+    /// The computation stops and returns `limit + 1` once the depth exceeds `limit`.
+    pub fn depth(&self, limit: usize, ctx: &TypeContext) -> usize {
+        self.depth_with_state(limit, ctx, &mut DepthState { seen: Vec::new(),
+                                                            memo: HashMap::new() })
+    }
+
+    fn depth_with_state(&self, limit: usize, ctx: &TypeContext, st: &mut DepthState) -> usize {
+        fn tables_depth(tab: &Tables, limit: usize, ctx: &TypeContext,
+                        st: &mut DepthState) -> usize {
+            match *tab {
+                Tables::Fields(ref rvar, ref meta) => {
+                    if st.seen.contains(rvar) {
+                        return 0;
+                    }
+                    if let Some(&depth) = st.memo.get(rvar) {
+                        return cmp::min(depth, limit + 1);
+                    }
+                    st.seen.push(rvar.clone());
+                    let mut depth = meta.as_ref().map_or(0, |meta| {
+                        meta.depth_with_state(limit, ctx, st)
+                    });
+                    let _ = ctx.list_rvar_fields(rvar.clone(), &mut |_k, v| {
+                        depth = cmp::max(depth, v.unlift().depth_with_state(limit, ctx, st));
+                        if depth > limit { Err(()) } else { Ok(()) }
+                    });
+                    st.seen.pop();
+                    // a depth cut off by the current limit is not exact,
+                    // and can be wrong for the same record visited with a larger limit
+                    if depth <= limit {
+                        st.memo.insert(rvar.clone(), depth);
+                    }
+                    depth
+                }
+                Tables::Array(ref v) | Tables::ArrayN(ref v) => {
+                    v.unlift().depth_with_state(limit, ctx, st)
+                }
+                Tables::Map(ref k, ref v) => {
+                    cmp::max(k.depth_with_state(limit, ctx, st),
+                             v.unlift().depth_with_state(limit, ctx, st))
+                }
+                Tables::All => 0,
+            }
+        }
+
+        fn functions_depth(func: &Functions, limit: usize, ctx: &TypeContext,
+                           st: &mut DepthState) -> usize {
+            match *func {
+                Functions::Simple(ref f) => {
+                    let returns = f.returns.as_ref().into_iter();
+                    f.args.head.iter().chain(f.args.tail.iter())
+                        .chain(returns.flat_map(|r| r.head.iter().chain(r.tail.iter())))
+                        .map(|t| t.depth_with_state(limit, ctx, st))
+                        .max().unwrap_or(0)
+                }
+                Functions::All => 0,
+            }
+        }
+
+        // the depth of composite types is one plus the maximal depth of their components
+        if limit == 0 {
+            return 1;
+        }
+        let inner = match *self {
+            T::Tables(ref tab) => tables_depth(tab, limit - 1, ctx, st),
+            T::Functions(ref func) => functions_depth(func, limit - 1, ctx, st),
+            T::TVar(tv) => match ctx.get_tvar_exact_type(tv) {
+                Some(ty) => return ty.depth_with_state(limit, ctx, st),
+                None => 0,
+            },
+            T::Union(ref u) => {
+                let tables = u.tables.as_ref().map_or(0, |tab| {
+                    tables_depth(tab, limit - 1, ctx, st)
+                });
+                let functions = u.functions.as_ref().map_or(0, |func| {
+                    functions_depth(func, limit - 1, ctx, st)
+                });
+                cmp::max(tables, functions)
+            }
+            _ => 0,
+        };
+        1 + inner
+    }
+
     /// Coerces "implicit" types into "explicit" types.
     ///
     /// This is the only possible way in Kailua for implicit coercions to happen,
@@ -329,7 +580,10 @@ impl<'a> T<'a> {
     ///
     /// Primarily used for function calls where all type variable and row variables are
     /// made fresh per each call, so that prior calls cannot affect constraints to later calls.
-    pub fn generalize(self, ctx: &mut TypeContext) -> T<'static> {
+    /// Type variables in `substs` are instead replaced to given type variables,
+    /// so that every occurrence of the same type parameter maps to the same type variable.
+    pub fn generalize(self, substs: &HashMap<TVar, TVar>,
+                      ctx: &mut TypeContext) -> T<'static> {
         match self {
             T::Dynamic(dyn) => T::Dynamic(dyn),
 
@@ -348,18 +602,21 @@ impl<'a> T<'a> {
             T::Str(s)     => T::Str(Cow::Owned(s.into_owned())),
 
             // tables are recursively altered
-            T::Tables(tab) => T::Tables(Cow::Owned(tab.into_owned().generalize(ctx))),
+            T::Tables(tab) => T::Tables(Cow::Owned(tab.into_owned().generalize(substs, ctx))),
 
             // functions are _not_ recursively altered (will be generalized at call site)
             T::Functions(func) => T::Functions(Cow::Owned(func.into_owned())),
 
             T::Class(c) => T::Class(c),
-            T::TVar(tv) => T::TVar(ctx.copy_tvar(tv)),
+            T::TVar(tv) => match substs.get(&tv) {
+                Some(&tv) => T::TVar(tv),
+                None => T::TVar(ctx.copy_tvar(tv)),
+            },
 
             // unions _are_ recursively altered
             T::Union(u) => {
                 let mut u = u.into_owned();
-                u.tables = u.tables.take().map(|tab| tab.generalize(ctx));
+                u.tables = u.tables.take().map(|tab| tab.generalize(substs, ctx));
                 T::Union(Cow::Owned(u))
             },
         }
@@ -392,24 +649,21 @@ impl<'a> T<'a> {
         }
     }
 
-    pub fn filter_by_flags<'b>(&'b self, flags: Flags,
-                               ctx: &mut TypeContext) -> TypeResult<Cow<'b, T<'a>>> {
-        fn flags_to_ubound(flags: Flags) -> T<'static> {
-            assert!(!flags.intersects(T_DYNAMIC));
-
-            let mut t = T::None;
-            if flags.contains(T_TRUE)       { t = t | T::True; }
-            if flags.contains(T_FALSE)      { t = t | T::False; }
-            if flags.contains(T_NONINTEGER) { t = t | T::Number; }
-            if flags.contains(T_INTEGER)    { t = t | T::Integer; }
-            if flags.contains(T_STRING)     { t = t | T::String; }
-            if flags.contains(T_TABLE)      { t = t | T::table(); }
-            if flags.contains(T_FUNCTION)   { t = t | T::function(); }
-            if flags.contains(T_THREAD)     { t = t | T::Thread; }
-            if flags.contains(T_USERDATA)   { t = t | T::UserData; }
-            t
+    /// Collapses the union type into the simplest equivalent type.
+    ///
+    /// `Unioned` keeps each kind of types separately and `Unioned::from` merges any union
+    /// operand, so the union can never directly contain another union: `A | (B | C)` is
+    /// always represented as `A | B | C`. What remains is a union with a single component,
+    /// which is collapsed into that component (except for explicitly written literal types).
+    pub fn flatten(&self) -> T<'static> {
+        match *self {
+            T::Union(ref u) => u.clone().into_owned().simplify(),
+            ref t => t.clone().into_send(),
         }
+    }
 
+    pub fn filter_by_flags<'b>(&'b self, flags: Flags,
+                               ctx: &mut TypeContext) -> TypeResult<Cow<'b, T<'a>>> {
         fn narrow_numbers<'a>(num: Cow<'a, Numbers>, flags: Flags) -> Option<Cow<'a, Numbers>> {
             let is_all = match num.as_ref() { &Numbers::All => true, _ => false };
             match (flags & T_NUMBER, is_all) {
@@ -421,7 +675,7 @@ impl<'a> T<'a> {
         }
 
         fn narrow_tvar(tvar: TVar, flags: Flags, ctx: &mut TypeContext) -> TypeResult<TVar> {
-            let ubound = flags_to_ubound(flags);
+            let ubound = T::ubound_from_flags(flags);
 
             // make a type variable i such that i <: ubound and i <: tvar
             let i = ctx.gen_tvar();
@@ -438,7 +692,7 @@ impl<'a> T<'a> {
         match *self {
             T::Dynamic(_) => Ok(Cow::Borrowed(self)),
             T::None => Ok(Cow::Borrowed(self)),
-            T::All => Ok(Cow::Owned(flags_to_ubound(flags))),
+            T::All => Ok(Cow::Owned(T::ubound_from_flags(flags))),
             T::Boolean => match flags & T_BOOLEAN {
                 T_BOOLEAN => Ok(Cow::Borrowed(self)),
                 T_TRUE => Ok(Cow::Owned(T::True)),
@@ -634,7 +888,7 @@ impl<'a> T<'a> {
             let (t, (keep1, keep2)) = match (&*t1, &*t2) {
                 // dynamic eclipses everything else
                 (&T::Dynamic(dyn1), &T::Dynamic(dyn2)) => {
-                    let dyn = dyn1.union(dyn2);
+                    let dyn = dyn1.union(&dyn2, explicit, ctx)?;
                     (T::Dynamic(dyn), (dyn1 == dyn, dyn2 == dyn))
                 },
                 (&T::Dynamic(dyn), _) => (T::Dynamic(dyn), LEFT),
@@ -765,18 +1019,18 @@ impl<'a> T<'a> {
                     let mut inner = TyInner::new(t, nil1.union(nil2));
                     if tag1 == tag2 { inner.set_tag(tag1); }
                     inner.set_display_hint(hint.map(|hint| Box::new(hint.into_owned())));
-                    Ok(Err(Ty { inner: Box::new(inner) }))
+                    Ok(Err(Ty { inner: Arc::new(inner) }))
                 },
                 (Some((nil, tag)), None, hint) | (None, Some((nil, tag)), hint) => {
                     let mut inner = TyInner::new(t, nil);
                     inner.set_tag(tag);
                     inner.set_display_hint(hint.map(|hint| Box::new(hint.into_owned())));
-                    Ok(Err(Ty { inner: Box::new(inner) }))
+                    Ok(Err(Ty { inner: Arc::new(inner) }))
                 },
                 (None, None, Some(hint)) => {
                     let mut inner = TyInner::new(t, Nil::Silent);
                     inner.set_display_hint(Some(Box::new(hint.into_owned())));
-                    Ok(Err(Ty { inner: Box::new(inner) }))
+                    Ok(Err(Ty { inner: Arc::new(inner) }))
                 }
                 (None, None, None) => Ok(Ok(t)),
             }
@@ -965,6 +1219,9 @@ impl<'a> Display for T<'a> {
             T::Str(ref s) => write!(f, "{:?}", s),
 
             T::TVar(tv) => {
+                if let Some(name) = st.tvar_name(tv) {
+                    return write!(f, "{:+}", name);
+                }
                 if st.is_tvar_seen(tv) {
                     return write!(f, "<...>");
                 } else {
@@ -987,6 +1244,34 @@ impl<'a> Display for T<'a> {
             T::Union(ref u)         => fmt::Display::fmt(&u.display(st), f),
         }
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        match *self {
+            T::Dynamic(Dyn::User) => Some("WHATEVER".to_owned()),
+            T::Dynamic(Dyn::Oops) => None,
+            T::All => Some("any".to_owned()),
+            T::None => None,
+            T::TVar(tv) => ctx.get_tvar_exact_type(tv).and_then(|t| t.brief_displayed(ctx)),
+
+            // literals and other subtypes are summarized into their categories
+            _ => {
+                let flags = self.flags();
+                let mut names = Vec::new();
+                if flags.intersects(T_BOOLEAN) { names.push("boolean"); }
+                if flags.intersects(T_NONINTEGER) {
+                    names.push("number");
+                } else if flags.intersects(T_INTEGER) {
+                    names.push("integer");
+                }
+                if flags.intersects(T_STRING) { names.push("string"); }
+                if flags.intersects(T_TABLE) { names.push("table"); }
+                if flags.intersects(T_FUNCTION) { names.push("function"); }
+                if flags.intersects(T_THREAD) { names.push("thread"); }
+                if flags.intersects(T_USERDATA) { names.push("userdata"); }
+                if names.is_empty() { None } else { Some(names.join("|")) }
+            }
+        }
+    }
 }
 
 impl<'a> fmt::Debug for T<'a> {
@@ -1164,24 +1449,24 @@ impl TyInner {
 /// an optional type tag (`Tag`) and an optional display name (`DisplayName`).
 #[derive(Clone, PartialEq)]
 pub struct Ty {
-    inner: Box<TyInner>,
+    inner: Arc<TyInner>,
 }
 
 impl Ty {
     pub fn dummy() -> Ty {
-        Ty { inner: Box::new(TyInner::new(T::dummy(), Nil::Silent)) }
+        Ty { inner: Arc::new(TyInner::new(T::dummy(), Nil::Silent)) }
     }
 
     pub fn silent_nil() -> Ty {
-        Ty { inner: Box::new(TyInner::new(T::None, Nil::Silent)) }
+        Ty { inner: Arc::new(TyInner::new(T::None, Nil::Silent)) }
     }
 
     pub fn noisy_nil() -> Ty {
-        Ty { inner: Box::new(TyInner::new(T::None, Nil::Noisy)) }
+        Ty { inner: Arc::new(TyInner::new(T::None, Nil::Noisy)) }
     }
 
     pub fn new(ty: T<'static>) -> Ty {
-        Ty { inner: Box::new(TyInner::new(ty, Nil::Silent)) }
+        Ty { inner: Arc::new(TyInner::new(ty, Nil::Silent)) }
     }
 
     pub fn from_kind(kind: &Spanned<Kind>, resolv: &mut TypeResolver) -> Result<Ty> {
@@ -1229,7 +1514,7 @@ impl Ty {
                 resolv.context_mut().assert_rvar_closed(rvar.clone()).expect(
                     "cannot make a fresh row variable not extensible"
                 );
-                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar))))
+                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar, None))))
             },
 
             K::Record(ref fields, extensible) => {
@@ -1262,7 +1547,7 @@ impl Ty {
                         "cannot make a fresh row variable not extensible"
                     );
                 }
-                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar))))
+                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar, None))))
             }
 
             K::Tuple(ref fields) => {
@@ -1280,7 +1565,7 @@ impl Ty {
                 resolv.context_mut().assert_rvar_closed(rvar.clone()).expect(
                     "cannot make a fresh row variable not extensible"
                 );
-                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar))))
+                Ty::new(T::Tables(Cow::Owned(Tables::Fields(rvar, None))))
             },
 
             K::Array(ref v) => {
@@ -1328,7 +1613,7 @@ impl Ty {
                         resolv.warn(attr, m::DuplicateAttr { ty: ty.display(resolv.context()) })
                               .done()?;
                     } else {
-                        ty.inner.set_tag(Some(tag));
+                        ty.inner_mut().set_tag(Some(tag));
                     }
                 }
                 ty
@@ -1343,24 +1628,24 @@ impl Ty {
     }
 
     pub fn or_nil(mut self, nil: Nil) -> Ty {
-        self.inner.set_nil(nil);
+        self.inner_mut().set_nil(nil);
         self
     }
 
     pub fn with_nil(mut self) -> Ty {
         let nil = self.inner.nil().with_nil();
-        self.inner.set_nil(nil);
+        self.inner_mut().set_nil(nil);
         self
     }
 
     pub fn without_nil(mut self) -> Ty {
         let nil = self.inner.nil().without_nil();
-        self.inner.set_nil(nil);
+        self.inner_mut().set_nil(nil);
         self
     }
 
     pub fn union_nil(mut self, nil: Nil) -> Ty {
-        self.inner.union_nil(nil);
+        self.inner_mut().union_nil(nil);
         self
     }
 
@@ -1375,17 +1660,17 @@ impl Ty {
     }
 
     pub fn with_tag<T: Into<Option<Tag>>>(mut self, tag: T) -> Ty {
-        self.inner.set_tag(tag.into());
+        self.inner_mut().set_tag(tag.into());
         self
     }
 
     pub fn and_display(mut self, disp: DisplayName) -> Ty {
         // update if there is no hint already set, or the new display can override the old hint
         if self.inner.display_hint().map_or(true, |hint| disp.can_override(&hint.name)) {
-            self.inner.set_display_hint_with_name(Some(disp.clone()));
+            self.inner_mut().set_display_hint_with_name(Some(disp.clone()));
 
             // also update the union if any, but only when there are no other hints for union
-            if let T::Union(ref mut u) = *self.inner.ty_mut() {
+            if let T::Union(ref mut u) = *self.inner_mut().ty_mut() {
                 if u.display_hints.is_empty() {
                     let flags = u.flags();
                     u.to_mut().display_hints.push((flags, disp));
@@ -1397,14 +1682,14 @@ impl Ty {
     }
 
     pub fn truthy(mut self) -> Ty {
-        self.inner.remap_ty_and_hint(|t| t.truthy());
+        self.inner_mut().remap_ty_and_hint(|t| t.truthy());
         let nil = self.inner.nil().without_nil();
-        self.inner.set_nil(nil);
+        self.inner_mut().set_nil(nil);
         self
     }
 
     pub fn falsy(mut self) -> Ty {
-        self.inner.remap_ty_and_hint(|t| t.falsy());
+        self.inner_mut().remap_ty_and_hint(|t| t.falsy());
         self
     }
 
@@ -1426,16 +1711,30 @@ impl Ty {
     }
 
     pub fn coerce(mut self) -> Ty {
-        self.inner.remap_ty_and_hint(|t| t.coerce());
+        self.inner_mut().remap_ty_and_hint(|t| t.coerce());
         self
     }
 
-    pub fn generalize(mut self, ctx: &mut TypeContext) -> Ty {
-        self.inner.remap_ty(|t| t.generalize(ctx));
+    pub fn generalize(mut self, substs: &HashMap<TVar, TVar>, ctx: &mut TypeContext) -> Ty {
+        self.inner_mut().remap_ty(|t| t.generalize(substs, ctx));
         // this will only alter tvars/rvars, so display hint doesn't change
         self
     }
 
+    /// Same to `Tables::to_covariant_elements` but applies to a type with a single table type.
+    pub fn to_covariant_elements(&self) -> Option<Ty> {
+        let tab = match *self.inner.ty() {
+            T::Tables(ref tab) => tab.to_covariant_elements(),
+            _ => None,
+        };
+
+        tab.map(|tab| {
+            let mut ty = self.clone();
+            ty.inner_mut().remap_ty(|_| T::Tables(Cow::Owned(tab)));
+            ty
+        })
+    }
+
     pub fn flags(&self) -> Flags {
         let mut flags = self.inner.ty().flags();
         if self.inner.nil() == Nil::Noisy {
@@ -1445,14 +1744,50 @@ impl Ty {
     }
 
     pub fn filter_by_flags(mut self, flags: Flags, ctx: &mut TypeContext) -> TypeResult<Ty> {
-        self.inner.remap_ty_and_hint_res(|t| t.filter_by_flags(flags, ctx))?;
+        self.inner_mut().remap_ty_and_hint_res(|t| t.filter_by_flags(flags, ctx))?;
         if !flags.contains(T_NOISY_NIL) {
             let nil = self.inner.nil().without_nil();
-            self.inner.set_nil(nil);
+            self.inner_mut().set_nil(nil);
         }
         Ok(self)
     }
 
+    /// Returns a type for values of this type which are not of the other type (`self \ other`).
+    ///
+    /// Any union component which is a subtype of the other type is removed,
+    /// so a non-union type is either kept or removed as a whole (see `Unioned::remove_ty`).
+    /// `nil` is removed only when the other type explicitly has `nil`.
+    /// Dynamic types, type variables and `any` are kept as they cannot be narrowed,
+    /// and nothing is removed when the other type is a type variable.
+    /// Likewise, components with unbound type variables inside are kept.
+    /// This does not bind any type variable, as the subtyping is only queried.
+    pub fn difference(&self, other: &Ty, ctx: &mut TypeContext) -> TypeResult<Ty> {
+        let mut ty = self.clone();
+
+        if other.get_tvar().is_none() {
+            ty.inner_mut().remap_ty_and_hint_res(|t| -> TypeResult<Cow<T<'static>>> {
+                match *t {
+                    T::Dynamic(_) | T::All | T::TVar(_) => Ok(Cow::Borrowed(t)),
+                    _ => {
+                        let orig = Unioned::from(t, ctx)?;
+                        let mut u = orig.clone();
+                        u.remove_ty(other, ctx)?;
+                        if u == orig {
+                            Ok(Cow::Borrowed(t))
+                        } else {
+                            Ok(Cow::Owned(u.simplify()))
+                        }
+                    }
+                }
+            })?;
+        }
+
+        if other.nil() == Nil::Noisy {
+            ty = ty.without_nil();
+        }
+        Ok(ty)
+    }
+
     fn display_hint(&self) -> Option<&DisplayHint> {
         self.inner.display_hint()
     }
@@ -1500,7 +1835,16 @@ impl Ty {
     }
 
     pub fn unwrap(self) -> T<'static> {
-        self.inner.unwrap_ty()
+        // avoids cloning the whole inner representation when possible
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner.unwrap_ty(),
+            Err(inner) => inner.ty().clone(),
+        }
+    }
+
+    // the inner representation is shared among clones, so it should be copied on write
+    fn inner_mut(&mut self) -> &mut TyInner {
+        Arc::make_mut(&mut self.inner)
     }
 }
 
@@ -1510,7 +1854,7 @@ impl Dummy for Ty {
 
 impl<'a> From<T<'a>> for Ty {
     fn from(ty: T<'a>) -> Ty {
-        Ty { inner: Box::new(TyInner::new(ty.into_send(), Nil::Silent)) }
+        Ty { inner: Arc::new(TyInner::new(ty.into_send(), Nil::Silent)) }
     }
 }
 
@@ -1520,7 +1864,7 @@ impl ops::Deref for Ty {
 }
 
 impl ops::DerefMut for Ty {
-    fn deref_mut(&mut self) -> &mut T<'static> { self.inner.ty_mut() }
+    fn deref_mut(&mut self) -> &mut T<'static> { self.inner_mut().ty_mut() }
 }
 
 fn tag_is_sub(lhs: Option<Tag>, rhs: Option<Tag>) -> bool {
@@ -1576,12 +1920,12 @@ macro_rules! define_ty_impls {
                 let $l = self;
                 let $r = other;
                 let mut ty = match $lty.union_with_hints($lhint, $rty, $rhint, explicit, ctx) {
-                    Ok(Ok(t)) => Ty { inner: Box::new(TyInner::new(t, Nil::Absent)) },
+                    Ok(Ok(t)) => Ty { inner: Arc::new(TyInner::new(t, Nil::Absent)) },
                     Ok(Err(ty)) => ty,
                     Err(r) => return Err(r.cannot_union($origin, self, other, explicit, ctx)),
                 };
-                ty.inner.union_nil($union_nil);
-                ty.inner.union_tag($union_tag);
+                ty.inner_mut().union_nil($union_nil);
+                ty.inner_mut().union_tag($union_tag);
                 Ok(ty)
             }
         }
@@ -1818,6 +2162,17 @@ impl Display for Ty {
             Nil::Absent => write!(f, "!"),
         }
     }
+
+    fn brief_displayed(&self, ctx: &TypeContext) -> Option<String> {
+        // tags and display hints are ignored
+        match (self.inner.ty(), self.nil()) {
+            (&T::None, Nil::Silent) | (&T::None, Nil::Noisy) => Some("nil".to_owned()),
+            (ty, Nil::Noisy) => ty.brief_displayed(ctx).map(|brief| {
+                if brief.contains('|') { format!("({})?", brief) } else { brief + "?" }
+            }),
+            (ty, _) => ty.brief_displayed(ctx),
+        }
+    }
 }
 
 impl fmt::Debug for Ty {
@@ -1949,6 +2304,14 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_union_dyn() {
+        check_base!(Dyn::User, Dyn::User; Dyn::User);
+        check_base!(Dyn::User, Dyn::Oops; Dyn::Oops);
+        check_base!(Dyn::Oops, Dyn::User; Dyn::Oops);
+        check_base!(Dyn::Oops, Dyn::Oops; Dyn::Oops);
+    }
+
     #[test]
     fn test_union_t() {
         macro_rules! check {
@@ -2138,6 +2501,31 @@ mod tests {
                implicit=nil(T::String | T::Integer));
     }
 
+    #[test]
+    fn test_flatten() {
+        // nested unions are always merged
+        let nested = T::Integer | (T::String | T::Boolean);
+        let flat = (T::Integer | T::String) | T::Boolean;
+        assert_eq!(nested.flatten(), flat);
+        if let T::Union(ref u) = nested.flatten() {
+            assert_eq!(u.flags(), T_INTEGER | T_STRING | T_BOOLEAN);
+        } else {
+            panic!("{:?} should be a union", nested.flatten());
+        }
+
+        // a single non-literal component is collapsed
+        let mut single = Unioned::empty();
+        single.strings = Some(Strings::All);
+        assert_eq!(T::Union(Cow::Owned(single)).flatten(), T::String);
+
+        // explicit literals are retained
+        let lit = T::Union(Cow::Owned(Unioned::explicit_int(42)));
+        assert_eq!(lit.flatten(), lit);
+
+        // non-unions are left as is
+        assert_eq!(T::Integer.flatten(), T::Integer);
+    }
+
     #[test]
     fn test_sub() {
         /*
@@ -2224,6 +2612,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_difference() {
+        let mut types = Types::new(Locale::dummy(), Box::new(DummyClassProvider));
+        let mut diff = |l: Ty, r: Ty| l.difference(&r, &mut types).unwrap();
+
+        assert_eq!(diff(nil(T::Integer), Ty::noisy_nil()), Ty::new(T::Integer));
+        assert_eq!(diff(nil(T::Integer), Ty::new(T::String)), nil(T::Integer));
+        assert_eq!(diff(Ty::new(T::Integer), Ty::new(T::Number)), Ty::new(T::None));
+        assert_eq!(diff(Ty::new(T::Number), Ty::new(T::Integer)), Ty::new(T::Number));
+        assert_eq!(diff(Ty::new(T::Integer | T::String), Ty::new(T::String)),
+                   Ty::new(T::Integer));
+        assert_eq!(diff(nil(T::Integer | T::String | T::Boolean), nil(T::Boolean)),
+                   Ty::new(T::Integer | T::String));
+        assert_eq!(diff(Ty::new(T::strs(vec![s("a"), s("b")])), Ty::new(T::Str(os("a")))),
+                   Ty::new(T::Union(Cow::Owned(Unioned::explicit_str(s("b"))))));
+        assert_eq!(diff(Ty::new(T::Boolean), Ty::new(T::True)).flags(), T_FALSE);
+        assert_eq!(diff(Ty::new(T::Dynamic(Dyn::User)), Ty::new(T::String)),
+                   Ty::new(T::Dynamic(Dyn::User)));
+        assert_eq!(diff(Ty::new(T::Integer | T::String), Ty::new(T::All)), Ty::new(T::None));
+
+        // type variables inside components are not bound, so such components are kept
+        let v1 = types.gen_tvar();
+        let bounds = types.get_tvar_bounds(v1);
+        let vector = |t: T<'static>| Ty::new(T::array(Slot::new(F::Var, Ty::new(t))));
+        assert_eq!(vector(T::TVar(v1)).difference(&vector(T::Integer), &mut types).unwrap(),
+                   vector(T::TVar(v1)));
+        assert_eq!(types.get_tvar_bounds(v1), bounds);
+
+        // ...unless they are already tightly bound
+        let v2 = types.gen_tvar();
+        types.assert_tvar_eq(v2, &Ty::new(T::Integer)).unwrap();
+        assert_eq!(vector(T::TVar(v2)).difference(&vector(T::Integer), &mut types).unwrap(),
+                   Ty::new(T::None));
+    }
+
+    #[test]
+    fn test_from_flags() {
+        let roundtrip = |t: T| T::from_flags(t.flags()).map(|t| Ty::new(t));
+
+        for t in vec![T::Dynamic(Dyn::User), T::Dynamic(Dyn::Oops), T::All, T::None,
+                      T::Boolean, T::True, T::False, T::Thread, T::UserData,
+                      T::Number, T::Integer, T::String, T::table(), T::function()] {
+            assert_eq!(roundtrip(t.clone()), Some(Ty::new(t)));
+        }
+
+        // subtypes are converted to the type with the same flags
+        assert_eq!(roundtrip(T::Int(42)), Some(Ty::new(T::Integer)));
+        assert_eq!(roundtrip(T::Str(os("foo"))), Some(Ty::new(T::String)));
+
+        // no exact representations
+        assert_eq!(T::from_flags(T_NONINTEGER), None);
+        assert_eq!(T::from_flags(T_INTEGER | T_STRING), None);
+        assert_eq!(T::from_flags(T_NOISY_NIL), None);
+        assert_eq!(T::from_flags(T_TRUTHY), None);
+    }
+
     #[test]
     fn test_eq() {
         // tag subtyping
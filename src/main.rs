@@ -14,10 +14,14 @@ use std::path::Path;
 use clap::{App, Error, ErrorKind};
 use kailua_workspace::Workspace;
 
-fn parse_and_check(workspace: &Workspace, quiet: bool) -> Result<(), String> {
+fn parse_and_check(workspace: &Workspace, quiet: bool,
+                   verify_annotations: bool, dump_signature: bool) -> Result<(), String> {
     use std::str;
     use std::io;
-    use std::cell::RefCell;
+    use std::fs::File;
+    use std::cmp;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashSet;
     use std::rc::Rc;
     use std::path::Path;
 
@@ -28,6 +32,7 @@ fn parse_and_check(workspace: &Workspace, quiet: bool) -> Result<(), String> {
     use kailua_check::check_from_chunk_with_preloading;
     use kailua_check::env::Context;
     use kailua_check::options::FsSource;
+    use kailua_check::signature::write_signature;
     use kailua_workspace::WorkspaceOptions;
 
     struct LocalFsSource {
@@ -57,9 +62,13 @@ fn parse_and_check(workspace: &Workspace, quiet: bool) -> Result<(), String> {
         }
     }
 
+    // tracks the report state by itself, since the inner report doesn't see reports when quiet
     struct OptionalConsoleReport {
         quiet: bool,
         report: ConsoleReport,
+        seen: RefCell<HashSet<String>>,
+        last_error: Cell<Option<Span>>,
+        max_kind: Cell<Option<Kind>>,
     }
 
     impl Report for OptionalConsoleReport {
@@ -68,23 +77,47 @@ fn parse_and_check(workspace: &Workspace, quiet: bool) -> Result<(), String> {
         }
 
         fn add_span(&self, kind: Kind, span: Span, msg: &Localize) -> kailua_diag::Result<()> {
+            self.max_kind.set(cmp::max(self.max_kind.get(), Some(kind)));
+            if kind >= Kind::Error {
+                self.last_error.set(Some(span));
+            }
             if self.quiet {
-                Ok(())
+                if kind == Kind::Fatal { Err(Stop) } else { Ok(()) }
             } else {
                 self.report.add_span(kind, span, msg)
             }
         }
+
+        fn max_kind(&self) -> Option<Kind> {
+            self.max_kind.get()
+        }
+
+        fn mark_once(&self, key: &str) -> bool {
+            self.seen.borrow_mut().insert(key.to_owned())
+        }
+
+        fn last_error_span(&self) -> Option<Span> {
+            self.last_error.get()
+        }
+
+        fn is_empty(&self) -> bool {
+            self.max_kind.get().is_none()
+        }
     }
 
     let source = Rc::new(RefCell::new(Source::new()));
     let report = Rc::new(TrackMaxKind::new(OptionalConsoleReport {
         quiet: quiet,
         report: ConsoleReport::with_locale(source.clone(), workspace.message_locale()),
+        seen: RefCell::new(HashSet::new()),
+        last_error: Cell::new(None),
+        max_kind: Cell::new(None),
     }));
 
     // TODO multiple outputs should deduplicate warnings if possible
     for start_path in workspace.start_paths() {
         let mut context = Context::new(report.clone());
+        context.set_verify_annotations(verify_annotations);
 
         let fssource = LocalFsSource { source: source.clone() };
         let filechunk = match fssource.chunk_from_path((**start_path).without_loc(), &report) {
@@ -106,6 +139,13 @@ fn parse_and_check(workspace: &Workspace, quiet: bool) -> Result<(), String> {
         if !(output.is_ok() && report.can_continue()) {
             return Err(format!("Stopped due to prior errors"));
         }
+
+        if dump_signature {
+            let stub_path = start_path.with_extension("d.lua");
+            File::create(&stub_path).and_then(|f| write_signature(&context, f)).map_err(|e| {
+                format!("Couldn't write a signature file `{}`: {}", stub_path.display(), e)
+            })?;
+        }
     }
 
     Ok(())
@@ -153,6 +193,16 @@ fn build_app() -> App<'static, 'static> {
                  Similar to `--add-package-cpath` but will ignore the default values.")
             (@arg quiet: -q --quiet
                 "Suppresses all reports.")
+            (@arg verify_annotations: --("verify-annotations")
+                "Verifies that every explicit type annotation is at least as general as \
+                 the inferred type, and reports an inconsistent annotation otherwise.\n\
+                 Useful for catching stale annotations after refactoring.")
+            (@arg dump_signature: --("dump-signature")
+                "Writes a signature stub `FILE.d.lua` next to each start path `FILE.lua`, \
+                 containing `--# assume global` for every global name defined while checking \
+                 and the value returned from `FILE.lua` if any.\n\
+                 The stub can be used in place of the sources \
+                 to distribute the type information of the library.")
             (@arg message_locale: -l --("message-locale") [LOCALE]
                 "Sets the message locales. Defaults to the system language.")
             (@arg path:
@@ -245,6 +295,8 @@ pub fn main() {
             parse_package_paths("set_package_cpath", "add_package_cpath").or(config.package_cpath);
 
         let quiet = matches.is_present("quiet");
+        let verify_annotations = matches.is_present("verify_annotations");
+        let dump_signature = matches.is_present("dump_signature");
 
         let message_locale = if let Some(locale) = matches.value_of("message_locale") {
             if let Some(locale) = Locale::new(locale) {
@@ -257,7 +309,8 @@ pub fn main() {
         };
 
         if let Some(workspace) = Workspace::new(&config, message_locale) {
-            if let Err(e) = parse_and_check(&workspace, quiet) {
+            if let Err(e) = parse_and_check(&workspace, quiet, verify_annotations,
+                                            dump_signature) {
                 // clap does not have something like ErrorKind::Other :(
                 io_error(&e);
             }